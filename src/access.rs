@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use actix_web::http::{Method, StatusCode};
+use actix_web::{HttpRequest, HttpResponse, web};
+
+use super::config;
+use super::expiry::ExpiryStore;
+use super::meta_file;
+use super::metadata::MetadataStore;
+use super::path::SafePath;
+use super::uploads::parse_duration;
+
+/// How often recorded downloads are written to `file-metadata.json`.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often the uploads root is walked for `delete_unaccessed_after`.
+const COLLECT_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Downloads not yet written to the metadata store.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PendingAccess {
+    pub count: u64,
+    pub last_unix: u64,
+}
+
+/// Download counts and last-download times for `track_access`. Downloads
+/// are gathered in memory and written in one batch every `FLUSH_INTERVAL`,
+/// so serving a file doesn't rewrite the metadata index each time; a crash
+/// loses at most that much.
+pub struct AccessTracker {
+    /// `delete_unaccessed_after`, when set and valid.
+    idle_limit: Option<Duration>,
+    pending: Mutex<HashMap<PathBuf, PendingAccess>>,
+}
+
+impl AccessTracker {
+    /// `None` unless `track_access` is set.
+    pub fn from_config(config: &config::Folio) -> Option<Self> {
+        if !config.track_access {
+            return None;
+        }
+        let idle_limit = (!config.delete_unaccessed_after.is_empty())
+            .then(|| parse_duration(&config.delete_unaccessed_after))
+            .and_then(|limit| {
+                limit
+                    .inspect_err(|e| {
+                        log::warn!(
+                            "invalid delete_unaccessed_after '{}': {}, idle files are kept",
+                            config.delete_unaccessed_after,
+                            e
+                        )
+                    })
+                    .ok()
+            });
+        Some(Self {
+            idle_limit,
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Count a download of `relative`.
+    pub fn record(&self, relative: &Path) {
+        let mut pending = self.pending.lock().unwrap();
+        let access = pending.entry(relative.to_path_buf()).or_default();
+        access.count += 1;
+        access.last_unix = now_unix_secs();
+    }
+
+    /// Downloads of `relative` since the last flush.
+    pub fn pending(&self, relative: &Path) -> Option<PendingAccess> {
+        self.pending.lock().unwrap().get(relative).copied()
+    }
+
+    /// Add the pending downloads to the metadata store. Returns how many
+    /// files were updated; on failure the downloads are kept for next time.
+    pub async fn flush(&self, metadata_store: &MetadataStore) -> Result<usize, String> {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        if pending.is_empty() {
+            return Ok(0);
+        }
+        let updates: Vec<_> = pending.iter().map(|(p, a)| (p.clone(), *a)).collect();
+        let result = metadata_store
+            .update_many(updates, |m, access| {
+                m.access_count += access.count;
+                m.last_access_unix = Some(access.last_unix);
+            })
+            .await;
+        if let Err(e) = result {
+            let mut current = self.pending.lock().unwrap();
+            for (path, access) in pending {
+                let entry = current.entry(path).or_default();
+                entry.count += access.count;
+                entry.last_unix = entry.last_unix.max(access.last_unix);
+            }
+            return Err(e);
+        }
+        Ok(pending.len())
+    }
+
+    /// Flush every `FLUSH_INTERVAL` and, with `delete_unaccessed_after`,
+    /// expire idle files every `COLLECT_INTERVAL`.
+    pub fn spawn_flusher(
+        self: Arc<Self>,
+        config: web::Data<config::Folio>,
+        metadata_store: Arc<MetadataStore>,
+        expiry_store: Arc<ExpiryStore>,
+    ) {
+        actix_web::rt::spawn(async move {
+            let mut ticks = actix_web::rt::time::interval(FLUSH_INTERVAL);
+            let mut last_collect = Instant::now();
+            loop {
+                ticks.tick().await;
+                if let Err(e) = self.flush(&metadata_store).await {
+                    log::error!("failed to record file accesses: {}", e);
+                    continue;
+                }
+                let Some(idle_limit) = self.idle_limit else {
+                    continue;
+                };
+                if last_collect.elapsed() < COLLECT_INTERVAL {
+                    continue;
+                }
+                last_collect = Instant::now();
+                match collect_idle(&config, &metadata_store, &expiry_store, idle_limit).await {
+                    Ok(0) => {}
+                    Ok(n) => log::info!("expiring {} files not downloaded in {:?}", n, idle_limit),
+                    Err(e) => log::error!("failed to collect idle files: {}", e),
+                }
+            }
+        });
+    }
+}
+
+/// Count a successful `GET` of `path` when `track_access` is on. `HEAD`,
+/// `304 Not Modified` and failed requests are not downloads.
+pub fn record_download(req: &HttpRequest, path: &SafePath, response: &HttpResponse) {
+    let Some(tracker) = req.app_data::<web::Data<Arc<AccessTracker>>>() else {
+        return;
+    };
+    if req.method() == Method::GET
+        && matches!(
+            response.status(),
+            StatusCode::OK | StatusCode::PARTIAL_CONTENT
+        )
+    {
+        tracker.record(path.as_path());
+    }
+}
+
+/// Schedule every file whose last download, or modification when it was
+/// never downloaded, is older than `idle_limit` to expire right away; the
+/// expiry sweeper then deletes it like any other expired file.
+pub async fn collect_idle(
+    config: &config::Folio,
+    metadata_store: &MetadataStore,
+    expiry_store: &ExpiryStore,
+    idle_limit: Duration,
+) -> Result<usize, String> {
+    let last_access: HashMap<PathBuf, u64> = metadata_store
+        .entries()
+        .await?
+        .into_iter()
+        .filter_map(|m| Some((PathBuf::from(m.path), m.last_access_unix?)))
+        .collect();
+    let root = config.resolve_base(&config.uploads_path);
+    let cutoff = now_unix_secs().saturating_sub(idle_limit.as_secs());
+    let idle = web::block(move || {
+        let mut idle = Vec::new();
+        find_idle(&root, &root, &last_access, cutoff, &mut idle).map(|()| idle)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("walk uploads root: {}", e))?;
+
+    for path in &idle {
+        expiry_store.schedule(path, Duration::ZERO).await?;
+    }
+    Ok(idle.len())
+}
+
+/// Regular files below `dir` last used before `cutoff`. Hidden entries,
+/// such as one-time files being served, and meta files are skipped.
+fn find_idle(
+    root: &Path,
+    dir: &Path,
+    last_access: &HashMap<PathBuf, u64>,
+    cutoff: u64,
+    idle: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+        if file_type.is_dir() {
+            find_idle(root, &path, last_access, cutoff, idle)?;
+            continue;
+        }
+        if !file_type.is_file() || meta_file::is_meta_file(&path) {
+            continue;
+        }
+        let Ok(relative) = path.strip_prefix(root) else {
+            continue;
+        };
+        let last_used = match last_access.get(relative) {
+            Some(&unix) => unix,
+            None => entry
+                .metadata()?
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        };
+        if last_used < cutoff {
+            idle.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn now_unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config(temp_dir: &tempfile::TempDir) -> config::Folio {
+        config::Folio {
+            uploads_path: temp_dir
+                .path()
+                .join("uploads")
+                .to_string_lossy()
+                .to_string(),
+            data_path: temp_dir.path().join("data").to_string_lossy().to_string(),
+            track_access: true,
+            delete_unaccessed_after: "30d".to_string(),
+            ..config::Folio::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn flush_adds_pending_downloads_to_metadata() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = test_config(&temp_dir);
+        let metadata_store = MetadataStore::new(&config);
+        let tracker = AccessTracker::from_config(&config).unwrap();
+
+        tracker.record(Path::new("a.txt"));
+        tracker.record(Path::new("a.txt"));
+        assert_eq!(tracker.pending(Path::new("a.txt")).unwrap().count, 2);
+        assert_eq!(tracker.flush(&metadata_store).await.unwrap(), 1);
+        assert!(tracker.pending(Path::new("a.txt")).is_none());
+
+        tracker.record(Path::new("a.txt"));
+        tracker.flush(&metadata_store).await.unwrap();
+        let metadata = metadata_store
+            .get(Path::new("a.txt"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(metadata.access_count, 3);
+        assert!(metadata.last_access_unix.is_some());
+    }
+
+    #[tokio::test]
+    async fn idle_files_are_scheduled_to_expire() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = test_config(&temp_dir);
+        let uploads = temp_dir.path().join("uploads");
+        std::fs::create_dir_all(uploads.join("docs")).unwrap();
+        for name in ["old.txt", "docs/used.txt", "new.txt"] {
+            std::fs::write(uploads.join(name), name).unwrap();
+        }
+        let month_ago = SystemTime::now() - Duration::from_secs(31 * 86400);
+        for name in ["old.txt", "docs/used.txt"] {
+            let file = std::fs::File::options()
+                .write(true)
+                .open(uploads.join(name))
+                .unwrap();
+            file.set_modified(month_ago).unwrap();
+        }
+        let metadata_store = MetadataStore::new(&config);
+        let expiry_store = ExpiryStore::new(&config);
+        metadata_store
+            .update(Path::new("docs/used.txt"), |m| {
+                m.last_access_unix = Some(now_unix_secs())
+            })
+            .await
+            .unwrap();
+
+        let idle_limit = Duration::from_secs(30 * 86400);
+        let n = collect_idle(&config, &metadata_store, &expiry_store, idle_limit)
+            .await
+            .unwrap();
+
+        assert_eq!(n, 1);
+        assert!(
+            expiry_store
+                .expire_at(&uploads.join("old.txt"))
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            expiry_store
+                .expire_at(&uploads.join("docs/used.txt"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,708 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::http::{StatusCode, header};
+use actix_web::{HttpRequest, HttpResponse, Responder, get, post, web};
+use globset::{Glob, GlobMatcher};
+use serde::{Deserialize, Serialize};
+
+use super::alias::AliasStore;
+use super::auth::{AccessAuth, AccessIdentity, VerifiedIdentity};
+use super::config;
+use super::error::FolioError;
+use super::expiry::ExpiryStore;
+use super::meta_file;
+use super::metadata::MetadataStore;
+use super::uploads::{UPLOAD_ID_LENGTH, UploadId, ensure_writable};
+
+/// Verify the caller's Access token and require an email listed in
+/// `admin_emails`. With no admins configured, admin routes are closed.
+pub async fn require_admin(
+    req: &HttpRequest,
+    access_auth: &web::Data<Arc<AccessAuth>>,
+    config: &config::Folio,
+) -> Result<AccessIdentity, FolioError> {
+    let identity = VerifiedIdentity::from_request(req, access_auth)
+        .await
+        .map_err(|err| FolioError::Unauthorized {
+            reason: err.message().to_string(),
+        })?
+        .0;
+
+    let email = identity.email.as_deref().unwrap_or("");
+    if email.is_empty() || !config.admin_emails.iter().any(|admin| admin == email) {
+        log::warn!("admin access denied: email '{}' path={}", email, req.path());
+        return Err(FolioError::Forbidden {
+            reason: "admin access required".to_string(),
+        });
+    }
+
+    Ok(identity)
+}
+
+#[derive(Deserialize)]
+pub struct BulkDeleteRequest {
+    /// Glob matched against paths relative to the uploads root.
+    glob: String,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkDeleteResponse {
+    pub dry_run: bool,
+    pub count: usize,
+    /// Matched files, relative to the uploads root.
+    pub deleted: Vec<String>,
+}
+
+/// Delete every file under `uploads_path` matching a glob, e.g.
+/// `{"glob": "tmp/**/*.log"}`. `dry_run` only lists what would go.
+/// Expirations, metadata and aliases of deleted files go with them.
+#[post("/admin/files/delete")]
+pub async fn bulk_delete(
+    req: HttpRequest,
+    config: web::Data<config::Folio>,
+    access_auth: web::Data<Arc<AccessAuth>>,
+    metadata_store: web::Data<Arc<MetadataStore>>,
+    expiry_store: web::Data<Arc<ExpiryStore>>,
+    alias_store: web::Data<Arc<AliasStore>>,
+    body: web::Json<BulkDeleteRequest>,
+) -> Result<impl Responder, FolioError> {
+    let admin = require_admin(&req, &access_auth, &config).await?;
+    ensure_writable(&config)?;
+    let matcher = Glob::new(&body.glob)
+        .map_err(|e| FolioError::BadRequest {
+            reason: format!("invalid glob '{}': {}", body.glob, e),
+        })?
+        .compile_matcher();
+
+    let root = config
+        .resolve_base(&config.uploads_path)
+        .canonicalize()
+        .map_err(|e| FolioError::store_error(e.to_string(), "resolve uploads root"))?;
+    let walk_root = root.clone();
+    let matches = web::block(move || {
+        let mut matches = Vec::new();
+        collect_matches(&walk_root, &walk_root, &matcher, &mut matches).map(|_| matches)
+    })
+    .await
+    .map_err(|e| FolioError::store_error(e.to_string(), "walk uploads"))?
+    .map_err(|e| FolioError::store_error(e.to_string(), "walk uploads"))?;
+
+    let mut deleted = Vec::with_capacity(matches.len());
+    for relative in matches {
+        let full_path = root.join(&relative);
+        // Re-check containment right before deleting; the tree may have
+        // changed (e.g. a directory swapped for a symlink) since the walk.
+        let Ok(canonical) = full_path.canonicalize() else {
+            continue;
+        };
+        if !canonical.starts_with(&root) {
+            log::warn!(
+                "bulk delete skipped out-of-root path: {}",
+                canonical.display()
+            );
+            continue;
+        }
+
+        let relative = relative.to_string_lossy().to_string();
+        if !body.dry_run {
+            if let Err(e) = tokio::fs::remove_file(&canonical).await {
+                log::error!("bulk delete failed for {}: {}", relative, e);
+                continue;
+            }
+            meta_file::remove(&canonical);
+            let relative_path = Path::new(&relative);
+            let scheduled_path = config.build_full_upload_path(relative_path);
+            if let Err(e) = expiry_store.cancel(&scheduled_path).await {
+                log::error!("failed to cancel expiration of {}: {}", relative, e);
+            }
+            if let Err(e) = metadata_store.remove(relative_path).await {
+                log::error!("failed to drop metadata for {}: {}", relative, e);
+            }
+            if let Err(e) = alias_store.remove_path(relative_path).await {
+                log::error!("failed to drop aliases of {}: {}", relative, e);
+            }
+            log::info!(
+                "bulk delete: {} removed by {}",
+                relative,
+                admin.email.as_deref().unwrap_or(&admin.sub)
+            );
+        }
+        deleted.push(relative);
+    }
+
+    Ok(HttpResponse::Ok().json(BulkDeleteResponse {
+        dry_run: body.dry_run,
+        count: deleted.len(),
+        deleted,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct CancelExpirationsRequest {
+    /// Matched against the start of paths relative to the uploads root,
+    /// e.g. `temp/`.
+    prefix: String,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CancelExpirationsResponse {
+    pub dry_run: bool,
+    pub count: usize,
+    /// Files that no longer expire, relative to the uploads root.
+    pub cancelled: Vec<String>,
+}
+
+/// Cancel the scheduled expiration of every upload under a path prefix, e.g.
+/// `{"prefix": "temp/"}`, keeping the files until they are deleted by hand.
+/// `dry_run` only lists them.
+#[post("/admin/expirations/cancel")]
+pub async fn cancel_expirations(
+    req: HttpRequest,
+    config: web::Data<config::Folio>,
+    access_auth: web::Data<Arc<AccessAuth>>,
+    expiry_store: web::Data<Arc<ExpiryStore>>,
+    body: web::Json<CancelExpirationsRequest>,
+) -> Result<impl Responder, FolioError> {
+    let admin = require_admin(&req, &access_auth, &config).await?;
+    if body.prefix.is_empty() {
+        return Err(FolioError::BadRequest {
+            reason: "prefix must not be empty".to_string(),
+        });
+    }
+
+    let matched = expiry_store
+        .cancel_prefix(&body.prefix, body.dry_run)
+        .await
+        .map_err(|e| FolioError::store_error(e, "cancel expirations"))?;
+    let root = config.build_full_upload_path(Path::new(""));
+    let mut cancelled = Vec::with_capacity(matched.len());
+    for full_path in matched {
+        if !body.dry_run {
+            meta_file::set_expire_at(&full_path, None);
+        }
+        let relative = full_path.strip_prefix(&root).unwrap_or(&full_path);
+        cancelled.push(relative.to_string_lossy().to_string());
+    }
+    if !body.dry_run {
+        log::info!(
+            "cancelled {} expirations under '{}' for {}",
+            cancelled.len(),
+            body.prefix,
+            admin.email.as_deref().unwrap_or(&admin.sub)
+        );
+    }
+
+    Ok(HttpResponse::Ok().json(CancelExpirationsResponse {
+        dry_run: body.dry_run,
+        count: cancelled.len(),
+        cancelled,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct SelfTestQuery {
+    /// `1`/`true` also schedules, looks up and cancels an expiration.
+    expiry: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SelfTestStep {
+    pub name: &'static str,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SelfTestStep {
+    fn new(name: &'static str, result: Result<(), String>) -> Self {
+        if let Err(e) = &result {
+            log::error!("self-test step '{}' failed: {}", name, e);
+        }
+        Self {
+            name,
+            ok: result.is_ok(),
+            error: result.err(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SelfTestReport {
+    pub passed: bool,
+    pub steps: Vec<SelfTestStep>,
+}
+
+/// Round-trip a tiny file through the uploads root to verify a deployment:
+/// write, read back, optionally expire, delete. Answers `503` when any step
+/// fails, so it can double as a post-deploy check.
+#[post("/admin/selftest")]
+pub async fn self_test(
+    req: HttpRequest,
+    config: web::Data<config::Folio>,
+    access_auth: web::Data<Arc<AccessAuth>>,
+    expiry_store: web::Data<Arc<ExpiryStore>>,
+    query: web::Query<SelfTestQuery>,
+) -> Result<impl Responder, FolioError> {
+    require_admin(&req, &access_auth, &config).await?;
+
+    let file_name = format!(
+        ".folio-selftest-{}",
+        UploadId::new(UPLOAD_ID_LENGTH).file_name(None)
+    );
+    let full_path = config.build_full_upload_path(Path::new(&file_name));
+    let content = format!("folio self-test {}", file_name);
+    let mut steps = Vec::new();
+
+    let written = tokio::fs::write(&full_path, &content)
+        .await
+        .map_err(|e| format!("{}: {}", full_path.display(), e));
+    let written_ok = written.is_ok();
+    steps.push(SelfTestStep::new("write", written));
+    if written_ok {
+        let read = match tokio::fs::read_to_string(&full_path).await {
+            Ok(read_back) if read_back == content => Ok(()),
+            Ok(_) => Err("content read back differs from what was written".to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        steps.push(SelfTestStep::new("read", read));
+
+        if matches!(query.expiry.as_deref(), Some("1" | "true")) {
+            let expiry = check_expiry(&expiry_store, &full_path).await;
+            steps.push(SelfTestStep::new("expiry", expiry));
+        }
+
+        let deleted = tokio::fs::remove_file(&full_path)
+            .await
+            .map_err(|e| e.to_string());
+        steps.push(SelfTestStep::new("delete", deleted));
+    }
+
+    let passed = steps.iter().all(|step| step.ok);
+    log::info!("self-test {}", if passed { "passed" } else { "failed" });
+    let status = if passed {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Ok(HttpResponse::build(status).json(SelfTestReport { passed, steps }))
+}
+
+/// Schedule an expiration, find it in the index and cancel it again.
+async fn check_expiry(expiry_store: &ExpiryStore, path: &Path) -> Result<(), String> {
+    let scheduled = expiry_store.schedule(path, Duration::from_secs(60)).await?;
+    let found = expiry_store.expire_at(path).await;
+    let cancelled = expiry_store.cancel(path).await;
+
+    match found? {
+        Some(expire_at) if expire_at == scheduled => cancelled,
+        other => Err(format!(
+            "scheduled expiry at {} but the index has {:?}",
+            scheduled, other
+        )),
+    }
+}
+
+#[derive(Serialize)]
+pub struct DebugConfigResponse<'a> {
+    /// The merged configuration, with paths resolved and secrets masked.
+    pub config: serde_json::Value,
+    /// Where each top-level key came from.
+    pub sources: &'a config::ConfigSources,
+}
+
+/// The effective configuration after defaults, `Folio.toml`, its profile
+/// section and `FOLIO_*` variables were merged, to see which of them set a
+/// value that is not taking effect.
+#[get("/debug/config")]
+pub async fn debug_config(
+    req: HttpRequest,
+    config: web::Data<config::Folio>,
+    access_auth: web::Data<Arc<AccessAuth>>,
+    sources: web::Data<Arc<config::ConfigSources>>,
+) -> Result<impl Responder, FolioError> {
+    require_admin(&req, &access_auth, &config).await?;
+    let effective = config.effective().map_err(|e| FolioError::Internal {
+        source: e.to_string(),
+        context: Some("serialize config".to_string()),
+    })?;
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(DebugConfigResponse {
+            config: effective,
+            sources: &sources,
+        }))
+}
+
+/// Regular files below `dir` whose path relative to `root` matches.
+/// Symlinks are never followed.
+fn collect_matches(
+    root: &Path,
+    dir: &Path,
+    matcher: &GlobMatcher,
+    matches: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+        if file_type.is_dir() {
+            collect_matches(root, &path, matcher, matches)?;
+        } else if file_type.is_file()
+            && !meta_file::is_meta_file(&path)
+            && let Ok(relative) = path.strip_prefix(root)
+            && matcher.is_match(relative)
+        {
+            matches.push(relative.to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{App, test};
+
+    use crate::test_utils::make_hs256_token;
+
+    fn token(email: &str) -> String {
+        make_hs256_token(
+            "test-secret",
+            "user-1",
+            Some(email),
+            &[],
+            "https://issuer.example.com",
+            "folio-app",
+            3600,
+        )
+    }
+
+    fn tree_config(temp_dir: &tempfile::TempDir) -> config::Folio {
+        config::Folio {
+            uploads_path: temp_dir
+                .path()
+                .join("uploads")
+                .to_string_lossy()
+                .to_string(),
+            data_path: temp_dir.path().join("data").to_string_lossy().to_string(),
+            admin_emails: vec!["admin@example.com".to_string()],
+            ..config::Folio::default()
+        }
+    }
+
+    async fn call(
+        temp_dir: &tempfile::TempDir,
+        email: &str,
+        body: serde_json::Value,
+    ) -> (StatusCode, serde_json::Value) {
+        let config = tree_config(temp_dir);
+        let metadata_store = Arc::new(MetadataStore::new(&config));
+        let expiry_store = Arc::new(ExpiryStore::new(&config));
+        let alias_store = Arc::new(AliasStore::new(&config));
+        let access_auth = Arc::new(AccessAuth::from_parts(
+            "https://issuer.example.com",
+            "folio-app",
+            Some("test-secret"),
+        ));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(metadata_store))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(alias_store))
+                .service(bulk_delete),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/admin/files/delete")
+            .insert_header(("Cf-Access-Jwt-Assertion", token(email)))
+            .set_json(body)
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        let status = response.status();
+        (status, test::read_body_json(response).await)
+    }
+
+    fn setup_tree() -> tempfile::TempDir {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let uploads = temp_dir.path().join("uploads");
+        std::fs::create_dir_all(uploads.join("tmp/a")).unwrap();
+        std::fs::write(uploads.join("tmp/a/x.log"), "x").unwrap();
+        std::fs::write(uploads.join("tmp/y.log"), "y").unwrap();
+        std::fs::write(uploads.join("tmp/keep.txt"), "k").unwrap();
+        std::fs::write(uploads.join("root.log"), "r").unwrap();
+        temp_dir
+    }
+
+    #[actix_web::test]
+    async fn dry_run_lists_without_deleting() {
+        let temp_dir = setup_tree();
+        let (status, body) = call(
+            &temp_dir,
+            "admin@example.com",
+            serde_json::json!({ "glob": "tmp/**/*.log", "dry_run": true }),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["count"], 2);
+        assert!(temp_dir.path().join("uploads/tmp/y.log").exists());
+    }
+
+    #[actix_web::test]
+    async fn deletes_matches_only() {
+        let temp_dir = setup_tree();
+        let (status, body) = call(
+            &temp_dir,
+            "admin@example.com",
+            serde_json::json!({ "glob": "tmp/**/*.log" }),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["count"], 2);
+        let uploads = temp_dir.path().join("uploads");
+        assert!(!uploads.join("tmp/a/x.log").exists());
+        assert!(!uploads.join("tmp/y.log").exists());
+        assert!(uploads.join("tmp/keep.txt").exists());
+        assert!(uploads.join("root.log").exists());
+    }
+
+    #[actix_web::test]
+    async fn deleting_drops_expirations_and_aliases() {
+        let temp_dir = setup_tree();
+        let config = tree_config(&temp_dir);
+        let expiry_store = ExpiryStore::new(&config);
+        let alias_store = AliasStore::new(&config);
+        let full_path = |relative: &str| config.build_full_upload_path(Path::new(relative));
+        for relative in ["tmp/y.log", "root.log"] {
+            expiry_store
+                .schedule(&full_path(relative), Duration::from_secs(3600))
+                .await
+                .unwrap();
+        }
+        alias_store
+            .create("latest-log", Path::new("tmp/y.log"))
+            .await
+            .unwrap();
+
+        let (status, _) = call(
+            &temp_dir,
+            "admin@example.com",
+            serde_json::json!({ "glob": "tmp/**/*.log" }),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        let y_log = full_path("tmp/y.log");
+        assert!(expiry_store.expire_at(&y_log).await.unwrap().is_none());
+        let root_log = full_path("root.log");
+        assert!(expiry_store.expire_at(&root_log).await.unwrap().is_some());
+        assert!(alias_store.resolve("latest-log").await.unwrap().is_none());
+    }
+
+    #[actix_web::test]
+    async fn cancels_expirations_under_a_prefix() {
+        let temp_dir = setup_tree();
+        let config = config::Folio {
+            uploads_path: temp_dir
+                .path()
+                .join("uploads")
+                .to_string_lossy()
+                .to_string(),
+            data_path: temp_dir.path().join("data").to_string_lossy().to_string(),
+            admin_emails: vec!["admin@example.com".to_string()],
+            ..config::Folio::default()
+        };
+        let expiry_store = Arc::new(ExpiryStore::new(&config));
+        let full_path = |relative: &str| config.build_full_upload_path(Path::new(relative));
+        for relative in ["tmp/a/x.log", "tmp/y.log", "root.log"] {
+            expiry_store
+                .schedule(&full_path(relative), Duration::from_secs(3600))
+                .await
+                .unwrap();
+        }
+        let (x_log, root_log) = (full_path("tmp/a/x.log"), full_path("root.log"));
+        let access_auth = Arc::new(AccessAuth::from_parts(
+            "https://issuer.example.com",
+            "folio-app",
+            Some("test-secret"),
+        ));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(expiry_store.clone()))
+                .service(cancel_expirations),
+        )
+        .await;
+        let cancel = |body: serde_json::Value| {
+            test::TestRequest::post()
+                .uri("/admin/expirations/cancel")
+                .insert_header(("Cf-Access-Jwt-Assertion", token("admin@example.com")))
+                .set_json(body)
+                .to_request()
+        };
+
+        let body: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            cancel(serde_json::json!({ "prefix": "tmp/", "dry_run": true })),
+        )
+        .await;
+        assert_eq!(body["count"], 2);
+        assert!(expiry_store.expire_at(&x_log).await.unwrap().is_some());
+
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, cancel(serde_json::json!({ "prefix": "tmp/" })))
+                .await;
+        let mut cancelled: Vec<&str> = body["cancelled"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|path| path.as_str().unwrap())
+            .collect();
+        cancelled.sort();
+        assert_eq!(cancelled, ["tmp/a/x.log", "tmp/y.log"]);
+        assert!(expiry_store.expire_at(&x_log).await.unwrap().is_none());
+        assert!(expiry_store.expire_at(&root_log).await.unwrap().is_some());
+
+        let res = test::call_service(&app, cancel(serde_json::json!({ "prefix": "" }))).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    async fn call_self_test(
+        uploads_path: &Path,
+        data_path: &Path,
+    ) -> (StatusCode, serde_json::Value) {
+        let config = config::Folio {
+            uploads_path: uploads_path.to_string_lossy().to_string(),
+            data_path: data_path.to_string_lossy().to_string(),
+            admin_emails: vec!["admin@example.com".to_string()],
+            ..config::Folio::default()
+        };
+        let expiry_store = Arc::new(ExpiryStore::new(&config));
+        let access_auth = Arc::new(AccessAuth::from_parts(
+            "https://issuer.example.com",
+            "folio-app",
+            Some("test-secret"),
+        ));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(expiry_store))
+                .service(self_test),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/admin/selftest?expiry=1")
+            .insert_header(("Cf-Access-Jwt-Assertion", token("admin@example.com")))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        let status = response.status();
+        (status, test::read_body_json(response).await)
+    }
+
+    #[actix_web::test]
+    async fn self_test_round_trips_a_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (status, body) = call_self_test(temp_dir.path(), temp_dir.path()).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["passed"], true);
+        let names: Vec<&str> = body["steps"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|step| step["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["write", "read", "expiry", "delete"]);
+        let leftovers = std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter(|e| {
+                e.file_name()
+                    .to_string_lossy()
+                    .starts_with(".folio-selftest-")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[actix_web::test]
+    async fn self_test_reports_unwritable_storage() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let missing = temp_dir.path().join("missing");
+        let (status, body) = call_self_test(&missing, temp_dir.path()).await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["passed"], false);
+        assert_eq!(body["steps"][0]["name"], "write");
+        assert_eq!(body["steps"][0]["ok"], false);
+        assert!(body["steps"][0]["error"].is_string());
+    }
+
+    #[actix_web::test]
+    async fn rejects_non_admins() {
+        let temp_dir = setup_tree();
+        let (status, _) = call(
+            &temp_dir,
+            "someone@example.com",
+            serde_json::json!({ "glob": "**" }),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(temp_dir.path().join("uploads/root.log").exists());
+    }
+
+    #[actix_web::test]
+    async fn debug_config_is_redacted_and_admin_only() {
+        let config = config::Folio {
+            tls_key_path: "/etc/folio/key.pem".to_string(),
+            admin_emails: vec!["admin@example.com".to_string()],
+            ..config::Folio::default()
+        };
+        let mut sources = config::ConfigSources::default();
+        sources.0.insert("port".into(), "env FOLIO_PORT".into());
+        let access_auth = Arc::new(AccessAuth::from_parts(
+            "https://issuer.example.com",
+            "folio-app",
+            Some("test-secret"),
+        ));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(sources)))
+                .service(debug_config),
+        )
+        .await;
+        let request = |email: &str| {
+            test::TestRequest::get()
+                .uri("/debug/config")
+                .insert_header(("Cf-Access-Jwt-Assertion", token(email)))
+                .to_request()
+        };
+
+        let response = test::call_service(&app, request("someone@example.com")).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = test::call_service(&app, request("admin@example.com")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["config"]["port"], 8000);
+        assert_eq!(body["config"]["tls_key_path"], "<redacted>");
+        assert_eq!(body["sources"]["port"], "env FOLIO_PORT");
+    }
+}
@@ -0,0 +1,316 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use actix_web::{HttpRequest, HttpResponse, Responder, get, post, web};
+use serde::{Deserialize, Serialize};
+
+use super::config;
+use super::error::FolioError;
+use super::files::symlink_violation;
+use super::meta_file;
+use super::path::SafePath;
+use super::public_url::public_url;
+use super::store::JsonFileStore;
+
+/// Longest alias accepted by `POST /uploads/<id>/alias`.
+pub const MAX_ALIAS_LENGTH: usize = 64;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AliasEntry {
+    pub alias: String,
+    /// Target file, relative to the uploads root.
+    pub path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct AliasIndex {
+    entries: Vec<AliasEntry>,
+}
+
+/// Human-friendly names for uploaded files, resolved by `GET /a/<alias>`.
+pub struct AliasStore {
+    store: JsonFileStore<AliasIndex>,
+}
+
+impl AliasStore {
+    pub fn new(config: &config::Folio) -> Self {
+        let index_path = config.build_full_data_path(&PathBuf::from("aliases.json"));
+        Self {
+            store: JsonFileStore::new(index_path),
+        }
+    }
+
+    /// Point `alias` at `relative_path`. Returns `false`, changing nothing,
+    /// when the alias already names another file.
+    pub async fn create(&self, alias: &str, relative_path: &Path) -> Result<bool, String> {
+        let _guard = self.store.lock().await?;
+        let mut index = self.store.load().await?;
+        let normalized = relative_path.to_string_lossy().to_string();
+
+        if let Some(existing) = index.entries.iter().find(|e| e.alias == alias) {
+            return Ok(existing.path == normalized);
+        }
+        index.entries.push(AliasEntry {
+            alias: alias.to_string(),
+            path: normalized,
+        });
+
+        self.store.save(&index).await?;
+        Ok(true)
+    }
+
+    pub async fn resolve(&self, alias: &str) -> Result<Option<String>, String> {
+        let _guard = self.store.lock().await?;
+        let index = self.store.load().await?;
+
+        Ok(index
+            .entries
+            .into_iter()
+            .find(|e| e.alias == alias)
+            .map(|e| e.path))
+    }
+
+    /// Point every alias of `from` at `to`.
+    pub async fn retarget(&self, from: &Path, to: &Path) -> Result<(), String> {
+        let _guard = self.store.lock().await?;
+        let mut index = self.store.load().await?;
+        let from = from.to_string_lossy().to_string();
+        let to = to.to_string_lossy().to_string();
+
+        let mut changed = false;
+        for entry in index.entries.iter_mut().filter(|e| e.path == from) {
+            entry.path = to.clone();
+            changed = true;
+        }
+        if !changed {
+            return Ok(());
+        }
+
+        self.store.save(&index).await
+    }
+
+    /// Drop every alias of a file, or of any file below a directory.
+    pub async fn remove_path(&self, relative_path: &Path) -> Result<(), String> {
+        let _guard = self.store.lock().await?;
+        let mut index = self.store.load().await?;
+        let normalized = relative_path.to_string_lossy().to_string();
+        let prefix = format!("{}/", normalized);
+
+        let before = index.entries.len();
+        index
+            .entries
+            .retain(|e| e.path != normalized && !e.path.starts_with(&prefix));
+        if index.entries.len() == before {
+            return Ok(());
+        }
+
+        self.store.save(&index).await
+    }
+}
+
+/// Aliases are 1–64 ASCII letters, digits, `-` or `_`, so they are safe in
+/// URLs without escaping and can't be mistaken for paths.
+pub fn validate_alias(alias: &str) -> Result<(), FolioError> {
+    let valid = !alias.is_empty()
+        && alias.len() <= MAX_ALIAS_LENGTH
+        && alias
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(FolioError::BadRequest {
+            reason: format!(
+                "invalid alias '{}': use 1-{} letters, digits, '-' or '_'",
+                alias, MAX_ALIAS_LENGTH
+            ),
+        });
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct AliasRequest {
+    alias: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AliasResponse {
+    pub message: String,
+    pub alias: String,
+    pub url: String,
+    /// The aliased file, relative to the uploads root.
+    pub path: String,
+}
+
+/// Give an uploaded file a memorable alias.
+///
+/// `<id>` is the file's path as it appears in its `/files/` URL (e.g.
+/// `a1B2c3D4.png`); a bare id without extension is matched against files
+/// in the uploads root.
+#[post("/uploads/{id:.*}/alias")]
+pub async fn create_alias(
+    req: HttpRequest,
+    config: web::Data<config::Folio>,
+    alias_store: web::Data<Arc<AliasStore>>,
+    id: web::Path<String>,
+    body: web::Json<AliasRequest>,
+) -> Result<impl Responder, FolioError> {
+    validate_alias(&body.alias)?;
+    let path = find_upload(&config, &id)?;
+
+    let created = alias_store
+        .create(&body.alias, path.as_path())
+        .await
+        .map_err(|e| FolioError::store_error(e, "save alias"))?;
+    if !created {
+        return Err(FolioError::AliasTaken {
+            alias: body.alias.clone(),
+        });
+    }
+    log::info!("alias '{}' now points to {}", body.alias, path);
+
+    let url = public_url(&req, &config, &format!("/a/{}", body.alias));
+    Ok(HttpResponse::Created()
+        .append_header(("Location", url.clone()))
+        .json(AliasResponse {
+            message: "alias created successfully".to_string(),
+            alias: body.alias.clone(),
+            url,
+            path: path.to_string(),
+        }))
+}
+
+/// Redirect to the file behind an alias. Aliases whose file is gone are
+/// dropped on the way.
+#[get("/a/{alias}")]
+pub async fn resolve_alias(
+    config: web::Data<config::Folio>,
+    alias_store: web::Data<Arc<AliasStore>>,
+    alias: web::Path<String>,
+) -> Result<impl Responder, FolioError> {
+    let not_found = || FolioError::NotFound {
+        path: format!("a/{}", alias),
+    };
+    let Some(path) = alias_store
+        .resolve(&alias)
+        .await
+        .map_err(|e| FolioError::store_error(e, "resolve alias"))?
+    else {
+        return Err(not_found());
+    };
+
+    if !config.build_full_upload_path(Path::new(&path)).is_file() {
+        if let Err(e) = alias_store.remove_path(Path::new(&path)).await {
+            log::error!("failed to drop stale alias '{}': {}", alias, e);
+        }
+        return Err(not_found());
+    }
+
+    Ok(HttpResponse::Found()
+        .append_header(("Location", format!("/files/{}", path)))
+        .finish())
+}
+
+/// Resolve the `<id>` of `/uploads/<id>/...` routes to a stored file.
+/// Files reached through a symbolic link that `follow_symlinks` does not
+/// allow are not found, as on `/files`.
+pub(crate) fn find_upload(config: &config::Folio, id: &str) -> Result<SafePath, FolioError> {
+    let path = find_in(&config.build_full_upload_path(Path::new("")), id)?;
+    if let Some(reason) = symlink_violation(config, path.as_path()) {
+        log::warn!("refusing upload {}: {}", id, reason);
+        return Err(FolioError::NotFound {
+            path: id.to_string(),
+        });
+    }
+    Ok(path)
+}
+
+/// Resolve `id` below `root`: a path relative to it, or the stem of a file
+/// directly in it.
+pub(crate) fn find_in(root: &Path, id: &str) -> Result<SafePath, FolioError> {
+    let path = SafePath::from_user_input(Path::new(id))?;
+    let not_found = || FolioError::NotFound {
+        path: id.to_string(),
+    };
+    if meta_file::is_meta_file(path.as_path()) {
+        return Err(not_found());
+    }
+    if root.join(path.as_path()).is_file() {
+        return Ok(path);
+    }
+
+    if path.as_path().components().count() != 1 || path.as_path().extension().is_some() {
+        return Err(not_found());
+    }
+    let entries = std::fs::read_dir(root).map_err(|_| not_found())?;
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+        .map(|entry| PathBuf::from(entry.file_name()))
+        .find(|name| name.file_stem().is_some_and(|stem| stem == id))
+        .ok_or_else(not_found)
+        .and_then(|name| SafePath::from_user_input(&name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{StatusCode, header};
+    use actix_web::{App, test as awtest};
+
+    #[test]
+    fn alias_charset() {
+        assert!(validate_alias("my-report_2024").is_ok());
+        assert!(validate_alias("").is_err());
+        assert!(validate_alias("a/b").is_err());
+        assert!(validate_alias("é").is_err());
+        assert!(validate_alias(&"a".repeat(MAX_ALIAS_LENGTH + 1)).is_err());
+    }
+
+    #[actix_web::test]
+    async fn creates_and_resolves_aliases() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("a1B2c3D4.pdf"), "report").unwrap();
+        std::fs::write(temp_dir.path().join("e5F6g7H8.txt"), "other").unwrap();
+        let config = config::Folio {
+            uploads_path: temp_dir.path().to_string_lossy().to_string(),
+            data_path: temp_dir.path().to_string_lossy().to_string(),
+            ..config::Folio::default()
+        };
+        let alias_store = Arc::new(AliasStore::new(&config));
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(alias_store))
+                .service(create_alias)
+                .service(resolve_alias),
+        )
+        .await;
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads/a1B2c3D4/alias")
+            .set_json(serde_json::json!({ "alias": "my-report" }))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads/e5F6g7H8.txt/alias")
+            .set_json(serde_json::json!({ "alias": "my-report" }))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let req = awtest::TestRequest::get().uri("/a/my-report").to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            response.headers().get(header::LOCATION).unwrap(),
+            "/files/a1B2c3D4.pdf"
+        );
+
+        std::fs::remove_file(temp_dir.path().join("a1B2c3D4.pdf")).unwrap();
+        let req = awtest::TestRequest::get().uri("/a/my-report").to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
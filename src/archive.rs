@@ -0,0 +1,203 @@
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use actix_web::{HttpResponse, web};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use super::config;
+use super::error::FolioError;
+use super::expiry::ExpiryStore;
+use super::manifest::public_files_below;
+use super::metadata::MetadataStore;
+use super::path::SafePath;
+use super::private_index::PrivateIndexStore;
+
+/// Bytes of compressed output gathered before a chunk is sent to the client.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Format of `GET /files/<dir>?archive=`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum ArchiveFormat {
+    #[serde(rename = "tar.gz")]
+    TarGz,
+}
+
+impl ArchiveFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            Self::TarGz => "tar.gz",
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::TarGz => "application/gzip",
+        }
+    }
+}
+
+/// Hands the archive to the response stream in `CHUNK_SIZE` pieces. Fails
+/// with `BrokenPipe` once the client has gone away, which stops the build.
+struct ChannelWriter {
+    tx: mpsc::Sender<std::io::Result<web::Bytes>>,
+    buf: Vec<u8>,
+}
+
+impl ChannelWriter {
+    fn send(&mut self) -> std::io::Result<()> {
+        let chunk = web::Bytes::from(std::mem::take(&mut self.buf));
+        self.tx
+            .blocking_send(Ok(chunk))
+            .map_err(|_| std::io::Error::new(ErrorKind::BrokenPipe, "client went away"))
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CHUNK_SIZE {
+            self.send()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        self.send()
+    }
+}
+
+/// Write `(full path, name in archive)` pairs as a gzip-compressed tar.
+/// Modes and mtimes are kept; files removed since they were listed (e.g.
+/// by the expiry sweeper) are skipped.
+fn write_tar_gz(entries: Vec<(PathBuf, PathBuf)>, out: impl Write) -> std::io::Result<()> {
+    let mut tar = tar::Builder::new(GzEncoder::new(out, Compression::default()));
+    for (full, name) in entries {
+        match tar.append_path_with_name(&full, &name) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                log::debug!("{} vanished while archiving, skipped", full.display());
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    tar.into_inner()?.finish()?.flush()
+}
+
+/// Stream an archive of every file below a directory that its public
+/// listing would show. Entries are named `<dir>/<path below it>`.
+///
+/// The archive is built on a blocking thread as the client reads it, so it
+/// is never held in memory as a whole.
+pub async fn serve_archive(
+    config: &config::Folio,
+    private_index: &PrivateIndexStore,
+    metadata_store: &MetadataStore,
+    expiry_store: &ExpiryStore,
+    path: &SafePath,
+    format: ArchiveFormat,
+) -> Result<HttpResponse, FolioError> {
+    let files = public_files_below(
+        config,
+        private_index,
+        metadata_store,
+        expiry_store,
+        path.as_path(),
+    )
+    .await?;
+    let top = path
+        .as_path()
+        .file_name()
+        .map_or_else(|| "files".to_string(), |n| n.to_string_lossy().to_string());
+    let base = path.as_path().to_path_buf();
+    let entries: Vec<(PathBuf, PathBuf)> = files
+        .into_iter()
+        .map(|relative| {
+            let full = config.build_full_upload_path(&relative);
+            let below = relative.strip_prefix(&base).unwrap_or(&relative);
+            (full, Path::new(&top).join(below))
+        })
+        .collect();
+
+    let (tx, rx) = mpsc::channel(4);
+    let error_tx = tx.clone();
+    let archive_path = path.to_string();
+    tokio::task::spawn_blocking(move || {
+        let out = ChannelWriter {
+            tx,
+            buf: Vec::with_capacity(CHUNK_SIZE),
+        };
+        let result = match format {
+            ArchiveFormat::TarGz => write_tar_gz(entries, out),
+        };
+        match result {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => {
+                log::debug!("archive of {} abandoned by client", archive_path);
+            }
+            Err(e) => {
+                log::error!("failed to archive {}: {}", archive_path, e);
+                let _ = error_tx.blocking_send(Err(e));
+            }
+        }
+    });
+    let body = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}.{}\"", top, format.extension()),
+        ))
+        .streaming(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn tar_gz_keeps_names_and_modes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let script = temp_dir.path().join("run.sh");
+        std::fs::write(&script, "#!/bin/sh\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let mut out = Vec::new();
+        write_tar_gz(
+            vec![
+                (script, PathBuf::from("docs/bin/run.sh")),
+                (
+                    temp_dir.path().join("gone.txt"),
+                    PathBuf::from("docs/gone.txt"),
+                ),
+            ],
+            &mut out,
+        )
+        .unwrap();
+
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(&out[..]));
+        let mut entries = archive.entries().unwrap();
+        let mut entry = entries.next().unwrap().unwrap();
+        assert_eq!(entry.path().unwrap(), Path::new("docs/bin/run.sh"));
+        #[cfg(unix)]
+        assert_eq!(entry.header().mode().unwrap() & 0o777, 0o755);
+        let mut content = String::new();
+        entry.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "#!/bin/sh\n");
+        drop(entry);
+        assert!(entries.next().is_none());
+    }
+}
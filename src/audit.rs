@@ -0,0 +1,235 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{Method, header};
+use actix_web::middleware::Next;
+use actix_web::{HttpMessage, web};
+use serde::Serialize;
+
+use crate::auth::AccessIdentity;
+use crate::config;
+use crate::path::SafePath;
+use crate::public_url::{decode_path, location_path};
+
+/// One line of the audit trail.
+#[derive(Debug, Serialize)]
+pub struct AuditEntry {
+    pub timestamp_unix_ms: u128,
+    pub method: String,
+    pub path: String,
+    /// File affected, relative to the uploads root.
+    pub resolved_path: Option<String>,
+    pub status: u16,
+    pub client_ip: Option<String>,
+    pub request_bytes: Option<u64>,
+    /// Size of the file after the request, when it still exists.
+    pub file_bytes: Option<u64>,
+    pub identity: Option<String>,
+}
+
+#[derive(Debug)]
+enum AuditTarget {
+    Disabled,
+    Stdout,
+    File(PathBuf),
+}
+
+/// Append-only JSON lines log of mutating requests.
+///
+/// The file is reopened for every entry so external rotation (rename and
+/// let us create a fresh file) works without signalling the server.
+#[derive(Debug)]
+pub struct AuditLog {
+    target: AuditTarget,
+    lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(config: &config::Folio) -> Self {
+        let target = match config.audit_log.as_str() {
+            "" => AuditTarget::Disabled,
+            "-" => AuditTarget::Stdout,
+            path => AuditTarget::File(config.resolve_base(path)),
+        };
+        AuditLog {
+            target,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !matches!(self.target, AuditTarget::Disabled)
+    }
+
+    /// Write one entry and flush it to stable storage before returning.
+    pub fn record(&self, entry: &AuditEntry) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        match &self.target {
+            AuditTarget::Disabled => Ok(()),
+            AuditTarget::Stdout => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(&line)?;
+                stdout.flush()
+            }
+            AuditTarget::File(path) => {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                file.write_all(&line)?;
+                file.sync_data()
+            }
+        }
+    }
+}
+
+/// Record every mutating request once its response is known.
+///
+/// Wrapped around the whole app so new handlers are covered automatically.
+pub async fn audit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let audit_log = req
+        .app_data::<web::Data<Arc<AuditLog>>>()
+        .filter(|log| log.is_enabled())
+        .cloned();
+    let Some(audit_log) = audit_log.filter(|_| is_mutating(req.method())) else {
+        return next.call(req).await;
+    };
+
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+    let client_ip = req
+        .connection_info()
+        .realip_remote_addr()
+        .map(str::to_string);
+    let request_bytes = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok());
+
+    let res = next.call(req).await?;
+
+    let config = res
+        .request()
+        .app_data::<web::Data<config::Folio>>()
+        .cloned();
+    let resolved_path = resolved_path(&res);
+    let file_bytes = match (&config, &resolved_path) {
+        (Some(config), Some(p)) => std::fs::metadata(config.build_full_upload_path(Path::new(p)))
+            .ok()
+            .filter(|m| m.is_file())
+            .map(|m| m.len()),
+        _ => None,
+    };
+    let identity = res
+        .request()
+        .extensions()
+        .get::<AccessIdentity>()
+        .map(|id| id.email.clone().unwrap_or_else(|| id.sub.clone()));
+
+    let entry = AuditEntry {
+        timestamp_unix_ms: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0),
+        method,
+        path,
+        resolved_path,
+        status: res.status().as_u16(),
+        client_ip,
+        request_bytes,
+        file_bytes,
+        identity,
+    };
+    let result = web::block(move || audit_log.record(&entry)).await;
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => log::error!("failed to write audit log entry: {}", e),
+        Err(e) => log::error!("failed to write audit log entry: {}", e),
+    }
+
+    Ok(res)
+}
+
+/// File a request affected, relative to the uploads root.
+pub(crate) fn resolved_path<B>(res: &ServiceResponse<B>) -> Option<String> {
+    // Route params are only known once the request has been routed.
+    let path_param = res.request().match_info().get("path").map(str::to_string);
+    // Uploads and renamed creates report where the file really landed.
+    res.headers()
+        .get(header::LOCATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| location_path(v).strip_prefix("/files/"))
+        .map(decode_path)
+        .or(path_param)
+        .and_then(|p| SafePath::from_user_input(Path::new(&p)).ok())
+        .map(|p| p.as_path().to_string_lossy().to_string())
+}
+
+fn is_mutating(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::middleware::from_fn;
+    use actix_web::{App, HttpResponse, test as awtest};
+
+    #[actix_web::test]
+    async fn records_mutating_requests_only() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("a.txt"), "hello").unwrap();
+        let log_path = temp_dir.path().join("audit.log");
+        let config = config::Folio {
+            uploads_path: temp_dir.path().to_string_lossy().to_string(),
+            audit_log: log_path.to_string_lossy().to_string(),
+            ..config::Folio::default()
+        };
+        let audit_log = Arc::new(AuditLog::new(&config));
+
+        let app = awtest::init_service(
+            App::new()
+                .wrap(from_fn(audit))
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(audit_log))
+                .route(
+                    "/files/{path:.*}",
+                    web::route().to(|| async { HttpResponse::Ok().finish() }),
+                ),
+        )
+        .await;
+
+        let req = awtest::TestRequest::get().uri("/files/a.txt").to_request();
+        awtest::call_service(&app, req).await;
+        let req = awtest::TestRequest::put()
+            .uri("/files/a.txt")
+            .peer_addr("10.0.0.7:5000".parse().unwrap())
+            .set_payload("hello")
+            .to_request();
+        awtest::call_service(&app, req).await;
+
+        let contents = std::fs::read_to_string(&log_path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let entry: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(entry["method"], "PUT");
+        assert_eq!(entry["resolved_path"], "a.txt");
+        assert_eq!(entry["status"], 200);
+        assert_eq!(entry["client_ip"], "10.0.0.7");
+        assert_eq!(entry["request_bytes"], 5);
+        assert_eq!(entry["file_bytes"], 5);
+        assert!(entry["identity"].is_null());
+    }
+}
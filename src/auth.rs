@@ -1,7 +1,7 @@
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use actix_web::{HttpRequest, http::StatusCode, web};
+use actix_web::{HttpMessage, HttpRequest, http::StatusCode, web};
 use jsonwebtoken::{
     Algorithm, DecodingKey, Validation, decode, decode_header, errors::ErrorKind as JwtErrorKind,
 };
@@ -121,6 +121,12 @@ impl AccessAuth {
             client: Client::new(),
         }
     }
+
+    /// Whether an audience is set, i.e. Access tokens can be verified at all.
+    pub fn is_configured(&self) -> bool {
+        !self.audience.is_empty()
+    }
+
     pub async fn get_jwks(&self, url: &str) -> Result<JwkSet, String> {
         // Check if cache is valid first
         let needs_fetch = {
@@ -260,7 +266,11 @@ impl VerifiedIdentity {
         })?;
 
         match auth.verify_and_authorize(token).await {
-            Ok(identity) => Ok(VerifiedIdentity(identity)),
+            Ok(identity) => {
+                // Expose the caller to middleware (e.g. the audit log).
+                request.extensions_mut().insert(identity.clone());
+                Ok(VerifiedIdentity(identity))
+            }
             Err(err) => {
                 log::warn!(
                     "private auth deny: code={} status={} path={} method={}",
@@ -477,7 +487,7 @@ mod tests {
             Some("allowed@example.com"),
             &["team-a"],
             "https://issuer.example.com",
-            &["folio-app"],
+            ["folio-app"],
             3600,
         );
 
@@ -0,0 +1,139 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{Method, header};
+use actix_web::middleware::Next;
+use actix_web::{HttpResponse, web};
+
+use crate::config;
+use crate::public_url::request_origin;
+
+/// Paths probed by orchestrators and scrapers by whatever name they know.
+const EXEMPT_PATHS: &[&str] = &["/health", "/ready", "/metrics"];
+
+/// Redirect requests arriving under another host name to `canonical_host`,
+/// keeping path and query, so every generated file URL uses one origin.
+pub async fn redirect_to_canonical_host(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let location = req
+        .app_data::<web::Data<config::Folio>>()
+        .and_then(|config| canonical_location(&req, config));
+
+    match location {
+        Some(location) => {
+            // 308 keeps the method and body of writes; 301 is the classic
+            // permanent redirect for reads.
+            let mut response = if matches!(*req.method(), Method::GET | Method::HEAD) {
+                HttpResponse::MovedPermanently()
+            } else {
+                HttpResponse::PermanentRedirect()
+            };
+            let response = response
+                .insert_header((header::LOCATION, location))
+                .finish();
+            Ok(req.into_response(response).map_into_right_body())
+        }
+        None => next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body),
+    }
+}
+
+/// Forwarded scheme and host are only believed from `trusted_proxies`, so
+/// anyone else can't pick the redirect's scheme or dodge it with a spoofed
+/// `X-Forwarded-Host`.
+fn canonical_location(req: &ServiceRequest, config: &config::Folio) -> Option<String> {
+    let canonical_host = &config.canonical_host;
+    if canonical_host.is_empty() || EXEMPT_PATHS.contains(&req.path()) {
+        return None;
+    }
+
+    let (scheme, host) = request_origin(req.request(), config);
+    if host.eq_ignore_ascii_case(canonical_host) {
+        return None;
+    }
+
+    let path_and_query = req.uri().path_and_query().map_or("/", |pq| pq.as_str());
+    Some(format!("{}://{}{}", scheme, canonical_host, path_and_query))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::{App, test};
+
+    async fn call(method: Method, uri: &str, host: &str) -> ServiceResponse<impl MessageBody> {
+        call_via(
+            test::TestRequest::default()
+                .method(method)
+                .uri(uri)
+                .insert_header((header::HOST, host)),
+        )
+        .await
+    }
+
+    async fn call_via(req: test::TestRequest) -> ServiceResponse<impl MessageBody> {
+        let config = config::Folio {
+            canonical_host: "folio.example.com".to_string(),
+            trusted_proxies: vec!["10.0.0.1".to_string()],
+            ..config::Folio::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(redirect_to_canonical_host))
+                .app_data(web::Data::new(config))
+                .default_service(web::to(|| async { HttpResponse::Ok().finish() })),
+        )
+        .await;
+        test::call_service(&app, req.to_request()).await
+    }
+
+    #[actix_web::test]
+    async fn redirects_other_hosts_preserving_path_and_query() {
+        let response = call(Method::GET, "/files/a.txt?password=x", "folio.internal").await;
+
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(
+            response.headers().get(header::LOCATION).unwrap(),
+            "http://folio.example.com/files/a.txt?password=x"
+        );
+
+        let response = call(Method::POST, "/uploads", "folio.internal").await;
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    }
+
+    #[actix_web::test]
+    async fn passes_canonical_host_and_exempt_paths() {
+        let response = call(Method::GET, "/files/a.txt", "Folio.Example.com").await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = call(Method::GET, "/health", "10.0.0.5:8000").await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn trusts_forwarded_host_only_from_trusted_proxies() {
+        let forwarded = |peer: &str| {
+            test::TestRequest::get()
+                .uri("/files/a.txt")
+                .peer_addr(peer.parse().unwrap())
+                .insert_header((header::HOST, "folio.internal"))
+                .insert_header(("X-Forwarded-Host", "folio.example.com"))
+                .insert_header(("X-Forwarded-Proto", "https"))
+        };
+
+        let response = call_via(forwarded("10.0.0.1:4000")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = call_via(forwarded("203.0.113.9:4000")).await;
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(
+            response.headers().get(header::LOCATION).unwrap(),
+            "http://folio.example.com/files/a.txt"
+        );
+    }
+}
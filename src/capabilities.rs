@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use actix_web::{HttpResponse, Responder, get, web};
+use serde::Serialize;
+
+use super::auth::AccessAuth;
+use super::config;
+use super::uploads::{self, UPLOAD_ID_LENGTH};
+
+/// Server limits and features advertised to API clients.
+#[derive(Debug, Serialize)]
+pub struct Capabilities {
+    pub max_upload_bytes: usize,
+    pub min_upload_bytes: usize,
+    /// Empty means any content type is accepted.
+    pub allowed_content_types: Vec<String>,
+    /// Empty means any extension is accepted, except `blocked_extensions`.
+    pub allowed_extensions: Vec<String>,
+    pub blocked_extensions: Vec<String>,
+    pub default_expire: String,
+    pub default_expire_secs: u64,
+    /// Longest accepted TTL, when `max_expire` is configured.
+    pub max_expire_secs: Option<u64>,
+    /// Whether Cloudflare Access is configured (`FOLIO_CF_ACCESS_AUD`), so
+    /// private files and `/admin/*` accept an Access token.
+    pub auth_required: bool,
+    /// `max_upload_bytes_by_type`: limits overriding `max_upload_bytes` for
+    /// matching content types.
+    pub max_upload_bytes_by_type: BTreeMap<String, usize>,
+    pub upload_protocols: Vec<&'static str>,
+    /// Length of the random part of an upload id.
+    pub id_length: usize,
+    /// `upload_id_prefix`, present before the random part.
+    pub id_prefix: String,
+    /// Whether files can be downloaded from this server (`serve_files`).
+    pub serves_files: bool,
+    /// Whether writes are disabled (`read_only`).
+    pub read_only: bool,
+}
+
+impl Capabilities {
+    pub fn from_config(config: &config::Folio, access_auth: &AccessAuth) -> Self {
+        Self {
+            max_upload_bytes: config.max_upload_size,
+            min_upload_bytes: config.min_upload_bytes,
+            allowed_content_types: config.allowed_content_types.clone(),
+            allowed_extensions: config.allowed_extensions.clone(),
+            blocked_extensions: config.blocked_extensions.clone(),
+            default_expire: config.default_expire.clone(),
+            default_expire_secs: uploads::default_ttl(config).as_secs(),
+            max_expire_secs: uploads::max_ttl(config).map(|ttl| ttl.as_secs()),
+            auth_required: access_auth.is_configured(),
+            max_upload_bytes_by_type: config.max_upload_bytes_by_type.clone(),
+            upload_protocols: vec!["multipart", "json-base64", "parts"],
+            id_length: UPLOAD_ID_LENGTH,
+            id_prefix: config.upload_id_prefix.clone(),
+            serves_files: config.serve_files,
+            read_only: config.read_only,
+        }
+    }
+}
+
+#[get("/capabilities")]
+pub async fn get_capabilities(
+    config: web::Data<config::Folio>,
+    access_auth: web::Data<Arc<AccessAuth>>,
+) -> impl Responder {
+    HttpResponse::Ok().json(Capabilities::from_config(&config, &access_auth))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, http::StatusCode, test};
+
+    #[actix_web::test]
+    async fn reports_configured_limits() {
+        let config = config::Folio {
+            max_upload_size: 1024,
+            default_expire: "1h".to_string(),
+            allowed_content_types: vec!["image/png".to_string()],
+            ..config::Folio::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(Arc::new(AccessAuth::from_parts(
+                    "https://issuer.example.com",
+                    "",
+                    None,
+                ))))
+                .service(get_capabilities),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/capabilities").to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["max_upload_bytes"], 1024);
+        assert_eq!(body["allowed_content_types"][0], "image/png");
+        assert_eq!(body["default_expire"], "1h");
+        assert_eq!(body["default_expire_secs"], 3600);
+        assert_eq!(body["auth_required"], false);
+        assert_eq!(body["upload_protocols"][0], "multipart");
+        assert_eq!(body["id_length"], UPLOAD_ID_LENGTH);
+        assert_eq!(body["serves_files"], true);
+    }
+
+    #[actix_web::test]
+    async fn reports_auth_required_when_access_is_configured() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config::Folio::default()))
+                .app_data(web::Data::new(Arc::new(AccessAuth::from_parts(
+                    "https://issuer.example.com",
+                    "folio-app",
+                    Some("test-secret"),
+                ))))
+                .service(get_capabilities),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/capabilities").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["auth_required"], true);
+    }
+}
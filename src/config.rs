@@ -1,14 +1,280 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::{Component, Path, PathBuf};
 
+use super::hashing::HashAlgorithm;
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Folio {
     pub address: String,
     pub port: u16,
+    /// PEM certificate chain and private key. With both set the server
+    /// terminates TLS itself and speaks HTTP/2; empty serves plain HTTP.
+    pub tls_cert_path: String,
+    pub tls_key_path: String,
+    /// Concurrent connections each worker accepts; `0` keeps the default
+    /// (25k).
+    pub max_connections: usize,
     pub web_path: String,
+    /// Fail startup instead of warning when `web_path` and `uploads_path`
+    /// contain one another.
+    pub reject_overlapping_paths: bool,
     pub uploads_path: String,
+    /// Fail startup when `uploads_path` does not exist instead of creating
+    /// it, e.g. to catch a volume that was not mounted.
+    pub require_existing_uploads_dir: bool,
+    /// Serve and write through symbolic links below `uploads_path`, as long
+    /// as they resolve inside it. Off: such paths are refused.
+    pub follow_symlinks: bool,
+    /// Hash for `<file>.meta.json`, `?echo=` and listing ETags.
+    pub hash_algorithm: HashAlgorithm,
     pub data_path: String,
     pub max_upload_size: usize,
+    /// Overrides of `max_upload_size` by content type, exact or wildcard
+    /// (e.g. `"image/*" = 10485760`). The most specific match wins.
+    pub max_upload_bytes_by_type: BTreeMap<String, usize>,
+    /// Total uncompressed bytes one `POST /uploads/extract` archive may
+    /// unpack to, against zip bombs.
+    pub max_extract_size: u64,
+    /// Smallest file accepted by `/uploads` and `POST`/`PUT /files`; `0`
+    /// allows empty files.
+    pub min_upload_bytes: usize,
+    /// Multipart field carrying the file on `/uploads` and `POST`/`PUT /files`.
+    pub upload_field_name: String,
+    /// TTL applied to `/uploads` when no `expire` query is given (e.g. `168h`).
+    pub default_expire: String,
+    /// TTLs for `/uploads` without `expire`, by file extension (e.g.
+    /// `log = "7d"`). Extensions not listed use `default_expire`.
+    pub expire_by_extension: BTreeMap<String, String>,
+    /// How long a `?staged=1` upload waits for its commit before it is
+    /// dropped (e.g. `1h`).
+    pub staged_upload_ttl: String,
+    /// Unfinished uploads (chunked upload ids, staged files) one client
+    /// address may have at once before new ones get `429`. `0` is unlimited.
+    pub max_pending_uploads_per_client: usize,
+    /// Chunked uploads without a new part for this long (e.g. `24h`) are
+    /// deleted by the expiry sweeper. Empty keeps them until completed.
+    pub abandoned_upload_age: String,
+    /// Content types accepted by `/uploads`, such as `image/png` or
+    /// `image/*`. Empty means any type is allowed.
+    pub allowed_content_types: Vec<String>,
+    /// File extensions accepted by `/uploads` and `POST`/`PUT /files`, without
+    /// the dot. Empty means any extension is allowed.
+    pub allowed_extensions: Vec<String>,
+    /// File extensions always refused, whatever the content type (e.g. `php`).
+    pub blocked_extensions: Vec<String>,
+    /// Maximum entries in a single upload directory. `0` disables the limit.
+    pub max_files_per_dir: usize,
+    /// `fsync` written files and their directory before answering, so
+    /// acknowledged uploads survive a power loss. Costs latency per write.
+    pub durable_writes: bool,
+    /// Audit log destination: empty disables it, `-` writes JSON lines to
+    /// stdout, anything else is a file path appended to per entry.
+    pub audit_log: String,
+    /// Requests taking longer than this many milliseconds, until their
+    /// response is fully sent, are logged as warnings. `0` disables it.
+    pub slow_request_threshold_ms: u64,
+    /// `/health` reports unavailable when `uploads_path` has less free space.
+    pub min_free_bytes: u64,
+    /// Same as `min_free_bytes`, as a percentage of the filesystem size.
+    /// Writes that would leave less free are also refused with `507`.
+    pub min_free_percent: f64,
+    /// Status returned by successful `/uploads` requests.
+    pub upload_success_status: UploadSuccessStatus,
+    /// Longest TTL a client may request (e.g. `30d`). Empty means no cap.
+    pub max_expire: String,
+    /// What happens to requests above `max_expire`.
+    pub expire_cap_mode: ExpireCapMode,
+    /// Up to this much random extra time (e.g. `10m`) is added to every
+    /// scheduled expiration, so files uploaded together do not all expire in
+    /// the same sweep. Empty adds none.
+    pub expire_jitter: String,
+    /// Expired files are hidden and answer `410` for this long (e.g. `7d`)
+    /// before they are deleted, and can be restored meanwhile. Empty
+    /// deletes them right away.
+    pub expire_grace_period: String,
+    /// How long requests for an expired file the sweeper deleted get `410`
+    /// rather than `404` (e.g. `7d`). Empty forgets them right away.
+    pub tombstone_ttl: String,
+    /// Count downloads and record the last one per file in the metadata
+    /// store. Costs a batched metadata write every 30 seconds.
+    pub track_access: bool,
+    /// With `track_access`, expire files not downloaded for this long (e.g.
+    /// `90d`), counting from their last change when never downloaded.
+    /// Empty keeps them.
+    pub delete_unaccessed_after: String,
+    /// How often the uploads and temp directories are walked for the
+    /// storage gauges on `/metrics` (e.g. `5m`). Empty disables them.
+    pub storage_metrics_interval: String,
+    /// How long `/uploads` remembers an `Idempotency-Key` and replays the
+    /// first successful response to retries (e.g. `24h`). Empty disables it.
+    pub idempotency_window: String,
+    /// Host name (optionally `host:port`) that requests are redirected to
+    /// when they arrive under another name. Empty disables the redirect.
+    pub canonical_host: String,
+    /// Decode image uploads and reject the ones that fail with `422`.
+    pub verify_images: bool,
+    /// Run `verify_images` after answering the upload with `202`; clients
+    /// poll `GET /uploads/<id>/status` until the file is ready.
+    pub async_processing: bool,
+    /// Largest width or height accepted by `GET /files/<path>?thumb=WxH`.
+    pub max_thumbnail_size: u32,
+    /// Uploads/creates written at once before new ones get `503`. `0` is unlimited.
+    pub max_concurrent_uploads: usize,
+    /// `Retry-After` seconds sent with `RATE_LIMITED` responses, e.g. when
+    /// every upload slot is taken, and `TOO_MANY_PENDING_UPLOADS` ones.
+    pub throttle_retry_after_secs: u64,
+    /// strftime layout (UTC) of the directory `/uploads` stores files under,
+    /// e.g. `%Y/%m/%d`. Empty stores them at the uploads root.
+    pub date_layout: String,
+    /// Prepended to generated upload ids (e.g. `folio-`), so they are
+    /// recognizable in shared storage. Empty by default.
+    pub upload_id_prefix: String,
+    /// Reject `/uploads` parts and JSON bodies without a filename with `400`.
+    pub require_filename: bool,
+    /// Stands in for a missing filename when picking the extension (e.g.
+    /// `upload.bin`). Empty leaves such uploads to their content type alone.
+    pub default_filename: String,
+    /// Longest extension `/uploads` gives a stored file, in characters.
+    pub max_extension_length: usize,
+    /// What happens to extensions longer than `max_extension_length`.
+    pub long_extension_mode: LongExtensionMode,
+    /// Give `/uploads` files the extension of their filename or content
+    /// type. When off, ids are opaque and the content type is kept in the
+    /// file's metadata for downloads.
+    pub preserve_extension: bool,
+    /// Longest file name (last path component, in bytes) accepted by
+    /// `POST`/`PUT /files/<path>`.
+    pub max_filename_length: usize,
+    /// Turn file names written through `POST`/`PUT /files/<path>`, and the
+    /// original names offered on download, into ASCII slugs.
+    pub slugify_filenames: bool,
+    /// Cloudflare Access emails allowed to call `/admin/*`. Empty disables
+    /// the admin routes.
+    pub admin_emails: Vec<String>,
+    /// Restrict `GET /stats/usage` to `admin_emails`.
+    pub usage_requires_admin: bool,
+    /// Named roots: top-level directories of `uploads_path` with their own
+    /// settings, keyed by directory name.
+    pub roots: BTreeMap<String, RootConfig>,
+    /// Origins allowed to call `/files` and `/uploads` from a browser; `*`
+    /// allows any. Empty disables CORS.
+    pub cors_allowed_origins: Vec<String>,
+    /// Origins an upload's `?redirect=` may point to besides paths on this
+    /// server, e.g. `https://example.com`.
+    pub upload_redirect_origins: Vec<String>,
+    /// Headers added to every `/files` and `/private-files` response (e.g.
+    /// `X-Robots-Tag = "noindex"`).
+    pub extra_headers: BTreeMap<String, String>,
+    /// Overrides of `extra_headers` for matching paths or content types,
+    /// applied in order.
+    pub extra_header_rules: Vec<ExtraHeaderRule>,
+    /// Seconds browsers may cache a preflight (`Access-Control-Max-Age`).
+    /// `0` omits the header.
+    pub cors_max_age: u64,
+    /// Seconds browsers and CDNs may cache public `/files` downloads
+    /// (`Cache-Control: public, max-age`), capped at the time left before
+    /// the file expires. `0` omits the header.
+    pub download_max_age: u64,
+    /// Methods advertised to preflights for `/files` and `/private-files`.
+    pub cors_files_methods: Vec<String>,
+    /// Methods advertised to preflights for `/uploads`.
+    pub cors_uploads_methods: Vec<String>,
+    /// Response headers browser scripts may read
+    /// (`Access-Control-Expose-Headers`). Empty omits the header.
+    pub cors_expose_headers: Vec<String>,
+    /// Let browsers send cookies and `Authorization` cross-origin
+    /// (`Access-Control-Allow-Credentials: true`).
+    pub cors_allow_credentials: bool,
+    /// Mount the download routes (`GET /files/*`, `/private-files/*`).
+    /// Turn off when files are served elsewhere, e.g. by a CDN.
+    pub serve_files: bool,
+    /// Charset added to `text/*` downloads whose `Content-Type` has none,
+    /// unless the file starts with a UTF-16 byte order mark. Empty leaves
+    /// the type as is.
+    pub default_charset: String,
+    /// Content types downloaded `inline` unless `?disposition=` says
+    /// otherwise, exact or wildcard like `image/*`. Others are served as
+    /// `attachment`.
+    pub inline_content_types: Vec<String>,
+    /// Freeze the content set: every write (`/uploads`, `POST`/`PUT`/`DELETE
+    /// /files`, expiration changes, admin deletes) gets `403`.
+    pub read_only: bool,
+    /// Refuse `DELETE /files` with `428` unless it carries `?confirm=` with
+    /// the target path or `X-Confirm-Delete: true`.
+    pub require_delete_confirmation: bool,
+    /// Origin for URLs in responses (e.g. `https://files.example.com`).
+    /// Empty keeps them relative unless `trusted_proxies` is set.
+    pub public_base_url: String,
+    /// Proxy addresses or CIDR ranges whose `X-Forwarded-Proto` and
+    /// `X-Forwarded-Host` are used for URLs in responses.
+    pub trusted_proxies: Vec<String>,
+    /// Record each upload's original filename, content type and uploader as
+    /// `user.folio.*` extended attributes (Linux), falling back to
+    /// `file-metadata.json` where the filesystem has none.
+    pub xattr_metadata: bool,
+    /// Write `<file>.meta.json` next to each upload with its original name,
+    /// content type, size, SHA-256 and timestamps.
+    pub meta_json_files: bool,
+    /// Enable test aids for client integration suites, such as `?echo=1`
+    /// on `/uploads`. Keep off in production.
+    pub debug_endpoints: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RootConfig {
+    /// Bytes the root may hold. `0` is unlimited.
+    pub quota_bytes: u64,
+    /// Replaces the global `allowed_content_types` for uploads into the root.
+    pub allowed_content_types: Option<Vec<String>>,
+    /// Replaces the global `inline_content_types` for downloads from the
+    /// root.
+    pub inline_content_types: Option<Vec<String>>,
+    /// Replaces the global `default_expire` for uploads into the root.
+    pub default_expire: Option<String>,
+    /// Replaces the global `min_free_percent` for writes into the root.
+    pub min_free_percent: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ExtraHeaderRule {
+    /// Glob matched against the file path below `/files/`.
+    pub path: Option<String>,
+    /// Response media type: `image/png`, `image/*` or `*/*`.
+    pub content_type: Option<String>,
+    /// Headers to set; an empty value removes the header.
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExpireCapMode {
+    /// Shorten the TTL to `max_expire` and flag it in the response.
+    #[default]
+    Clamp,
+    /// Fail the request with `400`.
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LongExtensionMode {
+    /// Fail the upload with `422`.
+    #[default]
+    Reject,
+    /// Keep the first `max_extension_length` characters.
+    Truncate,
+}
+
+/// `201 Created` is correct, but some clients and proxies only handle `200`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UploadSuccessStatus {
+    #[default]
+    Created,
+    Ok,
 }
 
 impl Folio {
@@ -23,6 +289,39 @@ impl Folio {
         }
     }
 
+    /// Settings of the named root `dir` (relative to the uploads root) lies
+    /// in, if any. `dir` is a directory: a file's parent, or `?dir=`.
+    pub fn root_config(&self, dir: &Path) -> Option<&RootConfig> {
+        let Some(Component::Normal(first)) = dir.components().next() else {
+            return None;
+        };
+        self.roots.get(first.to_str()?)
+    }
+
+    /// `allowed_content_types` for uploads into `dir`, with the named root's
+    /// override applied.
+    pub fn allowed_content_types_in(&self, dir: &Path) -> &[String] {
+        self.root_config(dir)
+            .and_then(|root| root.allowed_content_types.as_deref())
+            .unwrap_or(&self.allowed_content_types)
+    }
+
+    /// `inline_content_types` for downloads from `dir`, with the named
+    /// root's override applied.
+    pub fn inline_content_types_in(&self, dir: &Path) -> &[String] {
+        self.root_config(dir)
+            .and_then(|root| root.inline_content_types.as_deref())
+            .unwrap_or(&self.inline_content_types)
+    }
+
+    /// `min_free_percent` for writes into `dir`, with the named root's
+    /// override applied.
+    pub fn min_free_percent_in(&self, dir: &Path) -> f64 {
+        self.root_config(dir)
+            .and_then(|root| root.min_free_percent)
+            .unwrap_or(self.min_free_percent)
+    }
+
     /// Build full file path for uploads with normalized path
     pub fn build_full_upload_path(&self, relative_path: &Path) -> PathBuf {
         self.normalize_and_join(&self.resolve_base(&self.uploads_path), relative_path)
@@ -66,15 +365,161 @@ impl Folio {
     }
 }
 
+/// Shown in place of sensitive config values.
+const REDACTED: &str = "<redacted>";
+
+/// Settings whose values must not end up in logs: credentials, and where
+/// the TLS private key lives. Matched by name so new secrets are covered.
+fn is_sensitive(key: &str) -> bool {
+    ["secret", "token", "password", "credential", "key_path"]
+        .iter()
+        .any(|word| key.contains(word))
+}
+
+/// Mask the values of sensitive keys in `value`, at any depth. Empty values
+/// are kept, so an unset secret still reads as unset.
+fn redact(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let set = !matches!(value, serde_json::Value::Null)
+                    && value.as_str().is_none_or(|s| !s.is_empty());
+                if is_sensitive(&key.to_ascii_lowercase()) && set {
+                    *value = serde_json::Value::from(REDACTED);
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+impl Folio {
+    /// The effective configuration as JSON, with the directories resolved
+    /// to absolute paths and sensitive values masked.
+    pub fn effective(&self) -> Result<serde_json::Value, serde_json::Error> {
+        let mut value = serde_json::to_value(self)?;
+        for key in ["web_path", "uploads_path", "data_path"] {
+            let resolved = self.resolve_base(value[key].as_str().unwrap_or_default());
+            value[key] = serde_json::Value::from(resolved.to_string_lossy());
+        }
+        redact(&mut value);
+        Ok(value)
+    }
+}
+
+/// Where each top-level setting was loaded from: `default`, the config
+/// file (with the profile section, if that is what set it), or the
+/// environment variable. Recorded once at startup for `/debug/config`.
+#[derive(Debug, Default, Serialize)]
+#[serde(transparent)]
+pub struct ConfigSources(pub BTreeMap<String, String>);
+
+/// Startup summary of the effective configuration: one `key = value` line
+/// per setting, with the directories resolved to absolute paths and
+/// sensitive values masked. Use this rather than `Debug` for logging.
+impl std::fmt::Display for Folio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = self.effective().map_err(|_| std::fmt::Error)?;
+        let serde_json::Value::Object(settings) = value else {
+            return Err(std::fmt::Error);
+        };
+        write!(f, "effective configuration:")?;
+        for (key, value) in settings {
+            write!(f, "\n  {} = {}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
 impl Default for Folio {
     fn default() -> Folio {
         Folio {
             address: String::from("127.0.0.1"),
             port: 8000,
+            tls_cert_path: String::new(),
+            tls_key_path: String::new(),
+            max_connections: 0,
             web_path: String::from("./web/dist"),
+            reject_overlapping_paths: false,
             uploads_path: String::from("./uploads"),
+            require_existing_uploads_dir: false,
+            follow_symlinks: false,
+            hash_algorithm: HashAlgorithm::Sha256,
             data_path: String::from("./data"),
             max_upload_size: 25 * 1024 * 1024, // 25 MiB
+            max_upload_bytes_by_type: BTreeMap::new(),
+            max_extract_size: 1024 * 1024 * 1024, // 1 GiB
+            min_upload_bytes: 0,
+            upload_field_name: String::from("file"),
+            default_expire: String::from("168h"),
+            expire_by_extension: BTreeMap::new(),
+            staged_upload_ttl: String::from("1h"),
+            max_pending_uploads_per_client: 0,
+            abandoned_upload_age: String::from("24h"),
+            allowed_content_types: Vec::new(),
+            allowed_extensions: Vec::new(),
+            blocked_extensions: Vec::new(),
+            max_files_per_dir: 0,
+            durable_writes: false,
+            audit_log: String::new(),
+            slow_request_threshold_ms: 0,
+            min_free_bytes: 0,
+            min_free_percent: 0.0,
+            upload_success_status: UploadSuccessStatus::Created,
+            max_expire: String::new(),
+            expire_cap_mode: ExpireCapMode::Clamp,
+            expire_jitter: String::new(),
+            expire_grace_period: String::new(),
+            tombstone_ttl: String::new(),
+            track_access: false,
+            delete_unaccessed_after: String::new(),
+            storage_metrics_interval: "5m".to_string(),
+            idempotency_window: String::from("24h"),
+            canonical_host: String::new(),
+            verify_images: false,
+            async_processing: false,
+            max_thumbnail_size: 512,
+            max_concurrent_uploads: 0,
+            throttle_retry_after_secs: 5,
+            date_layout: String::new(),
+            upload_id_prefix: String::new(),
+            require_filename: false,
+            default_filename: String::new(),
+            max_extension_length: 16,
+            long_extension_mode: LongExtensionMode::Reject,
+            preserve_extension: true,
+            max_filename_length: 255,
+            slugify_filenames: false,
+            admin_emails: Vec::new(),
+            usage_requires_admin: false,
+            roots: BTreeMap::new(),
+            cors_allowed_origins: Vec::new(),
+            upload_redirect_origins: Vec::new(),
+            extra_headers: BTreeMap::new(),
+            extra_header_rules: Vec::new(),
+            cors_max_age: 0,
+            download_max_age: 0,
+            cors_files_methods: vec![String::from("GET"), String::from("HEAD")],
+            cors_uploads_methods: vec![String::from("POST")],
+            cors_expose_headers: vec![
+                String::from("Location"),
+                String::from("ETag"),
+                String::from("X-Request-Id"),
+            ],
+            cors_allow_credentials: false,
+            serve_files: true,
+            default_charset: String::from("utf-8"),
+            inline_content_types: vec![String::from("image/*"), String::from("application/pdf")],
+            read_only: false,
+            require_delete_confirmation: false,
+            public_base_url: String::new(),
+            trusted_proxies: Vec::new(),
+            xattr_metadata: false,
+            meta_json_files: false,
+            debug_endpoints: false,
         }
     }
 }
@@ -91,6 +536,40 @@ mod tests {
         assert_eq!(config.web_path, "./web/dist");
         assert_eq!(config.uploads_path, "./uploads");
         assert_eq!(config.max_upload_size, 25 * 1024 * 1024);
+        assert_eq!(config.default_expire, "168h");
+        assert!(config.allowed_content_types.is_empty());
+        assert_eq!(config.upload_success_status, UploadSuccessStatus::Created);
+    }
+
+    #[test]
+    fn display_masks_secrets_and_resolves_paths() {
+        let config = Folio {
+            tls_cert_path: String::from("/etc/folio/cert.pem"),
+            tls_key_path: String::from("/etc/folio/key.pem"),
+            data_path: String::from("/var/lib/folio"),
+            ..Folio::default()
+        };
+        let summary = config.to_string();
+
+        assert!(!summary.contains("key.pem"));
+        assert!(summary.contains("\n  tls_key_path = \"<redacted>\""));
+        assert!(summary.contains("\n  tls_cert_path = \"/etc/folio/cert.pem\""));
+        assert!(summary.contains("\n  data_path = \"/var/lib/folio\""));
+        let uploads = config.resolve_base(&config.uploads_path);
+        assert!(summary.contains(&format!("\n  uploads_path = \"{}\"", uploads.display())));
+        assert!(summary.contains("\n  port = 8000"));
+    }
+
+    #[test]
+    fn unset_secrets_stay_visible_as_unset() {
+        let mut value = serde_json::json!({
+            "api_token": "",
+            "webhook": { "secret": "hunter2", "url": "https://example.com" },
+        });
+        redact(&mut value);
+        assert_eq!(value["api_token"], "");
+        assert_eq!(value["webhook"]["secret"], REDACTED);
+        assert_eq!(value["webhook"]["url"], "https://example.com");
     }
 
     mod build_full_upload_path {
@@ -149,12 +628,9 @@ mod tests {
         #[test]
         fn with_custom_uploads_path() {
             let config = Folio {
-                address: String::from("127.0.0.1"),
-                port: 8000,
                 web_path: String::from("./web"),
                 uploads_path: String::from("./custom_uploads"),
-                data_path: String::from("./data"),
-                max_upload_size: 25 * 1024 * 1024,
+                ..Folio::default()
             };
             let path = config.build_full_upload_path(&PathBuf::from("test.txt"));
 
@@ -175,12 +651,9 @@ mod tests {
         #[test]
         fn absolute_path_ignores_current_dir() {
             let config = Folio {
-                address: String::from("127.0.0.1"),
-                port: 8000,
                 web_path: String::from("./web"),
                 uploads_path: String::from("/tmp/test_uploads"),
-                data_path: String::from("./data"),
-                max_upload_size: 25 * 1024 * 1024,
+                ..Folio::default()
             };
             let path = config.build_full_upload_path(&PathBuf::from("test.txt"));
 
@@ -0,0 +1,270 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{HttpResponse, web};
+
+use crate::config;
+
+/// Routes sharing one list of allowed CORS methods.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RouteGroup {
+    /// `/files/*` and `/private-files/*`.
+    Files,
+    /// `/uploads`.
+    Uploads,
+}
+
+impl RouteGroup {
+    fn of(path: &str) -> Option<Self> {
+        if path.starts_with("/files/") || path.starts_with("/private-files/") {
+            Some(Self::Files)
+        } else if path == "/uploads" {
+            Some(Self::Uploads)
+        } else {
+            None
+        }
+    }
+
+    fn methods(self, config: &config::Folio) -> &[String] {
+        match self {
+            Self::Files => &config.cors_files_methods,
+            Self::Uploads => &config.cors_uploads_methods,
+        }
+    }
+}
+
+/// Answer CORS preflights and tag responses for allowed origins.
+///
+/// Only `/files` and `/uploads` take part, each with its own method list, so
+/// browsers learn e.g. that `/files` is read-only without a failed request.
+pub async fn cors(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let config = req.app_data::<web::Data<config::Folio>>().cloned();
+    let origin = req
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let group = RouteGroup::of(req.path());
+    let (Some(config), Some(origin), Some(group)) = (config, origin, group) else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    };
+    if !origin_allowed(&config, &origin) {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    }
+
+    let is_preflight = req.method() == Method::OPTIONS
+        && req
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    if is_preflight {
+        let mut response = HttpResponse::build(StatusCode::NO_CONTENT);
+        response
+            .insert_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, origin))
+            .insert_header((header::VARY, "Origin"))
+            .insert_header((
+                header::ACCESS_CONTROL_ALLOW_METHODS,
+                group.methods(&config).join(", "),
+            ));
+        if let Some(headers) = req.headers().get(header::ACCESS_CONTROL_REQUEST_HEADERS) {
+            response.insert_header((header::ACCESS_CONTROL_ALLOW_HEADERS, headers.clone()));
+        }
+        if config.cors_max_age > 0 {
+            response.insert_header((header::ACCESS_CONTROL_MAX_AGE, config.cors_max_age));
+        }
+        if config.cors_allow_credentials {
+            response.insert_header((header::ACCESS_CONTROL_ALLOW_CREDENTIALS, "true"));
+        }
+        return Ok(req.into_response(response.finish()).map_into_right_body());
+    }
+
+    let mut res = next.call(req).await?;
+    if let Ok(origin) = HeaderValue::from_str(&origin) {
+        let headers = res.headers_mut();
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        headers.append(header::VARY, HeaderValue::from_static("Origin"));
+        if config.cors_allow_credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        if !config.cors_expose_headers.is_empty()
+            && let Ok(exposed) = HeaderValue::from_str(&config.cors_expose_headers.join(", "))
+        {
+            headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, exposed);
+        }
+    }
+    Ok(res.map_into_left_body())
+}
+
+fn origin_allowed(config: &config::Folio, origin: &str) -> bool {
+    config
+        .cors_allowed_origins
+        .iter()
+        .any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(origin))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::middleware::from_fn;
+    use actix_web::{App, test};
+
+    async fn call(req: test::TestRequest) -> ServiceResponse<impl MessageBody> {
+        call_with(
+            config::Folio {
+                cors_allowed_origins: vec!["https://app.example.com".to_string()],
+                cors_max_age: 600,
+                ..config::Folio::default()
+            },
+            req,
+        )
+        .await
+    }
+
+    async fn call_with(
+        config: config::Folio,
+        req: test::TestRequest,
+    ) -> ServiceResponse<impl MessageBody> {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(cors))
+                .app_data(web::Data::new(config))
+                .default_service(web::to(|| async { HttpResponse::Ok().finish() })),
+        )
+        .await;
+        test::call_service(&app, req.to_request()).await
+    }
+
+    fn preflight(uri: &str, origin: &str) -> test::TestRequest {
+        test::TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri(uri)
+            .insert_header((header::ORIGIN, origin))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "POST"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_HEADERS, "content-type"))
+    }
+
+    #[actix_web::test]
+    async fn preflight_lists_methods_per_route_group() {
+        let response = call(preflight("/files/a.txt", "https://app.example.com")).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(
+            headers.get(header::ACCESS_CONTROL_ALLOW_METHODS).unwrap(),
+            "GET, HEAD"
+        );
+        assert_eq!(headers.get(header::ACCESS_CONTROL_MAX_AGE).unwrap(), "600");
+        assert_eq!(
+            headers.get(header::ACCESS_CONTROL_ALLOW_HEADERS).unwrap(),
+            "content-type"
+        );
+
+        let response = call(preflight("/uploads", "https://app.example.com")).await;
+        assert_eq!(
+            response
+                .headers()
+                .get(header::ACCESS_CONTROL_ALLOW_METHODS)
+                .unwrap(),
+            "POST"
+        );
+    }
+
+    #[actix_web::test]
+    async fn ignores_unknown_origins_and_other_routes() {
+        let response = call(preflight("/files/a.txt", "https://evil.example.com")).await;
+        assert!(
+            !response
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        );
+
+        let response = call(preflight("/stats", "https://app.example.com")).await;
+        assert!(
+            !response
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        );
+    }
+
+    #[actix_web::test]
+    async fn tags_actual_responses() {
+        let response = call(
+            test::TestRequest::get()
+                .uri("/files/a.txt")
+                .insert_header((header::ORIGIN, "https://app.example.com")),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response
+                .headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .unwrap(),
+            "https://app.example.com"
+        );
+    }
+
+    #[actix_web::test]
+    async fn exposes_headers_and_allows_credentials() {
+        let config = |cors_allow_credentials, cors_expose_headers| config::Folio {
+            cors_allowed_origins: vec!["*".to_string()],
+            cors_allow_credentials,
+            cors_expose_headers,
+            ..config::Folio::default()
+        };
+        let exposed = config::Folio::default().cors_expose_headers;
+        let request = || {
+            test::TestRequest::post()
+                .uri("/uploads")
+                .insert_header((header::ORIGIN, "https://app.example.com"))
+        };
+
+        let response = call_with(config(true, exposed.clone()), request()).await;
+        let headers = response.headers();
+        assert_eq!(
+            headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "https://app.example.com"
+        );
+        assert_eq!(
+            headers
+                .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+                .unwrap(),
+            "true"
+        );
+        assert_eq!(
+            headers.get(header::ACCESS_CONTROL_EXPOSE_HEADERS).unwrap(),
+            "Location, ETag, X-Request-Id"
+        );
+
+        let response = call_with(
+            config(true, exposed),
+            preflight("/uploads", "https://app.example.com"),
+        )
+        .await;
+        assert_eq!(
+            response
+                .headers()
+                .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+                .unwrap(),
+            "true"
+        );
+
+        let response = call_with(config(false, Vec::new()), request()).await;
+        let headers = response.headers();
+        assert!(!headers.contains_key(header::ACCESS_CONTROL_ALLOW_CREDENTIALS));
+        assert!(!headers.contains_key(header::ACCESS_CONTROL_EXPOSE_HEADERS));
+    }
+}
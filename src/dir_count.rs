@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use super::error::FolioError;
+
+struct CachedCount {
+    count: usize,
+    modified: Option<SystemTime>,
+}
+
+/// Cached per-directory entry counts used to enforce `max_files_per_dir`.
+///
+/// A cached count is trusted only while the directory's mtime is unchanged.
+/// Adding or removing an entry bumps the mtime, so deletions by the expiry
+/// sweeper or out-of-band changes trigger a rescan, while our own creates
+/// update the cache in place via `record_created`.
+#[derive(Default)]
+pub struct DirEntryCounter {
+    cache: Mutex<HashMap<PathBuf, CachedCount>>,
+}
+
+impl DirEntryCounter {
+    /// Reject with `507 Insufficient Storage` when `dir` already holds
+    /// `max_files` entries. A `max_files` of `0` means unlimited.
+    pub fn ensure_capacity(&self, dir: &Path, max_files: usize) -> Result<(), FolioError> {
+        if max_files == 0 {
+            return Ok(());
+        }
+
+        let count = self.count(dir).map_err(|e| FolioError::Internal {
+            source: format!("failed to count directory entries: {}", e),
+            context: Some(format!("count entries in: {}", dir.display())),
+        })?;
+
+        if count >= max_files {
+            log::warn!(
+                "directory {} is full ({} entries, limit {})",
+                dir.display(),
+                count,
+                max_files
+            );
+            return Err(FolioError::InsufficientStorage {
+                reason: format!(
+                    "directory is full: {} entries reached the limit of {}",
+                    count, max_files
+                ),
+                limit: Some("max_files_per_dir"),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Account for a new entry created in `dir` without rescanning it.
+    pub fn record_created(&self, dir: &Path) {
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(cached) = cache.get_mut(dir) {
+            cached.count += 1;
+            cached.modified = modified(dir);
+        }
+    }
+
+    fn count(&self, dir: &Path) -> std::io::Result<usize> {
+        let modified = modified(dir);
+        let mut cache = self.cache.lock().unwrap();
+
+        if let Some(cached) = cache.get(dir)
+            && modified.is_some()
+            && cached.modified == modified
+        {
+            return Ok(cached.count);
+        }
+
+        let count = match std::fs::read_dir(dir) {
+            Ok(entries) => entries.count(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e),
+        };
+        cache.insert(dir.to_path_buf(), CachedCount { count, modified });
+        Ok(count)
+    }
+}
+
+fn modified(dir: &Path) -> Option<SystemTime> {
+    std::fs::metadata(dir).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlimited_when_zero() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("a.txt"), "a").unwrap();
+        let counter = DirEntryCounter::default();
+
+        assert!(counter.ensure_capacity(temp_dir.path(), 0).is_ok());
+    }
+
+    #[test]
+    fn rejects_full_directory_and_sees_deletions() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let counter = DirEntryCounter::default();
+
+        assert!(counter.ensure_capacity(temp_dir.path(), 2).is_ok());
+        std::fs::write(temp_dir.path().join("a.txt"), "a").unwrap();
+        counter.record_created(temp_dir.path());
+        std::fs::write(temp_dir.path().join("b.txt"), "b").unwrap();
+        counter.record_created(temp_dir.path());
+
+        let err = counter.ensure_capacity(temp_dir.path(), 2).unwrap_err();
+        assert!(matches!(err, FolioError::InsufficientStorage { .. }));
+
+        std::fs::remove_file(temp_dir.path().join("a.txt")).unwrap();
+        assert!(counter.ensure_capacity(temp_dir.path(), 2).is_ok());
+    }
+
+    #[test]
+    fn missing_directory_counts_as_empty() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let counter = DirEntryCounter::default();
+
+        assert!(
+            counter
+                .ensure_capacity(&temp_dir.path().join("missing"), 1)
+                .is_ok()
+        );
+    }
+}
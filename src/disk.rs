@@ -0,0 +1,145 @@
+#[cfg(unix)]
+use std::ffi::CString;
+use std::io;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use serde::Serialize;
+
+/// Space on the filesystem holding a path, as seen by unprivileged users.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct DiskSpace {
+    pub available_bytes: u64,
+    pub total_bytes: u64,
+    pub available_inodes: u64,
+    pub total_inodes: u64,
+}
+
+impl DiskSpace {
+    #[cfg(unix)]
+    pub fn of(path: &Path) -> io::Result<Self> {
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        // SAFETY: `c_path` is a valid NUL-terminated string and `stat` is a
+        // properly sized, writable statvfs struct.
+        if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Field widths differ between platforms, hence the casts.
+        #[allow(clippy::unnecessary_cast)]
+        let block_size = stat.f_frsize as u64;
+        #[allow(clippy::unnecessary_cast)]
+        let (available, total) = (stat.f_bavail as u64, stat.f_blocks as u64);
+        #[allow(clippy::unnecessary_cast)]
+        let (available_inodes, total_inodes) = (stat.f_favail as u64, stat.f_files as u64);
+
+        Ok(DiskSpace {
+            available_bytes: available.saturating_mul(block_size),
+            total_bytes: total.saturating_mul(block_size),
+            available_inodes,
+            total_inodes,
+        })
+    }
+
+    /// Without `statvfs` disk usage is unknown, which callers treat like a
+    /// failed lookup.
+    #[cfg(not(unix))]
+    pub fn of(_path: &Path) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "disk usage is only available on unix",
+        ))
+    }
+
+    /// Whether files can no longer be created although bytes remain.
+    /// Filesystems without a fixed inode table report zero total inodes and
+    /// never count as out of inodes.
+    pub fn out_of_inodes(&self) -> bool {
+        self.total_inodes > 0 && self.available_inodes == 0 && self.available_bytes > 0
+    }
+
+    pub fn available_percent(&self) -> f64 {
+        if self.total_bytes == 0 {
+            return 0.0;
+        }
+        self.available_bytes as f64 * 100.0 / self.total_bytes as f64
+    }
+}
+
+/// Why a write to `path` failed with `ENOSPC` (or `EDQUOT`): out of inodes
+/// when the filesystem says so, otherwise out of space. `path` need not exist
+/// yet; its closest existing ancestor is inspected.
+pub fn out_of_space_reason(error: &io::Error, path: &Path) -> Option<String> {
+    match error.kind() {
+        io::ErrorKind::QuotaExceeded => return Some("disk quota exceeded".to_string()),
+        io::ErrorKind::StorageFull => {}
+        _ => return None,
+    }
+    let space = path.ancestors().find_map(|dir| DiskSpace::of(dir).ok());
+    Some(match space {
+        Some(space) if space.out_of_inodes() => format!(
+            "no free inodes left ({} bytes still available)",
+            space.available_bytes
+        ),
+        _ => "no space left on device".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn reports_space_for_existing_dir() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let space = DiskSpace::of(temp_dir.path()).unwrap();
+
+        assert!(space.total_bytes > 0);
+        assert!(space.available_bytes <= space.total_bytes);
+        assert!((0.0..=100.0).contains(&space.available_percent()));
+    }
+
+    #[test]
+    fn inodes_only_run_out_with_bytes_left() {
+        let space = |available_bytes, available_inodes, total_inodes| DiskSpace {
+            available_bytes,
+            total_bytes: 1 << 30,
+            available_inodes,
+            total_inodes,
+        };
+        assert!(space(1 << 20, 0, 1000).out_of_inodes());
+        assert!(!space(0, 0, 1000).out_of_inodes());
+        assert!(!space(1 << 20, 10, 1000).out_of_inodes());
+        // btrfs and friends allocate inodes dynamically and report none.
+        assert!(!space(1 << 20, 0, 0).out_of_inodes());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn classifies_out_of_space_errors() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let target = temp_dir.path().join("not/yet/created.bin");
+
+        let full = io::Error::from_raw_os_error(libc::ENOSPC);
+        assert_eq!(
+            out_of_space_reason(&full, &target).as_deref(),
+            Some("no space left on device")
+        );
+        let quota = io::Error::from_raw_os_error(libc::EDQUOT);
+        assert_eq!(
+            out_of_space_reason(&quota, &target).as_deref(),
+            Some("disk quota exceeded")
+        );
+        let denied = io::Error::from_raw_os_error(libc::EACCES);
+        assert_eq!(out_of_space_reason(&denied, &target), None);
+    }
+
+    #[test]
+    fn missing_path_is_an_error() {
+        assert!(DiskSpace::of(Path::new("/definitely/not/here")).is_err());
+    }
+}
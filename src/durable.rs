@@ -0,0 +1,47 @@
+use std::path::Path;
+
+use super::config;
+use super::error::FolioError;
+
+/// With `durable_writes`, flush the file at `path` and the directory entry
+/// naming it to stable storage, so a success response survives a power
+/// loss. Each call costs two `fsync`s.
+pub async fn persist(config: &config::Folio, path: &Path) -> Result<(), FolioError> {
+    if !config.durable_writes {
+        return Ok(());
+    }
+    sync_file_and_dir(path).await.map_err(|e| {
+        log::error!("failed to sync {} to disk: {}", path.display(), e);
+        FolioError::write_error(&e, path, "sync file to disk")
+    })
+}
+
+async fn sync_file_and_dir(path: &Path) -> std::io::Result<()> {
+    tokio::fs::File::open(path).await?.sync_all().await?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    tokio::fs::File::open(parent).await?.sync_all().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn syncs_only_when_enabled() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("a.txt");
+        std::fs::write(&path, "content").unwrap();
+        let mut config = config::Folio::default();
+
+        let missing = temp_dir.path().join("missing.txt");
+        persist(&config, &missing).await.unwrap();
+
+        config.durable_writes = true;
+        persist(&config, &path).await.unwrap();
+        let err = persist(&config, &missing).await.unwrap_err();
+        assert_eq!(err.code(), "INTERNAL");
+    }
+}
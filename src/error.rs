@@ -1,5 +1,6 @@
 use actix_web::{HttpResponse, ResponseError, http::StatusCode};
 use serde::Serialize;
+use std::path::Path;
 
 /// Unified error type for all Folio operations.
 ///
@@ -10,21 +11,87 @@ pub enum FolioError {
     Unauthorized {
         reason: String,
     },
+    /// Like `Unauthorized`, but prompts HTTP clients for Basic credentials.
+    PasswordRequired {
+        path: String,
+    },
     NotFound {
         path: String,
     },
+    /// The file expired: it may still be restored in its grace period, or
+    /// was deleted recently enough to have a tombstone.
+    Expired {
+        path: String,
+    },
     Forbidden {
         reason: String,
     },
     Conflict {
         path: String,
     },
+    AliasTaken {
+        alias: String,
+    },
+    /// A part of a chunked upload was sent twice.
+    PartExists {
+        id: String,
+        part: u32,
+    },
+    /// `If-Match` no longer matches the file.
+    PreconditionFailed {
+        path: String,
+    },
+    /// A delete without confirmation under `require_delete_confirmation`.
+    ConfirmationRequired {
+        path: String,
+    },
     BadRequest {
         reason: String,
     },
+    /// A user-supplied path that `SafePath` refuses (`..`, absolute, ...).
+    InvalidPath {
+        reason: String,
+    },
     PayloadTooLarge {
         reason: String,
     },
+    UnsupportedMediaType {
+        content_type: String,
+    },
+    /// Rejected by `allowed_extensions` or `blocked_extensions`.
+    ExtensionNotAllowed {
+        extension: String,
+    },
+    UnprocessableEntity {
+        reason: String,
+    },
+    /// Out of space, or a configured storage limit was hit; `limit` names
+    /// the setting, e.g. `quota_bytes`.
+    InsufficientStorage {
+        reason: String,
+        limit: Option<&'static str>,
+    },
+    ServiceUnavailable {
+        reason: String,
+        retry_after_secs: u64,
+    },
+    /// The client already has `max_pending_uploads_per_client` unfinished
+    /// uploads (chunked or staged).
+    TooManyPendingUploads {
+        limit: usize,
+        retry_after_secs: u64,
+    },
+    /// Writes are paused by `POST /admin/maintenance`.
+    Maintenance {
+        message: String,
+        retry_after_secs: u64,
+    },
+    /// Shed by a limiter (e.g. `max_concurrent_uploads`); clients should back
+    /// off for `retry_after_secs` before retrying.
+    Throttled {
+        reason: String,
+        retry_after_secs: u64,
+    },
     Internal {
         source: String,
         context: Option<String>,
@@ -34,12 +101,26 @@ pub enum FolioError {
 impl FolioError {
     pub fn status(&self) -> StatusCode {
         match self {
-            Self::Unauthorized { .. } => StatusCode::UNAUTHORIZED,
+            Self::Unauthorized { .. } | Self::PasswordRequired { .. } => StatusCode::UNAUTHORIZED,
             Self::NotFound { .. } => StatusCode::NOT_FOUND,
+            Self::Expired { .. } => StatusCode::GONE,
             Self::Forbidden { .. } => StatusCode::FORBIDDEN,
-            Self::Conflict { .. } => StatusCode::CONFLICT,
-            Self::BadRequest { .. } => StatusCode::BAD_REQUEST,
+            Self::Conflict { .. } | Self::AliasTaken { .. } | Self::PartExists { .. } => {
+                StatusCode::CONFLICT
+            }
+            Self::PreconditionFailed { .. } => StatusCode::PRECONDITION_FAILED,
+            Self::ConfirmationRequired { .. } => StatusCode::PRECONDITION_REQUIRED,
+            Self::BadRequest { .. } | Self::InvalidPath { .. } => StatusCode::BAD_REQUEST,
             Self::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::UnsupportedMediaType { .. } | Self::ExtensionNotAllowed { .. } => {
+                StatusCode::UNSUPPORTED_MEDIA_TYPE
+            }
+            Self::UnprocessableEntity { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::InsufficientStorage { .. } => StatusCode::INSUFFICIENT_STORAGE,
+            Self::TooManyPendingUploads { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::ServiceUnavailable { .. } | Self::Maintenance { .. } | Self::Throttled { .. } => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
             Self::Internal { .. } => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -47,11 +128,43 @@ impl FolioError {
     pub fn message(&self) -> String {
         match self {
             Self::Unauthorized { reason } => reason.clone(),
+            Self::PasswordRequired { path } => format!("password required: {}", path),
             Self::NotFound { path } => format!("file not found: {}", path),
+            Self::Expired { path } => format!("file expired: {}", path),
             Self::Forbidden { reason } => reason.clone(),
             Self::Conflict { path } => format!("file already exists: {}", path),
-            Self::BadRequest { reason } => reason.clone(),
+            Self::AliasTaken { alias } => format!("alias already in use: {}", alias),
+            Self::PartExists { id, part } => {
+                format!("part {} of upload {} was already received", part, id)
+            }
+            Self::PreconditionFailed { path } => {
+                format!("file changed since it was read: {}", path)
+            }
+            Self::ConfirmationRequired { path } => format!(
+                "deleting requires ?confirm={} or X-Confirm-Delete: true",
+                path
+            ),
+            Self::BadRequest { reason } | Self::InvalidPath { reason } => reason.clone(),
             Self::PayloadTooLarge { reason } => reason.clone(),
+            Self::UnsupportedMediaType { content_type } => {
+                format!("content type not allowed: {}", content_type)
+            }
+            Self::ExtensionNotAllowed { extension } if extension.is_empty() => {
+                "files without an extension are not allowed".to_string()
+            }
+            Self::ExtensionNotAllowed { extension } => {
+                format!("file extension not allowed: .{}", extension)
+            }
+            Self::UnprocessableEntity { reason } => reason.clone(),
+            Self::InsufficientStorage { reason, .. } => reason.clone(),
+            Self::TooManyPendingUploads { limit, .. } => format!(
+                "too many unfinished uploads from this client (limit {}): complete or commit one first",
+                limit
+            ),
+            Self::ServiceUnavailable { reason, .. } | Self::Throttled { reason, .. } => {
+                reason.clone()
+            }
+            Self::Maintenance { message, .. } => message.clone(),
             Self::Internal { source, context } => match context {
                 Some(ctx) => format!("{}: {}", ctx, source),
                 None => source.clone(),
@@ -59,6 +172,79 @@ impl FolioError {
         }
     }
 
+    /// Stable machine-readable code for clients to branch on; unlike
+    /// `message`, never reworded.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Unauthorized { .. } => "UNAUTHORIZED",
+            Self::PasswordRequired { .. } => "PASSWORD_REQUIRED",
+            Self::NotFound { .. } => "NOT_FOUND",
+            Self::Expired { .. } => "EXPIRED",
+            Self::Forbidden { .. } => "FORBIDDEN",
+            Self::Conflict { .. } => "FILE_EXISTS",
+            Self::AliasTaken { .. } => "ALIAS_TAKEN",
+            Self::PartExists { .. } => "PART_EXISTS",
+            Self::PreconditionFailed { .. } => "PRECONDITION_FAILED",
+            Self::ConfirmationRequired { .. } => "CONFIRMATION_REQUIRED",
+            Self::BadRequest { .. } => "BAD_REQUEST",
+            Self::InvalidPath { .. } => "INVALID_PATH",
+            Self::PayloadTooLarge { .. } => "PAYLOAD_TOO_LARGE",
+            Self::UnsupportedMediaType { .. } => "UNSUPPORTED_MEDIA_TYPE",
+            Self::ExtensionNotAllowed { .. } => "EXTENSION_NOT_ALLOWED",
+            Self::UnprocessableEntity { .. } => "UNPROCESSABLE_ENTITY",
+            Self::InsufficientStorage { .. } => "INSUFFICIENT_STORAGE",
+            Self::TooManyPendingUploads { .. } => "TOO_MANY_PENDING_UPLOADS",
+            Self::ServiceUnavailable { .. } => "SERVICE_UNAVAILABLE",
+            Self::Maintenance { .. } => "MAINTENANCE",
+            Self::Throttled { .. } => "RATE_LIMITED",
+            Self::Internal { .. } => "INTERNAL",
+        }
+    }
+
+    /// Seconds the client should wait before retrying, sent as `Retry-After`
+    /// and `retry_after`.
+    pub fn retry_after_secs(&self) -> Option<u64> {
+        match self {
+            Self::ServiceUnavailable {
+                retry_after_secs, ..
+            }
+            | Self::Maintenance {
+                retry_after_secs, ..
+            }
+            | Self::Throttled {
+                retry_after_secs, ..
+            }
+            | Self::TooManyPendingUploads {
+                retry_after_secs, ..
+            } => Some(*retry_after_secs),
+            _ => None,
+        }
+    }
+
+    /// The setting whose limit was hit, sent as `limit`.
+    pub fn limit(&self) -> Option<&'static str> {
+        match self {
+            Self::InsufficientStorage { limit, .. } => *limit,
+            _ => None,
+        }
+    }
+
+    /// Map a failed write to `path`: running out of space, inodes or quota
+    /// becomes `InsufficientStorage` naming the cause, anything else an
+    /// internal error with context.
+    pub fn write_error(error: &std::io::Error, path: &Path, context: &str) -> Self {
+        match crate::disk::out_of_space_reason(error, path) {
+            Some(reason) => Self::InsufficientStorage {
+                reason: format!("failed to {}: {}", context, reason),
+                limit: None,
+            },
+            None => Self::Internal {
+                source: format!("failed to {}: {}", context, error),
+                context: Some(format!("{}: {}", context, path.display())),
+            },
+        }
+    }
+
     /// Convert a `Result<T, String>` from a store into an internal error with context.
     pub fn store_error(source: String, context: &str) -> Self {
         Self::Internal {
@@ -70,7 +256,12 @@ impl FolioError {
 
 #[derive(Serialize)]
 struct ErrorResponse {
+    code: &'static str,
     message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<&'static str>,
 }
 
 impl std::fmt::Display for FolioError {
@@ -85,8 +276,76 @@ impl ResponseError for FolioError {
     }
 
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status()).json(ErrorResponse {
+        let mut response = HttpResponse::build(self.status());
+        if let Self::PasswordRequired { .. } = self {
+            response.append_header(("WWW-Authenticate", "Basic realm=\"folio\""));
+        }
+        let retry_after = self.retry_after_secs();
+        if let Some(secs) = retry_after {
+            response.append_header(("Retry-After", secs.to_string()));
+        }
+        response.json(ErrorResponse {
+            code: self.code(),
             message: self.message(),
+            retry_after,
+            limit: self.limit(),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path::SafePath;
+
+    #[actix_web::test]
+    async fn error_body_carries_code_and_message() {
+        let err = SafePath::from_user_input(Path::new("../etc/passwd")).unwrap_err();
+        let response = err.error_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "INVALID_PATH");
+        assert_eq!(body["message"], "path contains '..': ../etc/passwd");
+        assert!(body.get("retry_after").is_none());
+    }
+
+    #[test]
+    fn full_disk_is_insufficient_storage() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("a1B2c3D4.txt");
+
+        let full = std::io::Error::from_raw_os_error(libc::ENOSPC);
+        let err = FolioError::write_error(&full, &path, "save file");
+        assert_eq!(err.status(), StatusCode::INSUFFICIENT_STORAGE);
+        assert_eq!(
+            err.message(),
+            "failed to save file: no space left on device"
+        );
+
+        let denied = std::io::Error::from_raw_os_error(libc::EACCES);
+        let err = FolioError::write_error(&denied, &path, "save file");
+        assert_eq!(err.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[actix_web::test]
+    async fn throttled_responses_carry_retry_after() {
+        let err = FolioError::Throttled {
+            reason: "too many concurrent uploads, retry later".to_string(),
+            retry_after_secs: 7,
+        };
+        let response = err.error_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get("Retry-After").unwrap(), "7");
+
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "RATE_LIMITED");
+        assert_eq!(body["retry_after"], 7);
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{Method, header};
+use actix_web::middleware::Next;
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, get, web};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use super::admin::require_admin;
+use super::audit::resolved_path;
+use super::auth::{AccessAuth, AccessIdentity};
+use super::config;
+use super::error::FolioError;
+
+/// Events kept for subscribers that connect later.
+const RECENT_EVENTS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    /// A file was stored by `/uploads` or `POST`/`PUT /files`.
+    Upload,
+    Delete,
+    /// The expiry sweeper removed a file.
+    Expire,
+}
+
+impl EventKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Upload => "upload",
+            Self::Delete => "delete",
+            Self::Expire => "expire",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub timestamp_unix_ms: u128,
+    pub kind: EventKind,
+    /// Relative to the uploads root.
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+}
+
+impl Event {
+    pub fn new(kind: EventKind, path: impl Into<String>) -> Self {
+        Self {
+            timestamp_unix_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis())
+                .unwrap_or(0),
+            kind,
+            path: path.into(),
+            identity: None,
+        }
+    }
+
+    /// One Server-Sent Events message.
+    fn to_sse(&self) -> web::Bytes {
+        let data = serde_json::to_string(self).unwrap_or_default();
+        web::Bytes::from(format!("event: {}\ndata: {}\n\n", self.kind.as_str(), data))
+    }
+}
+
+/// The last `RECENT_EVENTS` events, and a live feed of new ones for
+/// `GET /admin/events`. In memory only.
+pub struct EventBus {
+    recent: Mutex<VecDeque<Event>>,
+    tx: broadcast::Sender<Event>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (tx, _) = broadcast::channel(RECENT_EVENTS);
+        Self {
+            recent: Mutex::new(VecDeque::with_capacity(RECENT_EVENTS)),
+            tx,
+        }
+    }
+}
+
+impl EventBus {
+    pub fn publish(&self, event: Event) {
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == RECENT_EVENTS {
+            recent.pop_front();
+        }
+        recent.push_back(event.clone());
+        // Sent under the lock so `subscribe` never sees an event twice.
+        // Having no subscribers is not an error.
+        let _ = self.tx.send(event);
+    }
+
+    /// Recent events plus a receiver for everything published after them.
+    fn subscribe(&self) -> (VecDeque<Event>, broadcast::Receiver<Event>) {
+        let recent = self.recent.lock().unwrap();
+        (recent.clone(), self.tx.subscribe())
+    }
+}
+
+/// Publish an event for every successful upload, write and delete.
+///
+/// Wrapped around the whole app, like `audit`, and keyed on route patterns so
+/// other mutating routes (aliases, expiration changes) are left out.
+pub async fn publish_events(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let Some(bus) = req.app_data::<web::Data<Arc<EventBus>>>().cloned() else {
+        return next.call(req).await;
+    };
+
+    let res = next.call(req).await?;
+    if let Some(kind) = event_kind(&res)
+        && let Some(path) = resolved_path(&res)
+    {
+        let identity = res
+            .request()
+            .extensions()
+            .get::<AccessIdentity>()
+            .map(|id| id.email.clone().unwrap_or_else(|| id.sub.clone()));
+        bus.publish(Event {
+            identity,
+            ..Event::new(kind, path)
+        });
+    }
+    Ok(res)
+}
+
+fn event_kind<B>(res: &ServiceResponse<B>) -> Option<EventKind> {
+    if !res.status().is_success() {
+        return None;
+    }
+    let req = res.request();
+    match (req.method(), req.match_pattern()?.as_str()) {
+        (&Method::POST, "/uploads") => Some(EventKind::Upload),
+        (&Method::POST, "/uploads/{id:.*}/commit") => Some(EventKind::Upload),
+        (&Method::POST | &Method::PUT, "/files/{path:.*}") => Some(EventKind::Upload),
+        (&Method::DELETE, "/files/{path:.*}") => Some(EventKind::Delete),
+        _ => None,
+    }
+}
+
+/// Live view of uploads, deletes and expirations as Server-Sent Events,
+/// starting with the most recent ones.
+#[get("/admin/events")]
+pub async fn stream_events(
+    req: HttpRequest,
+    config: web::Data<config::Folio>,
+    access_auth: web::Data<Arc<AccessAuth>>,
+    bus: web::Data<Arc<EventBus>>,
+) -> Result<HttpResponse, FolioError> {
+    require_admin(&req, &access_auth, &config).await?;
+
+    let body = futures_util::stream::unfold(bus.subscribe(), |(mut backlog, mut rx)| async move {
+        let chunk = match backlog.pop_front() {
+            Some(event) => event.to_sse(),
+            None => match rx.recv().await {
+                Ok(event) => event.to_sse(),
+                // A comment line, so the client knows it missed some.
+                Err(RecvError::Lagged(skipped)) => {
+                    web::Bytes::from(format!(": {} events skipped\n\n", skipped))
+                }
+                Err(RecvError::Closed) => return None,
+            },
+        };
+        Some((Ok::<_, actix_web::Error>(chunk), (backlog, rx)))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::middleware::from_fn;
+    use actix_web::{App, delete, post, test as awtest};
+
+    #[delete("/files/{path:.*}")]
+    async fn delete_stub() -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    #[post("/files/{path:.*}/alias")]
+    async fn other_stub() -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    #[actix_web::test]
+    async fn publishes_successful_deletes_only() {
+        let bus = Arc::new(EventBus::default());
+        let (_, mut rx) = bus.subscribe();
+        let app = awtest::init_service(
+            App::new()
+                .wrap(from_fn(publish_events))
+                .app_data(web::Data::new(bus.clone()))
+                .service(other_stub)
+                .service(delete_stub),
+        )
+        .await;
+
+        let req = awtest::TestRequest::post()
+            .uri("/files/docs/a.txt/alias")
+            .to_request();
+        awtest::call_service(&app, req).await;
+        let req = awtest::TestRequest::delete()
+            .uri("/files/docs/a.txt")
+            .to_request();
+        awtest::call_service(&app, req).await;
+
+        let event = rx.try_recv().unwrap();
+        assert_eq!(event.kind, EventKind::Delete);
+        assert_eq!(event.path, "docs/a.txt");
+        assert!(rx.try_recv().is_err());
+        assert_eq!(bus.subscribe().0.len(), 1);
+    }
+
+    #[test]
+    fn recent_events_are_bounded() {
+        let bus = EventBus::default();
+        for i in 0..RECENT_EVENTS + 5 {
+            bus.publish(Event::new(EventKind::Upload, format!("{}.txt", i)));
+        }
+        let (recent, _) = bus.subscribe();
+        assert_eq!(recent.len(), RECENT_EVENTS);
+        assert_eq!(recent[0].path, "5.txt");
+        assert!(String::from_utf8_lossy(&recent[0].to_sse()).starts_with("event: upload\ndata: {"));
+    }
+}
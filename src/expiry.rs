@@ -1,25 +1,161 @@
+use std::collections::{BTreeMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use rand::RngExt;
 use serde::{Deserialize, Serialize};
 
+use super::alias::AliasStore;
 use super::config;
+use super::events::{Event, EventBus, EventKind};
+use super::meta_file;
+use super::parts::{abandoned_upload_age, parts_root, remove_abandoned};
+use super::staging::staging_root;
 use super::store::JsonFileStore;
+use super::uploads::parse_duration;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct ExpiryEntry {
     path: String,
     expire_at_unix: u64,
+    /// Set by the sweeper once the file expired with `expire_grace_period`:
+    /// until then the file is hidden but kept, so it can be restored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delete_at_unix: Option<u64>,
+}
+
+/// An expired file the sweeper deleted, remembered for `tombstone_ttl` so
+/// requests for it get `410 Gone` rather than `404`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Tombstone {
+    path: String,
+    deleted_at_unix: u64,
+    forget_at_unix: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 struct ExpiryIndex {
     entries: Vec<ExpiryEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tombstones: Vec<Tombstone>,
+}
+
+/// Point-in-time view of the background sweeper, reported by `/health`.
+#[derive(Debug, Serialize)]
+pub struct SweeperStatus {
+    pub running: bool,
+    pub healthy: bool,
+    pub interval_secs: u64,
+    pub started_at_unix: Option<u64>,
+    pub last_sweep_at_unix: Option<u64>,
+    pub last_sweep_ok: bool,
+    /// Times the sweeper crashed and was restarted.
+    pub restarts: u64,
+}
+
+#[derive(Default)]
+struct SweeperState {
+    running: AtomicBool,
+    interval_secs: AtomicU64,
+    started_at_unix: AtomicU64,
+    last_sweep_at_unix: AtomicU64,
+    last_sweep_ok: AtomicBool,
+    restarts: AtomicU64,
+}
+
+/// Counts of what the sweeper did since startup, exported at `/metrics`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExpiryMetrics {
+    pub sweeps_ok: u64,
+    pub sweeps_failed: u64,
+    /// Expired files removed from disk.
+    pub deleted: u64,
+    /// Expired files that were already gone (deleted by hand, moved, ...).
+    pub already_absent: u64,
+    /// Failed deletions by `std::io::ErrorKind`.
+    pub delete_failures: BTreeMap<String, u64>,
 }
 
+#[derive(Default)]
+struct ExpiryCounters {
+    sweeps_ok: AtomicU64,
+    sweeps_failed: AtomicU64,
+    deleted: AtomicU64,
+    already_absent: AtomicU64,
+    delete_failures: Mutex<BTreeMap<String, u64>>,
+}
+
+fn expire_jitter(config: &config::Folio) -> Duration {
+    if config.expire_jitter.is_empty() {
+        return Duration::ZERO;
+    }
+    parse_duration(&config.expire_jitter)
+        .inspect_err(|e| {
+            log::warn!(
+                "invalid expire_jitter '{}': {}, expirations are not spread",
+                config.expire_jitter,
+                e
+            )
+        })
+        .unwrap_or_default()
+}
+
+fn expire_grace_period(config: &config::Folio) -> Duration {
+    if config.expire_grace_period.is_empty() {
+        return Duration::ZERO;
+    }
+    parse_duration(&config.expire_grace_period)
+        .inspect_err(|e| {
+            log::warn!(
+                "invalid expire_grace_period '{}': {}, expired files are deleted right away",
+                config.expire_grace_period,
+                e
+            )
+        })
+        .unwrap_or_default()
+}
+
+fn tombstone_ttl(config: &config::Folio) -> Duration {
+    if config.tombstone_ttl.is_empty() {
+        return Duration::ZERO;
+    }
+    parse_duration(&config.tombstone_ttl)
+        .inspect_err(|e| {
+            log::warn!(
+                "invalid tombstone_ttl '{}': {}, expired files are not remembered",
+                config.tombstone_ttl,
+                e
+            )
+        })
+        .unwrap_or_default()
+}
+
+/// Longest wait between restarts of a crashing sweeper.
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(60);
+
+type SweepFn = fn(&ExpiryStore, &tokio::runtime::Runtime) -> Result<(), String>;
+
 pub struct ExpiryStore {
     uploads_root: PathBuf,
+    /// Staged uploads expire from here; see `staging::stage_upload`.
+    staging_root: PathBuf,
+    /// Chunked uploads idle for `abandoned_upload_age` are dropped from here.
+    parts_root: PathBuf,
+    abandoned_upload_age: Option<Duration>,
     store: JsonFileStore<ExpiryIndex>,
+    /// `expire_jitter`; zero when unset or invalid.
+    jitter: Duration,
+    /// `expire_grace_period`; zero when unset or invalid.
+    grace: Duration,
+    /// `tombstone_ttl`; zero when unset or invalid.
+    tombstone_ttl: Duration,
+    sweeper: SweeperState,
+    counters: ExpiryCounters,
+    /// Aliases of expired files are dropped along with them.
+    aliases: Option<Arc<AliasStore>>,
+    events: Option<Arc<EventBus>>,
 }
 
 impl ExpiryStore {
@@ -29,12 +165,88 @@ impl ExpiryStore {
 
         Self {
             uploads_root,
+            staging_root: staging_root(config),
+            parts_root: parts_root(config),
+            abandoned_upload_age: abandoned_upload_age(config),
             store: JsonFileStore::new(index_path),
+            jitter: expire_jitter(config),
+            grace: expire_grace_period(config),
+            tombstone_ttl: tombstone_ttl(config),
+            sweeper: SweeperState::default(),
+            counters: ExpiryCounters::default(),
+            aliases: None,
+            events: None,
+        }
+    }
+
+    pub fn with_aliases(mut self, aliases: Arc<AliasStore>) -> Self {
+        self.aliases = Some(aliases);
+        self
+    }
+
+    pub fn with_events(mut self, events: Arc<EventBus>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Number of files currently waiting to expire.
+    pub async fn pending_count(&self) -> Result<usize, String> {
+        let _guard = self.store.lock().await?;
+        Ok(self.store.load().await?.entries.len())
+    }
+
+    /// The sweeper is healthy once it is running and has swept (successfully)
+    /// within the last two intervals.
+    pub fn sweeper_status(&self) -> SweeperStatus {
+        let unix = |value: u64| (value != 0).then_some(value);
+        let running = self.sweeper.running.load(Ordering::Relaxed);
+        let interval_secs = self.sweeper.interval_secs.load(Ordering::Relaxed);
+        let started_at_unix = unix(self.sweeper.started_at_unix.load(Ordering::Relaxed));
+        let last_sweep_at_unix = unix(self.sweeper.last_sweep_at_unix.load(Ordering::Relaxed));
+        let last_sweep_ok = self.sweeper.last_sweep_ok.load(Ordering::Relaxed);
+
+        let deadline = interval_secs.saturating_mul(2);
+        let last_activity = last_sweep_at_unix.or(started_at_unix).unwrap_or(0);
+        let healthy = running
+            && now_unix_secs().saturating_sub(last_activity) <= deadline
+            && (last_sweep_at_unix.is_none() || last_sweep_ok);
+
+        SweeperStatus {
+            running,
+            healthy,
+            interval_secs,
+            started_at_unix,
+            last_sweep_at_unix,
+            last_sweep_ok,
+            restarts: self.sweeper.restarts.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn metrics(&self) -> ExpiryMetrics {
+        ExpiryMetrics {
+            sweeps_ok: self.counters.sweeps_ok.load(Ordering::Relaxed),
+            sweeps_failed: self.counters.sweeps_failed.load(Ordering::Relaxed),
+            deleted: self.counters.deleted.load(Ordering::Relaxed),
+            already_absent: self.counters.already_absent.load(Ordering::Relaxed),
+            delete_failures: self.counters.delete_failures.lock().unwrap().clone(),
         }
     }
 
-    pub async fn schedule(&self, path: &Path, ttl: Duration) -> Result<(), String> {
-        if !path.starts_with(&self.uploads_root) {
+    /// Whether `path` may be expired: it is below the uploads root or the
+    /// staging area.
+    fn is_managed(&self, path: &Path) -> bool {
+        path.starts_with(&self.uploads_root) || path.starts_with(&self.staging_root)
+    }
+
+    /// Schedule `path` for deletion after `ttl` plus a random share of
+    /// `expire_jitter`, replacing any previous schedule for it. Returns the
+    /// absolute expiry as a Unix timestamp.
+    ///
+    /// The jitter is drawn once here and stored with the entry, never
+    /// recomputed: restarts and later sweeps see the same expiry, and the
+    /// value returned to the client stays true.
+    pub async fn schedule(&self, path: &Path, ttl: Duration) -> Result<u64, String> {
+        if !self.is_managed(path) {
             return Err(format!(
                 "refuse to schedule path outside uploads root: {}",
                 path.display()
@@ -44,41 +256,242 @@ impl ExpiryStore {
         let _guard = self.store.lock().await?;
         let mut index = self.store.load().await?;
         let normalized = path.to_string_lossy().to_string();
+        let jitter = rand::rng().random_range(0..=self.jitter.as_secs());
+        let expire_at_unix = now_unix_secs()
+            .saturating_add(ttl.as_secs())
+            .saturating_add(jitter);
         index.entries.retain(|entry| entry.path != normalized);
+        index
+            .tombstones
+            .retain(|tombstone| tombstone.path != normalized);
         index.entries.push(ExpiryEntry {
             path: normalized,
-            expire_at_unix: now_unix_secs().saturating_add(ttl.as_secs()),
+            expire_at_unix,
+            delete_at_unix: None,
         });
 
+        self.store.save(&index).await?;
+        Ok(expire_at_unix)
+    }
+
+    /// When `path` is scheduled to expire, as a Unix timestamp.
+    pub async fn expire_at(&self, path: &Path) -> Result<Option<u64>, String> {
+        let _guard = self.store.lock().await?;
+        let index = self.store.load().await?;
+        let normalized = path.to_string_lossy().to_string();
+
+        Ok(index
+            .entries
+            .iter()
+            .find(|entry| entry.path == normalized)
+            .map(|entry| entry.expire_at_unix))
+    }
+
+    /// Whether expired files are kept for `expire_grace_period` first.
+    pub fn has_grace_period(&self) -> bool {
+        !self.grace.is_zero()
+    }
+
+    /// When `path`, expired and in its grace period, will be deleted.
+    pub async fn delete_at(&self, path: &Path) -> Result<Option<u64>, String> {
+        let _guard = self.store.lock().await?;
+        let index = self.store.load().await?;
+        let normalized = path.to_string_lossy().to_string();
+
+        Ok(index
+            .entries
+            .iter()
+            .find(|entry| entry.path == normalized)
+            .and_then(|entry| entry.delete_at_unix))
+    }
+
+    /// Whether deleted expired files are remembered for `tombstone_ttl`.
+    pub fn keeps_tombstones(&self) -> bool {
+        !self.tombstone_ttl.is_zero()
+    }
+
+    /// When `path` was deleted by the sweeper, if that was less than
+    /// `tombstone_ttl` ago.
+    pub async fn deleted_at(&self, path: &Path) -> Result<Option<u64>, String> {
+        let _guard = self.store.lock().await?;
+        let index = self.store.load().await?;
+        let normalized = path.to_string_lossy().to_string();
+        let now = now_unix_secs();
+
+        Ok(index
+            .tombstones
+            .iter()
+            .find(|tombstone| tombstone.path == normalized && tombstone.forget_at_unix > now)
+            .map(|tombstone| tombstone.deleted_at_unix))
+    }
+
+    /// Files that expired and are waiting out their grace period.
+    pub async fn in_grace_period(&self) -> Result<HashSet<PathBuf>, String> {
+        let _guard = self.store.lock().await?;
+        let index = self.store.load().await?;
+
+        Ok(index
+            .entries
+            .into_iter()
+            .filter(|entry| entry.delete_at_unix.is_some())
+            .map(|entry| PathBuf::from(entry.path))
+            .collect())
+    }
+
+    /// Carry the schedule of `from` over to `to`, keeping its expiry time.
+    pub async fn rename(&self, from: &Path, to: &Path) -> Result<(), String> {
+        if !to.starts_with(&self.uploads_root) {
+            return Err(format!(
+                "refuse to schedule path outside uploads root: {}",
+                to.display()
+            ));
+        }
+
+        let _guard = self.store.lock().await?;
+        let mut index = self.store.load().await?;
+        let from = from.to_string_lossy().to_string();
+        let to = to.to_string_lossy().to_string();
+
+        index.entries.retain(|entry| entry.path != to);
+        if let Some(entry) = index.entries.iter_mut().find(|entry| entry.path == from) {
+            entry.path = to;
+        }
+        self.store.save(&index).await
+    }
+
+    /// Forget the schedule for `path`, if any.
+    pub async fn cancel(&self, path: &Path) -> Result<(), String> {
+        let _guard = self.store.lock().await?;
+        let mut index = self.store.load().await?;
+        let normalized = path.to_string_lossy().to_string();
+
+        let before = index.entries.len();
+        index.entries.retain(|entry| entry.path != normalized);
+        if index.entries.len() == before {
+            return Ok(());
+        }
+
         self.store.save(&index).await
     }
 
-    pub fn spawn_sweeper(self: std::sync::Arc<Self>, interval: Duration) {
+    /// Forget the schedules of all uploads whose path below the uploads root
+    /// starts with `prefix`, or with `dry_run` only find them. Returns their
+    /// full paths.
+    pub async fn cancel_prefix(&self, prefix: &str, dry_run: bool) -> Result<Vec<PathBuf>, String> {
+        let _guard = self.store.lock().await?;
+        let mut index = self.store.load().await?;
+
+        let matches = |entry: &ExpiryEntry| {
+            Path::new(&entry.path)
+                .strip_prefix(&self.uploads_root)
+                .is_ok_and(|relative| relative.to_string_lossy().starts_with(prefix))
+        };
+        let matched: Vec<PathBuf> = index
+            .entries
+            .iter()
+            .filter(|entry| matches(entry))
+            .map(|entry| PathBuf::from(&entry.path))
+            .collect();
+        if dry_run || matched.is_empty() {
+            return Ok(matched);
+        }
+
+        index.entries.retain(|entry| !matches(entry));
+        self.store.save(&index).await?;
+        Ok(matched)
+    }
+
+    pub fn spawn_sweeper(self: Arc<Self>, interval: Duration) {
+        self.spawn_supervised(interval, |store, rt| rt.block_on(store.sweep_once()));
+    }
+
+    /// Run `sweep` every `interval` on a background thread, restarting it
+    /// with exponential backoff whenever it panics, so a transient fault
+    /// can't stop expirations for good. `running` is false while it waits.
+    fn spawn_supervised(self: Arc<Self>, interval: Duration, sweep: SweepFn) {
+        self.sweeper
+            .interval_secs
+            .store(interval.as_secs(), Ordering::Relaxed);
+        self.sweeper
+            .started_at_unix
+            .store(now_unix_secs(), Ordering::Relaxed);
+        self.sweeper.running.store(true, Ordering::Relaxed);
+
         std::thread::spawn(move || {
-            let rt = tokio::runtime::Runtime::new().unwrap();
+            let base_backoff = interval.clamp(Duration::from_millis(1), Duration::from_secs(1));
+            let mut backoff = base_backoff;
             loop {
-                std::thread::sleep(interval);
-                if let Err(err) = rt.block_on(self.sweep_once()) {
-                    log::error!("expiry sweep failed: {}", err);
+                self.sweeper.running.store(true, Ordering::Relaxed);
+                let started = std::time::Instant::now();
+                let reason = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    self.run_sweeps(interval, sweep)
+                }))
+                .unwrap_or_else(|panic| {
+                    panic
+                        .downcast_ref::<&str>()
+                        .map(|s| s.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| "panic".to_string())
+                });
+
+                self.sweeper.running.store(false, Ordering::Relaxed);
+                self.sweeper.last_sweep_ok.store(false, Ordering::Relaxed);
+                self.sweeper.restarts.fetch_add(1, Ordering::Relaxed);
+                // A sweeper that ran for a while before failing starts over.
+                if started.elapsed() > MAX_RESTART_BACKOFF {
+                    backoff = base_backoff;
                 }
+                log::error!(
+                    "expiry sweeper stopped: {}, restarting in {:?}",
+                    reason,
+                    backoff
+                );
+                std::thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_RESTART_BACKOFF);
             }
         });
     }
 
+    /// Sweep forever; returns only if the runtime can't be started.
+    fn run_sweeps(&self, interval: Duration, sweep: SweepFn) -> String {
+        let rt = match tokio::runtime::Runtime::new() {
+            Ok(rt) => rt,
+            Err(e) => return format!("failed to start runtime: {}", e),
+        };
+        loop {
+            std::thread::sleep(interval);
+            let result = sweep(self, &rt);
+            let outcome = match &result {
+                Ok(()) => &self.counters.sweeps_ok,
+                Err(err) => {
+                    log::error!("expiry sweep failed: {}", err);
+                    &self.counters.sweeps_failed
+                }
+            };
+            outcome.fetch_add(1, Ordering::Relaxed);
+            self.sweeper
+                .last_sweep_ok
+                .store(result.is_ok(), Ordering::Relaxed);
+            self.sweeper
+                .last_sweep_at_unix
+                .store(now_unix_secs(), Ordering::Relaxed);
+        }
+    }
+
     async fn sweep_once(&self) -> Result<(), String> {
         let _guard = self.store.lock().await?;
         let mut index = self.store.load().await?;
         let now = now_unix_secs();
 
         let mut kept = Vec::with_capacity(index.entries.len());
-        for entry in index.entries {
+        for mut entry in index.entries {
             if entry.expire_at_unix > now {
                 kept.push(entry);
                 continue;
             }
 
             let target = PathBuf::from(&entry.path);
-            if !target.starts_with(&self.uploads_root) {
+            if !self.is_managed(&target) {
                 log::warn!(
                     "skip deleting out-of-root path from expiry index: {}",
                     entry.path
@@ -86,22 +499,92 @@ impl ExpiryStore {
                 continue;
             }
 
-            if target.exists() {
-                match tokio::fs::remove_file(&target).await {
-                    Ok(_) => log::info!("expired file deleted: {}", target.display()),
-                    Err(err) => {
-                        log::error!(
-                            "failed to delete expired file {}: {}",
-                            target.display(),
-                            err
-                        )
+            // Expire in two steps: hide the file first, delete it once the
+            // grace period is over.
+            match entry.delete_at_unix {
+                None if !self.grace.is_zero() => {
+                    let delete_at = now.saturating_add(self.grace.as_secs());
+                    log::info!(
+                        "file expired, deleting it at {} unless restored: {}",
+                        delete_at,
+                        target.display()
+                    );
+                    entry.delete_at_unix = Some(delete_at);
+                    kept.push(entry);
+                    continue;
+                }
+                Some(delete_at) if delete_at > now => {
+                    kept.push(entry);
+                    continue;
+                }
+                _ => {}
+            }
+
+            match tokio::fs::remove_file(&target).await {
+                Ok(_) => {
+                    log::info!("expired file deleted: {}", target.display());
+                    meta_file::remove(&target);
+                    self.counters.deleted.fetch_add(1, Ordering::Relaxed);
+                    if !self.tombstone_ttl.is_zero() && target.starts_with(&self.uploads_root) {
+                        index
+                            .tombstones
+                            .retain(|tombstone| tombstone.path != entry.path);
+                        index.tombstones.push(Tombstone {
+                            path: entry.path.clone(),
+                            deleted_at_unix: now,
+                            forget_at_unix: now.saturating_add(self.tombstone_ttl.as_secs()),
+                        });
                     }
+                    if let (Some(events), Ok(relative)) =
+                        (&self.events, target.strip_prefix(&self.uploads_root))
+                    {
+                        events.publish(Event::new(EventKind::Expire, relative.to_string_lossy()));
+                    }
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    log::debug!("expired file already gone: {}", target.display());
+                    self.counters.already_absent.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(err) => {
+                    log::error!(
+                        "failed to delete expired file {}: {}",
+                        target.display(),
+                        err
+                    );
+                    *self
+                        .counters
+                        .delete_failures
+                        .lock()
+                        .unwrap()
+                        .entry(format!("{:?}", err.kind()))
+                        .or_default() += 1;
                 }
             }
+
+            if let (Some(aliases), Ok(relative)) =
+                (&self.aliases, target.strip_prefix(&self.uploads_root))
+                && let Err(err) = aliases.remove_path(relative).await
+            {
+                log::error!(
+                    "failed to drop aliases of expired file {}: {}",
+                    target.display(),
+                    err
+                );
+            }
         }
 
         index.entries = kept;
-        self.store.save(&index).await
+        index
+            .tombstones
+            .retain(|tombstone| tombstone.forget_at_unix > now);
+        self.store.save(&index).await?;
+
+        if let Some(age) = self.abandoned_upload_age
+            && let Err(err) = remove_abandoned(&self.parts_root, age)
+        {
+            log::error!("failed to drop abandoned chunked uploads: {}", err);
+        }
+        Ok(())
     }
 }
 
@@ -118,16 +601,40 @@ mod tests {
 
     fn test_store(temp_dir: &tempfile::TempDir) -> ExpiryStore {
         let config = config::Folio {
-            address: "127.0.0.1".to_string(),
-            port: 8000,
-            web_path: "./web/dist".to_string(),
             uploads_path: temp_dir.path().to_string_lossy().to_string(),
             data_path: temp_dir.path().to_string_lossy().to_string(),
-            max_upload_size: 25 * 1024 * 1024,
+            ..config::Folio::default()
         };
         ExpiryStore::new(&config)
     }
 
+    #[test]
+    fn sweeper_restarts_after_a_panic() {
+        static CALLS: AtomicU64 = AtomicU64::new(0);
+        fn flaky_sweep(_: &ExpiryStore, _: &tokio::runtime::Runtime) -> Result<(), String> {
+            if CALLS.fetch_add(1, Ordering::SeqCst) == 0 {
+                panic!("connection lost");
+            }
+            Ok(())
+        }
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(test_store(&temp_dir));
+        store
+            .clone()
+            .spawn_supervised(Duration::from_millis(10), flaky_sweep);
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while std::time::Instant::now() < deadline {
+            let status = store.sweeper_status();
+            if status.restarts == 1 && status.running && status.last_sweep_ok {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("sweeper did not recover: {:?}", store.sweeper_status());
+    }
+
     #[tokio::test]
     async fn schedule_writes_expiry_index() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -150,6 +657,34 @@ mod tests {
         assert_eq!(index.entries[0].path, file_path.to_string_lossy());
     }
 
+    #[tokio::test]
+    async fn schedule_spreads_expirations_by_jitter() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = config::Folio {
+            uploads_path: temp_dir.path().to_string_lossy().to_string(),
+            data_path: temp_dir.path().to_string_lossy().to_string(),
+            expire_jitter: "1h".to_string(),
+            ..config::Folio::default()
+        };
+        let store = ExpiryStore::new(&config);
+
+        let mut expiries = Vec::new();
+        for i in 0..20 {
+            let file_path = temp_dir.path().join(format!("{}.txt", i));
+            let before = now_unix_secs();
+            let expire_at = store
+                .schedule(&file_path, Duration::from_secs(60))
+                .await
+                .unwrap();
+            assert!(expire_at >= before + 60);
+            assert!(expire_at <= now_unix_secs() + 60 + 3600);
+            assert_eq!(store.expire_at(&file_path).await.unwrap(), Some(expire_at));
+            expiries.push(expire_at);
+        }
+        expiries.dedup();
+        assert!(expiries.len() > 1);
+    }
+
     #[tokio::test]
     async fn sweep_once_deletes_expired_file_and_prunes_entry() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -166,9 +701,122 @@ mod tests {
         store.sweep_once().await.unwrap();
 
         assert!(!file_path.exists());
+        assert_eq!(store.metrics().deleted, 1);
 
         let raw = std::fs::read_to_string(temp_dir.path().join("expiry-index.json")).unwrap();
         let index: ExpiryIndex = serde_json::from_str(&raw).unwrap();
         assert!(index.entries.is_empty());
     }
+
+    #[tokio::test]
+    async fn grace_period_keeps_expired_files_until_it_ends() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = ExpiryStore::new(&config::Folio {
+            uploads_path: temp_dir.path().to_string_lossy().to_string(),
+            data_path: temp_dir.path().to_string_lossy().to_string(),
+            expire_grace_period: "1s".to_string(),
+            ..config::Folio::default()
+        });
+
+        let file_path = temp_dir.path().join("expired.txt");
+        std::fs::write(&file_path, "bye").unwrap();
+        store
+            .schedule(&file_path, Duration::from_secs(0))
+            .await
+            .unwrap();
+        assert!(store.delete_at(&file_path).await.unwrap().is_none());
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        store.sweep_once().await.unwrap();
+        assert!(file_path.exists());
+        assert!(store.delete_at(&file_path).await.unwrap().is_some());
+        assert!(store.in_grace_period().await.unwrap().contains(&file_path));
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        store.sweep_once().await.unwrap();
+        assert!(!file_path.exists());
+        assert_eq!(store.pending_count().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn deleted_files_leave_a_tombstone_for_a_while() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = ExpiryStore::new(&config::Folio {
+            uploads_path: temp_dir.path().to_string_lossy().to_string(),
+            data_path: temp_dir.path().to_string_lossy().to_string(),
+            tombstone_ttl: "1s".to_string(),
+            ..config::Folio::default()
+        });
+
+        let file_path = temp_dir.path().join("expired.txt");
+        std::fs::write(&file_path, "bye").unwrap();
+        store
+            .schedule(&file_path, Duration::from_secs(0))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        store.sweep_once().await.unwrap();
+
+        assert!(!file_path.exists());
+        assert!(store.deleted_at(&file_path).await.unwrap().is_some());
+        assert!(
+            store
+                .deleted_at(&temp_dir.path().join("other.txt"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(store.deleted_at(&file_path).await.unwrap().is_none());
+        store.sweep_once().await.unwrap();
+        let raw = std::fs::read_to_string(temp_dir.path().join("expiry-index.json")).unwrap();
+        let index: ExpiryIndex = serde_json::from_str(&raw).unwrap();
+        assert!(index.tombstones.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sweep_once_drops_aliases_of_expired_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = config::Folio {
+            uploads_path: temp_dir.path().to_string_lossy().to_string(),
+            data_path: temp_dir.path().to_string_lossy().to_string(),
+            ..config::Folio::default()
+        };
+        let aliases = Arc::new(AliasStore::new(&config));
+        let store = ExpiryStore::new(&config).with_aliases(aliases.clone());
+
+        let file_path = config.build_full_upload_path(Path::new("report.pdf"));
+        std::fs::write(&file_path, "bye").unwrap();
+        aliases
+            .create("my-report", Path::new("report.pdf"))
+            .await
+            .unwrap();
+        store
+            .schedule(&file_path, Duration::from_secs(0))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        store.sweep_once().await.unwrap();
+
+        assert!(aliases.resolve("my-report").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn sweep_once_counts_already_absent_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = test_store(&temp_dir);
+
+        store
+            .schedule(&temp_dir.path().join("gone.txt"), Duration::from_secs(0))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        store.sweep_once().await.unwrap();
+
+        let metrics = store.metrics();
+        assert_eq!(metrics.already_absent, 1);
+        assert_eq!(metrics.deleted, 0);
+        assert!(metrics.delete_failures.is_empty());
+    }
 }
@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::web;
+use globset::{Glob, GlobMatcher};
+use mime_guess::Mime;
+
+use super::config;
+use super::mime_pattern::MimePattern;
+
+/// `extra_headers` and `extra_header_rules`, parsed once at startup.
+#[derive(Default)]
+pub struct ExtraHeaders {
+    base: Vec<(HeaderName, Option<HeaderValue>)>,
+    rules: Vec<Rule>,
+}
+
+struct Rule {
+    path: Option<GlobMatcher>,
+    content_type: Option<MimePattern>,
+    headers: Vec<(HeaderName, Option<HeaderValue>)>,
+}
+
+impl Rule {
+    fn matches(&self, path: Option<&str>, content_type: Option<&Mime>) -> bool {
+        let path_ok = self
+            .path
+            .as_ref()
+            .is_none_or(|glob| path.is_some_and(|p| glob.is_match(p)));
+        let type_ok = self
+            .content_type
+            .as_ref()
+            .is_none_or(|pattern| content_type.is_some_and(|ct| pattern.matches(ct)));
+        path_ok && type_ok
+    }
+}
+
+/// An empty value means "remove", so rules can drop a base header.
+fn parse_headers(
+    headers: &BTreeMap<String, String>,
+) -> Result<Vec<(HeaderName, Option<HeaderValue>)>, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let name = HeaderName::try_from(name.as_str())
+                .map_err(|e| format!("invalid header name {:?}: {}", name, e))?;
+            let value = if value.is_empty() {
+                None
+            } else {
+                Some(
+                    HeaderValue::try_from(value.as_str())
+                        .map_err(|e| format!("invalid value for {}: {}", name, e))?,
+                )
+            };
+            Ok((name, value))
+        })
+        .collect()
+}
+
+impl ExtraHeaders {
+    pub fn from_config(config: &config::Folio) -> Result<Self, String> {
+        let base = parse_headers(&config.extra_headers)?;
+        let rules = config
+            .extra_header_rules
+            .iter()
+            .map(|rule| {
+                let path = rule
+                    .path
+                    .as_deref()
+                    .map(|glob| {
+                        Glob::new(glob)
+                            .map(|g| g.compile_matcher())
+                            .map_err(|e| format!("invalid path glob {:?}: {}", glob, e))
+                    })
+                    .transpose()?;
+                let content_type = rule.content_type.as_deref().map(str::parse).transpose()?;
+                Ok(Rule {
+                    path,
+                    content_type,
+                    headers: parse_headers(&rule.headers)?,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { base, rules })
+    }
+
+    /// Apply the base headers, then every matching rule in order.
+    fn apply(&self, path: Option<&str>, headers: &mut HeaderMap) {
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<Mime>().ok());
+        let matching = self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(path, content_type.as_ref()))
+            .flat_map(|rule| &rule.headers);
+        for (name, value) in self.base.iter().chain(matching) {
+            match value {
+                Some(value) => headers.insert(name.clone(), value.clone()),
+                None => headers.remove(name),
+            };
+        }
+    }
+}
+
+/// Add the configured headers to download responses. `path` is matched as
+/// the file path below `/files/` (or `/private-files/`).
+pub async fn add_extra_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let extra = req
+        .app_data::<web::Data<Arc<ExtraHeaders>>>()
+        .map(|e| e.get_ref().clone());
+    let path = req.match_info().get("path").map(str::to_string);
+
+    let mut response = next.call(req).await?;
+    if let Some(extra) = extra {
+        extra.apply(path.as_deref(), response.headers_mut());
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn rules_override_base_headers_by_path_and_type() {
+        let config = config::Folio {
+            extra_headers: headers(&[("X-Robots-Tag", "noindex"), ("Cache-Control", "no-cache")]),
+            extra_header_rules: vec![
+                config::ExtraHeaderRule {
+                    content_type: Some("image/*".to_string()),
+                    headers: headers(&[("Cache-Control", "public, max-age=86400")]),
+                    ..config::ExtraHeaderRule::default()
+                },
+                config::ExtraHeaderRule {
+                    path: Some("public/**".to_string()),
+                    headers: headers(&[("X-Robots-Tag", "")]),
+                    ..config::ExtraHeaderRule::default()
+                },
+            ],
+            ..config::Folio::default()
+        };
+        let extra = ExtraHeaders::from_config(&config).unwrap();
+
+        let mut map = HeaderMap::new();
+        map.insert(header::CONTENT_TYPE, HeaderValue::from_static("image/png"));
+        extra.apply(Some("public/logo.png"), &mut map);
+        assert_eq!(map.get("cache-control").unwrap(), "public, max-age=86400");
+        assert!(map.get("x-robots-tag").is_none());
+
+        let mut map = HeaderMap::new();
+        map.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; charset=utf-8"),
+        );
+        extra.apply(Some("a1B2c3D4.txt"), &mut map);
+        assert_eq!(map.get("cache-control").unwrap(), "no-cache");
+        assert_eq!(map.get("x-robots-tag").unwrap(), "noindex");
+    }
+
+    #[test]
+    fn invalid_header_names_are_rejected() {
+        let config = config::Folio {
+            extra_headers: headers(&[("Bad Header", "x")]),
+            ..config::Folio::default()
+        };
+        assert!(ExtraHeaders::from_config(&config).is_err());
+    }
+}
@@ -0,0 +1,462 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::http::StatusCode;
+use actix_web::middleware::from_fn;
+use actix_web::{HttpRequest, HttpResponse, Responder, post, web};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use zip::ZipArchive;
+
+use super::config;
+use super::dir_count::DirEntryCounter;
+use super::durable::persist;
+use super::error::FolioError;
+use super::expiry::ExpiryStore;
+use super::files::validate_write_path;
+use super::path::SafePath;
+use super::public_url::public_url;
+use super::quota::{RootQuotas, check_free_space, quota_exceeded, root_quota};
+use super::upload_limit::limit_uploads;
+use super::uploads::{
+    check_content_type_allowed, check_extension_allowed, ensure_writable, requested_expire,
+    resolve_ttl, too_large, upload_size_limit,
+};
+
+#[derive(Deserialize)]
+pub struct ExtractQuery {
+    /// Subdirectory of the uploads root to extract into. Empty means the root.
+    dir: Option<String>,
+    /// TTL of every extracted file, as for `POST /uploads`.
+    expire: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExtractedFile {
+    /// Path relative to the uploads root.
+    pub path: String,
+    pub url: String,
+    pub size_bytes: u64,
+    pub expire_at_unix: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExtractResponse {
+    pub message: String,
+    pub files: Vec<ExtractedFile>,
+}
+
+/// A zip entry that passed every check, and where it goes.
+struct PlannedFile {
+    index: usize,
+    path: SafePath,
+    size: u64,
+    ttl: Duration,
+}
+
+/// Extract the zip archive in the request body into `?dir=`. Every entry is
+/// checked before anything is written: its path must stay inside the target
+/// directory and pass the same checks as `PUT /files/<path>`, must not exist
+/// yet, and must be allowed by the upload type and size limits. The declared
+/// sizes may add up to at most `max_extract_size`, and no entry may inflate
+/// past its declared size. Files written before a failure are removed again.
+#[post("/uploads/extract", wrap = "from_fn(limit_uploads)")]
+#[allow(clippy::too_many_arguments)]
+pub async fn extract_upload(
+    req: HttpRequest,
+    config: web::Data<config::Folio>,
+    expiry_store: web::Data<Arc<ExpiryStore>>,
+    dir_counter: web::Data<Arc<DirEntryCounter>>,
+    quotas: web::Data<Arc<RootQuotas>>,
+    query: web::Query<ExtractQuery>,
+    mut payload: web::Payload,
+) -> Result<impl Responder, FolioError> {
+    ensure_writable(&config)?;
+    let dir = match query.dir.as_deref().map(|dir| dir.trim_matches('/')) {
+        Some(dir) if !dir.is_empty() => SafePath::from_user_input(Path::new(dir))?
+            .as_path()
+            .to_path_buf(),
+        _ => PathBuf::new(),
+    };
+
+    let max_size = upload_size_limit(&config, Some(&"application/zip".parse().unwrap()));
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| FolioError::BadRequest {
+            reason: format!("invalid request body: {}", e),
+        })?;
+        if body.len() + chunk.len() > max_size {
+            return Err(too_large(body.len() + chunk.len(), max_size));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    let archive = Cursor::new(body.freeze());
+    let planned = web::block({
+        let (config, quotas, dir_counter) = (config.clone(), quotas.clone(), dir_counter.clone());
+        let expire = query.expire.clone();
+        move || {
+            let mut archive = ZipArchive::new(archive).map_err(|e| FolioError::BadRequest {
+                reason: format!("invalid zip archive: {}", e),
+            })?;
+            let planned = plan_extraction(&config, &quotas, &mut archive, &dir, expire)?;
+            write_entries(&config, &dir_counter, &mut archive, &planned)?;
+            Ok::<_, FolioError>(planned)
+        }
+    })
+    .await
+    .map_err(|e| FolioError::store_error(e.to_string(), "extract zip archive"))??;
+
+    let full_paths: Vec<PathBuf> = planned
+        .iter()
+        .map(|file| config.build_full_upload_path(file.path.as_path()))
+        .collect();
+    for full_path in &full_paths {
+        if let Err(e) = persist(&config, full_path).await {
+            remove_files(&full_paths);
+            return Err(e);
+        }
+    }
+
+    let mut files = Vec::with_capacity(planned.len());
+    for (file, full_path) in planned.iter().zip(&full_paths) {
+        if let Some((root, _)) = root_quota(&config, file.path.as_path()) {
+            quotas.record_write(root, 0, file.size);
+        }
+        let expire_at_unix = expiry_store
+            .schedule(full_path, file.ttl)
+            .await
+            .map_err(|e| FolioError::store_error(e, "schedule expiration"))?;
+        let path = file.path.as_path().to_string_lossy().to_string();
+        files.push(ExtractedFile {
+            url: public_url(&req, &config, &format!("/files/{}", path)),
+            path,
+            size_bytes: file.size,
+            expire_at_unix,
+        });
+    }
+
+    log::info!(
+        "extracted {} files into /{}",
+        files.len(),
+        query.dir.as_deref().unwrap_or_default().trim_matches('/')
+    );
+    Ok(
+        HttpResponse::build(StatusCode::CREATED).json(ExtractResponse {
+            message: "archive extracted successfully".to_string(),
+            files,
+        }),
+    )
+}
+
+/// Check every entry of `archive` without writing anything. Directory
+/// entries are skipped; their directories are created as files need them.
+fn plan_extraction<R: Read + std::io::Seek>(
+    config: &config::Folio,
+    quotas: &RootQuotas,
+    archive: &mut ZipArchive<R>,
+    dir: &Path,
+    expire: Option<String>,
+) -> Result<Vec<PlannedFile>, FolioError> {
+    let mut planned: Vec<PlannedFile> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut total = 0u64;
+    let mut by_root: BTreeMap<(&str, u64), u64> = BTreeMap::new();
+    for index in 0..archive.len() {
+        let entry = archive
+            .by_index_raw(index)
+            .map_err(|e| FolioError::BadRequest {
+                reason: format!("invalid zip entry {}: {}", index, e),
+            })?;
+        if entry.is_dir() {
+            continue;
+        }
+        let name = entry.name().to_string();
+        // `enclosed_name` refuses absolute paths and `..` escapes (zip-slip).
+        let Some(relative) = entry.enclosed_name() else {
+            log::warn!("zip entry escapes the target directory: {}", name);
+            return Err(FolioError::InvalidPath {
+                reason: format!("zip entry escapes the target directory: {}", name),
+            });
+        };
+        if entry.is_symlink() {
+            return Err(FolioError::InvalidPath {
+                reason: format!("zip entry is a symbolic link: {}", name),
+            });
+        }
+        let path = validate_write_path(config, &dir.join(relative).to_string_lossy())?;
+        let full_path = config.build_full_upload_path(path.as_path());
+        if !seen.insert(path.as_path().to_path_buf()) || full_path.exists() {
+            return Err(FolioError::Conflict {
+                path: path.to_string(),
+            });
+        }
+
+        let content_type = mime_guess::from_path(path.as_path()).first();
+        let upload_dir = path.as_path().parent().map(SafePath::from_user_input);
+        check_content_type_allowed(
+            content_type.as_ref(),
+            config,
+            upload_dir.transpose()?.as_ref(),
+        )?;
+        let extension = path.as_path().extension().map(|ext| ext.to_string_lossy());
+        check_extension_allowed(config, extension.as_deref())?;
+        let size = entry.size();
+        let limit = upload_size_limit(config, content_type.as_ref());
+        if size > limit as u64 {
+            return Err(too_large(size as usize, limit));
+        }
+        total += size;
+        if total > config.max_extract_size {
+            log::warn!(
+                "refusing zip archive: entries exceed max_extract_size {}",
+                config.max_extract_size
+            );
+            return Err(FolioError::PayloadTooLarge {
+                reason: format!(
+                    "archive too large: extracted files exceed {} byte limit",
+                    config.max_extract_size
+                ),
+            });
+        }
+        if let Some(root) = root_quota(config, path.as_path()) {
+            *by_root.entry(root).or_default() += size;
+        }
+
+        let file_name = path.as_path().to_string_lossy();
+        let (ttl, _) = resolve_ttl(
+            config,
+            requested_expire(config, expire.as_deref(), &file_name),
+        )?;
+        planned.push(PlannedFile {
+            index,
+            path,
+            size,
+            ttl,
+        });
+    }
+
+    check_free_space(config, &dir.join(".extract"), total)?;
+    for ((root, quota), bytes) in by_root {
+        let used = quotas
+            .usage(config, root)
+            .map_err(|e| FolioError::store_error(e.to_string(), "measure root usage"))?;
+        if used + bytes > quota {
+            return Err(quota_exceeded(root, used + bytes, quota));
+        }
+    }
+    Ok(planned)
+}
+
+/// Write the `planned` entries, each cut off one byte past its declared
+/// size, removing everything written so far on the first failure.
+fn write_entries<R: Read + std::io::Seek>(
+    config: &config::Folio,
+    dir_counter: &DirEntryCounter,
+    archive: &mut ZipArchive<R>,
+    planned: &[PlannedFile],
+) -> Result<(), FolioError> {
+    let mut written = Vec::with_capacity(planned.len());
+    for file in planned {
+        let full_path = config.build_full_upload_path(file.path.as_path());
+        let result = write_entry(config, dir_counter, archive, file, &full_path);
+        if result.is_ok() || full_path.exists() {
+            written.push(full_path);
+        }
+        if let Err(e) = result {
+            remove_files(&written);
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+fn write_entry<R: Read + std::io::Seek>(
+    config: &config::Folio,
+    dir_counter: &DirEntryCounter,
+    archive: &mut ZipArchive<R>,
+    file: &PlannedFile,
+    full_path: &Path,
+) -> Result<(), FolioError> {
+    let dir = full_path.parent().unwrap_or(full_path);
+    std::fs::create_dir_all(dir)
+        .map_err(|e| FolioError::write_error(&e, dir, "create directory"))?;
+    dir_counter.ensure_capacity(dir, config.max_files_per_dir)?;
+
+    let entry = archive
+        .by_index(file.index)
+        .map_err(|e| FolioError::BadRequest {
+            reason: format!("invalid zip entry {}: {}", file.path, e),
+        })?;
+    let mut output = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(full_path)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => FolioError::Conflict {
+                path: file.path.to_string(),
+            },
+            _ => FolioError::write_error(&e, full_path, "extract file"),
+        })?;
+    dir_counter.record_created(dir);
+    let copied =
+        std::io::copy(&mut entry.take(file.size + 1), &mut output).map_err(|e| match e.kind() {
+            std::io::ErrorKind::InvalidData => FolioError::BadRequest {
+                reason: format!("corrupt zip entry {}: {}", file.path, e),
+            },
+            _ => FolioError::write_error(&e, full_path, "extract file"),
+        })?;
+    if copied > file.size {
+        log::warn!("zip entry {} inflates past its declared size", file.path);
+        return Err(FolioError::PayloadTooLarge {
+            reason: format!(
+                "zip entry {} is larger than the {} bytes it declares",
+                file.path, file.size
+            ),
+        });
+    }
+    Ok(())
+}
+
+fn remove_files(paths: &[PathBuf]) {
+    for path in paths {
+        if let Err(e) = std::fs::remove_file(path) {
+            log::error!("failed to remove extracted file {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, test};
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    fn zip_of(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(content).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    async fn call(
+        temp_dir: &tempfile::TempDir,
+        config: config::Folio,
+        uri: &str,
+        body: Vec<u8>,
+    ) -> (StatusCode, serde_json::Value) {
+        let config = config::Folio {
+            uploads_path: temp_dir
+                .path()
+                .join("uploads")
+                .to_string_lossy()
+                .to_string(),
+            data_path: temp_dir.path().join("data").to_string_lossy().to_string(),
+            ..config
+        };
+        std::fs::create_dir_all(temp_dir.path().join("uploads")).unwrap();
+        let expiry_store = Arc::new(ExpiryStore::new(&config));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(Arc::new(RootQuotas::default())))
+                .service(extract_upload),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri(uri)
+            .insert_header(("Content-Type", "application/zip"))
+            .set_payload(body)
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        let status = response.status();
+        (status, test::read_body_json(response).await)
+    }
+
+    #[actix_web::test]
+    async fn extracts_into_the_target_directory() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let body = zip_of(&[("a.txt", b"alpha"), ("docs/b.txt", b"beta")]);
+        let (status, body) = call(
+            &temp_dir,
+            config::Folio::default(),
+            "/uploads/extract?dir=import",
+            body,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["files"][0]["path"], "import/a.txt");
+        assert_eq!(body["files"][1]["path"], "import/docs/b.txt");
+        assert_eq!(body["files"][1]["size_bytes"], 4);
+        let uploads = temp_dir.path().join("uploads");
+        assert_eq!(
+            std::fs::read_to_string(uploads.join("import/docs/b.txt")).unwrap(),
+            "beta"
+        );
+    }
+
+    #[actix_web::test]
+    async fn refuses_entries_escaping_the_directory() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let body = zip_of(&[("ok.txt", b"fine"), ("../../evil.txt", b"pwned")]);
+        let (status, body) = call(
+            &temp_dir,
+            config::Folio::default(),
+            "/uploads/extract?dir=import",
+            body,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "INVALID_PATH");
+        assert!(!temp_dir.path().join("evil.txt").exists());
+        assert!(!temp_dir.path().join("uploads/import/ok.txt").exists());
+    }
+
+    #[actix_web::test]
+    async fn caps_the_total_extracted_size() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let zeros = vec![0u8; 64 * 1024];
+        let body = zip_of(&[("a.bin", &zeros), ("b.bin", &zeros)]);
+        let config = config::Folio {
+            max_extract_size: 100 * 1024,
+            ..config::Folio::default()
+        };
+        let (status, _) = call(&temp_dir, config, "/uploads/extract", body).await;
+
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(!temp_dir.path().join("uploads/a.bin").exists());
+    }
+
+    #[actix_web::test]
+    async fn existing_files_are_a_conflict() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("uploads")).unwrap();
+        std::fs::write(temp_dir.path().join("uploads/a.txt"), "old").unwrap();
+        let body = zip_of(&[("a.txt", b"new")]);
+        let (status, _) = call(
+            &temp_dir,
+            config::Folio::default(),
+            "/uploads/extract",
+            body,
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("uploads/a.txt")).unwrap(),
+            "old"
+        );
+    }
+}
@@ -1,40 +1,328 @@
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use actix_files::NamedFile;
 use actix_multipart::Multipart;
-use actix_web::http::StatusCode;
-use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, post, put, web};
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::guard::GuardContext;
+use actix_web::http::header::{
+    self, CacheControl, CacheDirective, Charset, ContentEncoding, DispositionParam,
+    DispositionType, EntityTag, ExtendedValue, HeaderValue, IfMatch, IfRange, TryIntoHeaderValue,
+};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::{Next, from_fn};
+use actix_web::{
+    HttpMessage, HttpRequest, HttpResponse, Responder, delete, get, post, put, route, web,
+};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use futures_util::StreamExt;
-use serde_json::json;
+use rand::RngExt;
+use serde::{Deserialize, Serialize};
 use tokio::io::AsyncWriteExt;
 
+use super::access::record_download;
+use super::archive::{ArchiveFormat, serve_archive};
 use super::auth::{AccessAuth, VerifiedIdentity};
 use super::config;
+use super::dir_count::DirEntryCounter;
+use super::durable::persist;
 use super::error::FolioError;
-use super::path::SafePath;
+use super::expiry::ExpiryStore;
+use super::extra_headers::add_extra_headers;
+use super::hashing::HashAlgorithm;
+use super::images;
+use super::manifest::serve_manifest;
+use super::meta_file;
+use super::metadata::{self, FileMetadata, MetadataStore};
+use super::mime_pattern::MimePattern;
+use super::path::{SafePath, slugify_file_name};
 use super::private_index::PrivateIndexStore;
+use super::processing::ensure_processed;
+use super::public_url::{encode_path, public_url};
+use super::quota::enforce_quota;
+use super::upload_limit::limit_uploads;
+use super::upload_meta::read_xattrs;
+use super::uploads::{
+    UPLOAD_ID_LENGTH, UploadId, check_extension_allowed, ensure_writable, max_ttl, parse_duration,
+    resolve_ttl, too_small, upload_size_limit,
+};
+
+/// JSON body returned by the `/files` write endpoints.
+#[derive(Debug, Serialize)]
+pub struct FileResponse {
+    pub message: String,
+    /// Final path of the file when it differs from the requested one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub freed_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expire_at_unix: Option<u64>,
+    /// Set when the requested TTL was shortened to `max_expire`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub expire_clamped: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified_unix: Option<u64>,
+}
+
+impl FileResponse {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            path: None,
+            freed_bytes: None,
+            expire_at_unix: None,
+            expire_clamped: false,
+            modified_unix: None,
+        }
+    }
+}
+
+/// JSON body returned by `GET` on a directory.
+#[derive(Debug, Serialize)]
+pub struct DirectoryListing {
+    pub path: String,
+    pub entries: Vec<DirectoryEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DirectoryEntry {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: EntryKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified_unix: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Directory,
+}
+
+/// Order of entries in a directory listing (`?sort=`).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListingSort {
+    #[default]
+    Name,
+    /// Most recently modified files first; directories follow by name.
+    Modified,
+}
+
+#[derive(Deserialize)]
+struct ListingQuery {
+    #[serde(default)]
+    sort: ListingSort,
+}
+
+/// What `POST /files/<path>` does when the target already exists.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictStrategy {
+    #[default]
+    Error,
+    Rename,
+    Overwrite,
+}
+
+#[derive(Deserialize)]
+pub struct DownloadQuery {
+    password: Option<String>,
+    /// Serve a resized copy of an image instead, e.g. `200x150`.
+    thumb: Option<String>,
+    /// On a directory: a checksum manifest of its files instead of a listing.
+    manifest: Option<HashAlgorithm>,
+    /// On a directory: an archive of its files instead of a listing.
+    archive: Option<ArchiveFormat>,
+}
+
+/// `Content-Disposition` type of a download (`?disposition=`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Disposition {
+    Inline,
+    Attachment,
+}
+
+#[derive(Deserialize)]
+struct DispositionQuery {
+    disposition: Option<Disposition>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateQuery {
+    #[serde(default)]
+    on_conflict: ConflictStrategy,
+}
+
+#[derive(Deserialize)]
+pub struct ExpirationQuery {
+    expire: String,
+}
+
+#[derive(Deserialize)]
+pub struct UnexpireQuery {
+    expire: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TouchQuery {
+    /// Unix seconds to set instead of now.
+    mtime: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteQuery {
+    #[serde(default)]
+    recursive: bool,
+    /// The target path again, for `require_delete_confirmation`.
+    confirm: Option<String>,
+}
+
+/// Whether a delete of `path` was confirmed, by naming the path again in
+/// `?confirm=` or with `X-Confirm-Delete: true`.
+fn delete_confirmed(req: &HttpRequest, query: &DeleteQuery, path: &SafePath) -> bool {
+    let confirmed_by_header = req
+        .headers()
+        .get("X-Confirm-Delete")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("true"));
+    confirmed_by_header
+        || query
+            .confirm
+            .as_deref()
+            .is_some_and(|confirm| Path::new(confirm.trim_matches('/')) == path.as_path())
+}
 
 /// Ensure parent directories exist.
 fn ensure_parent_dirs(path: &Path) -> Result<(), FolioError> {
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent).map_err(|e| {
-            let message = format!("failed to create directories: {:?}", e);
-            log::error!("{}, path: {}", message, path.display());
-            FolioError::Internal {
-                source: message,
-                context: Some(format!("create directories for: {}", path.display())),
-            }
+            log::error!(
+                "failed to create directories: {:?}, path: {}",
+                e,
+                path.display()
+            );
+            FolioError::write_error(&e, path, "create directories")
         })?;
     }
     Ok(())
 }
 
-fn validate_path(path: web::Path<String>) -> Result<SafePath, FolioError> {
-    SafePath::from_user_input(Path::new(path.as_str()))
+/// `.meta.json` files are not reachable through `/files`, as if they did
+/// not exist.
+fn validate_path(config: &config::Folio, path: web::Path<String>) -> Result<SafePath, FolioError> {
+    let path = SafePath::from_user_input(Path::new(path.as_str()))?;
+    if meta_file::is_meta_file(path.as_path()) {
+        return Err(FolioError::NotFound {
+            path: path.to_string(),
+        });
+    }
+    if let Some(reason) = symlink_violation(config, path.as_path()) {
+        log::warn!("refusing {}: {}", path, reason);
+        return Err(FolioError::NotFound {
+            path: path.to_string(),
+        });
+    }
+    Ok(path)
+}
+
+/// Like `validate_path`, for the routes that write a file.
+pub fn validate_write_path(config: &config::Folio, path: &str) -> Result<SafePath, FolioError> {
+    let mut path = SafePath::from_user_input(Path::new(path))?;
+    if config.slugify_filenames {
+        path = path.with_slugified_name();
+    }
+    if meta_file::is_meta_file(path.as_path()) {
+        return Err(FolioError::BadRequest {
+            reason: format!(
+                "paths ending in {} are reserved for upload metadata",
+                meta_file::META_SUFFIX
+            ),
+        });
+    }
+    ensure_no_symlink_violation(config, &path)?;
+    Ok(path)
+}
+
+/// `400` for a write below `path` that `follow_symlinks` does not allow.
+pub fn ensure_no_symlink_violation(
+    config: &config::Folio,
+    path: &SafePath,
+) -> Result<(), FolioError> {
+    match symlink_violation(config, path.as_path()) {
+        Some(reason) => Err(FolioError::InvalidPath { reason }),
+        None => Ok(()),
+    }
+}
+
+/// Why `relative` may not be used, if it passes through a symbolic link
+/// below the uploads root: any link without `follow_symlinks`, and links
+/// resolving outside the root with it. Paths that do not exist yet are
+/// checked up to their deepest existing ancestor.
+pub(crate) fn symlink_violation(config: &config::Folio, relative: &Path) -> Option<String> {
+    let root = config.resolve_base(&config.uploads_path);
+    let mut current = root.clone();
+    let mut linked = false;
+    for component in relative.components() {
+        current.push(component);
+        match std::fs::symlink_metadata(&current) {
+            Ok(metadata) => linked |= metadata.file_type().is_symlink(),
+            Err(_) => break,
+        }
+    }
+    if !linked {
+        return None;
+    }
+    if !config.follow_symlinks {
+        return Some(format!(
+            "{} passes through a symbolic link",
+            relative.display()
+        ));
+    }
+
+    let resolved = current.ancestors().find_map(|p| p.canonicalize().ok());
+    let inside = match (resolved, root.canonicalize()) {
+        (Some(resolved), Ok(root)) => resolved.starts_with(root),
+        _ => false,
+    };
+    (!inside).then(|| format!("{} links outside the uploads root", relative.display()))
+}
+
+/// Reject a named-path write whose file name exceeds `max_filename_length`.
+fn check_filename_length(config: &config::Folio, path: &SafePath) -> Result<(), FolioError> {
+    let len = path.as_path().file_name().map_or(0, |name| name.len());
+    if len > config.max_filename_length {
+        return Err(FolioError::UnprocessableEntity {
+            reason: format!(
+                "file name too long: {} bytes exceeds {} byte limit",
+                len, config.max_filename_length
+            ),
+        });
+    }
+    Ok(())
 }
 
-async fn save_file_field(mut payload: Multipart, full_path: &Path) -> Result<(), FolioError> {
+/// Stream the `upload_field_name` field to `full_path`, giving up with `413`
+/// as soon as it passes the size limit for its type (`max_upload_size` or a
+/// `max_upload_bytes_by_type` entry) so oversize bodies never fully land on
+/// disk. The type comes from the path's extension, else the field's header.
+///
+/// The body goes to a hidden sibling first and is renamed over `full_path`
+/// only once it is complete, so a failed overwrite leaves the old file alone.
+async fn save_file_field(
+    mut payload: Multipart,
+    full_path: &Path,
+    config: &config::Folio,
+) -> Result<(), FolioError> {
+    let guessed = mime_guess::from_path(full_path).first();
     let mut found_file = false;
 
     while let Some(field) = payload.next().await {
@@ -42,7 +330,7 @@ async fn save_file_field(mut payload: Multipart, full_path: &Path) -> Result<(),
             reason: format!("invalid multipart payload: {}", e),
         })?;
 
-        if field.name() != Some("file") {
+        if field.name() != Some(config.upload_field_name.as_str()) {
             while let Some(chunk) = field.next().await {
                 chunk.map_err(|e| FolioError::BadRequest {
                     reason: format!("invalid multipart field: {}", e),
@@ -52,57 +340,208 @@ async fn save_file_field(mut payload: Multipart, full_path: &Path) -> Result<(),
         }
 
         found_file = true;
+        let max_size = upload_size_limit(config, guessed.as_ref().or(field.content_type()));
         ensure_parent_dirs(full_path)?;
-        let mut output = tokio::fs::File::create(full_path).await.map_err(|e| {
-            let message = format!("failed to create file: {:?}", e);
-            log::error!("multipart save error: {}", message);
-            FolioError::Internal {
-                source: message,
-                context: Some("create uploaded file".to_string()),
-            }
+        let part_path = part_path(full_path);
+        let mut output = tokio::fs::File::create(&part_path).await.map_err(|e| {
+            log::error!("multipart save error: failed to create file: {:?}", e);
+            FolioError::write_error(&e, full_path, "create uploaded file")
         })?;
 
+        let mut bytes_written: usize = 0;
         while let Some(chunk) = field.next().await {
-            let data = chunk.map_err(|e| FolioError::BadRequest {
-                reason: format!("invalid multipart file field: {}", e),
-            })?;
-            output.write_all(&data).await.map_err(|e| {
-                let message = format!("failed to save file: {:?}", e);
-                log::error!("multipart save error: {}", message);
-                FolioError::Internal {
-                    source: message,
-                    context: Some("save uploaded file".to_string()),
+            let data = match chunk {
+                Ok(data) => data,
+                Err(e) => {
+                    drop(output);
+                    let _ = tokio::fs::remove_file(&part_path).await;
+                    return Err(FolioError::BadRequest {
+                        reason: format!("invalid multipart file field: {}", e),
+                    });
                 }
-            })?;
+            };
+            bytes_written += data.len();
+            if bytes_written > max_size {
+                let message = format!("file too large: more than {} byte limit", max_size);
+                log::warn!(
+                    "multipart save error: {}, path: {}",
+                    message,
+                    full_path.display()
+                );
+                drop(output);
+                let _ = tokio::fs::remove_file(&part_path).await;
+                return Err(FolioError::PayloadTooLarge { reason: message });
+            }
+            if let Err(e) = output.write_all(&data).await {
+                log::error!("multipart save error: failed to save file: {:?}", e);
+                drop(output);
+                let _ = tokio::fs::remove_file(&part_path).await;
+                return Err(FolioError::write_error(&e, full_path, "save uploaded file"));
+            }
         }
 
-        output.flush().await.map_err(|e| {
-            let message = format!("failed to flush file: {:?}", e);
-            log::error!("multipart save error: {}", message);
-            FolioError::Internal {
-                source: message,
-                context: Some("flush uploaded file".to_string()),
-            }
-        })?;
+        if let Err(e) = output.flush().await {
+            log::error!("multipart save error: failed to flush file: {:?}", e);
+            drop(output);
+            let _ = tokio::fs::remove_file(&part_path).await;
+            return Err(FolioError::write_error(
+                &e,
+                full_path,
+                "flush uploaded file",
+            ));
+        }
+
+        if bytes_written < config.min_upload_bytes {
+            drop(output);
+            let _ = tokio::fs::remove_file(&part_path).await;
+            return Err(too_small(bytes_written, config.min_upload_bytes));
+        }
+
+        drop(output);
+        if let Err(e) = tokio::fs::rename(&part_path, full_path).await {
+            log::error!("multipart save error: failed to replace file: {:?}", e);
+            let _ = tokio::fs::remove_file(&part_path).await;
+            return Err(FolioError::write_error(&e, full_path, "save uploaded file"));
+        }
+        persist(config, full_path).await?;
     }
 
     if !found_file {
         return Err(FolioError::BadRequest {
-            reason: "multipart form is missing file field".to_string(),
+            reason: format!(
+                "multipart form is missing {} field",
+                config.upload_field_name
+            ),
         });
     }
 
     Ok(())
 }
 
-#[get("/files/{path:.*}")]
+/// Hidden sibling of `path` that an upload streams into before it is renamed
+/// into place. Random so concurrent uploads to the same path don't collide.
+fn part_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(
+        ".{}.part-{:08x}",
+        name,
+        rand::rng().random::<u32>()
+    ))
+}
+
+/// Strong validator in the same format as the `ETag` emitted by `NamedFile`.
+pub(crate) fn file_etag(metadata: &std::fs::Metadata) -> Option<EntityTag> {
+    let mtime = metadata.modified().ok()?;
+    let dur = mtime.duration_since(std::time::UNIX_EPOCH).ok()?;
+
+    #[cfg(unix)]
+    let ino = std::os::unix::fs::MetadataExt::ino(metadata);
+    #[cfg(not(unix))]
+    let ino = 0;
+
+    Some(EntityTag::new_strong(format!(
+        "{:x}:{:x}:{:x}:{:x}",
+        ino,
+        metadata.len(),
+        dur.as_secs(),
+        dur.subsec_nanos()
+    )))
+}
+
+/// Honor `If-Range` on downloads.
+///
+/// `NamedFile` serves `Range` requests but ignores `If-Range`, so a client
+/// resuming a file that has changed since would get a slice of the new
+/// content. When the validator no longer matches, drop `Range` so the full
+/// representation is served instead. The same goes for one-time files: the
+/// rest of a partial download could never be fetched.
+async fn if_range(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if req.headers().contains_key(header::RANGE) {
+        if let Some(validator) = req.get_header::<IfRange>()
+            && !if_range_matches(&req, &validator)
+        {
+            log::debug!(
+                "If-Range validator is stale, serving full file: {}",
+                req.path()
+            );
+            req.headers_mut().remove(header::RANGE);
+        } else if is_one_time(&req).await {
+            req.headers_mut().remove(header::RANGE);
+        }
+    }
+
+    next.call(req).await
+}
+
+async fn is_one_time(req: &ServiceRequest) -> bool {
+    let (Some(metadata_store), Some(path)) = (
+        req.app_data::<web::Data<Arc<MetadataStore>>>(),
+        req.match_info()
+            .get("path")
+            .and_then(|p| SafePath::from_user_input(Path::new(p)).ok()),
+    ) else {
+        return false;
+    };
+    metadata_store
+        .get(path.as_path())
+        .await
+        .is_ok_and(|m| m.is_some_and(|m| m.burn))
+}
+
+fn if_range_matches(req: &ServiceRequest, validator: &IfRange) -> bool {
+    let Some(config) = req.app_data::<web::Data<config::Folio>>() else {
+        return false;
+    };
+    let Some(path) = req
+        .match_info()
+        .get("path")
+        .and_then(|p| SafePath::from_user_input(Path::new(p)).ok())
+    else {
+        return false;
+    };
+    let full_path = config.build_full_upload_path(path.as_path());
+    let Ok(metadata) = std::fs::metadata(&full_path) else {
+        return false;
+    };
+
+    match validator {
+        IfRange::EntityTag(tag) => file_etag(&metadata).is_some_and(|etag| tag.strong_eq(&etag)),
+        // HTTP dates have one-second resolution.
+        IfRange::Date(date) => metadata.modified().is_ok_and(|mtime| {
+            let unix_secs = |t: std::time::SystemTime| {
+                t.duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .ok()
+            };
+            unix_secs(mtime).is_some() && unix_secs(mtime) == unix_secs((*date).into())
+        }),
+    }
+}
+
+#[route(
+    "/files/{path:.*}",
+    method = "GET",
+    method = "HEAD",
+    wrap = "from_fn(if_range)",
+    wrap = "from_fn(add_extra_headers)"
+)]
 pub async fn get_file(
     req: HttpRequest,
     config: web::Data<config::Folio>,
     private_index: web::Data<Arc<PrivateIndexStore>>,
+    metadata_store: web::Data<Arc<MetadataStore>>,
+    expiry_store: web::Data<Arc<ExpiryStore>>,
     path: web::Path<String>,
+    query: web::Query<DownloadQuery>,
 ) -> Result<HttpResponse, FolioError> {
-    let path = validate_path(path)?;
+    let path = validate_path(&config, path)?;
+    ensure_processed(&req, &path)?;
     let is_private = private_index
         .is_private(path.as_path())
         .await
@@ -114,24 +553,310 @@ pub async fn get_file(
             .finish());
     }
 
-    Ok(open_upload_file(&config, &path).await?.into_response(&req))
+    let metadata = metadata_store
+        .get(path.as_path())
+        .await
+        .map_err(|e| FolioError::store_error(e, "read file metadata"))?;
+    check_download_password(&req, metadata.as_ref(), &path, query.password.as_deref()).await?;
+    ensure_not_expired(&config, &expiry_store, &path).await?;
+
+    if query.manifest.is_some() && query.archive.is_some() {
+        return Err(FolioError::BadRequest {
+            reason: "manifest and archive cannot be combined".to_string(),
+        });
+    }
+    if is_directory(&config, &path) {
+        if let Some(format) = query.archive {
+            return serve_archive(
+                &config,
+                &private_index,
+                &metadata_store,
+                &expiry_store,
+                &path,
+                format,
+            )
+            .await;
+        }
+        if let Some(algorithm) = query.manifest {
+            return serve_manifest(
+                &req,
+                &config,
+                &private_index,
+                &metadata_store,
+                &expiry_store,
+                &path,
+                algorithm,
+            )
+            .await;
+        }
+        return list_directory(
+            &req,
+            &config,
+            &private_index,
+            &metadata_store,
+            &expiry_store,
+            &path,
+            None,
+        )
+        .await;
+    }
+    if query.manifest.is_some() || query.archive.is_some() {
+        return Err(FolioError::BadRequest {
+            reason: "manifest and archive are only available for directories".to_string(),
+        });
+    }
+
+    let burn = metadata.as_ref().is_some_and(|m| m.burn);
+    if let Some(thumb) = query.thumb.as_deref() {
+        // A cached thumbnail would outlive the one-time file.
+        if burn {
+            return Err(FolioError::BadRequest {
+                reason: "thumbnails are not available for one-time files".to_string(),
+            });
+        }
+        return serve_thumbnail(&req, &config, &path, metadata.as_ref(), thumb).await;
+    }
+
+    if burn {
+        return serve_burn_file(
+            &req,
+            &config,
+            &expiry_store,
+            &metadata_store,
+            &path,
+            metadata.as_ref(),
+        )
+        .await;
+    }
+
+    let mut response = serve_upload_file(&req, &config, &path, metadata.as_ref()).await?;
+    record_download(&req, &path, &response);
+    add_cache_control(
+        &config,
+        &expiry_store,
+        &path,
+        metadata.as_ref(),
+        &mut response,
+    )
+    .await?;
+    Ok(response)
+}
+
+/// Let browsers and CDNs cache a public download for `download_max_age`,
+/// but never past the file's expiry, so they don't keep serving it once it
+/// is deleted. Password-protected files are left uncached.
+async fn add_cache_control(
+    config: &config::Folio,
+    expiry_store: &ExpiryStore,
+    path: &SafePath,
+    metadata: Option<&FileMetadata>,
+    response: &mut HttpResponse,
+) -> Result<(), FolioError> {
+    let cacheable = response.status().is_success() || response.status() == StatusCode::NOT_MODIFIED;
+    if config.download_max_age == 0
+        || !cacheable
+        || metadata.is_some_and(|m| m.password_hash.is_some())
+    {
+        return Ok(());
+    }
+
+    let full_path = config.build_full_upload_path(&PathBuf::from(path.as_path()));
+    let expire_at = expiry_store
+        .expire_at(&full_path)
+        .await
+        .map_err(|e| FolioError::store_error(e, "read expiration"))?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let max_age = expire_at.map_or(config.download_max_age, |expire_at| {
+        config.download_max_age.min(expire_at.saturating_sub(now))
+    });
+    let cache_control = CacheControl(vec![
+        CacheDirective::Public,
+        CacheDirective::MaxAge(max_age.min(u32::MAX as u64) as u32),
+    ]);
+    if let Ok(value) = cache_control.try_into_value() {
+        response.headers_mut().insert(header::CACHE_CONTROL, value);
+    }
+    Ok(())
+}
+
+/// `410` for a file that expired and is kept for `expire_grace_period`, or
+/// was deleted on expiry less than `tombstone_ttl` ago.
+async fn ensure_not_expired(
+    config: &config::Folio,
+    expiry_store: &ExpiryStore,
+    path: &SafePath,
+) -> Result<(), FolioError> {
+    let full_path = config.build_full_upload_path(&PathBuf::from(path.as_path()));
+    let expired = |e: String| FolioError::store_error(e, "read expiration");
+    let in_grace = expiry_store.has_grace_period()
+        && expiry_store
+            .delete_at(&full_path)
+            .await
+            .map_err(expired)?
+            .is_some();
+    // A file since created at the same path is served as usual.
+    let deleted = !in_grace
+        && expiry_store.keeps_tombstones()
+        && !full_path.exists()
+        && expiry_store
+            .deleted_at(&full_path)
+            .await
+            .map_err(expired)?
+            .is_some();
+    if in_grace || deleted {
+        return Err(FolioError::Expired {
+            path: path.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Serve an image resized to fit within `thumb` (`<W>x<H>`).
+///
+/// Thumbnails are generated on first request and cached under
+/// `data_path/thumbnails`, keyed by source path, size and mtime.
+async fn serve_thumbnail(
+    req: &HttpRequest,
+    config: &config::Folio,
+    path: &SafePath,
+    metadata: Option<&FileMetadata>,
+    thumb: &str,
+) -> Result<HttpResponse, FolioError> {
+    let size = images::parse_thumb_size(thumb, config.max_thumbnail_size)
+        .map_err(|reason| FolioError::BadRequest { reason })?;
+    let file = open_upload_file(config, path).await?;
+    let content_type = metadata
+        .and_then(|m| m.content_type.as_deref())
+        .and_then(|ct| ct.parse::<mime_guess::Mime>().ok())
+        .unwrap_or_else(|| file.content_type().clone());
+    let format =
+        images::readable_format(&content_type).ok_or_else(|| FolioError::UnsupportedMediaType {
+            content_type: content_type.essence_str().to_string(),
+        })?;
+
+    let source = file.path().to_path_buf();
+    let thumbnail_format = images::thumbnail_format(format);
+    let cache_dir = config.build_full_data_path(Path::new("thumbnails"));
+    let cache_path = images::thumbnail_cache_path(&cache_dir, &source, size, thumbnail_format)
+        .map_err(|e| FolioError::store_error(e.to_string(), "locate thumbnail"))?;
+
+    if !cache_path.is_file() {
+        let dest = cache_path.clone();
+        web::block(move || images::render_thumbnail(&source, format, &dest, size))
+            .await
+            .map_err(|e| FolioError::store_error(e.to_string(), "render thumbnail"))?
+            .map_err(|e| {
+                log::warn!("thumbnail for {} failed: {}", path, e);
+                FolioError::UnprocessableEntity {
+                    reason: format!("cannot create thumbnail: {}", e),
+                }
+            })?;
+    }
+
+    let thumbnail = NamedFile::open_async(&cache_path)
+        .await
+        .map_err(|e| FolioError::store_error(e.to_string(), "open thumbnail"))?;
+    Ok(thumbnail
+        .set_content_type(
+            thumbnail_format
+                .to_mime_type()
+                .parse()
+                .unwrap_or(content_type),
+        )
+        .into_response(req))
+}
+
+/// Require the file's download password, if it has one, from `?password=`
+/// or HTTP Basic auth (any username).
+async fn check_download_password(
+    req: &HttpRequest,
+    metadata: Option<&FileMetadata>,
+    path: &SafePath,
+    query_password: Option<&str>,
+) -> Result<(), FolioError> {
+    let password_hash = metadata.and_then(|m| m.password_hash.clone());
+    let Some(password_hash) = password_hash else {
+        return Ok(());
+    };
+
+    let Some(password) = query_password
+        .map(str::to_string)
+        .or_else(|| basic_auth_password(req))
+    else {
+        return Err(FolioError::PasswordRequired {
+            path: path.to_string(),
+        });
+    };
+
+    let matches = web::block(move || metadata::verify_password(&password, &password_hash))
+        .await
+        .map_err(|e| FolioError::store_error(e.to_string(), "verify password"))?;
+    if !matches {
+        log::warn!("download password mismatch for path '{}'", path);
+        return Err(FolioError::Forbidden {
+            reason: "invalid password".to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+fn basic_auth_password(req: &HttpRequest) -> Option<String> {
+    let encoded = req
+        .headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(BASE64.decode(encoded.trim()).ok()?).ok()?;
+    decoded
+        .split_once(':')
+        .map(|(_, password)| password.to_string())
 }
 
-#[get("/private-files/{path:.*}")]
+#[route(
+    "/private-files/{path:.*}",
+    method = "GET",
+    method = "HEAD",
+    wrap = "from_fn(if_range)",
+    wrap = "from_fn(add_extra_headers)"
+)]
+#[allow(clippy::too_many_arguments)]
 pub async fn get_private_file(
     req: HttpRequest,
     config: web::Data<config::Folio>,
     private_index: web::Data<Arc<PrivateIndexStore>>,
     access_auth: web::Data<Arc<AccessAuth>>,
+    metadata_store: web::Data<Arc<MetadataStore>>,
+    expiry_store: web::Data<Arc<ExpiryStore>>,
     path: web::Path<String>,
-) -> Result<NamedFile, FolioError> {
-    let path = validate_path(path)?;
+    query: web::Query<DownloadQuery>,
+) -> Result<HttpResponse, FolioError> {
+    let path = validate_path(&config, path)?;
+    ensure_processed(&req, &path)?;
     let identity = VerifiedIdentity::from_request(&req, &access_auth)
         .await
         .map_err(|err| FolioError::Unauthorized {
             reason: err.message().to_string(),
         })?;
 
+    if is_directory(&config, &path) {
+        let email = identity.0.email.as_deref().unwrap_or("");
+        return list_directory(
+            &req,
+            &config,
+            &private_index,
+            &metadata_store,
+            &expiry_store,
+            &path,
+            Some(email),
+        )
+        .await;
+    }
+
     let entry = private_index
         .get_entry(path.as_path())
         .await
@@ -156,6 +881,13 @@ pub async fn get_private_file(
         }
     }
 
+    let metadata = metadata_store
+        .get(path.as_path())
+        .await
+        .map_err(|e| FolioError::store_error(e, "read file metadata"))?;
+    check_download_password(&req, metadata.as_ref(), &path, query.password.as_deref()).await?;
+    ensure_not_expired(&config, &expiry_store, &path).await?;
+
     log::info!(
         "private file access granted: sub={}, email={:?}, path={}",
         identity.0.sub,
@@ -163,89 +895,881 @@ pub async fn get_private_file(
         path
     );
 
-    open_upload_file(&config, &path).await
+    if metadata.as_ref().is_some_and(|m| m.burn) {
+        return serve_burn_file(
+            &req,
+            &config,
+            &expiry_store,
+            &metadata_store,
+            &path,
+            metadata.as_ref(),
+        )
+        .await;
+    }
+
+    let response = serve_upload_file(&req, &config, &path, metadata.as_ref()).await?;
+    record_download(&req, &path, &response);
+    Ok(response)
 }
 
-async fn open_upload_file(
+/// Serve a one-time file to exactly one downloader and delete it.
+///
+/// The file is first moved into a fresh hidden directory next to it; only one
+/// concurrent request can win that rename, the others get `404` as if it were
+/// already gone. The winner opens the file and unlinks it straight away, so
+/// the open handle streams the content and the space is released once the
+/// response completes.
+async fn serve_burn_file(
+    req: &HttpRequest,
     config: &config::Folio,
+    expiry_store: &ExpiryStore,
+    metadata_store: &MetadataStore,
     path: &SafePath,
-) -> Result<NamedFile, FolioError> {
-    let full_path = config.build_full_upload_path(&PathBuf::from(path.as_path()));
-
-    if !full_path.exists() {
+    metadata: Option<&FileMetadata>,
+) -> Result<HttpResponse, FolioError> {
+    let full_path = config.build_full_upload_path(path.as_path());
+    let (Some(parent), Some(file_name)) = (full_path.parent(), full_path.file_name()) else {
         return Err(FolioError::NotFound {
             path: path.to_string(),
         });
+    };
+    // Probing the file must not use up its one download.
+    if req.method() == Method::HEAD {
+        let mut response = serve_upload_file(req, config, path, metadata).await?;
+        response
+            .headers_mut()
+            .insert(header::ACCEPT_RANGES, HeaderValue::from_static("none"));
+        return Ok(response);
     }
+    let burn_error =
+        |e: std::io::Error| FolioError::store_error(e.to_string(), "claim one-time file");
 
-    if !full_path.is_file() {
-        return Err(FolioError::BadRequest {
-            reason: format!("path is not a file: {}", path),
-        });
-    }
-
-    NamedFile::open_async(full_path)
+    // Keeping the file name lets `NamedFile` derive the usual headers.
+    let claim_dir = parent.join(format!(
+        ".burn-{}",
+        UploadId::new(UPLOAD_ID_LENGTH).file_name(None)
+    ));
+    tokio::fs::create_dir(&claim_dir)
         .await
-        .map_err(|e| FolioError::Internal {
-            source: format!("failed to open file: {}", e),
-            context: Some(format!("open file: {}", path)),
-        })
-}
-
-#[post("/files/{path:.*}")]
-pub async fn create_file(
-    config: web::Data<config::Folio>,
-    path: web::Path<String>,
-    payload: Multipart,
-) -> Result<impl Responder, FolioError> {
-    let path = validate_path(path)?;
-    let full_path = config.build_full_upload_path(&PathBuf::from(path.as_path()));
+        .map_err(burn_error)?;
+    let claim_path = claim_dir.join(file_name);
+    if let Err(e) = tokio::fs::rename(&full_path, &claim_path).await {
+        let _ = tokio::fs::remove_dir(&claim_dir).await;
+        if e.kind() == std::io::ErrorKind::NotFound {
+            return Err(FolioError::NotFound {
+                path: path.to_string(),
+            });
+        }
+        return Err(burn_error(e));
+    }
 
-    if full_path.exists() {
-        return Err(FolioError::Conflict {
-            path: path.to_string(),
-        });
+    let file = NamedFile::open_async(&claim_path).await;
+    if let Err(e) = tokio::fs::remove_dir_all(&claim_dir).await {
+        log::error!("failed to remove one-time file {}: {}", path, e);
+    }
+    let mut file = file.map_err(burn_error)?;
+    if let Some(mime) = metadata
+        .and_then(|m| m.content_type.as_deref())
+        .and_then(|ct| ct.parse::<mime_guess::Mime>().ok())
+    {
+        file = file.set_content_type(mime);
     }
+    let file = with_disposition(req, config, path, file, metadata)?;
+    let file = with_charset(config, file);
 
-    save_file_field(payload, &full_path).await?;
+    if let Err(e) = expiry_store.cancel(&full_path).await {
+        log::error!("failed to cancel expiration of {}: {}", path, e);
+    }
+    if let Err(e) = metadata_store.remove(path.as_path()).await {
+        log::error!("failed to drop metadata for {}: {}", path, e);
+    }
+    meta_file::remove(&full_path);
+    log::info!("one-time file {} downloaded and deleted", path);
 
-    Ok(HttpResponse::build(StatusCode::CREATED).json(json!({
-        "message": "file created successfully"
-    })))
+    let mut response = file.into_response(req);
+    response
+        .headers_mut()
+        .insert(header::ACCEPT_RANGES, HeaderValue::from_static("none"));
+    Ok(response)
 }
 
-#[put("/files/{path:.*}")]
-pub async fn upsert_file(
-    config: web::Data<config::Folio>,
-    path: web::Path<String>,
-    payload: Multipart,
-) -> Result<impl Responder, FolioError> {
-    let path = validate_path(path)?;
-    let full_path = config.build_full_upload_path(&PathBuf::from(path.as_path()));
-    let file_exists = full_path.exists();
-
-    save_file_field(payload, &full_path).await?;
+fn is_directory(config: &config::Folio, path: &SafePath) -> bool {
+    config.build_full_upload_path(path.as_path()).is_dir()
+}
 
-    let status = if file_exists {
-        StatusCode::OK
-    } else {
-        StatusCode::CREATED
-    };
-    let message = if file_exists {
-        "file updated successfully"
+/// Answer `GET` on a directory with its entries as JSON, sorted by name or,
+/// with `?sort=modified`, newest file first.
+///
+/// Both download routes share this, so a directory never gets the
+/// "not a file" error. Password-protected and one-time files are never
+/// listed. Public listings (`viewer` unset) leave out private files;
+/// private listings show only the private files `viewer` may read.
+/// Symlinks are skipped unless `follow_symlinks` allows them.
+///
+/// The `ETag` is a hash of the listing itself, so a client polling with
+/// `If-None-Match` gets `304` until an entry is added, removed or modified.
+async fn list_directory(
+    req: &HttpRequest,
+    config: &config::Folio,
+    private_index: &PrivateIndexStore,
+    metadata_store: &MetadataStore,
+    expiry_store: &ExpiryStore,
+    path: &SafePath,
+    viewer: Option<&str>,
+) -> Result<HttpResponse, FolioError> {
+    let sort = web::Query::<ListingQuery>::from_query(req.query_string())
+        .map_err(|e| FolioError::BadRequest {
+            reason: format!("invalid sort: {}", e),
+        })?
+        .sort;
+    let full_path = config.build_full_upload_path(path.as_path());
+    let private = private_index
+        .entries_in(path.as_path())
+        .await
+        .map_err(|e| FolioError::store_error(e, "list private index"))?;
+    let hidden: Vec<String> = metadata_store
+        .entries_in(path.as_path())
+        .await
+        .map_err(|e| FolioError::store_error(e, "list file metadata"))?
+        .into_iter()
+        .filter(|m| m.password_hash.is_some() || m.burn)
+        .map(|m| m.path)
+        .collect();
+    let expired = if expiry_store.has_grace_period() {
+        expiry_store
+            .in_grace_period()
+            .await
+            .map_err(|e| FolioError::store_error(e, "list expired files"))?
     } else {
-        "file created successfully"
+        HashSet::new()
     };
 
-    Ok(HttpResponse::build(status).json(json!({ "message": message })))
-}
+    let list_error = |e: std::io::Error| FolioError::store_error(e.to_string(), "list directory");
+    let mut dir = tokio::fs::read_dir(&full_path).await.map_err(list_error)?;
+    let mut entries = Vec::new();
+    while let Some(entry) = dir.next_entry().await.map_err(list_error)? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let mut file_type = entry.file_type().await.map_err(list_error)?;
+        if file_type.is_symlink() {
+            if symlink_violation(config, &path.as_path().join(&name)).is_some() {
+                continue;
+            }
+            match tokio::fs::metadata(entry.path()).await {
+                Ok(metadata) => file_type = metadata.file_type(),
+                Err(_) => continue,
+            }
+        }
 
-#[delete("/files/{path:.*}")]
+        if file_type.is_dir() {
+            entries.push(DirectoryEntry {
+                name,
+                kind: EntryKind::Directory,
+                size: None,
+                modified_unix: None,
+            });
+            continue;
+        }
+        if !file_type.is_file() || meta_file::is_meta_file(Path::new(&name)) {
+            continue;
+        }
+
+        let relative = path.as_path().join(&name).to_string_lossy().to_string();
+        let private_entry = private.iter().find(|e| e.path == relative);
+        let visible = match viewer {
+            None => private_entry.is_none(),
+            Some(email) => {
+                private_entry.is_some_and(|e| e.authorized_emails.iter().any(|em| em == email))
+            }
+        };
+        if !visible || hidden.contains(&relative) || expired.contains(&entry.path()) {
+            continue;
+        }
+
+        let metadata = tokio::fs::metadata(entry.path())
+            .await
+            .map_err(list_error)?;
+        entries.push(DirectoryEntry {
+            name,
+            kind: EntryKind::File,
+            size: Some(metadata.len()),
+            modified_unix: metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
+        });
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    if sort == ListingSort::Modified {
+        // Stable, so ties and directories (no mtime) stay in name order.
+        entries.sort_by_key(|e| std::cmp::Reverse(e.modified_unix));
+    }
+
+    let body = serde_json::to_vec(&DirectoryListing {
+        path: path.to_string(),
+        entries,
+    })
+    .map_err(|e| FolioError::store_error(e.to_string(), "serialize directory listing"))?;
+    let etag = listing_etag(config.hash_algorithm, &body);
+
+    let unchanged = match req.get_header::<header::IfNoneMatch>() {
+        Some(header::IfNoneMatch::Any) => true,
+        Some(header::IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        None => false,
+    };
+    if unchanged {
+        return Ok(HttpResponse::NotModified()
+            .insert_header(header::ETag(etag))
+            .finish());
+    }
+
+    Ok(HttpResponse::Ok()
+        .insert_header(header::ETag(etag))
+        .content_type(mime_guess::mime::APPLICATION_JSON)
+        .body(body))
+}
+
+/// `<algorithm>-<first 128 bits of the digest>`, e.g. `"sha256-2cf24dba…"`.
+fn listing_etag(algorithm: HashAlgorithm, body: &[u8]) -> EntityTag {
+    let mut hex = algorithm.hash_bytes(body);
+    hex.truncate(32);
+    EntityTag::new_strong(format!("{}-{}", algorithm.name(), hex))
+}
+
+/// Stream a stored file, preferring a precompressed `<file>.br` / `<file>.gz`
+/// sibling when the client accepts that encoding and asked for no `Range`.
+/// The variant is served with the original file's `Content-Type` and
+/// filename.
+///
+/// A `content_type` recorded in the file's metadata replaces the one guessed
+/// from the extension.
+async fn serve_upload_file(
+    req: &HttpRequest,
+    config: &config::Folio,
+    path: &SafePath,
+    metadata: Option<&FileMetadata>,
+) -> Result<HttpResponse, FolioError> {
+    let mut file = open_upload_file(config, path).await?;
+    if let Some(mime) = metadata
+        .and_then(|m| m.content_type.as_deref())
+        .and_then(|ct| ct.parse::<mime_guess::Mime>().ok())
+    {
+        file = file.set_content_type(mime);
+    }
+    let file = with_disposition(req, config, path, file, metadata)?;
+    let file = with_charset(config, file);
+
+    for (encoding, suffix) in [
+        (ContentEncoding::Brotli, "br"),
+        (ContentEncoding::Gzip, "gz"),
+    ] {
+        // Ranges index the file itself, so resumed downloads always get it.
+        if req.headers().contains_key(header::RANGE) || !accepts_encoding(req, encoding.as_str()) {
+            continue;
+        }
+
+        let mut variant_path = file.path().as_os_str().to_owned();
+        variant_path.push(".");
+        variant_path.push(suffix);
+        let variant_path = PathBuf::from(variant_path);
+        if !variant_path.is_file() {
+            continue;
+        }
+
+        match NamedFile::open_async(&variant_path).await {
+            Ok(variant) => {
+                let mut response = variant
+                    .set_content_type(file.content_type().clone())
+                    .set_content_disposition(file.content_disposition().clone())
+                    .set_content_encoding(encoding)
+                    .into_response(req);
+                response
+                    .headers_mut()
+                    .insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+                return Ok(response);
+            }
+            Err(e) => log::warn!(
+                "failed to open precompressed variant {}: {}",
+                variant_path.display(),
+                e
+            ),
+        }
+    }
+
+    Ok(file.into_response(req))
+}
+
+/// Set `Content-Disposition` from `?disposition=`, else `inline` for
+/// `inline_content_types` and `attachment` for everything else. The file is
+/// named by its original upload name when one was recorded, slugified with
+/// `slugify_filenames`.
+fn with_disposition(
+    req: &HttpRequest,
+    config: &config::Folio,
+    path: &SafePath,
+    file: NamedFile,
+    metadata: Option<&FileMetadata>,
+) -> Result<NamedFile, FolioError> {
+    let requested = web::Query::<DispositionQuery>::from_query(req.query_string())
+        .map_err(|e| FolioError::BadRequest {
+            reason: format!("invalid disposition: {}", e),
+        })?
+        .disposition;
+    let disposition = requested.unwrap_or_else(|| {
+        let content_type = file.content_type();
+        let inline = config
+            .inline_content_types_in(path.as_path().parent().unwrap_or(Path::new("")))
+            .iter()
+            .filter_map(|pattern| pattern.parse::<MimePattern>().ok())
+            .any(|pattern| pattern.matches(content_type));
+        if inline {
+            Disposition::Inline
+        } else {
+            Disposition::Attachment
+        }
+    });
+
+    let mut content_disposition = file.content_disposition().clone();
+    content_disposition.disposition = match disposition {
+        Disposition::Inline => DispositionType::Inline,
+        Disposition::Attachment => DispositionType::Attachment,
+    };
+    let original_name = metadata
+        .and_then(|m| m.original_name.clone())
+        .or_else(|| read_xattrs(file.path()).original_name)
+        .map(|name| name.replace(|c: char| c.is_control(), ""))
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            if config.slugify_filenames {
+                slugify_file_name(&name)
+            } else {
+                name
+            }
+        });
+    if let Some(name) = original_name {
+        let mut parameters = vec![DispositionParam::Filename(name.clone())];
+        if !name.is_ascii() {
+            parameters.push(DispositionParam::FilenameExt(ExtendedValue {
+                charset: Charset::Ext(String::from("UTF-8")),
+                language_tag: None,
+                value: name.into_bytes(),
+            }));
+        }
+        content_disposition.parameters = parameters;
+    }
+    Ok(file.set_content_disposition(content_disposition))
+}
+
+/// Add a charset to a `text/*` type that has none: the one named by a
+/// UTF-16 byte order mark, else `default_charset`.
+fn with_charset(config: &config::Folio, file: NamedFile) -> NamedFile {
+    let content_type = file.content_type();
+    if content_type.type_() != mime_guess::mime::TEXT
+        || content_type.get_param(mime_guess::mime::CHARSET).is_some()
+    {
+        return file;
+    }
+
+    let charset = match sniff_bom(file.path()) {
+        Some(charset) => charset,
+        None if config.default_charset.is_empty() => return file,
+        None => config.default_charset.as_str(),
+    };
+    match format!("{}; charset={}", content_type.essence_str(), charset).parse() {
+        Ok(mime) => file.set_content_type(mime),
+        Err(_) => {
+            log::warn!("invalid default_charset: {:?}", config.default_charset);
+            file
+        }
+    }
+}
+
+/// Charset announced by a byte order mark at the start of `path`.
+fn sniff_bom(path: &Path) -> Option<&'static str> {
+    use std::io::Read;
+
+    let mut bom = [0u8; 3];
+    let read = std::fs::File::open(path)
+        .and_then(|mut f| f.read(&mut bom))
+        .ok()?;
+    match &bom[..read] {
+        [0xEF, 0xBB, 0xBF] => Some("utf-8"),
+        [0xFF, 0xFE, ..] => Some("utf-16le"),
+        [0xFE, 0xFF, ..] => Some("utf-16be"),
+        _ => None,
+    }
+}
+
+/// Whether `Accept-Encoding` lists `encoding` with a non-zero quality.
+fn accepts_encoding(req: &HttpRequest, encoding: &str) -> bool {
+    req.headers()
+        .get_all(header::ACCEPT_ENCODING)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|item| {
+            let mut params = item.split(';').map(str::trim);
+            let name = params.next().unwrap_or_default();
+            let rejected = params.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q <= 0.0)
+            });
+            name.eq_ignore_ascii_case(encoding) && !rejected
+        })
+}
+
+async fn open_upload_file(
+    config: &config::Folio,
+    path: &SafePath,
+) -> Result<NamedFile, FolioError> {
+    let full_path = config.build_full_upload_path(&PathBuf::from(path.as_path()));
+
+    if !full_path.exists() {
+        return Err(FolioError::NotFound {
+            path: path.to_string(),
+        });
+    }
+
+    if !full_path.is_file() {
+        return Err(FolioError::BadRequest {
+            reason: format!("path is not a file: {}", path),
+        });
+    }
+
+    NamedFile::open_async(full_path)
+        .await
+        .map_err(|e| FolioError::Internal {
+            source: format!("failed to open file: {}", e),
+            context: Some(format!("open file: {}", path)),
+        })
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValidateResponse {
+    pub valid: bool,
+    /// Why the path would be refused, one message per failed check.
+    pub reasons: Vec<String>,
+    /// Where the file would be stored when that differs from the request
+    /// (`slugify_filenames`, `on_conflict=rename`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+fn wants_validation(ctx: &GuardContext) -> bool {
+    has_query_param(ctx, "validate")
+}
+
+fn has_query_param(ctx: &GuardContext, name: &str) -> bool {
+    ctx.head().uri.query().is_some_and(|query| {
+        query
+            .split('&')
+            .any(|pair| pair.split('=').next() == Some(name))
+    })
+}
+
+/// `GET /files/<path>?validate`: run the checks of `POST /files/<path>`
+/// (`?on_conflict=` included) and report every one that fails, so forms
+/// can give feedback before uploading. Nothing is written; the filesystem
+/// is only asked whether the path exists. Always `200`.
+///
+/// Must be registered before `get_file`, which would serve the path.
+#[get("/files/{path:.*}", guard = "wants_validation")]
+pub async fn validate_file_path(
+    config: web::Data<config::Folio>,
+    path: web::Path<String>,
+    query: web::Query<CreateQuery>,
+) -> Result<impl Responder, FolioError> {
+    let requested = path.into_inner();
+    let mut reasons = Vec::new();
+    if let Err(e) = ensure_writable(&config) {
+        reasons.push(e.message());
+    }
+
+    let mut final_path = None;
+    match validate_write_path(&config, &requested) {
+        Err(e) => reasons.push(e.message()),
+        Ok(path) => {
+            final_path = slugified_path(&requested, &path);
+            let checks = [
+                check_filename_length(&config, &path),
+                check_extension_allowed(
+                    &config,
+                    path.as_path().extension().and_then(|ext| ext.to_str()),
+                ),
+            ];
+            reasons.extend(
+                checks
+                    .into_iter()
+                    .filter_map(|check| check.err())
+                    .map(|e| e.message()),
+            );
+
+            let full_path = config.build_full_upload_path(path.as_path());
+            if full_path.exists() {
+                let conflict = FolioError::Conflict {
+                    path: path.to_string(),
+                };
+                match query.on_conflict {
+                    ConflictStrategy::Error => reasons.push(conflict.message()),
+                    ConflictStrategy::Overwrite if !full_path.is_file() => {
+                        reasons.push(format!("path is not a file: {}", path))
+                    }
+                    ConflictStrategy::Overwrite => {}
+                    ConflictStrategy::Rename => match find_free_name(&config, path.as_path()) {
+                        Some(renamed) => final_path = Some(renamed.to_string_lossy().to_string()),
+                        None => reasons.push(conflict.message()),
+                    },
+                }
+            }
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(ValidateResponse {
+        valid: reasons.is_empty(),
+        reasons,
+        path: final_path,
+    }))
+}
+
+#[post(
+    "/files/{path:.*}",
+    wrap = "from_fn(enforce_quota)",
+    wrap = "from_fn(limit_uploads)"
+)]
+pub async fn create_file(
+    req: HttpRequest,
+    config: web::Data<config::Folio>,
+    dir_counter: web::Data<Arc<DirEntryCounter>>,
+    path: web::Path<String>,
+    query: web::Query<CreateQuery>,
+    payload: Multipart,
+) -> Result<impl Responder, FolioError> {
+    ensure_writable(&config)?;
+    let requested = path.into_inner();
+    let path = validate_write_path(&config, &requested)?;
+    check_filename_length(&config, &path)?;
+    check_extension_allowed(
+        &config,
+        path.as_path().extension().and_then(|ext| ext.to_str()),
+    )?;
+    let mut full_path = config.build_full_upload_path(&PathBuf::from(path.as_path()));
+    let mut renamed_to = slugified_path(&requested, &path);
+    let mut overwritten = false;
+
+    if full_path.exists() {
+        match query.on_conflict {
+            ConflictStrategy::Error => {
+                return Err(FolioError::Conflict {
+                    path: path.to_string(),
+                });
+            }
+            ConflictStrategy::Overwrite => {
+                if !full_path.is_file() {
+                    return Err(FolioError::BadRequest {
+                        reason: format!("path is not a file: {}", path),
+                    });
+                }
+                overwritten = true;
+            }
+            ConflictStrategy::Rename => {
+                let renamed = find_free_name(&config, path.as_path()).ok_or_else(|| {
+                    FolioError::Conflict {
+                        path: path.to_string(),
+                    }
+                })?;
+                full_path = config.build_full_upload_path(&renamed);
+                renamed_to = Some(renamed.to_string_lossy().to_string());
+            }
+        }
+    }
+
+    let dir = full_path.parent().unwrap_or(&full_path).to_path_buf();
+    if !overwritten {
+        dir_counter.ensure_capacity(&dir, config.max_files_per_dir)?;
+    }
+
+    save_file_field(payload, &full_path, &config).await?;
+
+    if overwritten {
+        return Ok(HttpResponse::Ok().json(FileResponse {
+            path: renamed_to,
+            ..FileResponse::new("file updated successfully")
+        }));
+    }
+
+    dir_counter.record_created(&dir);
+    let mut response = HttpResponse::build(StatusCode::CREATED);
+    if let Some(renamed) = &renamed_to {
+        log::info!("POST /files stored {} as {}", requested, renamed);
+        response.append_header((
+            "Location",
+            public_url(&req, &config, &format!("/files/{}", encode_path(renamed))),
+        ));
+    }
+    Ok(response.json(FileResponse {
+        path: renamed_to,
+        ..FileResponse::new("file created successfully")
+    }))
+}
+
+/// The path a write actually went to, when `slugify_filenames` changed the
+/// `requested` one.
+fn slugified_path(requested: &str, path: &SafePath) -> Option<String> {
+    let path = path.to_string();
+    (path != requested).then_some(path)
+}
+
+/// Find the first `name (n).ext` sibling of `path` that does not exist yet,
+/// or `name-n.ext` with `slugify_filenames`.
+fn find_free_name(config: &config::Folio, path: &Path) -> Option<PathBuf> {
+    const MAX_ATTEMPTS: u32 = 1000;
+
+    let stem = path.file_stem()?.to_string_lossy();
+    let extension = path.extension().map(|ext| ext.to_string_lossy());
+
+    (1..=MAX_ATTEMPTS)
+        .map(|n| {
+            let file_name = match (&extension, config.slugify_filenames) {
+                (Some(ext), false) => format!("{} ({}).{}", stem, n, ext),
+                (None, false) => format!("{} ({})", stem, n),
+                (Some(ext), true) => format!("{}-{}.{}", stem, n, ext),
+                (None, true) => format!("{}-{}", stem, n),
+            };
+            path.with_file_name(file_name)
+        })
+        .find(|candidate| !config.build_full_upload_path(candidate).exists())
+}
+
+fn wants_expiration(ctx: &GuardContext) -> bool {
+    has_query_param(ctx, "expire")
+}
+
+/// Replace the expiration of an existing file with a new TTL.
+///
+/// Must be registered before `upsert_file`, whose catch-all pattern would
+/// otherwise treat `.../expiration` as a file path. Only requests with
+/// `?expire=` land here, so files named `expiration` can still be written.
+#[put("/files/{path:.*}/expiration", guard = "wants_expiration")]
+pub async fn update_expiration(
+    config: web::Data<config::Folio>,
+    expiry_store: web::Data<Arc<ExpiryStore>>,
+    path: web::Path<String>,
+    query: web::Query<ExpirationQuery>,
+) -> Result<impl Responder, FolioError> {
+    ensure_writable(&config)?;
+    let path = validate_path(&config, path)?;
+    // `never` is only meaningful against a cap, where it clamps or rejects.
+    if query.expire != "never" || max_ttl(&config).is_none() {
+        parse_duration(&query.expire).map_err(|e| FolioError::BadRequest {
+            reason: format!("invalid expire '{}': {}", query.expire, e),
+        })?;
+    }
+    let (ttl, expire_clamped) = resolve_ttl(&config, Some(&query.expire))?;
+    let full_path = config.build_full_upload_path(&PathBuf::from(path.as_path()));
+
+    if !full_path.exists() {
+        return Err(FolioError::NotFound {
+            path: path.to_string(),
+        });
+    }
+
+    if !full_path.is_file() {
+        return Err(FolioError::BadRequest {
+            reason: format!("path is not a file: {}", path),
+        });
+    }
+
+    let expire_at_unix = expiry_store
+        .schedule(&full_path, ttl)
+        .await
+        .map_err(|e| FolioError::store_error(e, "schedule expiration"))?;
+    meta_file::set_expire_at(&full_path, Some(expire_at_unix));
+    log::info!("expiration of {} updated to {}", path, expire_at_unix);
+
+    Ok(HttpResponse::Ok().json(FileResponse {
+        expire_at_unix: Some(expire_at_unix),
+        expire_clamped,
+        ..FileResponse::new("expiration updated successfully")
+    }))
+}
+
+/// Restore a file in its `expire_grace_period`, so it is served and listed
+/// again. It gets a new expiration from `?expire=`, like a new upload.
+///
+/// Must be registered before `create_file`, whose catch-all pattern would
+/// otherwise treat `.../unexpire` as a file path.
+#[post("/files/{path:.*}/unexpire")]
+pub async fn unexpire_file(
+    config: web::Data<config::Folio>,
+    expiry_store: web::Data<Arc<ExpiryStore>>,
+    path: web::Path<String>,
+    query: web::Query<UnexpireQuery>,
+) -> Result<impl Responder, FolioError> {
+    ensure_writable(&config)?;
+    let path = validate_path(&config, path)?;
+    if let Some(expire) = query.expire.as_deref()
+        && (expire != "never" || max_ttl(&config).is_none())
+    {
+        parse_duration(expire).map_err(|e| FolioError::BadRequest {
+            reason: format!("invalid expire '{}': {}", expire, e),
+        })?;
+    }
+    let (ttl, expire_clamped) = resolve_ttl(&config, query.expire.as_deref())?;
+    let full_path = config.build_full_upload_path(&PathBuf::from(path.as_path()));
+
+    if !full_path.is_file() {
+        return Err(FolioError::NotFound {
+            path: path.to_string(),
+        });
+    }
+    let delete_at = expiry_store
+        .delete_at(&full_path)
+        .await
+        .map_err(|e| FolioError::store_error(e, "read expiration"))?;
+    if delete_at.is_none() {
+        return Err(FolioError::BadRequest {
+            reason: format!("file has not expired: {}", path),
+        });
+    }
+
+    let expire_at_unix = expiry_store
+        .schedule(&full_path, ttl)
+        .await
+        .map_err(|e| FolioError::store_error(e, "schedule expiration"))?;
+    meta_file::set_expire_at(&full_path, Some(expire_at_unix));
+    log::info!(
+        "restored expired file {}, expiring at {}",
+        path,
+        expire_at_unix
+    );
+
+    Ok(HttpResponse::Ok().json(FileResponse {
+        expire_at_unix: Some(expire_at_unix),
+        expire_clamped,
+        ..FileResponse::new("file restored successfully")
+    }))
+}
+
+/// Set a file's modification time to now, or to `?mtime=`, without
+/// rewriting it. Changes its `ETag` and `Last-Modified`.
+///
+/// Must be registered before `create_file`, whose catch-all pattern would
+/// otherwise treat `.../touch` as a file path.
+#[post("/files/{path:.*}/touch")]
+pub async fn touch_file(
+    config: web::Data<config::Folio>,
+    path: web::Path<String>,
+    query: web::Query<TouchQuery>,
+) -> Result<impl Responder, FolioError> {
+    ensure_writable(&config)?;
+    let path = validate_path(&config, path)?;
+    let full_path = config.build_full_upload_path(&PathBuf::from(path.as_path()));
+
+    if !full_path.exists() {
+        return Err(FolioError::NotFound {
+            path: path.to_string(),
+        });
+    }
+
+    if !full_path.is_file() {
+        return Err(FolioError::BadRequest {
+            reason: format!("path is not a file: {}", path),
+        });
+    }
+
+    let mtime = match query.mtime {
+        Some(secs) => filetime::FileTime::from_unix_time(secs, 0),
+        None => filetime::FileTime::now(),
+    };
+    let touch_path = full_path.clone();
+    web::block(move || filetime::set_file_mtime(&touch_path, mtime))
+        .await
+        .map_err(|e| FolioError::store_error(e.to_string(), "touch file"))?
+        .map_err(|e| FolioError::store_error(e.to_string(), "touch file"))?;
+    log::info!("touched {} (mtime {})", path, mtime.unix_seconds());
+
+    Ok(HttpResponse::Ok().json(FileResponse {
+        modified_unix: u64::try_from(mtime.unix_seconds()).ok(),
+        ..FileResponse::new("file touched successfully")
+    }))
+}
+
+#[put(
+    "/files/{path:.*}",
+    wrap = "from_fn(enforce_quota)",
+    wrap = "from_fn(limit_uploads)"
+)]
+pub async fn upsert_file(
+    config: web::Data<config::Folio>,
+    dir_counter: web::Data<Arc<DirEntryCounter>>,
+    path: web::Path<String>,
+    payload: Multipart,
+) -> Result<impl Responder, FolioError> {
+    ensure_writable(&config)?;
+    let requested = path.into_inner();
+    let path = validate_write_path(&config, &requested)?;
+    check_filename_length(&config, &path)?;
+    check_extension_allowed(
+        &config,
+        path.as_path().extension().and_then(|ext| ext.to_str()),
+    )?;
+    let full_path = config.build_full_upload_path(&PathBuf::from(path.as_path()));
+    let file_exists = full_path.exists();
+    let dir = full_path.parent().unwrap_or(&full_path);
+
+    if !file_exists {
+        dir_counter.ensure_capacity(dir, config.max_files_per_dir)?;
+    }
+
+    save_file_field(payload, &full_path, &config).await?;
+
+    if !file_exists {
+        dir_counter.record_created(dir);
+    }
+
+    let status = if file_exists {
+        StatusCode::OK
+    } else {
+        StatusCode::CREATED
+    };
+    let message = if file_exists {
+        "file updated successfully"
+    } else {
+        "file created successfully"
+    };
+
+    Ok(HttpResponse::build(status).json(FileResponse {
+        path: slugified_path(&requested, &path),
+        ..FileResponse::new(message)
+    }))
+}
+
+/// Whether `If-Match` (if any) allows changing the file at `full_path`.
+///
+/// Uses the strong comparison RFC 9110 requires, against the same `ETag`
+/// downloads carry. Directories have no `ETag`, so only `*` matches them.
+fn if_match_allows(req: &HttpRequest, full_path: &Path) -> bool {
+    let Some(IfMatch::Items(tags)) = req.get_header::<IfMatch>() else {
+        return true;
+    };
+    let current = std::fs::metadata(full_path)
+        .ok()
+        .filter(|m| m.is_file())
+        .and_then(|m| file_etag(&m));
+    current.is_some_and(|etag| tags.iter().any(|tag| tag.strong_eq(&etag)))
+}
+
+#[delete("/files/{path:.*}")]
 pub async fn delete_file(
+    req: HttpRequest,
     config: web::Data<config::Folio>,
+    metadata_store: web::Data<Arc<MetadataStore>>,
     path: web::Path<String>,
+    query: web::Query<DeleteQuery>,
 ) -> Result<impl Responder, FolioError> {
-    let path = validate_path(path)?;
+    ensure_writable(&config)?;
+    let path = validate_path(&config, path)?;
     let full_path = config.build_full_upload_path(&PathBuf::from(path.as_path()));
 
     if !full_path.exists() {
@@ -254,457 +1778,2430 @@ pub async fn delete_file(
         });
     }
 
-    if !full_path.is_file() {
-        return Err(FolioError::BadRequest {
-            reason: format!("path is not a file: {}", path),
-        });
-    }
+    let is_dir = full_path.is_dir();
+    if is_dir && (!query.recursive || path.as_path().as_os_str().is_empty()) {
+        return Err(FolioError::BadRequest {
+            reason: format!("path is not a file: {}", path),
+        });
+    }
+    if !if_match_allows(&req, &full_path) {
+        return Err(FolioError::PreconditionFailed {
+            path: path.to_string(),
+        });
+    }
+    if config.require_delete_confirmation && !delete_confirmed(&req, &query, &path) {
+        return Err(FolioError::ConfirmationRequired {
+            path: path.to_string(),
+        });
+    }
+
+    let delete_error = |e: std::io::Error| {
+        let message = format!("failed to delete file: {:?}", e);
+        log::error!("DELETE /files error: {}", message);
+        FolioError::Internal {
+            source: message,
+            context: Some(format!("delete file: {}", path)),
+        }
+    };
+
+    let freed_bytes = disk_usage(&full_path).map_err(delete_error)?;
+    if is_dir {
+        std::fs::remove_dir_all(&full_path).map_err(delete_error)?;
+    } else {
+        std::fs::remove_file(&full_path).map_err(delete_error)?;
+        meta_file::remove(&full_path);
+    }
+
+    // Don't let a later file at the same path inherit a stale password.
+    if let Err(e) = metadata_store.remove(path.as_path()).await {
+        log::error!("failed to drop metadata for {}: {}", path, e);
+    }
+
+    log::info!("deleted {} ({} bytes freed)", path, freed_bytes);
+
+    let message = if is_dir {
+        "directory deleted successfully"
+    } else {
+        "file deleted successfully"
+    };
+    Ok(HttpResponse::Ok().json(FileResponse {
+        freed_bytes: Some(freed_bytes),
+        ..FileResponse::new(message)
+    }))
+}
+
+/// Total size in bytes of a file, or of every file below a directory.
+///
+/// Symlinks are counted by their own size and never followed.
+pub fn disk_usage(path: &Path) -> std::io::Result<u64> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    let mut total = 0;
+    for entry in std::fs::read_dir(path)? {
+        total += disk_usage(&entry?.path())?;
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::BodySize;
+    use actix_web::{App, http::header, test};
+    use std::collections::BTreeMap;
+
+    use crate::test_utils::make_hs256_token;
+
+    fn test_state() -> (
+        config::Folio,
+        Arc<PrivateIndexStore>,
+        Arc<AccessAuth>,
+        tempfile::TempDir,
+    ) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = config::Folio {
+            uploads_path: temp_dir.path().to_string_lossy().to_string(),
+            data_path: temp_dir.path().to_string_lossy().to_string(),
+            ..config::Folio::default()
+        };
+
+        let private_index = Arc::new(PrivateIndexStore::new(&config));
+        let access_auth = Arc::new(crate::auth::AccessAuth::from_parts(
+            "https://issuer.example.com",
+            "folio-app",
+            Some("test-secret"),
+        ));
+
+        (config, private_index, access_auth, temp_dir)
+    }
+
+    fn test_expiry_store(temp_dir: &tempfile::TempDir) -> Arc<ExpiryStore> {
+        Arc::new(ExpiryStore::new(&config::Folio {
+            uploads_path: temp_dir.path().to_string_lossy().to_string(),
+            data_path: temp_dir.path().to_string_lossy().to_string(),
+            ..config::Folio::default()
+        }))
+    }
+
+    fn test_metadata_store(temp_dir: &tempfile::TempDir) -> Arc<MetadataStore> {
+        Arc::new(MetadataStore::new(&config::Folio {
+            data_path: temp_dir.path().to_string_lossy().to_string(),
+            ..config::Folio::default()
+        }))
+    }
+
+    fn multipart_body(filename: &str, content_type: Option<&str>, content: &str) -> String {
+        let content_type_header = content_type
+            .map(|ct| format!("Content-Type: {}\r\n", ct))
+            .unwrap_or_default();
+
+        format!(
+            "--X-BOUNDARY\r\n\
+             Content-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
+             {}\
+             \r\n\
+             {}\r\n\
+             --X-BOUNDARY--\r\n",
+            filename, content_type_header, content
+        )
+    }
+
+    #[actix_web::test]
+    async fn get_password_protected_file() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        std::fs::write(temp_dir.path().join("locked.txt"), "secret").unwrap();
+        let metadata_store = test_metadata_store(&temp_dir);
+        let password_hash = metadata::hash_password("hunter2").unwrap();
+        metadata_store
+            .update(Path::new("locked.txt"), |m| {
+                m.password_hash = Some(password_hash)
+            })
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(metadata_store))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_file),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/files/locked.txt")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(response.headers().contains_key(header::WWW_AUTHENTICATE));
+
+        let req = test::TestRequest::get()
+            .uri("/files/locked.txt?password=wrong")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::get()
+            .uri("/files/locked.txt?password=hunter2")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .uri("/files/locked.txt")
+            .insert_header((
+                header::AUTHORIZATION,
+                format!("Basic {}", BASE64.encode("anyone:hunter2")),
+            ))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(test::read_body(response).await, "secret");
+    }
+
+    #[actix_web::test]
+    async fn get_file_uses_stored_content_type() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        std::fs::write(temp_dir.path().join("data.bin"), "a,b").unwrap();
+        let metadata_store = test_metadata_store(&temp_dir);
+        metadata_store
+            .update(Path::new("data.bin"), |m| {
+                m.content_type = Some("text/csv".to_string())
+            })
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(metadata_store))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_file),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/files/data.bin").to_request();
+        let response = test::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let content_type = response.headers().get(header::CONTENT_TYPE).unwrap();
+        assert!(content_type.to_str().unwrap().starts_with("text/csv"));
+    }
+
+    #[actix_web::test]
+    async fn text_downloads_get_a_charset() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        std::fs::write(temp_dir.path().join("notes.txt"), "héllo").unwrap();
+        std::fs::write(temp_dir.path().join("wide.txt"), [0xFF, 0xFE, b'h', 0]).unwrap();
+        std::fs::write(temp_dir.path().join("logo.png"), "not really").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_file),
+        )
+        .await;
+
+        for (path, expected) in [
+            ("notes.txt", "text/plain; charset=utf-8"),
+            ("wide.txt", "text/plain; charset=utf-16le"),
+            ("logo.png", "image/png"),
+        ] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/files/{}", path))
+                .to_request();
+            let response = test::call_service(&app, req).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers().get(header::CONTENT_TYPE).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn disposition_follows_type_query_and_original_name() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        std::fs::write(temp_dir.path().join("logo.png"), "not really").unwrap();
+        std::fs::write(temp_dir.path().join("a1B2c3D4.csv"), "a,b").unwrap();
+        let metadata_store = test_metadata_store(&temp_dir);
+        metadata_store
+            .update(Path::new("a1B2c3D4.csv"), |m| {
+                m.original_name = Some("Q3 résumé.csv".to_string())
+            })
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(metadata_store))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_file),
+        )
+        .await;
+
+        for (uri, expected) in [
+            ("/files/logo.png", "inline; filename=\"logo.png\""),
+            (
+                "/files/logo.png?disposition=attachment",
+                "attachment; filename=\"logo.png\"",
+            ),
+            (
+                "/files/a1B2c3D4.csv",
+                "attachment; filename=\"Q3 résumé.csv\"; filename*=UTF-8''Q3%20r%C3%A9sum%C3%A9.csv",
+            ),
+        ] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let response = test::call_service(&app, req).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers().get(header::CONTENT_DISPOSITION).unwrap(),
+                expected,
+                "{}",
+                uri
+            );
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/files/logo.png?disposition=preview")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn named_root_replaces_inline_content_types() {
+        let (mut config, private_index, access_auth, temp_dir) = test_state();
+        config.roots = BTreeMap::from([(
+            "scans".to_string(),
+            config::RootConfig {
+                inline_content_types: Some(Vec::new()),
+                ..config::RootConfig::default()
+            },
+        )]);
+        std::fs::create_dir(temp_dir.path().join("scans")).unwrap();
+        std::fs::write(temp_dir.path().join("scans/page.png"), "not really").unwrap();
+        std::fs::write(temp_dir.path().join("page.png"), "not really").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_file),
+        )
+        .await;
+
+        for (uri, expected) in [
+            ("/files/scans/page.png", "attachment; filename=\"page.png\""),
+            ("/files/page.png", "inline; filename=\"page.png\""),
+        ] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let response = test::call_service(&app, req).await;
+            assert_eq!(
+                response.headers().get(header::CONTENT_DISPOSITION).unwrap(),
+                expected,
+                "{}",
+                uri
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn get_directory_lists_visible_entries() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        let docs = temp_dir.path().join("docs");
+        std::fs::create_dir_all(docs.join("sub")).unwrap();
+        std::fs::write(docs.join("b.txt"), "bb").unwrap();
+        std::fs::write(docs.join("a.txt"), "a").unwrap();
+        std::fs::write(docs.join("a.txt.meta.json"), "{}").unwrap();
+        std::fs::write(docs.join("secret.txt"), "s").unwrap();
+        std::fs::write(docs.join("locked.txt"), "l").unwrap();
+        private_index
+            .mark_private(Path::new("docs/secret.txt"), vec![])
+            .await
+            .unwrap();
+        let metadata_store = test_metadata_store(&temp_dir);
+        metadata_store
+            .update(Path::new("docs/locked.txt"), |m| {
+                m.password_hash = Some("hash".to_string())
+            })
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(metadata_store))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_file),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/files/docs").to_request();
+        let response = test::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["path"], "docs");
+        let entries = body["entries"].as_array().unwrap();
+        let names: Vec<&str> = entries
+            .iter()
+            .map(|e| e["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["a.txt", "b.txt", "sub"]);
+        assert_eq!(entries[1]["size"], 2);
+        assert_eq!(entries[2]["type"], "directory");
+
+        let req = test::TestRequest::get()
+            .uri("/files/docs/a.txt.meta.json")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn directory_listing_sorts_by_mtime_and_revalidates() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        let docs = temp_dir.path().join("docs");
+        std::fs::create_dir_all(&docs).unwrap();
+        for (name, age) in [("old.txt", 60), ("new.txt", 0)] {
+            let file = std::fs::File::create(docs.join(name)).unwrap();
+            file.set_modified(std::time::SystemTime::now() - std::time::Duration::from_secs(age))
+                .unwrap();
+        }
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_file),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/files/docs?sort=modified")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers().get(header::ETAG).unwrap().clone();
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["entries"][0]["name"], "new.txt");
+
+        let req = test::TestRequest::get()
+            .uri("/files/docs?sort=modified")
+            .insert_header((header::IF_NONE_MATCH, etag.clone()))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        std::fs::write(docs.join("added.txt"), "a").unwrap();
+        let req = test::TestRequest::get()
+            .uri("/files/docs?sort=modified")
+            .insert_header((header::IF_NONE_MATCH, etag))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .uri("/files/docs?sort=size")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn directory_manifest_lists_public_files_recursively() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        let docs = temp_dir.path().join("docs");
+        std::fs::create_dir_all(docs.join("sub")).unwrap();
+        std::fs::write(docs.join("a.txt"), "abc").unwrap();
+        std::fs::write(docs.join("sub/b.txt"), "abc").unwrap();
+        std::fs::write(docs.join("secret.txt"), "s").unwrap();
+        private_index
+            .mark_private(Path::new("docs/secret.txt"), vec![])
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_file),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/files/docs?manifest=md5")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            test::read_body(response).await,
+            "900150983cd24fb0d6963f7d28e17f72  a.txt\n\
+             900150983cd24fb0d6963f7d28e17f72  sub/b.txt\n"
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/files/docs/a.txt?manifest=sha256")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn directory_archive_streams_public_files_as_tar_gz() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        let docs = temp_dir.path().join("docs");
+        std::fs::create_dir_all(docs.join("sub")).unwrap();
+        std::fs::write(docs.join("a.txt"), "abc").unwrap();
+        std::fs::write(docs.join("sub/b.txt"), "def").unwrap();
+        std::fs::write(docs.join("secret.txt"), "s").unwrap();
+        private_index
+            .mark_private(Path::new("docs/secret.txt"), vec![])
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_file),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/files/docs?archive=tar.gz")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("Content-Disposition").unwrap(),
+            "attachment; filename=\"docs.tar.gz\""
+        );
+        let body = test::read_body(response).await;
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(&body[..]));
+        let names: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, ["docs/a.txt", "docs/sub/b.txt"]);
+
+        for uri in [
+            "/files/docs?archive=zip",
+            "/files/docs/a.txt?archive=tar.gz",
+        ] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let response = test::call_service(&app, req).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+        }
+    }
+
+    #[actix_web::test]
+    async fn archive_leaves_out_files_in_grace_period() {
+        let (mut config, private_index, access_auth, temp_dir) = test_state();
+        config.expire_grace_period = "7d".to_string();
+        let docs = temp_dir.path().join("docs");
+        std::fs::create_dir_all(&docs).unwrap();
+        std::fs::write(docs.join("a.txt"), "abc").unwrap();
+        std::fs::write(docs.join("expired.txt"), "old").unwrap();
+        // As left by a sweep that found the file expired.
+        let index = serde_json::json!({ "entries": [{
+            "path": config.build_full_upload_path(Path::new("docs/expired.txt")),
+            "expire_at_unix": 1,
+            "delete_at_unix": crate::test_utils::now_ts() + 3600,
+        }] });
+        std::fs::write(temp_dir.path().join("expiry-index.json"), index.to_string()).unwrap();
+        let expiry_store = Arc::new(ExpiryStore::new(&config));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(expiry_store))
+                .service(get_file),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/files/docs?archive=tar.gz")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = test::read_body(response).await;
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(&body[..]));
+        let names: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, ["docs/a.txt"]);
+    }
+
+    #[actix_web::test]
+    async fn get_private_directory_lists_authorized_files_only() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        let docs = temp_dir.path().join("docs");
+        std::fs::create_dir_all(&docs).unwrap();
+        for name in ["public.txt", "mine.txt", "theirs.txt"] {
+            std::fs::write(docs.join(name), name).unwrap();
+        }
+        private_index
+            .mark_private(
+                Path::new("docs/mine.txt"),
+                vec!["user@example.com".to_string()],
+            )
+            .await
+            .unwrap();
+        private_index
+            .mark_private(
+                Path::new("docs/theirs.txt"),
+                vec!["other@example.com".to_string()],
+            )
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_private_file),
+        )
+        .await;
+
+        let token = make_hs256_token(
+            "test-secret",
+            "user-1",
+            Some("user@example.com"),
+            &[],
+            "https://issuer.example.com",
+            "folio-app",
+            3600,
+        );
+        let req = test::TestRequest::get()
+            .uri("/private-files/docs")
+            .insert_header(("Cf-Access-Jwt-Assertion", token))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        let entries = body["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["name"], "mine.txt");
+    }
+
+    #[actix_web::test]
+    async fn get_one_time_file_serves_once() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        let full_path = temp_dir.path().join("once.txt");
+        std::fs::write(&full_path, "secret").unwrap();
+        let metadata_store = test_metadata_store(&temp_dir);
+        metadata_store
+            .update(Path::new("once.txt"), |m| m.burn = true)
+            .await
+            .unwrap();
+        let expiry_store = test_expiry_store(&temp_dir);
+        let full_path = config.build_full_upload_path(Path::new("once.txt"));
+        expiry_store
+            .schedule(&full_path, std::time::Duration::from_secs(60))
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(metadata_store.clone()))
+                .app_data(web::Data::new(expiry_store.clone()))
+                .service(get_file),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/files/once.txt").to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(test::read_body(response).await, "secret");
+
+        assert!(!full_path.exists());
+        assert_eq!(expiry_store.pending_count().await.unwrap(), 0);
+        assert!(
+            metadata_store
+                .get(Path::new("once.txt"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let leftovers = std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter(|e| e.file_name().to_string_lossy().starts_with(".burn-"))
+            .count();
+        assert_eq!(leftovers, 0);
+
+        let req = test::TestRequest::get().uri("/files/once.txt").to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn get_file_thumbnail() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        let mut png = std::io::Cursor::new(Vec::new());
+        image::RgbImage::new(40, 20)
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        std::fs::write(temp_dir.path().join("photo.png"), png.into_inner()).unwrap();
+        std::fs::write(temp_dir.path().join("notes.txt"), "text").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_file),
+        )
+        .await;
+
+        for _ in 0..2 {
+            let req = test::TestRequest::get()
+                .uri("/files/photo.png?thumb=10x10")
+                .to_request();
+            let response = test::call_service(&app, req).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers().get(header::CONTENT_TYPE).unwrap(),
+                "image/png"
+            );
+            let body = test::read_body(response).await;
+            let thumb = image::load_from_memory(&body).unwrap();
+            assert_eq!((thumb.width(), thumb.height()), (10, 5));
+        }
+        let cached = std::fs::read_dir(temp_dir.path().join("thumbnails"))
+            .unwrap()
+            .count();
+        assert_eq!(cached, 1);
+
+        let req = test::TestRequest::get()
+            .uri("/files/notes.txt?thumb=10x10")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let req = test::TestRequest::get()
+            .uri("/files/photo.png?thumb=4096x4096")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn create_file_success() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(create_file),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/files/test.txt")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body(
+                "test.txt",
+                Some("text/plain"),
+                "test content",
+            ))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let content = std::fs::read_to_string(temp_dir.path().join("test.txt")).unwrap();
+        assert_eq!(content, "test content");
+    }
+
+    #[actix_web::test]
+    async fn create_file_rejects_oversize_upload_and_removes_partial_file() {
+        let (mut config, private_index, access_auth, temp_dir) = test_state();
+        config.max_upload_size = 4;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(create_file),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/files/big.txt")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("big.txt", Some("text/plain"), "too large"))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(!temp_dir.path().join("big.txt").exists());
+    }
+
+    #[actix_web::test]
+    async fn upsert_rejects_oversize_upload_and_keeps_existing_file() {
+        let (mut config, private_index, access_auth, temp_dir) = test_state();
+        config.max_upload_size = 4;
+        std::fs::write(temp_dir.path().join("test.txt"), "orig").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(upsert_file),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/files/test.txt")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("test.txt", Some("text/plain"), "too large"))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let content = std::fs::read_to_string(temp_dir.path().join("test.txt")).unwrap();
+        assert_eq!(content, "orig");
+        let leftovers = std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name().to_string_lossy().contains(".part-"))
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[actix_web::test]
+    async fn rejects_file_names_over_max_filename_length() {
+        let (mut config, private_index, access_auth, temp_dir) = test_state();
+        config.max_filename_length = 8;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(upsert_file),
+        )
+        .await;
+
+        for (name, expected) in [
+            ("long-dir-name/a.txt", StatusCode::CREATED),
+            ("notes-2024.txt", StatusCode::UNPROCESSABLE_ENTITY),
+        ] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/files/{}", name))
+                .insert_header((
+                    header::CONTENT_TYPE,
+                    "multipart/form-data; boundary=X-BOUNDARY",
+                ))
+                .set_payload(multipart_body("a.txt", Some("text/plain"), "content"))
+                .to_request();
+            let response = test::call_service(&app, req).await;
+            assert_eq!(response.status(), expected, "{}", name);
+        }
+        assert!(!temp_dir.path().join("notes-2024.txt").exists());
+    }
+
+    #[actix_web::test]
+    async fn create_file_applies_min_upload_bytes() {
+        let (mut config, private_index, access_auth, temp_dir) = test_state();
+        config.min_upload_bytes = 1;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(create_file),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/files/empty.txt")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("empty.txt", Some("text/plain"), ""))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(!temp_dir.path().join("empty.txt").exists());
+    }
+
+    #[actix_web::test]
+    async fn upsert_rejects_too_small_upload_and_keeps_existing_file() {
+        let (mut config, private_index, access_auth, temp_dir) = test_state();
+        config.min_upload_bytes = 1;
+        std::fs::write(temp_dir.path().join("test.txt"), "original").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(upsert_file),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/files/test.txt")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("test.txt", Some("text/plain"), ""))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let content = std::fs::read_to_string(temp_dir.path().join("test.txt")).unwrap();
+        assert_eq!(content, "original");
+    }
+
+    #[actix_web::test]
+    async fn create_file_with_nested_path() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(create_file),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/files/folder/subfolder/test.txt")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body(
+                "test.txt",
+                Some("text/plain"),
+                "nested content",
+            ))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let content =
+            std::fs::read_to_string(temp_dir.path().join("folder/subfolder/test.txt")).unwrap();
+        assert_eq!(content, "nested content");
+    }
+
+    #[actix_web::test]
+    async fn create_file_already_exists() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        std::fs::write(temp_dir.path().join("test.txt"), "content 1").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(create_file),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/files/test.txt")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("test.txt", Some("text/plain"), "content 2"))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["code"], "FILE_EXISTS");
+        assert_eq!(body["message"], "file already exists: test.txt");
+        let content = std::fs::read_to_string(temp_dir.path().join("test.txt")).unwrap();
+        assert_eq!(content, "content 1");
+    }
+
+    #[actix_web::test]
+    async fn upsert_creates_new_file() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(upsert_file),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/files/test.txt")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body(
+                "test.txt",
+                Some("text/plain"),
+                "new content",
+            ))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let content = std::fs::read_to_string(temp_dir.path().join("test.txt")).unwrap();
+        assert_eq!(content, "new content");
+    }
+
+    #[actix_web::test]
+    async fn upsert_updates_existing_file() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        std::fs::write(temp_dir.path().join("test.txt"), "original").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(upsert_file),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/files/test.txt")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("test.txt", Some("text/plain"), "updated"))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let content = std::fs::read_to_string(temp_dir.path().join("test.txt")).unwrap();
+        assert_eq!(content, "updated");
+    }
+
+    #[actix_web::test]
+    async fn delete_file_success() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        std::fs::write(temp_dir.path().join("test.txt"), "content").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(delete_file),
+        )
+        .await;
+
+        let req = test::TestRequest::delete()
+            .uri("/files/test.txt")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!temp_dir.path().join("test.txt").exists());
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["freed_bytes"], 7);
+    }
+
+    #[actix_web::test]
+    async fn delete_file_if_match_refuses_changed_file() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        std::fs::write(temp_dir.path().join("test.txt"), "content").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_file)
+                .service(delete_file),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/files/test.txt").to_request();
+        let response = test::call_service(&app, req).await;
+        let seen = response.headers().get(header::ETAG).unwrap().clone();
+
+        std::fs::write(temp_dir.path().join("test.txt"), "changed by someone else").unwrap();
+        let req = test::TestRequest::delete()
+            .uri("/files/test.txt")
+            .insert_header((header::IF_MATCH, seen))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+        assert!(temp_dir.path().join("test.txt").exists());
+
+        let req = test::TestRequest::get().uri("/files/test.txt").to_request();
+        let response = test::call_service(&app, req).await;
+        let current = response.headers().get(header::ETAG).unwrap().clone();
+        let req = test::TestRequest::delete()
+            .uri("/files/test.txt")
+            .insert_header((header::IF_MATCH, current))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!temp_dir.path().join("test.txt").exists());
+    }
+
+    #[actix_web::test]
+    async fn delete_requires_confirmation_when_configured() {
+        let (mut config, private_index, access_auth, temp_dir) = test_state();
+        config.require_delete_confirmation = true;
+        std::fs::create_dir(temp_dir.path().join("docs")).unwrap();
+        std::fs::write(temp_dir.path().join("docs/a.txt"), "a").unwrap();
+        std::fs::write(temp_dir.path().join("docs/b.txt"), "b").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(delete_file),
+        )
+        .await;
+
+        for uri in ["/files/docs/a.txt", "/files/docs/a.txt?confirm=docs/b.txt"] {
+            let req = test::TestRequest::delete().uri(uri).to_request();
+            let response = test::call_service(&app, req).await;
+            assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+            let body: serde_json::Value = test::read_body_json(response).await;
+            assert_eq!(body["code"], "CONFIRMATION_REQUIRED");
+        }
+        assert!(temp_dir.path().join("docs/a.txt").exists());
+
+        let req = test::TestRequest::delete()
+            .uri("/files/docs/a.txt?confirm=docs/a.txt")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let req = test::TestRequest::delete()
+            .uri("/files/docs/b.txt")
+            .insert_header(("X-Confirm-Delete", "true"))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!temp_dir.path().join("docs/b.txt").exists());
+    }
+
+    #[actix_web::test]
+    async fn download_max_age_is_capped_by_expiry() {
+        let (mut config, private_index, access_auth, temp_dir) = test_state();
+        config.download_max_age = 3600;
+        for name in ["kept.txt", "expiring.txt", "locked.txt"] {
+            std::fs::write(temp_dir.path().join(name), name).unwrap();
+        }
+        let expiry_store = test_expiry_store(&temp_dir);
+        expiry_store
+            .schedule(
+                &temp_dir.path().join("expiring.txt"),
+                std::time::Duration::from_secs(60),
+            )
+            .await
+            .unwrap();
+        let metadata_store = test_metadata_store(&temp_dir);
+        metadata_store
+            .update(Path::new("locked.txt"), |m| {
+                m.password_hash = Some(metadata::hash_password("pw").unwrap())
+            })
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(metadata_store))
+                .app_data(web::Data::new(expiry_store))
+                .service(get_file),
+        )
+        .await;
+
+        let cache_control = |response: &ServiceResponse| {
+            response
+                .headers()
+                .get(header::CACHE_CONTROL)
+                .map(|v| v.to_str().unwrap().to_string())
+        };
+        let req = test::TestRequest::get().uri("/files/kept.txt").to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(
+            cache_control(&response).as_deref(),
+            Some("public, max-age=3600")
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/files/expiring.txt")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        let header = cache_control(&response).unwrap();
+        let max_age: u64 = header
+            .strip_prefix("public, max-age=")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(max_age <= 60);
+
+        let req = test::TestRequest::get()
+            .uri("/files/locked.txt?password=pw")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(cache_control(&response).is_none());
+    }
+
+    #[actix_web::test]
+    async fn read_only_rejects_writes_but_serves_files() {
+        let (mut config, private_index, access_auth, temp_dir) = test_state();
+        config.read_only = true;
+        std::fs::write(temp_dir.path().join("test.txt"), "content").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_file)
+                .service(upsert_file)
+                .service(delete_file),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/files/test.txt")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("test.txt", Some("text/plain"), "updated"))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::delete()
+            .uri("/files/test.txt")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::get().uri("/files/test.txt").to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(test::read_body(response).await, "content");
+    }
+
+    #[actix_web::test]
+    async fn delete_directory_recursive_reports_freed_bytes() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        std::fs::create_dir_all(temp_dir.path().join("testdir/nested")).unwrap();
+        std::fs::write(temp_dir.path().join("testdir/a.txt"), "12345").unwrap();
+        std::fs::write(temp_dir.path().join("testdir/nested/b.txt"), "678").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(delete_file),
+        )
+        .await;
+
+        let req = test::TestRequest::delete()
+            .uri("/files/testdir?recursive=true")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!temp_dir.path().join("testdir").exists());
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["freed_bytes"], 8);
+    }
+
+    #[actix_web::test]
+    async fn delete_file_not_found() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(delete_file),
+        )
+        .await;
+
+        let req = test::TestRequest::delete()
+            .uri("/files/nonexistent.txt")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn rejects_parent_directory_traversal() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(create_file),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/files/../escape.txt")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("escape.txt", Some("text/plain"), "content"))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+
+        assert!(matches!(
+            response.status(),
+            StatusCode::BAD_REQUEST | StatusCode::NOT_FOUND
+        ));
+        assert!(!temp_dir.path().join("escape.txt").exists());
+    }
+
+    #[actix_web::test]
+    async fn delete_directory_fails() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        std::fs::create_dir(temp_dir.path().join("testdir")).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(delete_file),
+        )
+        .await;
+
+        let req = test::TestRequest::delete()
+            .uri("/files/testdir")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn get_public_file_success() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        std::fs::write(temp_dir.path().join("public.txt"), "public-content").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_file),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/files/public.txt")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = test::read_body(response).await;
+        assert_eq!(body, "public-content");
+    }
+
+    #[actix_web::test]
+    async fn tracked_downloads_are_counted() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        std::fs::write(temp_dir.path().join("public.txt"), "public-content").unwrap();
+        let tracker = Arc::new(
+            crate::access::AccessTracker::from_config(&config::Folio {
+                track_access: true,
+                ..config::Folio::default()
+            })
+            .unwrap(),
+        );
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .app_data(web::Data::new(tracker.clone()))
+                .service(get_file),
+        )
+        .await;
+
+        for req in [
+            test::TestRequest::get().uri("/files/public.txt"),
+            test::TestRequest::default()
+                .method(actix_web::http::Method::HEAD)
+                .uri("/files/public.txt"),
+            test::TestRequest::get().uri("/files/missing.txt"),
+        ] {
+            test::call_service(&app, req.to_request()).await;
+        }
+
+        let pending = tracker.pending(Path::new("public.txt")).unwrap();
+        assert_eq!(pending.count, 1);
+        assert!(tracker.pending(Path::new("missing.txt")).is_none());
+    }
+
+    #[cfg(unix)]
+    #[actix_web::test]
+    async fn symlinks_are_followed_only_when_configured() {
+        for follow_symlinks in [false, true] {
+            let (config, private_index, access_auth, temp_dir) = test_state();
+            let outside = tempfile::tempdir().unwrap();
+            std::fs::write(temp_dir.path().join("real.txt"), "real").unwrap();
+            std::fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+            std::fs::create_dir(temp_dir.path().join("real-dir")).unwrap();
+            let link = |target: &Path, name: &str| {
+                std::os::unix::fs::symlink(target, temp_dir.path().join(name)).unwrap()
+            };
+            link(&temp_dir.path().join("real.txt"), "alias.txt");
+            link(&temp_dir.path().join("real-dir"), "linked-dir");
+            link(&outside.path().join("secret.txt"), "escape.txt");
+
+            let app = test::init_service(
+                App::new()
+                    .app_data(web::Data::new(config::Folio {
+                        follow_symlinks,
+                        ..config
+                    }))
+                    .app_data(web::Data::new(private_index))
+                    .app_data(web::Data::new(access_auth))
+                    .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                    .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                    .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                    .service(get_file)
+                    .service(create_file),
+            )
+            .await;
+            let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+            let res = test::call_service(&app, get("/files/alias.txt")).await;
+            if follow_symlinks {
+                assert_eq!(res.status(), StatusCode::OK);
+                assert_eq!(test::read_body(res).await, "real");
+            } else {
+                assert_eq!(res.status(), StatusCode::NOT_FOUND);
+            }
+            let res = test::call_service(&app, get("/files/escape.txt")).await;
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+            let res = test::call_service(&app, get("/files/")).await;
+            let listing: serde_json::Value = test::read_body_json(res).await;
+            let names: Vec<&str> = listing["entries"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|e| e["name"].as_str().unwrap())
+                .collect();
+            if follow_symlinks {
+                assert_eq!(names, ["alias.txt", "linked-dir", "real-dir", "real.txt"]);
+            } else {
+                assert_eq!(names, ["real-dir", "real.txt"]);
+            }
+
+            let req = test::TestRequest::post()
+                .uri("/files/linked-dir/new.txt")
+                .insert_header((
+                    header::CONTENT_TYPE,
+                    "multipart/form-data; boundary=X-BOUNDARY",
+                ))
+                .set_payload(multipart_body("new.txt", None, "new"))
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            if follow_symlinks {
+                assert_eq!(res.status(), StatusCode::CREATED);
+                assert!(temp_dir.path().join("real-dir/new.txt").is_file());
+            } else {
+                assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn get_private_file_redirects_to_private_prefix() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        std::fs::write(temp_dir.path().join("secret.txt"), "secret-content").unwrap();
+        private_index
+            .mark_private(&PathBuf::from("secret.txt"), vec![])
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_file),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/files/secret.txt")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            response.headers().get(header::LOCATION).unwrap(),
+            "/private-files/secret.txt"
+        );
+    }
+
+    #[actix_web::test]
+    async fn private_files_requires_access_jwt_header() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        std::fs::write(temp_dir.path().join("secret.txt"), "secret-content").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_private_file),
+        )
+        .await;
 
-    std::fs::remove_file(&full_path).map_err(|e| {
-        let message = format!("failed to delete file: {:?}", e);
-        log::error!("DELETE /files error: {}", message);
-        FolioError::Internal {
-            source: message,
-            context: Some(format!("delete file: {}", path)),
-        }
-    })?;
+        let req = test::TestRequest::get()
+            .uri("/private-files/secret.txt")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
 
-    Ok(HttpResponse::Ok().json(json!({
-        "message": "file deleted successfully"
-    })))
-}
+    #[actix_web::test]
+    async fn private_files_with_valid_hs256_jwt_returns_200() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        private_index
+            .mark_private(
+                &PathBuf::from("secret.txt"),
+                vec!["allowed@example.com".to_string()],
+            )
+            .await
+            .unwrap();
+        std::fs::write(temp_dir.path().join("secret.txt"), "secret-content").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_private_file),
+        )
+        .await;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use actix_web::{App, http::header, test};
+        let token = make_hs256_token(
+            "test-secret",
+            "user-1",
+            Some("allowed@example.com"),
+            &["team-a"],
+            "https://issuer.example.com",
+            "folio-app",
+            3600,
+        );
+        let req = test::TestRequest::get()
+            .uri("/private-files/secret.txt")
+            .insert_header(("Cf-Access-Jwt-Assertion", token))
+            .to_request();
+        let response = test::call_service(&app, req).await;
 
-    use crate::test_utils::make_hs256_token;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = test::read_body(response).await;
+        assert_eq!(body, "secret-content");
+    }
 
-    fn test_state() -> (
-        config::Folio,
-        Arc<PrivateIndexStore>,
-        Arc<AccessAuth>,
-        tempfile::TempDir,
-    ) {
-        let temp_dir = tempfile::tempdir().unwrap();
-        let config = config::Folio {
-            uploads_path: temp_dir.path().to_string_lossy().to_string(),
-            data_path: temp_dir.path().to_string_lossy().to_string(),
-            ..config::Folio::default()
-        };
+    #[actix_web::test]
+    async fn private_files_with_disallowed_email_returns_403() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        private_index
+            .mark_private(
+                &PathBuf::from("secret.txt"),
+                vec!["only@example.com".to_string()],
+            )
+            .await
+            .unwrap();
+        std::fs::write(temp_dir.path().join("secret.txt"), "secret-content").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_private_file),
+        )
+        .await;
 
-        let private_index = Arc::new(PrivateIndexStore::new(&config));
-        let access_auth = Arc::new(crate::auth::AccessAuth::from_parts(
+        let token = make_hs256_token(
+            "test-secret",
+            "user-1",
+            Some("blocked@example.com"),
+            &["team-a"],
             "https://issuer.example.com",
             "folio-app",
-            Some("test-secret"),
-        ));
+            3600,
+        );
+        let req = test::TestRequest::get()
+            .uri("/private-files/secret.txt")
+            .insert_header(("Cf-Access-Jwt-Assertion", token))
+            .to_request();
+        let response = test::call_service(&app, req).await;
 
-        (config, private_index, access_auth, temp_dir)
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
     }
 
-    fn multipart_body(filename: &str, content_type: Option<&str>, content: &str) -> String {
-        let content_type_header = content_type
-            .map(|ct| format!("Content-Type: {}\r\n", ct))
-            .unwrap_or_default();
-
-        format!(
-            "--X-BOUNDARY\r\n\
-             Content-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
-             {}\
-             \r\n\
-             {}\r\n\
-             --X-BOUNDARY--\r\n",
-            filename, content_type_header, content
+    #[actix_web::test]
+    async fn private_files_require_download_password() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        private_index
+            .mark_private(
+                &PathBuf::from("secret.txt"),
+                vec!["allowed@example.com".to_string()],
+            )
+            .await
+            .unwrap();
+        std::fs::write(temp_dir.path().join("secret.txt"), "secret-content").unwrap();
+        let metadata_store = test_metadata_store(&temp_dir);
+        let password_hash = metadata::hash_password("hunter2").unwrap();
+        metadata_store
+            .update(Path::new("secret.txt"), |m| {
+                m.password_hash = Some(password_hash)
+            })
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(metadata_store))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_private_file),
         )
+        .await;
+
+        let token = make_hs256_token(
+            "test-secret",
+            "user-1",
+            Some("allowed@example.com"),
+            &["team-a"],
+            "https://issuer.example.com",
+            "folio-app",
+            3600,
+        );
+        for (uri, expected) in [
+            ("/private-files/secret.txt", StatusCode::UNAUTHORIZED),
+            (
+                "/private-files/secret.txt?password=wrong",
+                StatusCode::FORBIDDEN,
+            ),
+            ("/private-files/secret.txt?password=hunter2", StatusCode::OK),
+        ] {
+            let req = test::TestRequest::get()
+                .uri(uri)
+                .insert_header(("Cf-Access-Jwt-Assertion", token.clone()))
+                .to_request();
+            let response = test::call_service(&app, req).await;
+            assert_eq!(response.status(), expected, "{}", uri);
+        }
     }
 
     #[actix_web::test]
-    async fn create_file_success() {
+    async fn range_with_matching_if_range_returns_partial_content() {
         let (config, private_index, access_auth, temp_dir) = test_state();
+        std::fs::write(temp_dir.path().join("video.bin"), "0123456789").unwrap();
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::new(config))
                 .app_data(web::Data::new(private_index))
                 .app_data(web::Data::new(access_auth))
-                .service(create_file),
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_file),
         )
         .await;
 
-        let req = test::TestRequest::post()
-            .uri("/files/test.txt")
-            .insert_header((
-                header::CONTENT_TYPE,
-                "multipart/form-data; boundary=X-BOUNDARY",
-            ))
-            .set_payload(multipart_body(
-                "test.txt",
-                Some("text/plain"),
-                "test content",
-            ))
+        let req = test::TestRequest::get()
+            .uri("/files/video.bin")
             .to_request();
         let response = test::call_service(&app, req).await;
+        let etag = response.headers().get(header::ETAG).unwrap().clone();
+        let last_modified = response
+            .headers()
+            .get(header::LAST_MODIFIED)
+            .unwrap()
+            .clone();
 
-        assert_eq!(response.status(), StatusCode::CREATED);
-        let content = std::fs::read_to_string(temp_dir.path().join("test.txt")).unwrap();
-        assert_eq!(content, "test content");
+        for validator in [etag, last_modified] {
+            let req = test::TestRequest::get()
+                .uri("/files/video.bin")
+                .insert_header((header::RANGE, "bytes=2-4"))
+                .insert_header((header::IF_RANGE, validator))
+                .to_request();
+            let response = test::call_service(&app, req).await;
+
+            assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+            assert_eq!(test::read_body(response).await, "234");
+        }
     }
 
     #[actix_web::test]
-    async fn create_file_with_nested_path() {
+    async fn downloads_can_be_probed_and_resumed() {
         let (config, private_index, access_auth, temp_dir) = test_state();
+        std::fs::write(temp_dir.path().join("video.bin"), "0123456789").unwrap();
+        std::fs::write(temp_dir.path().join("video.bin.gz"), "gzipped").unwrap();
+        std::fs::write(temp_dir.path().join("once.bin"), "0123456789").unwrap();
+        let metadata_store = test_metadata_store(&temp_dir);
+        metadata_store
+            .update(Path::new("once.bin"), |m| m.burn = true)
+            .await
+            .unwrap();
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::new(config))
                 .app_data(web::Data::new(private_index))
                 .app_data(web::Data::new(access_auth))
-                .service(create_file),
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(metadata_store))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_file),
         )
         .await;
+        let request = |method: Method, uri: &str, range: Option<&str>| {
+            let mut req = test::TestRequest::default()
+                .method(method)
+                .uri(uri)
+                .insert_header((header::ACCEPT_ENCODING, "gzip"));
+            if let Some(range) = range {
+                req = req.insert_header((header::RANGE, range.to_string()));
+            }
+            req.to_request()
+        };
 
-        let req = test::TestRequest::post()
-            .uri("/files/folder/subfolder/test.txt")
-            .insert_header((
-                header::CONTENT_TYPE,
-                "multipart/form-data; boundary=X-BOUNDARY",
-            ))
-            .set_payload(multipart_body(
-                "test.txt",
-                Some("text/plain"),
-                "nested content",
-            ))
-            .to_request();
-        let response = test::call_service(&app, req).await;
+        let response =
+            test::call_service(&app, request(Method::HEAD, "/files/video.bin", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::ACCEPT_RANGES).unwrap(),
+            "bytes"
+        );
+        assert!(response.headers().contains_key(header::ETAG));
 
-        assert_eq!(response.status(), StatusCode::CREATED);
-        let content =
-            std::fs::read_to_string(temp_dir.path().join("folder/subfolder/test.txt")).unwrap();
-        assert_eq!(content, "nested content");
+        let response = test::call_service(
+            &app,
+            request(Method::GET, "/files/video.bin", Some("bytes=6-")),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.response().body().size(), BodySize::Sized(4));
+        assert_eq!(test::read_body(response).await, "6789");
+
+        let response =
+            test::call_service(&app, request(Method::HEAD, "/files/once.bin", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::ACCEPT_RANGES).unwrap(),
+            "none"
+        );
+        assert!(temp_dir.path().join("once.bin").exists());
+
+        let response = test::call_service(
+            &app,
+            request(Method::GET, "/files/once.bin", Some("bytes=6-")),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(test::read_body(response).await, "0123456789");
+        assert!(!temp_dir.path().join("once.bin").exists());
     }
 
     #[actix_web::test]
-    async fn create_file_already_exists() {
+    async fn range_with_stale_if_range_returns_full_file() {
         let (config, private_index, access_auth, temp_dir) = test_state();
-        std::fs::write(temp_dir.path().join("test.txt"), "content 1").unwrap();
+        std::fs::write(temp_dir.path().join("video.bin"), "0123456789").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(private_index))
+                .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_file),
+        )
+        .await;
+
+        for validator in ["\"stale-etag\"", "Sat, 29 Oct 1994 19:43:31 GMT"] {
+            let req = test::TestRequest::get()
+                .uri("/files/video.bin")
+                .insert_header((header::RANGE, "bytes=2-4"))
+                .insert_header((header::IF_RANGE, validator))
+                .to_request();
+            let response = test::call_service(&app, req).await;
+
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(test::read_body(response).await, "0123456789");
+        }
+    }
+
+    #[actix_web::test]
+    async fn create_file_on_conflict_rename() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        std::fs::create_dir(temp_dir.path().join("docs")).unwrap();
+        std::fs::write(temp_dir.path().join("docs/file.txt"), "content 1").unwrap();
+        std::fs::write(temp_dir.path().join("docs/file (1).txt"), "content 2").unwrap();
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::new(config))
                 .app_data(web::Data::new(private_index))
                 .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
                 .service(create_file),
         )
         .await;
 
         let req = test::TestRequest::post()
-            .uri("/files/test.txt")
+            .uri("/files/docs/file.txt?on_conflict=rename")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("file.txt", Some("text/plain"), "content 3"))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(
+            response.headers().get(header::LOCATION).unwrap(),
+            "/files/docs/file%20%282%29.txt"
+        );
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["path"], "docs/file (2).txt");
+        let content = std::fs::read_to_string(temp_dir.path().join("docs/file (2).txt")).unwrap();
+        assert_eq!(content, "content 3");
+        let original = std::fs::read_to_string(temp_dir.path().join("docs/file.txt")).unwrap();
+        assert_eq!(original, "content 1");
+
+        std::fs::write(temp_dir.path().join("docs/résumé.txt"), "cv").unwrap();
+        let req = test::TestRequest::post()
+            .uri("/files/docs/r%C3%A9sum%C3%A9.txt?on_conflict=rename")
             .insert_header((
                 header::CONTENT_TYPE,
                 "multipart/form-data; boundary=X-BOUNDARY",
             ))
-            .set_payload(multipart_body("test.txt", Some("text/plain"), "content 2"))
+            .set_payload(multipart_body("cv.txt", Some("text/plain"), "new cv"))
             .to_request();
         let response = test::call_service(&app, req).await;
-
-        assert_eq!(response.status(), StatusCode::CONFLICT);
-        let content = std::fs::read_to_string(temp_dir.path().join("test.txt")).unwrap();
-        assert_eq!(content, "content 1");
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(
+            response.headers().get(header::LOCATION).unwrap(),
+            "/files/docs/r%C3%A9sum%C3%A9%20%281%29.txt"
+        );
     }
 
     #[actix_web::test]
-    async fn upsert_creates_new_file() {
-        let (config, private_index, access_auth, temp_dir) = test_state();
+    async fn validate_reports_every_failed_check_without_writing() {
+        let (mut config, private_index, access_auth, temp_dir) = test_state();
+        config.max_filename_length = 12;
+        config.blocked_extensions = vec!["exe".to_string()];
+        std::fs::create_dir(temp_dir.path().join("docs")).unwrap();
+        std::fs::write(temp_dir.path().join("docs/a.txt"), "a").unwrap();
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::new(config))
                 .app_data(web::Data::new(private_index))
                 .app_data(web::Data::new(access_auth))
-                .service(upsert_file),
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(validate_file_path)
+                .service(get_file),
         )
         .await;
+        let validate = async |uri: &str| {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let response = test::call_service(&app, req).await;
+            assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+            test::read_body_json::<serde_json::Value, _>(response).await
+        };
 
-        let req = test::TestRequest::put()
-            .uri("/files/test.txt")
-            .insert_header((
-                header::CONTENT_TYPE,
-                "multipart/form-data; boundary=X-BOUNDARY",
-            ))
-            .set_payload(multipart_body(
-                "test.txt",
-                Some("text/plain"),
-                "new content",
-            ))
+        let body = validate("/files/docs/b.txt?validate").await;
+        assert_eq!(body["valid"], true);
+        assert_eq!(body["reasons"], serde_json::json!([]));
+        assert!(!temp_dir.path().join("docs/b.txt").exists());
+
+        let body = validate("/files/docs/a.txt?validate").await;
+        assert_eq!(body["valid"], false);
+        assert_eq!(body["reasons"][0], "file already exists: docs/a.txt");
+        let body = validate("/files/docs/a.txt?validate&on_conflict=rename").await;
+        assert_eq!(body["valid"], true);
+        assert_eq!(body["path"], "docs/a (1).txt");
+
+        let body = validate("/files/docs/much-too-long.exe?validate=1").await;
+        assert_eq!(body["valid"], false);
+        assert_eq!(body["reasons"].as_array().unwrap().len(), 2);
+
+        let body = validate("/files/docs?validate&on_conflict=overwrite").await;
+        assert_eq!(body["reasons"][0], "path is not a file: docs");
+
+        // Without `validate`, the file is served as usual.
+        let req = test::TestRequest::get()
+            .uri("/files/docs/a.txt")
             .to_request();
         let response = test::call_service(&app, req).await;
-
-        assert_eq!(response.status(), StatusCode::CREATED);
-        let content = std::fs::read_to_string(temp_dir.path().join("test.txt")).unwrap();
-        assert_eq!(content, "new content");
+        assert_eq!(test::read_body(response).await, "a");
     }
 
     #[actix_web::test]
-    async fn upsert_updates_existing_file() {
-        let (config, private_index, access_auth, temp_dir) = test_state();
-        std::fs::write(temp_dir.path().join("test.txt"), "original").unwrap();
+    async fn slugify_filenames_normalizes_written_and_offered_names() {
+        let (mut config, private_index, access_auth, temp_dir) = test_state();
+        config.slugify_filenames = true;
+        std::fs::create_dir(temp_dir.path().join("My Docs")).unwrap();
+        std::fs::write(temp_dir.path().join("My Docs/q3-resume.PDF"), "old").unwrap();
+        std::fs::write(temp_dir.path().join("a1B2c3D4.csv"), "a,b").unwrap();
+        let metadata_store = test_metadata_store(&temp_dir);
+        metadata_store
+            .update(Path::new("a1B2c3D4.csv"), |m| {
+                m.original_name = Some("Q3 résumé.csv".to_string())
+            })
+            .await
+            .unwrap();
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::new(config))
                 .app_data(web::Data::new(private_index))
                 .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(metadata_store))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_file)
+                .service(create_file)
                 .service(upsert_file),
         )
         .await;
+        let write = |request: test::TestRequest, uri: &str| {
+            request
+                .uri(uri)
+                .insert_header((
+                    header::CONTENT_TYPE,
+                    "multipart/form-data; boundary=X-BOUNDARY",
+                ))
+                .set_payload(multipart_body("file.pdf", None, "new"))
+                .to_request()
+        };
 
-        let req = test::TestRequest::put()
-            .uri("/files/test.txt")
-            .insert_header((
-                header::CONTENT_TYPE,
-                "multipart/form-data; boundary=X-BOUNDARY",
-            ))
-            .set_payload(multipart_body("test.txt", Some("text/plain"), "updated"))
-            .to_request();
+        let req = write(
+            test::TestRequest::post(),
+            "/files/My%20Docs/Q3%20R%C3%A9sum%C3%A9.PDF?on_conflict=rename",
+        );
         let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["path"], "My Docs/q3-resume-1.PDF");
+        assert!(temp_dir.path().join("My Docs/q3-resume-1.PDF").is_file());
 
+        let req = write(
+            test::TestRequest::put(),
+            "/files/My%20Docs/Q3%20R%C3%A9sum%C3%A9.PDF",
+        );
+        let response = test::call_service(&app, req).await;
         assert_eq!(response.status(), StatusCode::OK);
-        let content = std::fs::read_to_string(temp_dir.path().join("test.txt")).unwrap();
-        assert_eq!(content, "updated");
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["path"], "My Docs/q3-resume.PDF");
+        let content = std::fs::read_to_string(temp_dir.path().join("My Docs/q3-resume.PDF"));
+        assert_eq!(content.unwrap(), "new");
+
+        let req = test::TestRequest::get()
+            .uri("/files/a1B2c3D4.csv")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(
+            response.headers().get(header::CONTENT_DISPOSITION).unwrap(),
+            "attachment; filename=\"q3-resume.csv\""
+        );
     }
 
     #[actix_web::test]
-    async fn delete_file_success() {
+    async fn create_file_on_conflict_overwrite() {
         let (config, private_index, access_auth, temp_dir) = test_state();
-        std::fs::write(temp_dir.path().join("test.txt"), "content").unwrap();
+        std::fs::write(temp_dir.path().join("test.txt"), "content 1").unwrap();
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::new(config))
                 .app_data(web::Data::new(private_index))
                 .app_data(web::Data::new(access_auth))
-                .service(delete_file),
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(create_file),
         )
         .await;
 
-        let req = test::TestRequest::delete()
-            .uri("/files/test.txt")
+        let req = test::TestRequest::post()
+            .uri("/files/test.txt?on_conflict=overwrite")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("test.txt", Some("text/plain"), "content 2"))
             .to_request();
         let response = test::call_service(&app, req).await;
 
         assert_eq!(response.status(), StatusCode::OK);
-        assert!(!temp_dir.path().join("test.txt").exists());
+        let content = std::fs::read_to_string(temp_dir.path().join("test.txt")).unwrap();
+        assert_eq!(content, "content 2");
     }
 
     #[actix_web::test]
-    async fn delete_file_not_found() {
-        let (config, private_index, access_auth, _temp_dir) = test_state();
+    async fn update_expiration_reschedules_file() {
+        let (config, private_index, access_auth, temp_dir) = test_state();
+        std::fs::write(temp_dir.path().join("report.txt"), "content").unwrap();
+        let expiry_store = Arc::new(ExpiryStore::new(&config));
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::new(config))
                 .app_data(web::Data::new(private_index))
                 .app_data(web::Data::new(access_auth))
-                .service(delete_file),
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(expiry_store.clone()))
+                .service(update_expiration)
+                .service(upsert_file),
         )
         .await;
 
-        let req = test::TestRequest::delete()
-            .uri("/files/nonexistent.txt")
+        for expire in ["1d", "1h"] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/files/report.txt/expiration?expire={}", expire))
+                .to_request();
+            let response = test::call_service(&app, req).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let body: serde_json::Value = test::read_body_json(response).await;
+            let expire_at = body["expire_at_unix"].as_u64().unwrap();
+            let now = crate::test_utils::now_ts() as u64;
+            assert!(expire_at > now);
+        }
+
+        let raw = std::fs::read_to_string(temp_dir.path().join("expiry-index.json")).unwrap();
+        let index: serde_json::Value = serde_json::from_str(&raw).unwrap();
+        let entries = index["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 1);
+        let expire_at = entries[0]["expire_at_unix"].as_u64().unwrap();
+        assert!(expire_at <= crate::test_utils::now_ts() as u64 + 3600);
+        assert_eq!(expiry_store.pending_count().await.unwrap(), 1);
+
+        let req = test::TestRequest::put()
+            .uri("/files/docs/expiration")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("expiration", None, "a file"))
             .to_request();
         let response = test::call_service(&app, req).await;
-        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let content = std::fs::read_to_string(temp_dir.path().join("docs/expiration")).unwrap();
+        assert_eq!(content, "a file");
     }
 
     #[actix_web::test]
-    async fn rejects_parent_directory_traversal() {
+    async fn touch_sets_mtime_without_rewriting() {
         let (config, private_index, access_auth, temp_dir) = test_state();
+        let file_path = temp_dir.path().join("report.txt");
+        std::fs::write(&file_path, "content").unwrap();
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::new(config))
                 .app_data(web::Data::new(private_index))
                 .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(touch_file)
                 .service(create_file),
         )
         .await;
 
         let req = test::TestRequest::post()
-            .uri("/files/../escape.txt")
-            .insert_header((
-                header::CONTENT_TYPE,
-                "multipart/form-data; boundary=X-BOUNDARY",
-            ))
-            .set_payload(multipart_body("escape.txt", Some("text/plain"), "content"))
+            .uri("/files/report.txt/touch?mtime=1000000000")
             .to_request();
         let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["modified_unix"], 1000000000);
+        let modified = std::fs::metadata(&file_path).unwrap().modified().unwrap();
+        assert_eq!(
+            modified,
+            std::time::UNIX_EPOCH + std::time::Duration::from_secs(1000000000)
+        );
+        assert_eq!(std::fs::read_to_string(&file_path).unwrap(), "content");
 
-        assert!(matches!(
-            response.status(),
-            StatusCode::BAD_REQUEST | StatusCode::NOT_FOUND
-        ));
-        assert!(!temp_dir.path().join("escape.txt").exists());
+        let req = test::TestRequest::post()
+            .uri("/files/report.txt/touch")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert!(body["modified_unix"].as_u64().unwrap() >= crate::test_utils::now_ts() as u64 - 5);
+
+        let req = test::TestRequest::post()
+            .uri("/files/missing.txt/touch")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(!temp_dir.path().join("missing.txt").exists());
     }
 
     #[actix_web::test]
-    async fn delete_directory_fails() {
-        let (config, private_index, access_auth, temp_dir) = test_state();
-        std::fs::create_dir(temp_dir.path().join("testdir")).unwrap();
+    async fn expired_files_in_grace_period_are_gone_until_restored() {
+        let (mut config, private_index, access_auth, temp_dir) = test_state();
+        config.expire_grace_period = "7d".to_string();
+        std::fs::write(temp_dir.path().join("report.txt"), "content").unwrap();
+        std::fs::write(temp_dir.path().join("other.txt"), "content").unwrap();
+        // As left by a sweep that found the file expired.
+        let index = serde_json::json!({ "entries": [{
+            "path": config.build_full_upload_path(Path::new("report.txt")),
+            "expire_at_unix": 1,
+            "delete_at_unix": crate::test_utils::now_ts() + 3600,
+        }] });
+        std::fs::write(temp_dir.path().join("expiry-index.json"), index.to_string()).unwrap();
+        let expiry_store = Arc::new(ExpiryStore::new(&config));
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::new(config))
                 .app_data(web::Data::new(private_index))
                 .app_data(web::Data::new(access_auth))
-                .service(delete_file),
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(expiry_store))
+                .service(unexpire_file)
+                .service(get_file),
         )
         .await;
 
-        let req = test::TestRequest::delete()
-            .uri("/files/testdir")
+        let req = test::TestRequest::get()
+            .uri("/files/report.txt")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::GONE);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["code"], "EXPIRED");
+
+        let req = test::TestRequest::get().uri("/files/").to_request();
+        let listing: serde_json::Value =
+            test::read_body_json(test::call_service(&app, req).await).await;
+        let names: Vec<&str> = listing["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["name"].as_str().unwrap())
+            .collect();
+        assert!(names.contains(&"other.txt"));
+        assert!(!names.contains(&"report.txt"));
+
+        let req = test::TestRequest::post()
+            .uri("/files/other.txt/unexpire")
             .to_request();
         let response = test::call_service(&app, req).await;
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::post()
+            .uri("/files/report.txt/unexpire?expire=1h")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert!(body["expire_at_unix"].as_u64().unwrap() > crate::test_utils::now_ts() as u64);
+
+        let req = test::TestRequest::get()
+            .uri("/files/report.txt")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
     }
 
     #[actix_web::test]
-    async fn get_public_file_success() {
-        let (config, private_index, access_auth, temp_dir) = test_state();
-        std::fs::write(temp_dir.path().join("public.txt"), "public-content").unwrap();
+    async fn recently_expired_files_are_gone_not_missing() {
+        let (mut config, private_index, access_auth, temp_dir) = test_state();
+        config.tombstone_ttl = "1d".to_string();
+        // As left by a sweep that deleted the expired file.
+        let index = serde_json::json!({ "entries": [], "tombstones": [{
+            "path": config.build_full_upload_path(Path::new("report.txt")),
+            "deleted_at_unix": crate::test_utils::now_ts(),
+            "forget_at_unix": crate::test_utils::now_ts() + 86400,
+        }] });
+        std::fs::write(temp_dir.path().join("expiry-index.json"), index.to_string()).unwrap();
+        let expiry_store = Arc::new(ExpiryStore::new(&config));
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::new(config))
                 .app_data(web::Data::new(private_index))
                 .app_data(web::Data::new(access_auth))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(expiry_store))
                 .service(get_file),
         )
         .await;
 
         let req = test::TestRequest::get()
-            .uri("/files/public.txt")
+            .uri("/files/report.txt")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::GONE);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["code"], "EXPIRED");
+
+        let req = test::TestRequest::get()
+            .uri("/files/never.txt")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // A new file at the same path is served.
+        std::fs::write(temp_dir.path().join("report.txt"), "new").unwrap();
+        let req = test::TestRequest::get()
+            .uri("/files/report.txt")
             .to_request();
         let response = test::call_service(&app, req).await;
         assert_eq!(response.status(), StatusCode::OK);
-        let body = test::read_body(response).await;
-        assert_eq!(body, "public-content");
     }
 
     #[actix_web::test]
-    async fn get_private_file_redirects_to_private_prefix() {
-        let (config, private_index, access_auth, temp_dir) = test_state();
-        std::fs::write(temp_dir.path().join("secret.txt"), "secret-content").unwrap();
-        private_index
-            .mark_private(&PathBuf::from("secret.txt"), vec![])
-            .await
-            .unwrap();
+    async fn update_expiration_clamps_to_max_expire() {
+        let (mut config, private_index, access_auth, temp_dir) = test_state();
+        config.max_expire = "1h".to_string();
+        std::fs::write(temp_dir.path().join("report.txt"), "content").unwrap();
+        let expiry_store = Arc::new(ExpiryStore::new(&config));
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::new(config))
                 .app_data(web::Data::new(private_index))
                 .app_data(web::Data::new(access_auth))
-                .service(get_file),
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(expiry_store))
+                .service(update_expiration),
         )
         .await;
 
-        let req = test::TestRequest::get()
-            .uri("/files/secret.txt")
+        let req = test::TestRequest::put()
+            .uri("/files/report.txt/expiration?expire=never")
             .to_request();
         let response = test::call_service(&app, req).await;
-        assert_eq!(response.status(), StatusCode::FOUND);
-        assert_eq!(
-            response.headers().get(header::LOCATION).unwrap(),
-            "/private-files/secret.txt"
-        );
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["expire_clamped"], true);
+        let expire_at = body["expire_at_unix"].as_u64().unwrap();
+        assert!(expire_at <= crate::test_utils::now_ts() as u64 + 3600);
     }
 
     #[actix_web::test]
-    async fn private_files_requires_access_jwt_header() {
+    async fn update_expiration_rejects_invalid_duration_and_missing_file() {
         let (config, private_index, access_auth, temp_dir) = test_state();
-        std::fs::write(temp_dir.path().join("secret.txt"), "secret-content").unwrap();
+        std::fs::write(temp_dir.path().join("report.txt"), "content").unwrap();
+        let expiry_store = Arc::new(ExpiryStore::new(&config));
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::new(config))
                 .app_data(web::Data::new(private_index))
                 .app_data(web::Data::new(access_auth))
-                .service(get_private_file),
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(expiry_store))
+                .service(update_expiration),
         )
         .await;
 
-        let req = test::TestRequest::get()
-            .uri("/private-files/secret.txt")
+        let req = test::TestRequest::put()
+            .uri("/files/report.txt/expiration?expire=soon")
             .to_request();
         let response = test::call_service(&app, req).await;
-        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::put()
+            .uri("/files/missing.txt/expiration?expire=1h")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 
     #[actix_web::test]
-    async fn private_files_with_valid_hs256_jwt_returns_200() {
+    async fn serves_precompressed_variant_when_accepted() {
         let (config, private_index, access_auth, temp_dir) = test_state();
-        private_index
-            .mark_private(
-                &PathBuf::from("secret.txt"),
-                vec!["allowed@example.com".to_string()],
-            )
-            .await
-            .unwrap();
-        std::fs::write(temp_dir.path().join("secret.txt"), "secret-content").unwrap();
+        std::fs::write(temp_dir.path().join("app.js"), "plain").unwrap();
+        std::fs::write(temp_dir.path().join("app.js.gz"), "gzip-bytes").unwrap();
+        std::fs::write(temp_dir.path().join("app.js.br"), "brotli-bytes").unwrap();
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::new(config))
                 .app_data(web::Data::new(private_index))
                 .app_data(web::Data::new(access_auth))
-                .service(get_private_file),
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(test_expiry_store(&temp_dir)))
+                .service(get_file),
         )
         .await;
 
-        let token = make_hs256_token(
-            "test-secret",
-            "user-1",
-            Some("allowed@example.com"),
-            &["team-a"],
-            "https://issuer.example.com",
-            "folio-app",
-            3600,
-        );
-        let req = test::TestRequest::get()
-            .uri("/private-files/secret.txt")
-            .insert_header(("Cf-Access-Jwt-Assertion", token))
-            .to_request();
-        let response = test::call_service(&app, req).await;
+        let cases = [
+            (Some("gzip, br"), Some("br"), "brotli-bytes"),
+            (Some("gzip, br;q=0"), Some("gzip"), "gzip-bytes"),
+            (Some("identity"), None, "plain"),
+            (None, None, "plain"),
+        ];
+        for (accept, expected_encoding, expected_body) in cases {
+            let mut req = test::TestRequest::get().uri("/files/app.js");
+            if let Some(accept) = accept {
+                req = req.insert_header((header::ACCEPT_ENCODING, accept));
+            }
+            let response = test::call_service(&app, req.to_request()).await;
 
-        assert_eq!(response.status(), StatusCode::OK);
-        let body = test::read_body(response).await;
-        assert_eq!(body, "secret-content");
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response
+                    .headers()
+                    .get(header::CONTENT_ENCODING)
+                    .map(|v| v.to_str().unwrap()),
+                expected_encoding
+            );
+            assert!(
+                response
+                    .headers()
+                    .get(header::CONTENT_TYPE)
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .contains("javascript")
+            );
+            assert_eq!(test::read_body(response).await, expected_body);
+        }
     }
 
     #[actix_web::test]
-    async fn private_files_with_disallowed_email_returns_403() {
-        let (config, private_index, access_auth, temp_dir) = test_state();
-        private_index
-            .mark_private(
-                &PathBuf::from("secret.txt"),
-                vec!["only@example.com".to_string()],
-            )
-            .await
-            .unwrap();
-        std::fs::write(temp_dir.path().join("secret.txt"), "secret-content").unwrap();
+    async fn create_file_rejected_when_directory_is_full() {
+        let (mut config, private_index, access_auth, temp_dir) = test_state();
+        config.max_files_per_dir = 1;
+        std::fs::create_dir(temp_dir.path().join("docs")).unwrap();
+        std::fs::write(temp_dir.path().join("docs/a.txt"), "a").unwrap();
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::new(config))
                 .app_data(web::Data::new(private_index))
                 .app_data(web::Data::new(access_auth))
-                .service(get_private_file),
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(create_file)
+                .service(upsert_file),
         )
         .await;
 
-        let token = make_hs256_token(
-            "test-secret",
-            "user-1",
-            Some("blocked@example.com"),
-            &["team-a"],
-            "https://issuer.example.com",
-            "folio-app",
-            3600,
-        );
-        let req = test::TestRequest::get()
-            .uri("/private-files/secret.txt")
-            .insert_header(("Cf-Access-Jwt-Assertion", token))
+        let req = test::TestRequest::post()
+            .uri("/files/docs/b.txt")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("b.txt", Some("text/plain"), "b"))
             .to_request();
         let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+        assert!(!temp_dir.path().join("docs/b.txt").exists());
 
-        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        // Overwriting an existing file does not add an entry.
+        let req = test::TestRequest::put()
+            .uri("/files/docs/a.txt")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("a.txt", Some("text/plain"), "updated"))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
     }
 }
@@ -0,0 +1,133 @@
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse};
+
+use super::config;
+use super::error::FolioError;
+
+/// Check a `?redirect=` target before anything is stored: a path on this
+/// server, or a URL on one of `upload_redirect_origins`. Anything else would
+/// make `/uploads` an open redirect.
+pub fn check_target(config: &config::Folio, target: &str) -> Result<(), FolioError> {
+    let allowed = if let Some(path) = target.strip_prefix('/') {
+        // `//host` and `/\host` are protocol-relative URLs to browsers.
+        !path.starts_with(['/', '\\'])
+    } else {
+        origin_of(target).is_some_and(|origin| {
+            config
+                .upload_redirect_origins
+                .iter()
+                .any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(origin))
+        })
+    };
+    if !allowed {
+        return Err(FolioError::BadRequest {
+            reason: format!(
+                "redirect must be a path or a URL on upload_redirect_origins: {}",
+                target
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// `scheme://host[:port]` of an absolute http(s) URL.
+fn origin_of(url: &str) -> Option<&str> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let authority_len = rest.find(['/', '?', '#', '\\']).unwrap_or(rest.len());
+    if authority_len == 0 {
+        return None;
+    }
+    Some(&url[..url.len() - rest.len() + authority_len])
+}
+
+/// Whether the client is a browser that would rather be sent somewhere than
+/// shown JSON, e.g. a plain HTML form post.
+pub fn wants_html(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"))
+}
+
+/// `303 See Other` to `target` with the uploaded file's URL added as `url`,
+/// or to the file itself without a target.
+pub fn see_other(target: Option<&str>, file_url: &str) -> HttpResponse {
+    let location = match target {
+        Some(target) => {
+            let (base, fragment) = target
+                .split_once('#')
+                .map_or((target, None), |(b, f)| (b, Some(f)));
+            let separator = if base.contains('?') { '&' } else { '?' };
+            let mut location = format!("{}{}url={}", base, separator, encode_query_value(file_url));
+            if let Some(fragment) = fragment {
+                location.push('#');
+                location.push_str(fragment);
+            }
+            location
+        }
+        None => file_url.to_string(),
+    };
+    HttpResponse::SeeOther()
+        .append_header((header::LOCATION, location))
+        .finish()
+}
+
+fn encode_query_value(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_local_paths_and_listed_origins_are_targets() {
+        let config = config::Folio {
+            upload_redirect_origins: vec!["https://example.com/".to_string()],
+            ..config::Folio::default()
+        };
+
+        assert!(check_target(&config, "/thanks.html").is_ok());
+        assert!(check_target(&config, "https://example.com/done?a=1").is_ok());
+        assert!(check_target(&config, "https://EXAMPLE.com").is_ok());
+        for target in [
+            "//evil.example/",
+            "/\\evil.example/",
+            "https://example.com.evil.example/",
+            "https://evil.example/?https://example.com",
+            "javascript:alert(1)",
+            "thanks.html",
+        ] {
+            assert!(check_target(&config, target).is_err(), "{}", target);
+        }
+    }
+
+    #[test]
+    fn redirects_carry_the_file_url() {
+        let location = |target| {
+            see_other(target, "https://files.example/files/a 1.txt")
+                .headers()
+                .get(header::LOCATION)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(location(None), "https://files.example/files/a 1.txt");
+        assert_eq!(
+            location(Some("/done?from=form#top")),
+            "/done?from=form&url=https%3A//files.example/files/a%201.txt#top"
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use std::io::Read;
+use std::path::Path;
+
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+/// Content hash used for checksums, manifests and listing ETags
+/// (`hash_algorithm`).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha1,
+    Blake3,
+    Md5,
+}
+
+impl HashAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha1 => "sha1",
+            Self::Blake3 => "blake3",
+            Self::Md5 => "md5",
+        }
+    }
+
+    pub fn hasher(self) -> ContentHasher {
+        match self {
+            Self::Sha256 => ContentHasher::Sha256(Sha256::new()),
+            Self::Sha1 => ContentHasher::Sha1(Sha1::new()),
+            Self::Blake3 => ContentHasher::Blake3(Box::new(blake3::Hasher::new())),
+            Self::Md5 => ContentHasher::Md5(Md5::new()),
+        }
+    }
+
+    /// Lowercase hex digest of `bytes`.
+    pub fn hash_bytes(self, bytes: &[u8]) -> String {
+        let mut hasher = self.hasher();
+        hasher.update(bytes);
+        hasher.finalize_hex()
+    }
+
+    /// Lowercase hex digest of the file at `path`, read in chunks.
+    pub fn hash_file(self, path: &Path) -> std::io::Result<String> {
+        let mut file = std::fs::File::open(path)?;
+        let mut hasher = self.hasher();
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+        Ok(hasher.finalize_hex())
+    }
+}
+
+/// A running hash of one of the `HashAlgorithm`s.
+pub enum ContentHasher {
+    Sha256(Sha256),
+    Sha1(Sha1),
+    Blake3(Box<blake3::Hasher>),
+    Md5(Md5),
+}
+
+impl ContentHasher {
+    pub fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Self::Sha256(h) => h.update(bytes),
+            Self::Sha1(h) => h.update(bytes),
+            Self::Blake3(h) => {
+                h.update(bytes);
+            }
+            Self::Md5(h) => h.update(bytes),
+        }
+    }
+
+    pub fn finalize_hex(self) -> String {
+        let digest = match self {
+            Self::Sha256(h) => h.finalize().to_vec(),
+            Self::Sha1(h) => h.finalize().to_vec(),
+            Self::Blake3(h) => h.finalize().as_bytes().to_vec(),
+            Self::Md5(h) => h.finalize().to_vec(),
+        };
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_match_reference_digests() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("a.txt");
+        std::fs::write(&path, "hello").unwrap();
+
+        for (algorithm, expected) in [
+            (
+                HashAlgorithm::Sha256,
+                "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+            ),
+            (
+                HashAlgorithm::Sha1,
+                "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d",
+            ),
+            (
+                HashAlgorithm::Blake3,
+                "ea8f163db38682925e4491c5e58d4bb3506ef8c14eb78a86e908c5624a67200f",
+            ),
+            (HashAlgorithm::Md5, "5d41402abc4b2a76b9719d911017c592"),
+        ] {
+            assert_eq!(algorithm.hash_file(&path).unwrap(), expected);
+            assert_eq!(algorithm.hash_bytes(b"hello"), expected);
+        }
+    }
+}
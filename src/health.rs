@@ -0,0 +1,364 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use actix_web::{HttpResponse, Responder, get, web};
+use rand::RngExt;
+use serde::{Deserialize, Serialize};
+
+use super::config;
+use super::disk::DiskSpace;
+use super::expiry::{ExpiryStore, SweeperStatus};
+
+#[derive(Deserialize)]
+pub struct HealthQuery {
+    verbose: Option<String>,
+}
+
+#[derive(Serialize)]
+struct HealthDetail {
+    status: &'static str,
+    expiry_sweeper: SweeperStatus,
+    pending_expirations: Option<usize>,
+    disk: Option<DiskDetail>,
+}
+
+#[derive(Serialize)]
+struct DiskDetail {
+    #[serde(flatten)]
+    space: DiskSpace,
+    available_percent: f64,
+    min_free_bytes: u64,
+    min_free_percent: f64,
+    ok: bool,
+}
+
+impl DiskDetail {
+    fn check(config: &config::Folio) -> Option<Self> {
+        let uploads_dir = config.resolve_base(&config.uploads_path);
+        let space = match DiskSpace::of(&uploads_dir) {
+            Ok(space) => space,
+            Err(e) => {
+                log::error!(
+                    "GET /health failed to stat {}: {}",
+                    uploads_dir.display(),
+                    e
+                );
+                return None;
+            }
+        };
+        let available_percent = space.available_percent();
+        let ok = space.available_bytes >= config.min_free_bytes
+            && available_percent >= config.min_free_percent;
+        if !ok {
+            log::warn!(
+                "GET /health low disk space on {}: {} bytes ({:.1}%) free",
+                uploads_dir.display(),
+                space.available_bytes,
+                available_percent
+            );
+        }
+
+        Some(DiskDetail {
+            space,
+            available_percent,
+            min_free_bytes: config.min_free_bytes,
+            min_free_percent: config.min_free_percent,
+            ok,
+        })
+    }
+}
+
+/// Readiness check. Returns `503` once free space on `uploads_path` drops
+/// below `min_free_bytes`/`min_free_percent`, so traffic is drained before
+/// uploads start failing.
+///
+/// `?verbose=1` adds a JSON report of the expiry sweeper and disk space so
+/// "uploaded but never expires" problems can be diagnosed without logs.
+#[get("/health")]
+pub async fn health(
+    query: web::Query<HealthQuery>,
+    config: web::Data<config::Folio>,
+    expiry_store: web::Data<Arc<ExpiryStore>>,
+) -> impl Responder {
+    let disk = DiskDetail::check(&config);
+    let disk_ok = disk.as_ref().is_none_or(|d| d.ok);
+
+    if !matches!(query.verbose.as_deref(), Some("1" | "true")) {
+        if !disk_ok {
+            return HttpResponse::ServiceUnavailable().body("LOW DISK SPACE");
+        }
+        return HttpResponse::Ok().body("OK");
+    }
+
+    let expiry_sweeper = expiry_store.sweeper_status();
+    let pending_expirations = match expiry_store.pending_count().await {
+        Ok(count) => Some(count),
+        Err(e) => {
+            log::error!("GET /health failed to read expiry index: {}", e);
+            None
+        }
+    };
+    let status = if !disk_ok {
+        "unavailable"
+    } else if expiry_sweeper.healthy && pending_expirations.is_some() && disk.is_some() {
+        "ok"
+    } else {
+        "degraded"
+    };
+
+    let detail = HealthDetail {
+        status,
+        expiry_sweeper,
+        pending_expirations,
+        disk,
+    };
+    if disk_ok {
+        HttpResponse::Ok().json(detail)
+    } else {
+        HttpResponse::ServiceUnavailable().json(detail)
+    }
+}
+
+/// One `/ready` check; `detail` says why it failed.
+#[derive(Serialize)]
+struct ReadyCheck {
+    name: &'static str,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+impl ReadyCheck {
+    fn new(name: &'static str, failure: Option<String>) -> Self {
+        Self {
+            name,
+            ok: failure.is_none(),
+            detail: failure,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ReadyDetail {
+    ready: bool,
+    checks: Vec<ReadyCheck>,
+}
+
+/// Write and delete a marker file in the uploads root.
+fn probe_writable(uploads_dir: &Path) -> std::io::Result<()> {
+    let marker = uploads_dir.join(format!(".folio-ready-{:08x}", rand::rng().random::<u32>()));
+    std::fs::write(&marker, b"ready")?;
+    std::fs::remove_file(&marker)
+}
+
+/// Readiness for traffic, as opposed to the `/health` liveness ping:
+/// `200` only when the uploads directory takes writes (skipped with
+/// `read_only`), the expiry sweeper is running and free space is above
+/// threshold. Otherwise `503`, with the failed checks in the body.
+#[get("/ready")]
+pub async fn ready(
+    config: web::Data<config::Folio>,
+    expiry_store: web::Data<Arc<ExpiryStore>>,
+) -> impl Responder {
+    let storage = if config.read_only {
+        None
+    } else {
+        let uploads_dir = config.resolve_base(&config.uploads_path);
+        web::block(move || probe_writable(&uploads_dir))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|r| r.map_err(|e| e.to_string()))
+            .err()
+    };
+
+    let sweeper = (!expiry_store.sweeper_status().running)
+        .then(|| "expiry sweeper is not running".to_string());
+
+    let disk = match DiskDetail::check(&config) {
+        Some(d) if d.ok => None,
+        Some(d) => Some(format!(
+            "{} bytes ({:.1}%) free",
+            d.space.available_bytes, d.available_percent
+        )),
+        None => Some("free space unknown".to_string()),
+    };
+
+    let checks = vec![
+        ReadyCheck::new("storage", storage),
+        ReadyCheck::new("expiry_sweeper", sweeper),
+        ReadyCheck::new("disk", disk),
+    ];
+    let ready = checks.iter().all(|c| c.ok);
+    if !ready {
+        log::warn!(
+            "GET /ready failing: {}",
+            checks
+                .iter()
+                .filter(|c| !c.ok)
+                .map(|c| c.name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    let detail = ReadyDetail { ready, checks };
+    if ready {
+        HttpResponse::Ok().json(detail)
+    } else {
+        HttpResponse::ServiceUnavailable().json(detail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, http::StatusCode, test};
+    use std::time::Duration;
+
+    use crate::config;
+
+    fn test_config(temp_dir: &tempfile::TempDir) -> config::Folio {
+        config::Folio {
+            uploads_path: temp_dir.path().to_string_lossy().to_string(),
+            data_path: temp_dir.path().to_string_lossy().to_string(),
+            ..config::Folio::default()
+        }
+    }
+
+    fn test_store(temp_dir: &tempfile::TempDir) -> Arc<ExpiryStore> {
+        Arc::new(ExpiryStore::new(&test_config(temp_dir)))
+    }
+
+    #[actix_web::test]
+    async fn plain_health_returns_ok_text() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(test_config(&temp_dir)))
+                .app_data(web::Data::new(test_store(&temp_dir)))
+                .service(health),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/health").to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(test::read_body(response).await, "OK");
+    }
+
+    #[actix_web::test]
+    async fn verbose_health_reports_sweeper() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = test_store(&temp_dir);
+        store
+            .schedule(&temp_dir.path().join("a.txt"), Duration::from_secs(60))
+            .await
+            .unwrap();
+        store.clone().spawn_sweeper(Duration::from_secs(60));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(test_config(&temp_dir)))
+                .app_data(web::Data::new(store))
+                .service(health),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/health?verbose=1")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["expiry_sweeper"]["running"], true);
+        assert_eq!(body["expiry_sweeper"]["interval_secs"], 60);
+        assert_eq!(body["pending_expirations"], 1);
+        assert_eq!(body["disk"]["ok"], true);
+        assert!(body["disk"]["total_bytes"].as_u64().unwrap() > 0);
+    }
+
+    #[actix_web::test]
+    async fn verbose_health_degraded_without_sweeper() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(test_config(&temp_dir)))
+                .app_data(web::Data::new(test_store(&temp_dir)))
+                .service(health),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/health?verbose=true")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["status"], "degraded");
+        assert_eq!(body["expiry_sweeper"]["running"], false);
+    }
+
+    #[actix_web::test]
+    async fn low_disk_space_is_unavailable() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = config::Folio {
+            min_free_bytes: u64::MAX,
+            ..test_config(&temp_dir)
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(test_store(&temp_dir)))
+                .service(health),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/health").to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let req = test::TestRequest::get()
+            .uri("/health?verbose=1")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["status"], "unavailable");
+        assert_eq!(body["disk"]["ok"], false);
+    }
+
+    #[actix_web::test]
+    async fn ready_needs_sweeper_and_writable_storage() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = test_store(&temp_dir);
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(test_config(&temp_dir)))
+                .app_data(web::Data::new(store.clone()))
+                .service(ready),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/ready").to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["ready"], false);
+        assert_eq!(body["checks"][0]["name"], "storage");
+        assert_eq!(body["checks"][0]["ok"], true);
+        assert_eq!(body["checks"][1]["name"], "expiry_sweeper");
+        assert_eq!(body["checks"][1]["ok"], false);
+
+        store.spawn_sweeper(Duration::from_secs(60));
+        let req = test::TestRequest::get().uri("/ready").to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let leftover_markers = std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter(|e| e.file_name().to_string_lossy().starts_with(".folio-ready"))
+            .count();
+        assert_eq!(leftover_markers, 0);
+    }
+}
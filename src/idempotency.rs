@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use actix_web::body::{BoxBody, MessageBody, to_bytes};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::http::header::HeaderMap;
+use actix_web::middleware::Next;
+use actix_web::{HttpResponse, ResponseError, web};
+
+use super::config;
+use super::error::FolioError;
+use super::upload_meta::uploader;
+use super::uploads::parse_duration;
+
+pub const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+
+/// Set on responses replayed from the cache.
+const REPLAYED: &str = "Idempotent-Replayed";
+
+/// Keys remembered at once; the oldest result is dropped to make room.
+const MAX_KEYS: usize = 10_000;
+
+const MAX_KEY_LENGTH: usize = 255;
+
+struct StoredResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: web::Bytes,
+}
+
+impl StoredResponse {
+    fn to_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status);
+        for (name, value) in &self.headers {
+            response.append_header((name.clone(), value.clone()));
+        }
+        response
+            .insert_header((REPLAYED, "true"))
+            .body(self.body.clone())
+    }
+}
+
+enum Entry {
+    InFlight,
+    Done(Arc<StoredResponse>),
+}
+
+enum Begin {
+    Started,
+    InFlight,
+    Replay(Arc<StoredResponse>),
+}
+
+/// Results of successful `/uploads` requests by `Idempotency-Key`, so a
+/// retried request gets the original response instead of a second file.
+/// Kept in memory for `idempotency_window`; a restart forgets them.
+pub struct IdempotencyCache {
+    window: Duration,
+    entries: Mutex<HashMap<String, (Instant, Entry)>>,
+}
+
+impl IdempotencyCache {
+    /// `None` when `idempotency_window` is empty or invalid.
+    pub fn from_config(config: &config::Folio) -> Option<Self> {
+        if config.idempotency_window.is_empty() {
+            return None;
+        }
+        let window = parse_duration(&config.idempotency_window)
+            .inspect_err(|e| {
+                log::warn!(
+                    "invalid idempotency_window '{}': {}, Idempotency-Key is ignored",
+                    config.idempotency_window,
+                    e
+                )
+            })
+            .ok()?;
+        Some(Self {
+            window,
+            entries: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn begin(&self, key: &str) -> Begin {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (created, entry)| {
+            matches!(entry, Entry::InFlight) || created.elapsed() < self.window
+        });
+        match entries.get(key) {
+            Some((_, Entry::InFlight)) => return Begin::InFlight,
+            Some((_, Entry::Done(stored))) => return Begin::Replay(stored.clone()),
+            None => {}
+        }
+
+        if entries.len() >= MAX_KEYS {
+            let oldest = entries
+                .iter()
+                .filter(|(_, (_, entry))| matches!(entry, Entry::Done(_)))
+                .min_by_key(|(_, (created, _))| *created)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key.to_string(), (Instant::now(), Entry::InFlight));
+        Begin::Started
+    }
+
+    fn complete(&self, key: &str, stored: StoredResponse) {
+        self.entries.lock().unwrap().insert(
+            key.to_string(),
+            (Instant::now(), Entry::Done(Arc::new(stored))),
+        );
+    }
+
+    fn abandon(&self, key: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        if matches!(entries.get(key), Some((_, Entry::InFlight))) {
+            entries.remove(key);
+        }
+    }
+}
+
+/// Releases a key whose request failed, errored or was dropped, so the
+/// client can retry it.
+struct Claim<'a> {
+    cache: &'a IdempotencyCache,
+    key: String,
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        self.cache.abandon(&self.key);
+    }
+}
+
+/// The request's `Idempotency-Key`, scoped to its uploader when known.
+async fn scoped_key(req: &ServiceRequest) -> Result<Option<String>, FolioError> {
+    let Some(value) = req.headers().get(IDEMPOTENCY_KEY) else {
+        return Ok(None);
+    };
+    let key = value
+        .to_str()
+        .ok()
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LENGTH)
+        .ok_or_else(|| FolioError::BadRequest {
+            reason: format!(
+                "{} must be 1 to {} visible ASCII characters",
+                IDEMPOTENCY_KEY, MAX_KEY_LENGTH
+            ),
+        })?;
+    let uploader = uploader(req.request()).await.unwrap_or_default();
+    Ok(Some(format!("{}\n{}", uploader, key)))
+}
+
+/// Route middleware replaying the response of an earlier successful upload
+/// with the same `Idempotency-Key`. A retry that arrives while the first
+/// request is still running gets `503` with `Retry-After`. Failed requests
+/// are not remembered.
+///
+/// Wrapped outside `limit_uploads`, so replays never take an upload slot.
+pub async fn replay_idempotent(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let Some(cache) = req.app_data::<web::Data<Arc<IdempotencyCache>>>().cloned() else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_boxed_body);
+    };
+    let key = match scoped_key(&req).await {
+        Ok(Some(key)) => key,
+        Ok(None) => {
+            return next
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_boxed_body);
+        }
+        Err(e) => return Ok(req.into_response(e.error_response())),
+    };
+
+    match cache.begin(&key) {
+        Begin::Started => {}
+        Begin::Replay(stored) => {
+            log::info!("replaying upload response for {}", IDEMPOTENCY_KEY);
+            return Ok(req.into_response(stored.to_response()));
+        }
+        Begin::InFlight => {
+            let e = FolioError::ServiceUnavailable {
+                reason: format!("a request with this {} is in progress", IDEMPOTENCY_KEY),
+                retry_after_secs: 1,
+            };
+            return Ok(req.into_response(e.error_response()));
+        }
+    }
+    let _claim = Claim {
+        cache: &cache,
+        key: key.clone(),
+    };
+
+    let res = next.call(req).await?;
+    if !res.status().is_success() {
+        return Ok(res.map_into_boxed_body());
+    }
+
+    let (req, res) = res.into_parts();
+    let (res, body) = res.into_parts();
+    let body = to_bytes(body)
+        .await
+        .map_err(|e| FolioError::store_error(e.into().to_string(), "read upload response"))?;
+    cache.complete(
+        &key,
+        StoredResponse {
+            status: res.status(),
+            headers: res.headers().clone(),
+            body: body.clone(),
+        },
+    );
+    Ok(ServiceResponse::new(req, res.set_body(body)).map_into_boxed_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::middleware::from_fn;
+    use actix_web::{App, HttpResponse, post, test as awtest};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    #[post("/uploads", wrap = "from_fn(replay_idempotent)")]
+    async fn upload_stub(body: String) -> HttpResponse {
+        if body == "fail" {
+            return HttpResponse::BadRequest().finish();
+        }
+        let n = CALLS.fetch_add(1, Ordering::SeqCst);
+        HttpResponse::Created()
+            .insert_header(("Location", format!("/files/{}.txt", n)))
+            .body(format!("upload {}", n))
+    }
+
+    fn with_window(window: &str) -> Option<IdempotencyCache> {
+        IdempotencyCache::from_config(&config::Folio {
+            idempotency_window: window.to_string(),
+            ..config::Folio::default()
+        })
+    }
+
+    #[actix_web::test]
+    async fn retries_replay_the_first_success() {
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(Arc::new(with_window("1h").unwrap())))
+                .service(upload_stub),
+        )
+        .await;
+        let upload = |key: &str, body: &'static str| {
+            awtest::TestRequest::post()
+                .uri("/uploads")
+                .insert_header((IDEMPOTENCY_KEY, key.to_string()))
+                .set_payload(body)
+                .to_request()
+        };
+
+        let res = awtest::call_service(&app, upload("k1", "fail")).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let first = awtest::call_service(&app, upload("k1", "data")).await;
+        assert_eq!(first.status(), StatusCode::CREATED);
+        let location = first.headers().get("location").unwrap().clone();
+        assert!(!first.headers().contains_key(REPLAYED));
+        let first_body = awtest::read_body(first).await;
+
+        let retry = awtest::call_service(&app, upload("k1", "data")).await;
+        assert_eq!(retry.status(), StatusCode::CREATED);
+        assert_eq!(retry.headers().get("location").unwrap(), &location);
+        assert_eq!(retry.headers().get(REPLAYED).unwrap(), "true");
+        assert_eq!(awtest::read_body(retry).await, first_body);
+
+        let other = awtest::call_service(&app, upload("k2", "data")).await;
+        assert_ne!(other.headers().get("location").unwrap(), &location);
+    }
+
+    #[test]
+    fn in_flight_keys_block_until_released() {
+        let cache = with_window("1h").unwrap();
+        assert!(matches!(cache.begin("k"), Begin::Started));
+        assert!(matches!(cache.begin("k"), Begin::InFlight));
+        cache.abandon("k");
+        assert!(matches!(cache.begin("k"), Begin::Started));
+
+        assert!(with_window("").is_none());
+        assert!(with_window("soon").is_none());
+    }
+}
@@ -0,0 +1,195 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::UNIX_EPOCH;
+
+use image::{ImageFormat, ImageReader};
+use mime_guess::Mime;
+use sha2::{Digest, Sha256};
+
+/// Decoder for `content_type`, if it is an image type this build can read.
+pub fn readable_format(content_type: &Mime) -> Option<ImageFormat> {
+    if content_type.type_() != mime_guess::mime::IMAGE {
+        return None;
+    }
+    ImageFormat::from_mime_type(content_type.essence_str()).filter(ImageFormat::reading_enabled)
+}
+
+/// Fully decode the image at `path` and return its dimensions.
+///
+/// The format is sniffed from the content, falling back to `format`, so a
+/// file whose bytes don't match what it claims to be fails to decode.
+/// CPU-heavy; call off the async executor.
+pub fn decode_dimensions(path: &Path, format: ImageFormat) -> Result<(u32, u32), String> {
+    let mut reader = ImageReader::open(path)
+        .map_err(|e| format!("open image failed: {}", e))?
+        .with_guessed_format()
+        .map_err(|e| format!("read image failed: {}", e))?;
+    if reader.format().is_none() {
+        reader.set_format(format);
+    }
+    let image = reader
+        .decode()
+        .map_err(|e| format!("decode image failed: {}", e))?;
+    Ok((image.width(), image.height()))
+}
+
+/// Parse a `?thumb=` value such as `200x150`.
+pub fn parse_thumb_size(s: &str, max: u32) -> Result<(u32, u32), String> {
+    let (w, h) = s
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("invalid thumb size '{}': expected <W>x<H>", s))?;
+    let parse = |v: &str| {
+        v.parse::<u32>()
+            .ok()
+            .filter(|v| *v > 0)
+            .ok_or_else(|| format!("invalid thumb size '{}': expected <W>x<H>", s))
+    };
+    let (w, h) = (parse(w)?, parse(h)?);
+    if w > max || h > max {
+        return Err(format!(
+            "thumb size '{}' exceeds max_thumbnail_size {}",
+            s, max
+        ));
+    }
+    Ok((w, h))
+}
+
+/// Format thumbnails are written in: JPEG stays JPEG, everything else is PNG.
+pub fn thumbnail_format(source: ImageFormat) -> ImageFormat {
+    match source {
+        ImageFormat::Jpeg => ImageFormat::Jpeg,
+        _ => ImageFormat::Png,
+    }
+}
+
+/// Cache location for a thumbnail. The key covers the source's path and
+/// modification time, so a replaced file never serves a stale thumbnail.
+pub fn thumbnail_cache_path(
+    cache_dir: &Path,
+    source: &Path,
+    size: (u32, u32),
+    format: ImageFormat,
+) -> std::io::Result<PathBuf> {
+    let mtime = std::fs::metadata(source)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let mut hasher = Sha256::new();
+    hasher.update(source.as_os_str().as_encoded_bytes());
+    hasher.update(format!("|{}x{}|{}", size.0, size.1, mtime));
+    let key: String = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let extension = format.extensions_str().first().copied().unwrap_or("img");
+    Ok(cache_dir.join(format!("{}.{}", key, extension)))
+}
+
+/// Resize `source` to fit within `size` (keeping the aspect ratio) and write
+/// it to `dest` atomically. CPU-heavy; call off the async executor.
+pub fn render_thumbnail(
+    source: &Path,
+    source_format: ImageFormat,
+    dest: &Path,
+    size: (u32, u32),
+) -> Result<(), String> {
+    let mut reader = ImageReader::open(source)
+        .map_err(|e| format!("open image failed: {}", e))?
+        .with_guessed_format()
+        .map_err(|e| format!("read image failed: {}", e))?;
+    if reader.format().is_none() {
+        reader.set_format(source_format);
+    }
+    let image = reader
+        .decode()
+        .map_err(|e| format!("decode image failed: {}", e))?;
+    let thumbnail = image.thumbnail(size.0, size.1);
+
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("create thumbnail dir failed: {}", e))?;
+    }
+    let format = thumbnail_format(source_format);
+    // Unique per call so concurrent first requests don't clobber each other.
+    static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+    let tmp_path = dest.with_extension(format!(
+        "{}.{}.tmp",
+        std::process::id(),
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    // JPEG has no alpha channel.
+    let written = if format == ImageFormat::Jpeg {
+        thumbnail.into_rgb8().save_with_format(&tmp_path, format)
+    } else {
+        thumbnail.save_with_format(&tmp_path, format)
+    };
+    written.map_err(|e| {
+        let _ = std::fs::remove_file(&tmp_path);
+        format!("write thumbnail failed: {}", e)
+    })?;
+    std::fs::rename(&tmp_path, dest).map_err(|e| format!("replace thumbnail failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png_bytes(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        image::RgbImage::new(width, height)
+            .write_to(&mut bytes, ImageFormat::Png)
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn readable_format_only_for_supported_images() {
+        let png: Mime = "image/png".parse().unwrap();
+        let svg: Mime = "image/svg+xml".parse().unwrap();
+        let text: Mime = "text/plain".parse().unwrap();
+
+        assert_eq!(readable_format(&png), Some(ImageFormat::Png));
+        assert_eq!(readable_format(&svg), None);
+        assert_eq!(readable_format(&text), None);
+    }
+
+    #[test]
+    fn parses_thumb_sizes() {
+        assert_eq!(parse_thumb_size("200x150", 512).unwrap(), (200, 150));
+        assert!(parse_thumb_size("200", 512).is_err());
+        assert!(parse_thumb_size("0x10", 512).is_err());
+        assert!(parse_thumb_size("1024x10", 512).is_err());
+    }
+
+    #[test]
+    fn renders_thumbnail_within_bounds() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let source = temp_dir.path().join("big.png");
+        std::fs::write(&source, png_bytes(40, 20)).unwrap();
+        let dest = thumbnail_cache_path(
+            &temp_dir.path().join("thumbs"),
+            &source,
+            (10, 10),
+            ImageFormat::Png,
+        )
+        .unwrap();
+
+        render_thumbnail(&source, ImageFormat::Png, &dest, (10, 10)).unwrap();
+
+        assert_eq!(decode_dimensions(&dest, ImageFormat::Png).unwrap(), (10, 5));
+    }
+
+    #[test]
+    fn decodes_valid_and_rejects_corrupt_images() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let good = temp_dir.path().join("good.png");
+        std::fs::write(&good, png_bytes(3, 2)).unwrap();
+        let bad = temp_dir.path().join("bad.png");
+        std::fs::write(&bad, b"definitely not a png").unwrap();
+
+        assert_eq!(decode_dimensions(&good, ImageFormat::Png).unwrap(), (3, 2));
+        assert!(decode_dimensions(&bad, ImageFormat::Png).is_err());
+    }
+}
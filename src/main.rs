@@ -1,99 +1,373 @@
+mod access;
+mod admin;
+mod alias;
+mod archive;
+mod audit;
 mod auth;
+mod canonical_host;
+mod capabilities;
 mod config;
+mod cors;
+mod dir_count;
+mod disk;
+mod durable;
 mod error;
+mod events;
 mod expiry;
+mod extra_headers;
+mod extract;
 mod files;
+mod form_redirect;
+mod hashing;
+mod health;
+mod idempotency;
+mod images;
+mod maintenance;
+mod manifest;
+mod meta_file;
+mod metadata;
+mod metrics;
+mod mime_pattern;
+mod parts;
 mod path;
+mod pending;
+mod pretty_json;
 mod private_index;
+mod processing;
+mod public_url;
+mod quota;
+mod relocate;
+mod slow_requests;
+mod staging;
+mod stats;
 mod store;
 #[cfg(test)]
 mod test_utils;
+mod tls;
+mod upload_limit;
+mod upload_meta;
 mod uploads;
 
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 
 use actix_files::Files;
-use actix_web::{App, HttpResponse, HttpServer, get, web};
-use figment::Figment;
+use actix_web::middleware::from_fn;
+use actix_web::{App, HttpResponse, HttpServer, web};
 use figment::providers::{Env, Format, Serialized, Toml};
-
-#[get("/health")]
-async fn health() -> &'static str {
-    "OK"
-}
+use figment::{Figment, Profile, Source};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     pretty_env_logger::init();
 
-    let mut config = load_config();
-    apply_rocket_compat_env(&mut config);
-    log::info!("Using config: {:?}", config);
+    let (mut config, mut config_sources) = load_config();
+    apply_rocket_compat_env(&mut config, &mut config_sources);
+    log::info!("{}", config);
 
     // Ensure runtime data directories exist
     let uploads_dir = config.resolve_base(&config.uploads_path);
     let data_dir = config.resolve_base(&config.data_path);
-    std::fs::create_dir_all(&uploads_dir).unwrap_or_else(|e| {
-        panic!("Failed to create uploads directory {}: {}", uploads_dir.display(), e)
-    });
+    prepare_uploads_dir(&config, &uploads_dir).unwrap_or_else(|e| panic!("{}", e));
+    if let Err(e) = check_path_overlap(&config, &uploads_dir) {
+        if config.reject_overlapping_paths {
+            panic!("{}", e);
+        }
+        log::warn!("{}", e);
+    }
     std::fs::create_dir_all(&data_dir).unwrap_or_else(|e| {
         panic!("Failed to create data directory {}: {}", data_dir.display(), e)
     });
 
-    let expiry_store = Arc::new(expiry::ExpiryStore::new(&config));
+    let alias_store = Arc::new(alias::AliasStore::new(&config));
+    let event_bus = Arc::new(events::EventBus::default());
+    let expiry_store = Arc::new(
+        expiry::ExpiryStore::new(&config)
+            .with_aliases(alias_store.clone())
+            .with_events(event_bus.clone()),
+    );
     expiry_store.clone().spawn_sweeper(Duration::from_secs(60));
 
     let private_index_store = Arc::new(private_index::PrivateIndexStore::new(&config));
+    let metadata_store = Arc::new(metadata::MetadataStore::new(&config));
     let access_auth = Arc::new(auth::AccessAuth::from_env());
+    let dir_counter = Arc::new(dir_count::DirEntryCounter::default());
+    let audit_log = Arc::new(audit::AuditLog::new(&config));
+    let upload_limiter = Arc::new(upload_limit::UploadLimiter::new(&config));
+    let pending_uploads = Arc::new(pending::PendingUploads::new(&config));
+    let root_quotas = Arc::new(quota::RootQuotas::default());
+    let glob_usage = Arc::new(stats::GlobUsage::default());
+    let maintenance = Arc::new(maintenance::Maintenance::default());
+    let hash_cache = Arc::new(manifest::HashCache::default());
+    let processing_tracker = Arc::new(processing::ProcessingTracker::default());
+    let extra_headers = Arc::new(
+        extra_headers::ExtraHeaders::from_config(&config)
+            .unwrap_or_else(|e| panic!("Invalid extra_headers config: {}", e)),
+    );
+    let idempotency_cache = idempotency::IdempotencyCache::from_config(&config).map(Arc::new);
+    let access_tracker = access::AccessTracker::from_config(&config).map(Arc::new);
+    let storage_gauges = metrics::StorageGauges::from_config(&config).map(Arc::new);
+    if let Err(e) = mime_pattern::MimePattern::parse_all(&config.allowed_content_types) {
+        panic!("Invalid allowed_content_types config: {}", e);
+    }
+    if let Err(e) = mime_pattern::MimePattern::parse_all(&config.inline_content_types) {
+        panic!("Invalid inline_content_types config: {}", e);
+    }
+    let size_types: Vec<String> = config.max_upload_bytes_by_type.keys().cloned().collect();
+    if let Err(e) = mime_pattern::MimePattern::parse_all(&size_types) {
+        panic!("Invalid max_upload_bytes_by_type config: {}", e);
+    }
+    for (name, root) in &config.roots {
+        for (key, patterns) in [
+            ("allowed_content_types", &root.allowed_content_types),
+            ("inline_content_types", &root.inline_content_types),
+        ] {
+            if let Some(Err(e)) = patterns
+                .as_deref()
+                .map(mime_pattern::MimePattern::parse_all)
+            {
+                panic!("Invalid {} config for root '{}': {}", key, name, e);
+            }
+        }
+    }
+    if config.cors_allow_credentials && config.cors_allowed_origins.iter().any(|o| o == "*") {
+        log::warn!(
+            "cors_allow_credentials with origin '*' lets any site make credentialed requests"
+        );
+    }
 
     let bind = (config.address.clone(), config.port);
+    let tls_config = tls::server_config(&config)?;
+    let max_connections = config.max_connections;
     let web_path = config.web_path.clone();
+    let serve_files = config.serve_files;
+    let config = web::Data::new(config);
+    if let Some(tracker) = &access_tracker {
+        tracker
+            .clone()
+            .spawn_flusher(config.clone(), metadata_store.clone(), expiry_store.clone());
+    }
+    if let Some(gauges) = &storage_gauges {
+        gauges.clone().spawn_refresher(config.clone());
+    }
+    let config_sources = Arc::new(config_sources);
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(config::Folio {
-                address: config.address.clone(),
-                port: config.port,
-                web_path: config.web_path.clone(),
-                uploads_path: config.uploads_path.clone(),
-                data_path: config.data_path.clone(),
-                max_upload_size: config.max_upload_size,
-            }))
+            .wrap(from_fn(maintenance::reject_writes))
+            .wrap(from_fn(events::publish_events))
+            .wrap(from_fn(slow_requests::log_slow_requests))
+            .wrap(from_fn(audit::audit))
+            .wrap(from_fn(canonical_host::redirect_to_canonical_host))
+            .wrap(from_fn(pretty_json::pretty_print))
+            // Outermost, so preflights are answered before any redirect.
+            .wrap(from_fn(cors::cors))
+            .app_data(config.clone())
             .app_data(web::Data::new(expiry_store.clone()))
             .app_data(web::Data::new(private_index_store.clone()))
+            .app_data(web::Data::new(metadata_store.clone()))
             .app_data(web::Data::new(access_auth.clone()))
-            .service(health)
+            .app_data(web::Data::new(dir_counter.clone()))
+            .app_data(web::Data::new(audit_log.clone()))
+            .app_data(web::Data::new(upload_limiter.clone()))
+            .app_data(web::Data::new(pending_uploads.clone()))
+            .app_data(web::Data::new(root_quotas.clone()))
+            .app_data(web::Data::new(glob_usage.clone()))
+            .app_data(web::Data::new(maintenance.clone()))
+            .app_data(web::Data::new(hash_cache.clone()))
+            .app_data(web::Data::new(alias_store.clone()))
+            .app_data(web::Data::new(processing_tracker.clone()))
+            .app_data(web::Data::new(extra_headers.clone()))
+            .app_data(web::Data::new(event_bus.clone()))
+            .app_data(web::Data::new(config_sources.clone()))
+            .configure(|cfg| {
+                // `Idempotency-Key` is ignored when `idempotency_window` is empty.
+                if let Some(cache) = &idempotency_cache {
+                    cfg.app_data(web::Data::new(cache.clone()));
+                }
+                // Downloads are only counted with `track_access`.
+                if let Some(tracker) = &access_tracker {
+                    cfg.app_data(web::Data::new(tracker.clone()));
+                }
+                // `/metrics` leaves out storage gauges without `storage_metrics_interval`.
+                if let Some(gauges) = &storage_gauges {
+                    cfg.app_data(web::Data::new(gauges.clone()));
+                }
+            })
+            .service(health::health)
+            .service(health::ready)
+            .service(metrics::metrics)
+            .service(capabilities::get_capabilities)
+            .service(stats::get_stats)
+            .service(stats::get_usage)
+            .service(uploads::upload_json)
             .service(uploads::upload_file)
-            .service(files::get_file)
+            .service(extract::extract_upload)
+            .service(alias::create_alias)
+            .service(alias::resolve_alias)
+            .service(relocate::relocate_upload)
+            .service(upload_meta::get_upload_metadata)
+            .service(processing::get_upload_status)
+            .service(staging::commit_upload)
+            .service(parts::upload_part)
+            .service(parts::complete_upload)
+            .service(uploads::delete_upload)
+            .service(files::validate_file_path)
+            .configure(|cfg| {
+                // API-only deployments leave downloads to e.g. a CDN.
+                if serve_files {
+                    cfg.service(files::get_file)
+                        .service(files::get_private_file);
+                }
+            })
+            .service(files::touch_file)
+            .service(files::unexpire_file)
             .service(files::create_file)
+            .service(files::update_expiration)
             .service(files::upsert_file)
             .service(files::delete_file)
-            .service(files::get_private_file)
+            .service(admin::bulk_delete)
+            .service(admin::self_test)
+            .service(maintenance::set_maintenance)
+            .service(admin::cancel_expirations)
+            .service(admin::debug_config)
+            .service(events::stream_events)
             .service(
                 Files::new("/", web_path.clone())
                     .index_file("index.html")
                     .default_handler(web::to(|| async { HttpResponse::NotFound().finish() })),
             )
-    })
-    .bind(bind)?
-    .run()
-    .await
+    });
+    let server = match max_connections {
+        0 => server,
+        n => server.max_connections(n),
+    };
+    let server = match tls_config {
+        Some(tls_config) => server.bind_rustls_0_23(bind, tls_config)?,
+        None => server.bind(bind)?,
+    };
+    server.run().await
 }
 
-fn load_config() -> config::Folio {
-    Figment::from(Serialized::defaults(config::Folio::default()))
-        .merge(Toml::file("Folio.toml"))
-        .merge(Env::prefixed("FOLIO_").global())
-        .extract()
-        .unwrap()
+/// Environment variable naming the `Folio.toml` section to apply, e.g.
+/// `FOLIO_PROFILE=production` for `[production]`.
+const PROFILE_ENV: &str = "FOLIO_PROFILE";
+
+fn load_config() -> (config::Folio, config::ConfigSources) {
+    let profile = Profile::from_env_or(PROFILE_ENV, Profile::Default);
+    let file = Figment::from(Toml::file("Folio.toml"));
+    let figment = config_figment(file.clone(), &profile);
+    let config = figment.extract().unwrap();
+    let sources = config_sources(&figment, &file, &profile, &config);
+    (config, sources)
+}
+
+/// Precedence, lowest first: built-in defaults, top-level keys of the
+/// config file, the file's `[<profile>]` section, `FOLIO_*` variables.
+fn config_figment(file: Figment, profile: &Profile) -> Figment {
+    let mut figment =
+        Figment::from(Serialized::defaults(config::Folio::default())).merge(file.clone());
+    if *profile != Profile::Default {
+        let section = profile.as_str().as_str();
+        if file.find_value(section).is_err() {
+            log::warn!("config profile [{}] not found in Folio.toml", section);
+        }
+        figment = figment.merge(file.focus(section));
+    }
+    figment.merge(Env::prefixed("FOLIO_").ignore(&["PROFILE"]).global())
+}
+
+/// Which provider of `config_figment` set each top-level key of `config`.
+fn config_sources(
+    figment: &Figment,
+    file: &Figment,
+    profile: &Profile,
+    config: &config::Folio,
+) -> config::ConfigSources {
+    let keys = match serde_json::to_value(config) {
+        Ok(serde_json::Value::Object(settings)) => settings.into_iter().map(|(key, _)| key),
+        _ => return config::ConfigSources::default(),
+    };
+    let section = profile.as_str().as_str();
+    let sources = keys
+        .map(|key| {
+            let source = match figment.find_metadata(&key) {
+                Some(metadata) if metadata.name.contains("environment") => {
+                    format!("env FOLIO_{}", metadata.interpolate(profile, &[&key]))
+                }
+                Some(metadata) => match &metadata.source {
+                    Some(source @ Source::File(_)) => {
+                        let in_section = *profile != Profile::Default
+                            && file.focus(section).find_value(&key).is_ok();
+                        match in_section {
+                            true => format!("{} [{}]", source, section),
+                            false => source.to_string(),
+                        }
+                    }
+                    _ => String::from("default"),
+                },
+                None => String::from("default"),
+            };
+            (key, source)
+        })
+        .collect();
+    config::ConfigSources(sources)
 }
 
-fn apply_rocket_compat_env(config: &mut config::Folio) {
+/// Create `uploads_path` if it is missing, or refuse to with
+/// `require_existing_uploads_dir`, so an unmounted volume is caught before
+/// anything is written to the wrong place.
+fn prepare_uploads_dir(config: &config::Folio, dir: &Path) -> Result<(), String> {
+    if dir.is_dir() {
+        log::info!("Using uploads directory {}", dir.display());
+        return Ok(());
+    }
+    if config.require_existing_uploads_dir {
+        return Err(format!(
+            "Uploads directory {} does not exist (require_existing_uploads_dir is set)",
+            dir.display()
+        ));
+    }
+    std::fs::create_dir_all(dir).map_err(|e| {
+        format!(
+            "Failed to create uploads directory {}: {}",
+            dir.display(),
+            e
+        )
+    })?;
+    log::warn!("Created missing uploads directory {}", dir.display());
+    Ok(())
+}
+
+/// `web_path` is served at `/` without any of the checks on `/files`, so an
+/// uploads directory inside it (or around it) exposes uploads unexpectedly.
+/// A `web_path` that does not exist cannot overlap.
+fn check_path_overlap(config: &config::Folio, uploads_dir: &Path) -> Result<(), String> {
+    let (Ok(web), Ok(uploads)) = (
+        config.resolve_base(&config.web_path).canonicalize(),
+        uploads_dir.canonicalize(),
+    ) else {
+        return Ok(());
+    };
+    if uploads.starts_with(&web) || web.starts_with(&uploads) {
+        return Err(format!(
+            "web_path {} and uploads_path {} overlap: uploads may be served by the web UI file server",
+            web.display(),
+            uploads.display()
+        ));
+    }
+    Ok(())
+}
+
+fn apply_rocket_compat_env(config: &mut config::Folio, sources: &mut config::ConfigSources) {
     if std::env::var_os("FOLIO_ADDRESS").is_none()
         && let Ok(address) = std::env::var("ROCKET_ADDRESS")
     {
         config.address = address;
+        sources
+            .0
+            .insert("address".into(), "env ROCKET_ADDRESS".into());
     }
 
     if std::env::var_os("FOLIO_PORT").is_none()
@@ -101,5 +375,97 @@ fn apply_rocket_compat_env(config: &mut config::Folio) {
         && let Ok(port) = port.parse()
     {
         config.port = port;
+        sources.0.insert("port".into(), "env ROCKET_PORT".into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = r#"
+        port = 9000
+        default_expire = "1d"
+
+        [production]
+        default_expire = "7d"
+        verify_images = true
+    "#;
+
+    #[test]
+    fn profile_section_overrides_top_level_keys() {
+        let file = || Figment::from(Toml::string(FILE));
+
+        let config: config::Folio = config_figment(file(), &Profile::Default).extract().unwrap();
+        assert_eq!(config.port, 9000);
+        assert_eq!(config.default_expire, "1d");
+        assert!(!config.verify_images);
+
+        let config: config::Folio = config_figment(file(), &Profile::new("production"))
+            .extract()
+            .unwrap();
+        assert_eq!(config.port, 9000);
+        assert_eq!(config.default_expire, "7d");
+        assert!(config.verify_images);
+    }
+
+    #[test]
+    fn sources_name_the_file_section_or_default() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("Folio.toml");
+        std::fs::write(&path, FILE).unwrap();
+        let file = Figment::from(Toml::file(&path));
+        let profile = Profile::new("production");
+
+        let figment = config_figment(file.clone(), &profile);
+        let config = figment.extract().unwrap();
+        let sources = config_sources(&figment, &file, &profile, &config).0;
+        let file_name = path.display().to_string();
+        assert_eq!(sources["port"], file_name);
+        assert_eq!(
+            sources["default_expire"],
+            format!("{} [production]", file_name)
+        );
+        assert_eq!(sources["max_connections"], "default");
+    }
+
+    #[test]
+    fn missing_uploads_dir_is_created_unless_required() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().join("uploads");
+        let required = config::Folio {
+            require_existing_uploads_dir: true,
+            ..config::Folio::default()
+        };
+
+        assert!(prepare_uploads_dir(&required, &dir).is_err());
+        assert!(!dir.exists());
+
+        prepare_uploads_dir(&config::Folio::default(), &dir).unwrap();
+        assert!(dir.is_dir());
+        assert!(prepare_uploads_dir(&required, &dir).is_ok());
+    }
+
+    #[test]
+    fn overlapping_web_and_uploads_paths_are_reported() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let web = temp_dir.path().join("web");
+        std::fs::create_dir_all(web.join("uploads")).unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("uploads")).unwrap();
+        let config = config::Folio {
+            web_path: web.to_string_lossy().to_string(),
+            ..config::Folio::default()
+        };
+
+        assert!(check_path_overlap(&config, &web.join("uploads")).is_err());
+        assert!(check_path_overlap(&config, &web).is_err());
+        assert!(check_path_overlap(&config, temp_dir.path()).is_err());
+        assert!(check_path_overlap(&config, &temp_dir.path().join("uploads")).is_ok());
+
+        let missing_web = config::Folio {
+            web_path: web.join("dist").to_string_lossy().to_string(),
+            ..config::Folio::default()
+        };
+        assert!(check_path_overlap(&missing_web, &web).is_ok());
     }
 }
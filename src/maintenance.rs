@@ -0,0 +1,228 @@
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{HttpRequest, HttpResponse, Responder, ResponseError, post, web};
+use serde::{Deserialize, Serialize};
+
+use super::admin::require_admin;
+use super::auth::AccessAuth;
+use super::config;
+use super::error::FolioError;
+
+/// `Retry-After` of rejected writes when the toggle names none.
+const DEFAULT_RETRY_AFTER_SECS: u64 = 300;
+
+/// Message of rejected writes when the toggle names none.
+const DEFAULT_MESSAGE: &str = "server is under maintenance, writes are paused";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MaintenanceState {
+    pub enabled: bool,
+    pub message: String,
+    pub retry_after_secs: u64,
+    /// When the current state was set, `None` if it never was.
+    pub since_unix: Option<u64>,
+}
+
+/// Whether writes are paused for maintenance. Kept in memory for the life
+/// of the process; a restart comes back with writes enabled.
+pub struct Maintenance {
+    state: Mutex<MaintenanceState>,
+}
+
+impl Default for Maintenance {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(MaintenanceState {
+                enabled: false,
+                message: DEFAULT_MESSAGE.to_string(),
+                retry_after_secs: DEFAULT_RETRY_AFTER_SECS,
+                since_unix: None,
+            }),
+        }
+    }
+}
+
+impl Maintenance {
+    pub fn state(&self) -> MaintenanceState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// `503` with `Retry-After` while maintenance is on.
+    pub fn check(&self) -> Result<(), FolioError> {
+        let state = self.state.lock().unwrap();
+        if !state.enabled {
+            return Ok(());
+        }
+        Err(FolioError::Maintenance {
+            message: state.message.clone(),
+            retry_after_secs: state.retry_after_secs,
+        })
+    }
+
+    fn set(&self, new: MaintenanceState) -> MaintenanceState {
+        let mut state = self.state.lock().unwrap();
+        *state = new;
+        state.clone()
+    }
+}
+
+/// App middleware answering writes with `503` while maintenance is on.
+/// Writes are any method but `GET`, `HEAD` and `OPTIONS`; `/admin/*` stays
+/// open so the toggle can be flipped back and operators can clean up.
+/// Requests already past this point run to completion.
+pub async fn reject_writes(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let is_write = !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+        && !req.path().starts_with("/admin/");
+    let maintenance = req.app_data::<web::Data<Arc<Maintenance>>>().cloned();
+    if let (true, Some(maintenance)) = (is_write, maintenance)
+        && let Err(e) = maintenance.check()
+    {
+        let response = e.error_response();
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+#[derive(Deserialize)]
+pub struct MaintenanceRequest {
+    enabled: bool,
+    /// Shown to rejected clients instead of the default message.
+    message: Option<String>,
+    retry_after_secs: Option<u64>,
+}
+
+/// Turn maintenance mode on or off, e.g. `{"enabled": true}`, and answer
+/// with the new state.
+#[post("/admin/maintenance")]
+pub async fn set_maintenance(
+    req: HttpRequest,
+    config: web::Data<config::Folio>,
+    access_auth: web::Data<Arc<AccessAuth>>,
+    maintenance: web::Data<Arc<Maintenance>>,
+    body: web::Json<MaintenanceRequest>,
+) -> Result<impl Responder, FolioError> {
+    let admin = require_admin(&req, &access_auth, &config).await?;
+    let body = body.into_inner();
+    let previous = maintenance.state();
+    let state = maintenance.set(MaintenanceState {
+        enabled: body.enabled,
+        message: body
+            .message
+            .filter(|message| !message.is_empty())
+            .unwrap_or_else(|| DEFAULT_MESSAGE.to_string()),
+        retry_after_secs: body.retry_after_secs.unwrap_or(DEFAULT_RETRY_AFTER_SECS),
+        since_unix: if previous.enabled == body.enabled {
+            previous.since_unix
+        } else {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|since| since.as_secs())
+        },
+    });
+
+    let by = admin.email.as_deref().unwrap_or(&admin.sub);
+    match (previous.enabled, state.enabled) {
+        (false, true) => log::warn!("maintenance mode enabled by {}: {}", by, state.message),
+        (true, false) => log::warn!("maintenance mode disabled by {}", by),
+        _ => log::info!(
+            "maintenance mode left {} by {}",
+            if state.enabled { "on" } else { "off" },
+            by
+        ),
+    }
+    Ok(HttpResponse::Ok().json(state))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{StatusCode, header};
+    use actix_web::middleware::from_fn;
+    use actix_web::{App, test};
+
+    use crate::test_utils::make_hs256_token;
+
+    fn token(email: &str) -> String {
+        make_hs256_token(
+            "test-secret",
+            "user-1",
+            Some(email),
+            &[],
+            "https://issuer.example.com",
+            "folio-app",
+            3600,
+        )
+    }
+
+    #[actix_web::test]
+    async fn toggle_pauses_writes_but_not_reads() {
+        let config = config::Folio {
+            admin_emails: vec!["admin@example.com".to_string()],
+            ..config::Folio::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(reject_writes))
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(Arc::new(AccessAuth::from_parts(
+                    "https://issuer.example.com",
+                    "folio-app",
+                    Some("test-secret"),
+                ))))
+                .app_data(web::Data::new(Arc::new(Maintenance::default())))
+                .service(set_maintenance)
+                .default_service(web::to(|| async { HttpResponse::Ok().finish() })),
+        )
+        .await;
+        let toggle = |email: &str, body: serde_json::Value| {
+            test::TestRequest::post()
+                .uri("/admin/maintenance")
+                .insert_header(("Cf-Access-Jwt-Assertion", token(email)))
+                .set_json(body)
+                .to_request()
+        };
+
+        let req = toggle("user@example.com", serde_json::json!({ "enabled": true }));
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let req = toggle(
+            "admin@example.com",
+            serde_json::json!({ "enabled": true, "retry_after_secs": 120 }),
+        );
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["enabled"], true);
+        assert!(body["since_unix"].is_u64());
+
+        let req = test::TestRequest::post().uri("/uploads").to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "120");
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["code"], "MAINTENANCE");
+        assert_eq!(body["message"], DEFAULT_MESSAGE);
+
+        let req = test::TestRequest::get().uri("/files/a.txt").to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let req = toggle("admin@example.com", serde_json::json!({ "enabled": false }));
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["enabled"], false);
+
+        let req = test::TestRequest::delete().uri("/files/a.txt").to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use actix_web::{HttpRequest, HttpResponse, web};
+use futures_util::StreamExt;
+
+use super::config;
+use super::error::FolioError;
+use super::expiry::ExpiryStore;
+use super::hashing::HashAlgorithm;
+use super::meta_file;
+use super::metadata::MetadataStore;
+use super::path::SafePath;
+use super::private_index::PrivateIndexStore;
+
+/// Conventional name of a manifest in `algorithm`'s format, as read by e.g.
+/// `sha256sum -c` or `b3sum -c`.
+fn manifest_file_name(algorithm: HashAlgorithm) -> &'static str {
+    match algorithm {
+        HashAlgorithm::Sha256 => "SHA256SUMS",
+        HashAlgorithm::Sha1 => "SHA1SUMS",
+        HashAlgorithm::Blake3 => "B3SUMS",
+        HashAlgorithm::Md5 => "MD5SUMS",
+    }
+}
+
+struct CachedHash {
+    modified: SystemTime,
+    len: u64,
+    hex: String,
+}
+
+/// File hashes by path and algorithm, reused while the file's modification
+/// time and size are unchanged.
+#[derive(Default)]
+pub struct HashCache {
+    entries: Mutex<HashMap<(PathBuf, HashAlgorithm), CachedHash>>,
+}
+
+impl HashCache {
+    /// Hex digest of `path`. Reads the whole file on a miss; call off the
+    /// async executor.
+    pub fn hash(&self, path: &Path, algorithm: HashAlgorithm) -> std::io::Result<String> {
+        let metadata = std::fs::metadata(path)?;
+        let modified = metadata.modified()?;
+        let key = (path.to_path_buf(), algorithm);
+        if let Some(cached) = self.entries.lock().unwrap().get(&key)
+            && cached.modified == modified
+            && cached.len == metadata.len()
+        {
+            return Ok(cached.hex.clone());
+        }
+
+        let hex = algorithm.hash_file(path)?;
+        self.entries.lock().unwrap().insert(
+            key,
+            CachedHash {
+                modified,
+                len: metadata.len(),
+                hex: hex.clone(),
+            },
+        );
+        Ok(hex)
+    }
+}
+
+/// Files below `dir` that a public listing would show, relative to the
+/// uploads root and sorted. Private, password-protected and one-time files,
+/// files in their expiry grace period and symlinks are left out.
+pub(crate) async fn public_files_below(
+    config: &config::Folio,
+    private_index: &PrivateIndexStore,
+    metadata_store: &MetadataStore,
+    expiry_store: &ExpiryStore,
+    dir: &Path,
+) -> Result<Vec<PathBuf>, FolioError> {
+    let list_error = |e: std::io::Error| FolioError::store_error(e.to_string(), "walk directory");
+    let expired = if expiry_store.has_grace_period() {
+        expiry_store
+            .in_grace_period()
+            .await
+            .map_err(|e| FolioError::store_error(e, "list expired files"))?
+    } else {
+        HashSet::new()
+    };
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let private: Vec<String> = private_index
+            .entries_in(&dir)
+            .await
+            .map_err(|e| FolioError::store_error(e, "list private index"))?
+            .into_iter()
+            .map(|e| e.path)
+            .collect();
+        let hidden: Vec<String> = metadata_store
+            .entries_in(&dir)
+            .await
+            .map_err(|e| FolioError::store_error(e, "list file metadata"))?
+            .into_iter()
+            .filter(|m| m.password_hash.is_some() || m.burn)
+            .map(|m| m.path)
+            .collect();
+
+        let mut entries = tokio::fs::read_dir(config.build_full_upload_path(&dir))
+            .await
+            .map_err(list_error)?;
+        while let Some(entry) = entries.next_entry().await.map_err(list_error)? {
+            let file_type = entry.file_type().await.map_err(list_error)?;
+            let relative = dir.join(entry.file_name());
+            if file_type.is_dir() {
+                pending.push(relative);
+                continue;
+            }
+
+            let key = relative.to_string_lossy();
+            if file_type.is_file()
+                && !meta_file::is_meta_file(&relative)
+                && !private.iter().any(|p| *p == key)
+                && !hidden.iter().any(|p| *p == key)
+                && !expired.contains(&entry.path())
+            {
+                files.push(relative);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Stream a `sha256sum`-style manifest (`<hex>  <path>` per line) of every
+/// file below a directory, with paths relative to it.
+///
+/// Hashes are taken from the `HashCache` in app data when one is registered.
+pub async fn serve_manifest(
+    req: &HttpRequest,
+    config: &config::Folio,
+    private_index: &PrivateIndexStore,
+    metadata_store: &MetadataStore,
+    expiry_store: &ExpiryStore,
+    path: &SafePath,
+    algorithm: HashAlgorithm,
+) -> Result<HttpResponse, FolioError> {
+    let files = public_files_below(
+        config,
+        private_index,
+        metadata_store,
+        expiry_store,
+        path.as_path(),
+    )
+    .await?;
+    let cache = req
+        .app_data::<web::Data<Arc<HashCache>>>()
+        .map(|c| c.get_ref().clone())
+        .unwrap_or_default();
+    let base = path.as_path().to_path_buf();
+    let full_paths: Vec<(PathBuf, PathBuf)> = files
+        .into_iter()
+        .map(|relative| {
+            let full = config.build_full_upload_path(&relative);
+            let shown = relative
+                .strip_prefix(&base)
+                .map(Path::to_path_buf)
+                .unwrap_or(relative);
+            (full, shown)
+        })
+        .collect();
+
+    let lines = futures_util::stream::iter(full_paths).then(move |(full, shown)| {
+        let cache = cache.clone();
+        async move {
+            let hex = web::block(move || cache.hash(&full, algorithm))
+                .await
+                .map_err(std::io::Error::other)??;
+            Ok::<_, std::io::Error>(web::Bytes::from(format!("{}  {}\n", hex, shown.display())))
+        }
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .insert_header((
+            "Content-Disposition",
+            format!("inline; filename=\"{}\"", manifest_file_name(algorithm)),
+        ))
+        .streaming(lines))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_match_known_digests() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("abc.txt");
+        std::fs::write(&path, "abc").unwrap();
+        let cache = HashCache::default();
+
+        assert_eq!(
+            cache.hash(&path, HashAlgorithm::Sha256).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            cache.hash(&path, HashAlgorithm::Sha1).unwrap(),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            cache.hash(&path, HashAlgorithm::Md5).unwrap(),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+    }
+
+    #[test]
+    fn cache_follows_file_changes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("a.txt");
+        std::fs::write(&path, "abc").unwrap();
+        let cache = HashCache::default();
+        let first = cache.hash(&path, HashAlgorithm::Md5).unwrap();
+
+        std::fs::write(&path, "abcd").unwrap();
+        assert_ne!(cache.hash(&path, HashAlgorithm::Md5).unwrap(), first);
+    }
+}
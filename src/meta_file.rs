@@ -0,0 +1,176 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::hashing::HashAlgorithm;
+
+/// Suffix of the metadata file written next to an upload with
+/// `meta_json_files`. Paths ending in it are never served, listed or
+/// written through `/files`.
+pub const META_SUFFIX: &str = ".meta.json";
+
+/// `<file>.meta.json`: what is known about an upload, kept next to it so it
+/// survives the loss of `data_path`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetaFile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uploader: Option<String>,
+    pub size_bytes: u64,
+    /// Files written before `hash_algorithm` existed only have `sha256`.
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+    #[serde(alias = "sha256")]
+    pub hash: String,
+    pub created_at_unix: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expire_at_unix: Option<u64>,
+}
+
+impl MetaFile {
+    /// Describe the file at `full_path`, hashing its content with
+    /// `hash_algorithm`.
+    pub fn describe(full_path: &Path, hash_algorithm: HashAlgorithm) -> std::io::Result<Self> {
+        Ok(Self {
+            size_bytes: std::fs::metadata(full_path)?.len(),
+            hash_algorithm,
+            hash: hash_algorithm.hash_file(full_path)?,
+            created_at_unix: chrono::Utc::now().timestamp().max(0) as u64,
+            ..Self::default()
+        })
+    }
+
+    /// Write it next to `full_path`, replacing any earlier one.
+    pub fn write(&self, full_path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self)?;
+        let target = meta_path(full_path);
+        let tmp = target.with_extension("json.tmp");
+        std::fs::write(&tmp, json)?;
+        std::fs::rename(&tmp, &target)
+    }
+
+    /// The metadata stored next to `full_path`, if any can be read.
+    pub fn read(full_path: &Path) -> Option<Self> {
+        let raw = std::fs::read(meta_path(full_path)).ok()?;
+        serde_json::from_slice(&raw)
+            .inspect_err(|e| log::warn!("ignoring unreadable {}: {}", META_SUFFIX, e))
+            .ok()
+    }
+}
+
+/// Whether `path` names a metadata file rather than an upload.
+pub fn is_meta_file(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().ends_with(META_SUFFIX))
+}
+
+pub fn meta_path(full_path: &Path) -> PathBuf {
+    let mut path = full_path.as_os_str().to_owned();
+    path.push(META_SUFFIX);
+    PathBuf::from(path)
+}
+
+/// Drop the metadata file of a deleted upload. Having none is not an error.
+pub fn remove(full_path: &Path) {
+    match std::fs::remove_file(meta_path(full_path)) {
+        Err(e) if e.kind() != ErrorKind::NotFound => log::error!(
+            "failed to remove {} of {}: {}",
+            META_SUFFIX,
+            full_path.display(),
+            e
+        ),
+        _ => {}
+    }
+}
+
+/// Move the metadata file along with its upload, which may have crossed
+/// filesystems.
+pub fn rename(from: &Path, to: &Path) {
+    let Some(meta) = MetaFile::read(from) else {
+        return;
+    };
+    match meta.write(to) {
+        Ok(()) => remove(from),
+        Err(e) => log::error!(
+            "failed to move {} of {}: {}",
+            META_SUFFIX,
+            from.display(),
+            e
+        ),
+    }
+}
+
+/// Record a new expiration (`None`: never) in the metadata file, if the
+/// upload has one.
+pub fn set_expire_at(full_path: &Path, expire_at_unix: Option<u64>) {
+    let Some(mut meta) = MetaFile::read(full_path) else {
+        return;
+    };
+    meta.expire_at_unix = expire_at_unix;
+    if let Err(e) = meta.write(full_path) {
+        log::error!(
+            "failed to update {} of {}: {}",
+            META_SUFFIX,
+            full_path.display(),
+            e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_next_to_the_upload() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upload = temp_dir.path().join("a1B2c3D4.txt");
+        std::fs::write(&upload, "hello").unwrap();
+
+        let meta = MetaFile {
+            original_name: Some("notes.txt".to_string()),
+            ..MetaFile::describe(&upload, HashAlgorithm::Sha256).unwrap()
+        };
+        assert_eq!(meta.size_bytes, 5);
+        assert_eq!(
+            meta.hash,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        meta.write(&upload).unwrap();
+        assert!(temp_dir.path().join("a1B2c3D4.txt.meta.json").is_file());
+
+        set_expire_at(&upload, Some(1_700_000_000));
+        let read = MetaFile::read(&upload).unwrap();
+        assert_eq!(read.original_name.as_deref(), Some("notes.txt"));
+        assert_eq!(read.expire_at_unix, Some(1_700_000_000));
+
+        remove(&upload);
+        assert!(MetaFile::read(&upload).is_none());
+    }
+
+    #[test]
+    fn reads_files_from_before_hash_algorithm() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upload = temp_dir.path().join("a1B2c3D4.txt");
+        std::fs::write(
+            meta_path(&upload),
+            r#"{"size_bytes":5,"sha256":"2cf24dba","created_at_unix":1}"#,
+        )
+        .unwrap();
+
+        let meta = MetaFile::read(&upload).unwrap();
+        assert_eq!(meta.hash_algorithm, HashAlgorithm::Sha256);
+        assert_eq!(meta.hash, "2cf24dba");
+    }
+
+    #[test]
+    fn recognizes_meta_file_names() {
+        assert!(is_meta_file(Path::new("docs/a1B2c3D4.pdf.meta.json")));
+        assert!(!is_meta_file(Path::new("docs/meta.json")));
+        assert!(!is_meta_file(Path::new("a1B2c3D4.meta.json.txt")));
+    }
+}
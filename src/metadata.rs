@@ -0,0 +1,233 @@
+use std::path::{Path, PathBuf};
+
+use argon2::Argon2;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use serde::{Deserialize, Serialize};
+
+use super::config;
+use super::store::JsonFileStore;
+
+/// Per-file settings that don't belong in the file itself.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct FileMetadata {
+    pub path: String,
+    /// Argon2 PHC string; downloads must present the matching password.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_hash: Option<String>,
+    /// Overrides the extension-derived `Content-Type` on download.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// One-time file: deleted by its first download.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub burn: bool,
+    /// Client-side file name, kept here when `xattr_metadata` can't use
+    /// extended attributes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original_name: Option<String>,
+    /// Access identity that uploaded the file, under the same condition.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uploader: Option<String>,
+    /// Successful downloads counted with `track_access`.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub access_count: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_access_unix: Option<u64>,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct MetadataIndex {
+    entries: Vec<FileMetadata>,
+}
+
+pub struct MetadataStore {
+    store: JsonFileStore<MetadataIndex>,
+}
+
+impl MetadataStore {
+    pub fn new(config: &config::Folio) -> Self {
+        let index_path = config.build_full_data_path(&PathBuf::from("file-metadata.json"));
+        Self {
+            store: JsonFileStore::new(index_path),
+        }
+    }
+
+    /// Create or modify the entry for `relative_path` in place.
+    pub async fn update(
+        &self,
+        relative_path: &Path,
+        apply: impl FnOnce(&mut FileMetadata),
+    ) -> Result<(), String> {
+        let _guard = self.store.lock().await?;
+        let mut index = self.store.load().await?;
+        let normalized = relative_path.to_string_lossy().to_string();
+
+        match index.entries.iter_mut().find(|e| e.path == normalized) {
+            Some(entry) => apply(entry),
+            None => {
+                let mut entry = FileMetadata {
+                    path: normalized,
+                    ..FileMetadata::default()
+                };
+                apply(&mut entry);
+                index.entries.push(entry);
+            }
+        }
+
+        self.store.save(&index).await
+    }
+
+    /// Like `update` for several files at once, with a single write.
+    pub async fn update_many<T>(
+        &self,
+        updates: Vec<(PathBuf, T)>,
+        apply: impl Fn(&mut FileMetadata, T),
+    ) -> Result<(), String> {
+        let _guard = self.store.lock().await?;
+        let mut index = self.store.load().await?;
+
+        for (relative_path, value) in updates {
+            let normalized = relative_path.to_string_lossy().to_string();
+            match index.entries.iter_mut().find(|e| e.path == normalized) {
+                Some(entry) => apply(entry, value),
+                None => {
+                    let mut entry = FileMetadata {
+                        path: normalized,
+                        ..FileMetadata::default()
+                    };
+                    apply(&mut entry, value);
+                    index.entries.push(entry);
+                }
+            }
+        }
+
+        self.store.save(&index).await
+    }
+
+    pub async fn get(&self, relative_path: &Path) -> Result<Option<FileMetadata>, String> {
+        let _guard = self.store.lock().await?;
+        let index = self.store.load().await?;
+        let normalized = relative_path.to_string_lossy().to_string();
+
+        Ok(index.entries.into_iter().find(|e| e.path == normalized))
+    }
+
+    pub async fn entries(&self) -> Result<Vec<FileMetadata>, String> {
+        let _guard = self.store.lock().await?;
+        Ok(self.store.load().await?.entries)
+    }
+
+    /// Entries for files directly inside `dir`.
+    pub async fn entries_in(&self, dir: &Path) -> Result<Vec<FileMetadata>, String> {
+        let _guard = self.store.lock().await?;
+        let index = self.store.load().await?;
+
+        Ok(index
+            .entries
+            .into_iter()
+            .filter(|e| Path::new(&e.path).parent() == Some(dir))
+            .collect())
+    }
+
+    /// Move the entry for a file to its new path, replacing any stale entry
+    /// already there.
+    pub async fn rename(&self, from: &Path, to: &Path) -> Result<(), String> {
+        let _guard = self.store.lock().await?;
+        let mut index = self.store.load().await?;
+        let from = from.to_string_lossy().to_string();
+        let to = to.to_string_lossy().to_string();
+
+        index.entries.retain(|e| e.path != to);
+        if let Some(entry) = index.entries.iter_mut().find(|e| e.path == from) {
+            entry.path = to;
+        }
+        self.store.save(&index).await
+    }
+
+    /// Drop the entry for a file, or every entry below a directory.
+    pub async fn remove(&self, relative_path: &Path) -> Result<(), String> {
+        let _guard = self.store.lock().await?;
+        let mut index = self.store.load().await?;
+        let normalized = relative_path.to_string_lossy().to_string();
+        let prefix = format!("{}/", normalized);
+
+        let before = index.entries.len();
+        index
+            .entries
+            .retain(|e| e.path != normalized && !e.path.starts_with(&prefix));
+        if index.entries.len() == before {
+            return Ok(());
+        }
+
+        self.store.save(&index).await
+    }
+}
+
+/// Hash a download password with a fresh salt. CPU-heavy; call off the
+/// async executor.
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("hash password failed: {}", e))
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .map(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Folio;
+
+    fn setup_store(temp_path: &Path) -> MetadataStore {
+        let config = Folio {
+            data_path: temp_path.to_str().unwrap().to_string(),
+            ..Folio::default()
+        };
+        MetadataStore::new(&config)
+    }
+
+    #[test]
+    fn password_round_trip() {
+        let hash = hash_password("s3cret").unwrap();
+
+        assert!(hash.starts_with("$argon2"));
+        assert!(verify_password("s3cret", &hash));
+        assert!(!verify_password("wrong", &hash));
+        assert!(!verify_password("s3cret", "not-a-hash"));
+    }
+
+    #[tokio::test]
+    async fn update_get_and_remove_by_prefix() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = setup_store(temp_dir.path());
+
+        store
+            .update(Path::new("docs/a.txt"), |m| {
+                m.password_hash = Some("h".to_string())
+            })
+            .await
+            .unwrap();
+        store.update(Path::new("b.txt"), |_| {}).await.unwrap();
+
+        let entry = store.get(Path::new("docs/a.txt")).await.unwrap().unwrap();
+        assert_eq!(entry.password_hash.as_deref(), Some("h"));
+
+        store.remove(Path::new("docs")).await.unwrap();
+        assert!(store.get(Path::new("docs/a.txt")).await.unwrap().is_none());
+        assert!(store.get(Path::new("b.txt")).await.unwrap().is_some());
+    }
+}
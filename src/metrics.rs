@@ -0,0 +1,283 @@
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::{HttpResponse, Responder, get, web};
+
+use super::config;
+use super::expiry::{ExpiryMetrics, ExpiryStore};
+use super::files::disk_usage;
+use super::meta_file;
+use super::parts::parts_root;
+use super::staging::staging_root;
+use super::uploads::parse_duration;
+
+/// What a walk of the storage directories found.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StorageSnapshot {
+    /// Files below `uploads_path`, without meta files.
+    pub files: u64,
+    /// Bytes of everything below `uploads_path`.
+    pub bytes: u64,
+    /// Bytes of unfinished chunked and staged uploads below `data_path`.
+    pub temp_bytes: u64,
+    /// Modification time of the oldest file, if there is one.
+    pub oldest_modified_unix: Option<u64>,
+    pub scanned_at_unix: u64,
+}
+
+/// Storage gauges for `/metrics`, refreshed every
+/// `storage_metrics_interval` in the background so scrapes never walk the
+/// disk. Empty until the first walk finishes.
+pub struct StorageGauges {
+    interval: Duration,
+    latest: Mutex<Option<StorageSnapshot>>,
+}
+
+impl StorageGauges {
+    /// `None` when `storage_metrics_interval` is empty or invalid.
+    pub fn from_config(config: &config::Folio) -> Option<Self> {
+        if config.storage_metrics_interval.is_empty() {
+            return None;
+        }
+        match parse_duration(&config.storage_metrics_interval) {
+            Ok(interval) if !interval.is_zero() => Some(Self {
+                interval,
+                latest: Mutex::new(None),
+            }),
+            _ => {
+                log::warn!(
+                    "invalid storage_metrics_interval '{}', storage gauges are disabled",
+                    config.storage_metrics_interval
+                );
+                None
+            }
+        }
+    }
+
+    pub fn latest(&self) -> Option<StorageSnapshot> {
+        *self.latest.lock().unwrap()
+    }
+
+    /// Walk now and keep the result.
+    pub async fn refresh(&self, config: &config::Folio) -> std::io::Result<StorageSnapshot> {
+        let uploads = config.resolve_base(&config.uploads_path);
+        let temp_dirs = [staging_root(config), parts_root(config)];
+        let snapshot = web::block(move || {
+            let mut snapshot = StorageSnapshot::default();
+            walk_uploads(&uploads, &mut snapshot)?;
+            for dir in &temp_dirs {
+                snapshot.temp_bytes += match disk_usage(dir) {
+                    Ok(bytes) => bytes,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+                    Err(e) => return Err(e),
+                };
+            }
+            snapshot.scanned_at_unix = now_unix_secs();
+            Ok(snapshot)
+        })
+        .await
+        .map_err(std::io::Error::other)??;
+        *self.latest.lock().unwrap() = Some(snapshot);
+        Ok(snapshot)
+    }
+
+    /// Refresh every `storage_metrics_interval`, starting right away.
+    pub fn spawn_refresher(self: Arc<Self>, config: web::Data<config::Folio>) {
+        actix_web::rt::spawn(async move {
+            let mut ticks = actix_web::rt::time::interval(self.interval);
+            loop {
+                ticks.tick().await;
+                if let Err(e) = self.refresh(&config).await {
+                    log::error!("failed to measure storage for metrics: {}", e);
+                }
+            }
+        });
+    }
+}
+
+/// Count files and bytes below `dir`. Symlinks are not followed.
+fn walk_uploads(dir: &Path, snapshot: &mut StorageSnapshot) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            walk_uploads(&entry.path(), snapshot)?;
+            continue;
+        }
+        if !file_type.is_file() {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        snapshot.bytes += metadata.len();
+        if meta_file::is_meta_file(&entry.path()) {
+            continue;
+        }
+        snapshot.files += 1;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        snapshot.oldest_modified_unix = Some(
+            snapshot
+                .oldest_modified_unix
+                .map_or(modified, |oldest| oldest.min(modified)),
+        );
+    }
+    Ok(())
+}
+
+fn now_unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Prometheus text exposition of the expiry sweeper counters and, once
+/// measured, the storage gauges as of `now_unix`.
+fn render(expiry: &ExpiryMetrics, storage: Option<&StorageSnapshot>, now_unix: u64) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "# HELP folio_expiry_sweeps_total Expiry sweeps run, by result.\n\
+         # TYPE folio_expiry_sweeps_total counter\n\
+         folio_expiry_sweeps_total{{result=\"ok\"}} {}\n\
+         folio_expiry_sweeps_total{{result=\"error\"}} {}",
+        expiry.sweeps_ok, expiry.sweeps_failed
+    );
+    let _ = writeln!(
+        out,
+        "# HELP folio_expired_files_total Expired files handled by the sweeper, by outcome.\n\
+         # TYPE folio_expired_files_total counter\n\
+         folio_expired_files_total{{outcome=\"deleted\"}} {}\n\
+         folio_expired_files_total{{outcome=\"already_absent\"}} {}",
+        expiry.deleted, expiry.already_absent
+    );
+    let _ = writeln!(
+        out,
+        "# HELP folio_expiry_delete_failures_total Failed deletions of expired files, by error kind.\n\
+         # TYPE folio_expiry_delete_failures_total counter"
+    );
+    for (kind, count) in &expiry.delete_failures {
+        let _ = writeln!(
+            out,
+            "folio_expiry_delete_failures_total{{kind=\"{}\"}} {}",
+            kind, count
+        );
+    }
+
+    let Some(storage) = storage else {
+        return out;
+    };
+    let _ = writeln!(
+        out,
+        "# HELP folio_upload_files Files stored below uploads_path.\n\
+         # TYPE folio_upload_files gauge\n\
+         folio_upload_files {}\n\
+         # HELP folio_upload_bytes Bytes stored below uploads_path.\n\
+         # TYPE folio_upload_bytes gauge\n\
+         folio_upload_bytes {}\n\
+         # HELP folio_temp_bytes Bytes held by unfinished chunked and staged uploads.\n\
+         # TYPE folio_temp_bytes gauge\n\
+         folio_temp_bytes {}",
+        storage.files, storage.bytes, storage.temp_bytes
+    );
+    if let Some(oldest) = storage.oldest_modified_unix {
+        let _ = writeln!(
+            out,
+            "# HELP folio_oldest_upload_age_seconds Age of the least recently modified stored file.\n\
+             # TYPE folio_oldest_upload_age_seconds gauge\n\
+             folio_oldest_upload_age_seconds {}",
+            now_unix.saturating_sub(oldest)
+        );
+    }
+    let _ = writeln!(
+        out,
+        "# HELP folio_storage_scan_timestamp_seconds When the storage gauges were measured.\n\
+         # TYPE folio_storage_scan_timestamp_seconds gauge\n\
+         folio_storage_scan_timestamp_seconds {}",
+        storage.scanned_at_unix
+    );
+    out
+}
+
+#[get("/metrics")]
+pub async fn metrics(
+    expiry_store: web::Data<Arc<ExpiryStore>>,
+    storage: Option<web::Data<Arc<StorageGauges>>>,
+) -> impl Responder {
+    let storage = storage.and_then(|gauges| gauges.latest());
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(render(
+            &expiry_store.metrics(),
+            storage.as_ref(),
+            now_unix_secs(),
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn renders_counters_with_labels() {
+        let text = render(
+            &ExpiryMetrics {
+                sweeps_ok: 3,
+                sweeps_failed: 1,
+                deleted: 5,
+                already_absent: 2,
+                delete_failures: BTreeMap::from([("PermissionDenied".to_string(), 4)]),
+            },
+            None,
+            0,
+        );
+
+        assert!(text.contains("folio_expiry_sweeps_total{result=\"ok\"} 3\n"));
+        assert!(text.contains("folio_expiry_sweeps_total{result=\"error\"} 1\n"));
+        assert!(text.contains("folio_expired_files_total{outcome=\"deleted\"} 5\n"));
+        assert!(text.contains("folio_expired_files_total{outcome=\"already_absent\"} 2\n"));
+        assert!(text.contains("folio_expiry_delete_failures_total{kind=\"PermissionDenied\"} 4\n"));
+    }
+
+    #[actix_web::test]
+    async fn storage_gauges_come_from_the_last_walk() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = config::Folio {
+            uploads_path: temp_dir
+                .path()
+                .join("uploads")
+                .to_string_lossy()
+                .to_string(),
+            data_path: temp_dir.path().join("data").to_string_lossy().to_string(),
+            storage_metrics_interval: "1m".to_string(),
+            ..config::Folio::default()
+        };
+        let uploads = temp_dir.path().join("uploads");
+        std::fs::create_dir_all(uploads.join("docs")).unwrap();
+        std::fs::write(uploads.join("a.txt"), "12345").unwrap();
+        std::fs::write(uploads.join("docs/b.txt"), "123").unwrap();
+        std::fs::write(uploads.join("docs/b.txt.meta.json"), "{}").unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("data/parts/up-1")).unwrap();
+        std::fs::write(temp_dir.path().join("data/parts/up-1/1"), "1234567").unwrap();
+        let gauges = StorageGauges::from_config(&config).unwrap();
+        assert!(gauges.latest().is_none());
+
+        let snapshot = gauges.refresh(&config).await.unwrap();
+        assert_eq!(gauges.latest(), Some(snapshot));
+        assert_eq!(snapshot.files, 2);
+        assert_eq!(snapshot.bytes, 10);
+        assert_eq!(snapshot.temp_bytes, 7);
+
+        let oldest = snapshot.oldest_modified_unix.unwrap();
+        let text = render(&ExpiryMetrics::default(), Some(&snapshot), oldest + 90);
+        assert!(text.contains("\nfolio_upload_files 2\n"));
+        assert!(text.contains("\nfolio_temp_bytes 7\n"));
+        assert!(text.contains("\nfolio_oldest_upload_age_seconds 90\n"));
+    }
+}
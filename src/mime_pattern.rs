@@ -0,0 +1,98 @@
+use std::str::FromStr;
+
+use mime_guess::Mime;
+
+/// A content type filter from the config: an exact type (`image/png`), every
+/// subtype of a type (`image/*`), or anything (`*/*`).
+///
+/// Matching is case-insensitive and ignores parameters on both sides, so
+/// `text/plain` matches `text/plain; charset=utf-8`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MimePattern {
+    Any,
+    Type(String),
+    Exact(String),
+}
+
+impl MimePattern {
+    pub fn matches(&self, content_type: &Mime) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Type(top) => content_type.type_().as_str().eq_ignore_ascii_case(top),
+            Self::Exact(essence) => content_type.essence_str().eq_ignore_ascii_case(essence),
+        }
+    }
+
+    /// How narrowly the pattern matches, for picking the most specific of
+    /// several matching entries: exact types over `type/*` over `*/*`.
+    pub fn specificity(&self) -> u8 {
+        match self {
+            Self::Any => 0,
+            Self::Type(_) => 1,
+            Self::Exact(_) => 2,
+        }
+    }
+
+    /// Parse every entry of a config list, failing on the first invalid one.
+    pub fn parse_all(patterns: &[String]) -> Result<Vec<Self>, String> {
+        patterns.iter().map(|p| p.parse()).collect()
+    }
+}
+
+impl FromStr for MimePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let essence = s
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let token = |t: &str| {
+            !t.is_empty()
+                && t.chars()
+                    .all(|c| c.is_ascii_graphic() && !matches!(c, '*' | '/' | ';'))
+        };
+        match essence.split_once('/') {
+            Some(("*", "*")) => Ok(Self::Any),
+            Some((top, "*")) if token(top) => Ok(Self::Type(top.to_string())),
+            Some((top, sub)) if token(top) && token(sub) => Ok(Self::Exact(essence)),
+            _ => Err(format!("invalid content type pattern {:?}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mime(s: &str) -> Mime {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn matches_exact_wildcard_and_any() {
+        let exact: MimePattern = "Image/PNG".parse().unwrap();
+        assert!(exact.matches(&mime("image/png")));
+        assert!(!exact.matches(&mime("image/jpeg")));
+
+        let images: MimePattern = "image/*".parse().unwrap();
+        assert!(images.matches(&mime("image/webp")));
+        assert!(!images.matches(&mime("text/plain")));
+
+        let any: MimePattern = "*/*".parse().unwrap();
+        assert!(any.matches(&mime("application/octet-stream")));
+
+        let text: MimePattern = "text/plain; charset=utf-8".parse().unwrap();
+        assert_eq!(text, MimePattern::Exact("text/plain".to_string()));
+        assert!(text.matches(&mime("text/plain; charset=iso-8859-1")));
+    }
+
+    #[test]
+    fn rejects_malformed_patterns() {
+        for pattern in ["", "image", "*/png", "image/", "ima ge/png", "image/p*"] {
+            assert!(pattern.parse::<MimePattern>().is_err(), "{}", pattern);
+        }
+    }
+}
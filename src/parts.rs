@@ -0,0 +1,625 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::middleware::from_fn;
+use actix_web::{HttpRequest, HttpResponse, Responder, post, web};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use super::config;
+use super::dir_count::DirEntryCounter;
+use super::error::FolioError;
+use super::expiry::ExpiryStore;
+use super::hashing::HashAlgorithm;
+use super::metadata::MetadataStore;
+use super::pending::{check_pending, pending_uploads};
+use super::private_index::PrivateIndexStore;
+use super::quota::enforce_quota;
+use super::upload_limit::limit_uploads;
+use super::uploads::{
+    UploadQuery, UploadSource, ensure_writable, largest_upload_limit, parse_duration, store_upload,
+    too_small,
+};
+
+/// Highest part number accepted by `POST /uploads/<id>/part/<n>`.
+pub const MAX_PARTS: u32 = 10_000;
+
+/// Longest client-chosen id of a chunked upload.
+const MAX_ID_LENGTH: usize = 64;
+
+/// Fallback when `abandoned_upload_age` cannot be parsed.
+const FALLBACK_ABANDONED_AGE: Duration = Duration::from_secs(24 * 3600);
+
+/// Missing part numbers listed in an error before the rest are elided.
+const MISSING_PARTS_SHOWN: usize = 20;
+
+/// Where the parts of chunked uploads wait for `POST /uploads/<id>/complete`:
+/// below `data_path`, one directory per upload id.
+pub fn parts_root(config: &config::Folio) -> PathBuf {
+    config.build_full_data_path(Path::new("parts"))
+}
+
+/// `abandoned_upload_age`; `None` when empty, which keeps parts until their
+/// upload is completed.
+pub fn abandoned_upload_age(config: &config::Folio) -> Option<Duration> {
+    if config.abandoned_upload_age.is_empty() {
+        return None;
+    }
+    Some(
+        parse_duration(&config.abandoned_upload_age).unwrap_or_else(|e| {
+            log::warn!(
+                "invalid abandoned_upload_age '{}': {}, falling back to 24h",
+                config.abandoned_upload_age,
+                e
+            );
+            FALLBACK_ABANDONED_AGE
+        }),
+    )
+}
+
+/// Directory holding the parts of upload `id`. Ids are chosen by the client,
+/// so they are limited to characters that are safe as a single path
+/// component.
+fn parts_dir(config: &config::Folio, id: &str) -> Result<PathBuf, FolioError> {
+    let valid = (1..=MAX_ID_LENGTH).contains(&id.len())
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if !valid {
+        return Err(FolioError::BadRequest {
+            reason: format!(
+                "invalid upload id '{}': use 1 to {} letters, digits, '-' or '_'",
+                id, MAX_ID_LENGTH
+            ),
+        });
+    }
+    Ok(parts_root(config).join(id))
+}
+
+/// Size of every complete part in `dir`, by part number. Parts still being
+/// written (`<n>.partial`) are left out.
+fn received_parts(dir: &Path) -> std::io::Result<BTreeMap<u32, u64>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e),
+    };
+    let mut parts = BTreeMap::new();
+    for entry in entries {
+        let entry = entry?;
+        let Some(part) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        parts.insert(part, entry.metadata()?.len());
+    }
+    Ok(parts)
+}
+
+/// Delete the parts of chunked uploads below `root` that received nothing
+/// for `age`, returning how many uploads were dropped.
+pub fn remove_abandoned(root: &Path, age: Duration) -> std::io::Result<usize> {
+    let entries = match std::fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let mut removed = 0;
+    for entry in entries {
+        let entry = entry?;
+        // A new part changes the directory's modification time.
+        let idle = entry.metadata()?.modified()?.elapsed().unwrap_or_default();
+        if idle < age {
+            continue;
+        }
+        match std::fs::remove_dir_all(entry.path()) {
+            Ok(()) => {
+                log::info!(
+                    "dropped abandoned chunked upload {}",
+                    entry.file_name().to_string_lossy()
+                );
+                removed += 1;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(removed)
+}
+
+/// JSON body returned by `POST /uploads/<id>/part/<n>`.
+#[derive(Debug, Serialize)]
+pub struct PartResponse {
+    pub message: String,
+    pub id: String,
+    pub part: u32,
+    pub size_bytes: u64,
+    /// Bytes received so far over all parts of the upload.
+    pub received_bytes: u64,
+}
+
+/// Store part `<n>` (from 1) of chunked upload `<id>`; the request body is
+/// the raw bytes of the part. Parts can arrive in any order, but each only
+/// once, and all of them together may not exceed the largest upload size
+/// limit; the limit for the file's own content type is checked on
+/// completion. The first part of a new id counts against
+/// `max_pending_uploads_per_client` until the upload is completed or
+/// abandoned.
+#[post("/uploads/{id}/part/{n}", wrap = "from_fn(limit_uploads)")]
+pub async fn upload_part(
+    req: HttpRequest,
+    config: web::Data<config::Folio>,
+    path: web::Path<(String, u32)>,
+    mut payload: web::Payload,
+) -> Result<impl Responder, FolioError> {
+    ensure_writable(&config)?;
+    let max_size = largest_upload_limit(&config) as u64;
+    let (id, part) = path.into_inner();
+    if !(1..=MAX_PARTS).contains(&part) {
+        return Err(FolioError::BadRequest {
+            reason: format!("part numbers run from 1 to {}, got {}", MAX_PARTS, part),
+        });
+    }
+    let dir = parts_dir(&config, &id)?;
+    if !dir.exists() {
+        check_pending(&req, &config)?;
+    }
+    let received: u64 = received_parts(&dir)
+        .map_err(|e| FolioError::store_error(e.to_string(), "list upload parts"))?
+        .values()
+        .sum();
+
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| FolioError::write_error(&e, &dir, "create parts directory"))?;
+    let part_path = dir.join(part.to_string());
+    let partial_path = dir.join(format!("{}.partial", part));
+    let mut output = match tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&partial_path)
+        .await
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            return Err(FolioError::PartExists { id, part });
+        }
+        Err(e) => return Err(FolioError::write_error(&e, &partial_path, "save part")),
+    };
+
+    let mut size_bytes = 0u64;
+    let mut written = Ok(());
+    while let Some(chunk) = payload.next().await {
+        let data = match chunk {
+            Ok(data) => data,
+            Err(e) => {
+                written = Err(FolioError::BadRequest {
+                    reason: format!("invalid request body: {}", e),
+                });
+                break;
+            }
+        };
+        size_bytes += data.len() as u64;
+        if received + size_bytes > max_size {
+            written = Err(FolioError::PayloadTooLarge {
+                reason: format!(
+                    "upload {} too large: parts exceed {} byte limit",
+                    id, max_size
+                ),
+            });
+            break;
+        }
+        if let Err(e) = output.write_all(&data).await {
+            written = Err(FolioError::write_error(&e, &partial_path, "save part"));
+            break;
+        }
+    }
+    if written.is_ok()
+        && let Err(e) = output.flush().await
+    {
+        written = Err(FolioError::write_error(&e, &partial_path, "flush part"));
+    }
+    drop(output);
+
+    // A hard link fails if the part appeared meanwhile, so a part is never
+    // replaced and is only visible once it is complete.
+    let written = written.and_then(|()| {
+        std::fs::hard_link(&partial_path, &part_path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => FolioError::PartExists {
+                id: id.clone(),
+                part,
+            },
+            _ => FolioError::write_error(&e, &part_path, "save part"),
+        })
+    });
+    if let Err(e) = tokio::fs::remove_file(&partial_path).await {
+        log::error!("failed to remove {}: {}", partial_path.display(), e);
+    }
+    written?;
+    if let Some((pending, client)) = pending_uploads(&req, &config) {
+        pending.record(client, dir);
+    }
+    log::info!(
+        "stored part {} of upload {} ({} bytes)",
+        part,
+        id,
+        size_bytes
+    );
+
+    Ok(HttpResponse::Created().json(PartResponse {
+        message: "part stored successfully".to_string(),
+        id,
+        part,
+        size_bytes,
+        received_bytes: received + size_bytes,
+    }))
+}
+
+/// JSON body of `POST /uploads/<id>/complete`.
+#[derive(Deserialize)]
+pub struct CompleteRequest {
+    filename: Option<String>,
+    content_type: Option<String>,
+    authorized_emails: Option<String>,
+    /// Total bytes over all parts.
+    size: u64,
+    /// Hex digest of the whole file, checked after the parts are joined.
+    hash: Option<String>,
+    /// Algorithm of `hash`; `hash_algorithm` from the config by default.
+    hash_algorithm: Option<HashAlgorithm>,
+}
+
+/// `400` naming the part numbers below the highest one that never arrived.
+fn check_contiguous(id: &str, parts: &BTreeMap<u32, u64>) -> Result<(), FolioError> {
+    let last = parts.keys().next_back().copied().unwrap_or(0);
+    let missing: Vec<String> = (1..=last)
+        .filter(|n| !parts.contains_key(n))
+        .map(|n| n.to_string())
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let mut listed = missing[..missing.len().min(MISSING_PARTS_SHOWN)].join(", ");
+    if missing.len() > MISSING_PARTS_SHOWN {
+        listed.push_str(&format!(
+            " and {} more",
+            missing.len() - MISSING_PARTS_SHOWN
+        ));
+    }
+    Err(FolioError::BadRequest {
+        reason: format!("upload {} is missing parts {}", id, listed),
+    })
+}
+
+/// Concatenate `parts` of `dir` in order into `to`, returning the digest of
+/// the result.
+fn join_parts(
+    dir: &Path,
+    parts: &[u32],
+    to: &Path,
+    hash_algorithm: HashAlgorithm,
+) -> std::io::Result<String> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut output = std::fs::File::create(to)?;
+    let mut hasher = hash_algorithm.hasher();
+    let mut buf = vec![0; 64 * 1024];
+    for part in parts {
+        let mut input = std::fs::File::open(dir.join(part.to_string()))?;
+        loop {
+            let n = input.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            output.write_all(&buf[..n])?;
+        }
+    }
+    output.flush()?;
+    Ok(hasher.finalize_hex())
+}
+
+/// Join the parts of chunked upload `<id>` into a regular upload. The body
+/// declares the total `size` and optionally a `hash`; query parameters are
+/// those of `POST /uploads`. Parts are removed once the file is stored;
+/// until then, a failed request can be retried after sending what is
+/// missing.
+#[post(
+    "/uploads/{id}/complete",
+    wrap = "from_fn(enforce_quota)",
+    wrap = "from_fn(limit_uploads)"
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn complete_upload(
+    req: HttpRequest,
+    config: web::Data<config::Folio>,
+    expiry_store: web::Data<Arc<ExpiryStore>>,
+    private_store: web::Data<Arc<PrivateIndexStore>>,
+    metadata_store: web::Data<Arc<MetadataStore>>,
+    dir_counter: web::Data<Arc<DirEntryCounter>>,
+    id: web::Path<String>,
+    query: web::Query<UploadQuery>,
+    body: web::Json<CompleteRequest>,
+) -> Result<impl Responder, FolioError> {
+    ensure_writable(&config)?;
+    query.check_options(&req, &config)?;
+    let body = body.into_inner();
+    let dir = parts_dir(&config, &id)?;
+    let parts = received_parts(&dir)
+        .map_err(|e| FolioError::store_error(e.to_string(), "list upload parts"))?;
+    if parts.is_empty() {
+        return Err(FolioError::NotFound {
+            path: format!("parts of upload {}", id),
+        });
+    }
+    check_contiguous(&id, &parts)?;
+
+    let received: u64 = parts.values().sum();
+    if received < body.size {
+        return Err(FolioError::BadRequest {
+            reason: format!(
+                "upload {} is incomplete: {} of {} bytes received in parts 1 to {}",
+                id,
+                received,
+                body.size,
+                parts.len()
+            ),
+        });
+    }
+    if received > body.size {
+        return Err(FolioError::UnprocessableEntity {
+            reason: format!(
+                "upload {} has {} bytes in its parts, more than the declared {}",
+                id, received, body.size
+            ),
+        });
+    }
+    if (received as usize) < config.min_upload_bytes {
+        return Err(too_small(received as usize, config.min_upload_bytes));
+    }
+
+    let hash_algorithm = body.hash_algorithm.unwrap_or(config.hash_algorithm);
+    let expected_hash = body.hash.map(|hash| hash.to_ascii_lowercase());
+    let order: Vec<u32> = parts.into_keys().collect();
+    let source = UploadSource {
+        filename: body.filename,
+        content_type: body.content_type,
+        authorized_emails: body.authorized_emails,
+    };
+    let response = store_upload(
+        &req,
+        &config,
+        &expiry_store,
+        &private_store,
+        &metadata_store,
+        &dir_counter,
+        &query,
+        source,
+        received as usize,
+        async |full_path| {
+            let (from, to) = (dir.clone(), full_path.to_path_buf());
+            let joined = web::block(move || join_parts(&from, &order, &to, hash_algorithm))
+                .await
+                .map_err(|e| FolioError::store_error(e.to_string(), "join upload parts"))?;
+            let hash = match joined {
+                Ok(hash) => hash,
+                Err(e) => {
+                    let _ = tokio::fs::remove_file(full_path).await;
+                    return Err(FolioError::write_error(&e, full_path, "join upload parts"));
+                }
+            };
+            match expected_hash {
+                Some(expected) if expected != hash => {
+                    let _ = tokio::fs::remove_file(full_path).await;
+                    Err(FolioError::UnprocessableEntity {
+                        reason: format!(
+                            "{} mismatch for upload {}: expected {}, got {}",
+                            hash_algorithm.name(),
+                            id,
+                            expected,
+                            hash
+                        ),
+                    })
+                }
+                _ => Ok(()),
+            }
+        },
+    )
+    .await?;
+
+    if let Err(e) = tokio::fs::remove_dir_all(&dir).await {
+        log::error!("failed to remove parts of upload {}: {}", id, e);
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pending::PendingUploads;
+    use actix_web::http::{StatusCode, header};
+    use actix_web::{App, test as awtest};
+
+    #[test]
+    fn lists_missing_parts() {
+        let parts = BTreeMap::from([(1, 5), (2, 5), (5, 1)]);
+        let err = check_contiguous("abc", &parts).unwrap_err();
+        assert_eq!(err.message(), "upload abc is missing parts 3, 4");
+
+        let parts = BTreeMap::from([(1, 5), (2, 5)]);
+        assert!(check_contiguous("abc", &parts).is_ok());
+
+        let parts = BTreeMap::from([(30, 1)]);
+        let err = check_contiguous("abc", &parts).unwrap_err();
+        assert!(err.message().ends_with("19, 20 and 9 more"));
+    }
+
+    #[actix_web::test]
+    async fn assembles_parts_in_order() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let test_config = || config::Folio {
+            uploads_path: temp_dir
+                .path()
+                .join("uploads")
+                .to_string_lossy()
+                .to_string(),
+            data_path: temp_dir.path().join("data").to_string_lossy().to_string(),
+            ..config::Folio::default()
+        };
+        let config = test_config();
+        let expiry_store = Arc::new(ExpiryStore::new(&config));
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(expiry_store.clone()))
+                .app_data(web::Data::new(Arc::new(PrivateIndexStore::new(&config))))
+                .app_data(web::Data::new(Arc::new(MetadataStore::new(&config))))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_config()))
+                .service(upload_part)
+                .service(complete_upload),
+        )
+        .await;
+        let part = |n: u32, body: &'static str| {
+            awtest::TestRequest::post()
+                .uri(&format!("/uploads/big-1/part/{}", n))
+                .set_payload(body)
+                .to_request()
+        };
+        let complete = |body: serde_json::Value| {
+            awtest::TestRequest::post()
+                .uri("/uploads/big-1/complete?expire=1h")
+                .set_json(body)
+                .to_request()
+        };
+        let hash = HashAlgorithm::Sha256.hash_bytes(b"hello world");
+
+        let res = awtest::call_service(&app, complete(serde_json::json!({"size": 11}))).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let res = awtest::call_service(&app, part(3, "rld")).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let res = awtest::call_service(&app, part(1, "hello")).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let res = awtest::call_service(&app, part(1, "HELLO")).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        let body: serde_json::Value = awtest::read_body_json(res).await;
+        assert_eq!(body["code"], "PART_EXISTS");
+
+        let res = awtest::call_service(&app, complete(serde_json::json!({"size": 11}))).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = awtest::read_body_json(res).await;
+        assert_eq!(body["message"], "upload big-1 is missing parts 2");
+
+        let res = awtest::call_service(&app, part(2, " wo")).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let body: serde_json::Value = awtest::read_body_json(res).await;
+        assert_eq!(body["received_bytes"], 11);
+
+        let res = awtest::call_service(
+            &app,
+            complete(serde_json::json!({"size": 11, "hash": "00", "filename": "greeting.txt"})),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let res = awtest::call_service(
+            &app,
+            complete(serde_json::json!({"size": 11, "hash": hash, "filename": "greeting.txt"})),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let body: serde_json::Value = awtest::read_body_json(res).await;
+        let url = body["url"].as_str().unwrap();
+        assert!(url.ends_with(".txt"));
+        let served = config.build_full_upload_path(Path::new(url.strip_prefix("/files/").unwrap()));
+        assert_eq!(std::fs::read_to_string(&served).unwrap(), "hello world");
+        assert!(expiry_store.expire_at(&served).await.unwrap().is_some());
+        assert!(!parts_root(&config).join("big-1").exists());
+    }
+
+    #[actix_web::test]
+    async fn limits_pending_uploads_per_client() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = config::Folio {
+            data_path: temp_dir.path().to_string_lossy().to_string(),
+            max_pending_uploads_per_client: 1,
+            ..config::Folio::default()
+        };
+        let pending = Arc::new(PendingUploads::new(&config));
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(pending))
+                .app_data(web::Data::new(config))
+                .service(upload_part),
+        )
+        .await;
+        let part = |uri: &str, peer: &str| {
+            awtest::TestRequest::post()
+                .uri(uri)
+                .peer_addr(peer.parse().unwrap())
+                .set_payload("x")
+                .to_request()
+        };
+
+        let res = awtest::call_service(&app, part("/uploads/a/part/1", "198.51.100.4:1")).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let res = awtest::call_service(&app, part("/uploads/a/part/2", "198.51.100.4:2")).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let res = awtest::call_service(&app, part("/uploads/b/part/1", "198.51.100.4:3")).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers().get(header::RETRY_AFTER).unwrap(), "5");
+        let res = awtest::call_service(&app, part("/uploads/b/part/1", "198.51.100.5:1")).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        remove_abandoned(&temp_dir.path().join("parts"), Duration::from_secs(3600)).unwrap();
+        assert!(temp_dir.path().join("parts/a").is_dir());
+        let removed = remove_abandoned(&temp_dir.path().join("parts"), Duration::ZERO).unwrap();
+        assert_eq!(removed, 2);
+        let res = awtest::call_service(&app, part("/uploads/b/part/1", "198.51.100.4:4")).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+    }
+
+    #[actix_web::test]
+    async fn refuses_bad_ids_and_oversized_parts() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = config::Folio {
+            data_path: temp_dir.path().to_string_lossy().to_string(),
+            max_upload_size: 8,
+            ..config::Folio::default()
+        };
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .service(upload_part),
+        )
+        .await;
+        let part = |uri: &str, body: &'static str| {
+            awtest::TestRequest::post()
+                .uri(uri)
+                .set_payload(body)
+                .to_request()
+        };
+
+        let res = awtest::call_service(&app, part("/uploads/a.b/part/1", "x")).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let res = awtest::call_service(&app, part("/uploads/abc/part/0", "x")).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = awtest::call_service(&app, part("/uploads/abc/part/1", "12345")).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let res = awtest::call_service(&app, part("/uploads/abc/part/2", "12345")).await;
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let dir = temp_dir.path().join("parts/abc");
+        assert!(dir.join("1").is_file());
+        assert!(!dir.join("2").exists());
+        assert!(!dir.join("2.partial").exists());
+    }
+}
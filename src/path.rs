@@ -19,7 +19,7 @@ impl SafePath {
         // Check for explicit `..` in the string representation
         if path.to_string_lossy().contains("..") {
             log::warn!("path traversal attempt in user input: {}", path.display());
-            return Err(FolioError::BadRequest {
+            return Err(FolioError::InvalidPath {
                 reason: format!("path contains '..': {}", path.to_string_lossy()),
             });
         }
@@ -34,7 +34,7 @@ impl SafePath {
                         other,
                         path.display()
                     );
-                    return Err(FolioError::BadRequest {
+                    return Err(FolioError::InvalidPath {
                         reason: format!("invalid path component in: {}", path.to_string_lossy()),
                     });
                 }
@@ -48,6 +48,47 @@ impl SafePath {
     pub fn as_path(&self) -> &Path {
         &self.0
     }
+
+    /// The same path with its last component run through
+    /// [`slugify_file_name`]; directories are left alone.
+    pub fn with_slugified_name(&self) -> Self {
+        match self.0.file_name() {
+            Some(name) => SafePath(
+                self.0
+                    .with_file_name(slugify_file_name(&name.to_string_lossy())),
+            ),
+            None => self.clone(),
+        }
+    }
+}
+
+/// `name` as a lowercase ASCII slug for `slugify_filenames`: the stem is
+/// transliterated and every run of other characters becomes one `-`, so
+/// `Q3 Résumé (final).PDF` turns into `q3-resume-final.PDF`. An ASCII
+/// alphanumeric extension is kept as is, any other is slugified too. A stem
+/// with nothing left becomes `file`.
+pub fn slugify_file_name(name: &str) -> String {
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && !extension.is_empty() => {
+            (stem, Some(extension))
+        }
+        _ => (name, None),
+    };
+    let mut slug = slug::slugify(stem);
+    if slug.is_empty() {
+        slug.push_str("file");
+    }
+    let extension = extension.map(|extension| {
+        if extension.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            extension.to_string()
+        } else {
+            slug::slugify(extension)
+        }
+    });
+    match extension.filter(|extension| !extension.is_empty()) {
+        Some(extension) => format!("{}.{}", slug, extension),
+        None => slug,
+    }
 }
 
 impl std::fmt::Display for SafePath {
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use actix_web::{HttpRequest, web};
+
+use super::config;
+use super::error::FolioError;
+use super::public_url::client_ip;
+
+/// Unfinished uploads by client address, for
+/// `max_pending_uploads_per_client`: the parts directory of a chunked
+/// upload, or the file of a staged one. An upload stops counting once its
+/// path is gone, whether it was completed, committed, or swept. Kept in
+/// memory only, so uploads started before a restart are not counted.
+pub struct PendingUploads {
+    max_per_client: usize,
+    retry_after_secs: u64,
+    owners: Mutex<HashMap<IpAddr, Vec<PathBuf>>>,
+}
+
+impl PendingUploads {
+    pub fn new(config: &config::Folio) -> Self {
+        Self {
+            max_per_client: config.max_pending_uploads_per_client,
+            retry_after_secs: config.throttle_retry_after_secs,
+            owners: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// `429` when `client` already has `max_per_client` unfinished uploads.
+    pub fn check(&self, client: IpAddr) -> Result<(), FolioError> {
+        if self.max_per_client == 0 {
+            return Ok(());
+        }
+        let mut owners = self.owners.lock().unwrap();
+        owners.retain(|_, paths| {
+            paths.retain(|path| path.exists());
+            !paths.is_empty()
+        });
+        let pending = owners.get(&client).map_or(0, Vec::len);
+        if pending >= self.max_per_client {
+            log::warn!(
+                "refusing new upload from {}: {} unfinished uploads",
+                client,
+                pending
+            );
+            return Err(FolioError::TooManyPendingUploads {
+                limit: self.max_per_client,
+                retry_after_secs: self.retry_after_secs,
+            });
+        }
+        Ok(())
+    }
+
+    /// Count `path` against `client` until it is gone.
+    pub fn record(&self, client: IpAddr, path: PathBuf) {
+        if self.max_per_client == 0 {
+            return;
+        }
+        let mut owners = self.owners.lock().unwrap();
+        let paths = owners.entry(client).or_default();
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+}
+
+/// The tracker and the requesting client, when both are known.
+pub fn pending_uploads(
+    req: &HttpRequest,
+    config: &config::Folio,
+) -> Option<(Arc<PendingUploads>, IpAddr)> {
+    let pending = req.app_data::<web::Data<Arc<PendingUploads>>>()?;
+    Some((pending.get_ref().clone(), client_ip(req, config)?))
+}
+
+/// `429` if the requesting client may not start another unfinished upload.
+pub fn check_pending(req: &HttpRequest, config: &config::Folio) -> Result<(), FolioError> {
+    match pending_uploads(req, config) {
+        Some((pending, client)) => pending.check(client),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finished_uploads_stop_counting() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let pending = PendingUploads::new(&config::Folio {
+            max_pending_uploads_per_client: 2,
+            ..config::Folio::default()
+        });
+        let (client, other) = (
+            "198.51.100.4".parse().unwrap(),
+            "198.51.100.5".parse().unwrap(),
+        );
+        let upload = |name: &str| {
+            let path = temp_dir.path().join(name);
+            std::fs::create_dir(&path).unwrap();
+            path
+        };
+
+        pending.record(client, upload("a"));
+        pending.check(client).unwrap();
+        pending.record(client, upload("b"));
+        let err = pending.check(client).unwrap_err();
+        assert_eq!(err.code(), "TOO_MANY_PENDING_UPLOADS");
+        pending.check(other).unwrap();
+
+        std::fs::remove_dir(temp_dir.path().join("a")).unwrap();
+        pending.check(client).unwrap();
+    }
+
+    #[test]
+    fn zero_is_unlimited() {
+        let pending = PendingUploads::new(&config::Folio::default());
+        let client = "198.51.100.4".parse().unwrap();
+        for i in 0..10 {
+            pending.record(client, PathBuf::from(format!("/tmp/{}", i)));
+        }
+        pending.check(client).unwrap();
+    }
+}
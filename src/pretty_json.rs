@@ -0,0 +1,115 @@
+use actix_web::body::{BoxBody, MessageBody, to_bytes};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::middleware::Next;
+use actix_web::web;
+use mime_guess::Mime;
+
+/// Whether the client asked for indented JSON: `?pretty=1` (or `true`), or
+/// a `pretty=1` parameter on `application/json` in `Accept`.
+fn wants_pretty(req: &ServiceRequest) -> bool {
+    let query = web::Query::<Vec<(String, String)>>::from_query(req.query_string())
+        .map(|query| query.into_inner())
+        .unwrap_or_default();
+    if query
+        .iter()
+        .any(|(key, value)| key == "pretty" && matches!(value.as_str(), "1" | "true"))
+    {
+        return true;
+    }
+
+    req.headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| {
+            accept
+                .split(',')
+                .filter_map(|range| range.trim().parse::<Mime>().ok())
+                .filter(|mime| mime.essence_str() == "application/json")
+                .any(|mime| {
+                    matches!(
+                        mime.get_param("pretty").map(|v| v.as_str()),
+                        Some("1" | "true")
+                    )
+                })
+        })
+}
+
+fn is_json(res: &ServiceResponse<impl MessageBody>) -> bool {
+    res.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<Mime>().ok())
+        .is_some_and(|mime| mime.essence_str() == "application/json")
+}
+
+/// Indent JSON for humans, keeping the field order. `None` if `body` isn't
+/// valid JSON.
+fn prettify(body: &[u8]) -> Option<Vec<u8>> {
+    let mut pretty = Vec::with_capacity(body.len() * 2);
+    let mut deserializer = serde_json::Deserializer::from_slice(body);
+    let mut serializer = serde_json::Serializer::pretty(&mut pretty);
+    serde_transcode::transcode(&mut deserializer, &mut serializer).ok()?;
+    deserializer.end().ok()?;
+    pretty.push(b'\n');
+    Some(pretty)
+}
+
+/// App-wide middleware pretty-printing JSON responses, errors included, on
+/// request. Other responses, e.g. file downloads, pass through untouched;
+/// compact JSON stays the default.
+pub async fn pretty_print(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let pretty = wants_pretty(&req);
+    let res = next.call(req).await?;
+    if !pretty || !is_json(&res) {
+        return Ok(res.map_into_boxed_body());
+    }
+
+    let (req, res) = res.into_parts();
+    let (mut res, body) = res.into_parts();
+    let body = to_bytes(body).await.map_err(Into::into)?;
+    let body = match prettify(&body) {
+        Some(pretty) => web::Bytes::from(pretty),
+        None => body,
+    };
+    res.headers_mut().remove(header::CONTENT_LENGTH);
+    Ok(ServiceResponse::new(req, res.set_body(body)).map_into_boxed_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::middleware::from_fn;
+    use actix_web::{App, HttpResponse, get, test};
+
+    #[get("/thing")]
+    async fn thing() -> HttpResponse {
+        HttpResponse::Ok().json(serde_json::json!({ "b": 1, "a": [true] }))
+    }
+
+    async fn body_for(req: test::TestRequest) -> String {
+        let app = test::init_service(App::new().wrap(from_fn(pretty_print)).service(thing)).await;
+        let response = test::call_service(&app, req.to_request()).await;
+        String::from_utf8(test::read_body(response).await.to_vec()).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn indents_json_on_request_only() {
+        let compact = body_for(test::TestRequest::get().uri("/thing")).await;
+        assert!(!compact.contains('\n'));
+
+        let pretty = body_for(test::TestRequest::get().uri("/thing?pretty=1")).await;
+        assert!(pretty.starts_with("{\n  \"a\": [\n    true\n  ],\n  \"b\": 1\n}"));
+
+        let accept = body_for(
+            test::TestRequest::get()
+                .uri("/thing")
+                .insert_header(("Accept", "application/json; pretty=1")),
+        )
+        .await;
+        assert_eq!(accept, pretty);
+    }
+}
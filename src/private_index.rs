@@ -61,6 +61,47 @@ impl PrivateIndexStore {
 
         Ok(index.entries.iter().any(|e| e.path == normalized))
     }
+
+    /// Move the entry for a file to its new path, replacing any stale entry
+    /// already there.
+    pub async fn rename(&self, from: &Path, to: &Path) -> Result<(), String> {
+        let _guard = self.store.lock().await?;
+        let mut index = self.store.load().await?;
+        let from = from.to_string_lossy().to_string();
+        let to = to.to_string_lossy().to_string();
+
+        index.entries.retain(|e| e.path != to);
+        if let Some(entry) = index.entries.iter_mut().find(|e| e.path == from) {
+            entry.path = to;
+        }
+        self.store.save(&index).await
+    }
+
+    /// Drop the entry for a deleted file, if any.
+    pub async fn remove(&self, relative_path: &Path) -> Result<(), String> {
+        let _guard = self.store.lock().await?;
+        let mut index = self.store.load().await?;
+        let normalized = relative_path.to_string_lossy().to_string();
+
+        let before = index.entries.len();
+        index.entries.retain(|e| e.path != normalized);
+        if index.entries.len() == before {
+            return Ok(());
+        }
+        self.store.save(&index).await
+    }
+
+    /// Entries for files directly inside `dir`.
+    pub async fn entries_in(&self, dir: &Path) -> Result<Vec<PrivateEntry>, String> {
+        let _guard = self.store.lock().await?;
+        let index = self.store.load().await?;
+
+        Ok(index
+            .entries
+            .into_iter()
+            .filter(|e| Path::new(&e.path).parent() == Some(dir))
+            .collect())
+    }
 }
 
 #[cfg(test)]
@@ -72,12 +113,10 @@ mod tests {
 
     fn setup_store(temp_path: &Path) -> PrivateIndexStore {
         let config = Folio {
-            address: "127.0.0.1".to_string(),
-            port: 8000,
             web_path: "".to_string(),
             uploads_path: temp_path.to_str().unwrap().to_string(),
             data_path: temp_path.to_str().unwrap().to_string(),
-            max_upload_size: 25 * 1024 * 1024,
+            ..Folio::default()
         };
         PrivateIndexStore::new(&config)
     }
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use actix_web::{HttpRequest, HttpResponse, Responder, get, web};
+use image::ImageFormat;
+use serde::Serialize;
+
+use super::alias::find_upload;
+use super::config;
+use super::error::FolioError;
+use super::images;
+use super::meta_file;
+use super::metadata::MetadataStore;
+use super::path::SafePath;
+
+/// How long finished results stay available to `GET /uploads/<id>/status`.
+const RESULT_RETENTION: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessingStatus {
+    Pending,
+    Ready,
+    Rejected,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadStatus {
+    pub status: ProcessingStatus,
+    /// Why the upload was rejected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+}
+
+impl UploadStatus {
+    pub fn pending() -> Self {
+        Self {
+            status: ProcessingStatus::Pending,
+            detail: None,
+            width: None,
+            height: None,
+        }
+    }
+}
+
+struct Tracked {
+    status: UploadStatus,
+    updated: Instant,
+}
+
+/// State of uploads whose processing continues after `/uploads` returned,
+/// by path relative to the uploads root. Kept in memory only: a restart
+/// forgets results, and files that are still there count as ready.
+#[derive(Default)]
+pub struct ProcessingTracker {
+    entries: Mutex<HashMap<String, Tracked>>,
+}
+
+impl ProcessingTracker {
+    pub fn set(&self, relative_path: &str, status: UploadStatus) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, t| {
+            t.status.status == ProcessingStatus::Pending || t.updated.elapsed() < RESULT_RETENTION
+        });
+        entries.insert(
+            relative_path.to_string(),
+            Tracked {
+                status,
+                updated: Instant::now(),
+            },
+        );
+    }
+
+    pub fn is_pending(&self, relative_path: &str) -> bool {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(relative_path)
+            .is_some_and(|t| t.status.status == ProcessingStatus::Pending)
+    }
+
+    /// Tracked upload by path, or by id (file name without extension).
+    fn find(&self, id: &str) -> Option<(String, UploadStatus)> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .iter()
+            .find(|(path, _)| {
+                path.as_str() == id || Path::new(path).file_stem().is_some_and(|s| s == id)
+            })
+            .map(|(path, t)| (path.clone(), t.status.clone()))
+    }
+}
+
+/// The tracker to defer work to, when `async_processing` is on.
+pub fn deferred_tracker(
+    req: &HttpRequest,
+    config: &config::Folio,
+) -> Option<Arc<ProcessingTracker>> {
+    if !config.async_processing {
+        return None;
+    }
+    req.app_data::<web::Data<Arc<ProcessingTracker>>>()
+        .map(|t| t.get_ref().clone())
+}
+
+/// `503` for downloads of uploads that are still being processed.
+pub fn ensure_processed(req: &HttpRequest, path: &SafePath) -> Result<(), FolioError> {
+    let pending = req
+        .app_data::<web::Data<Arc<ProcessingTracker>>>()
+        .is_some_and(|t| t.is_pending(&path.to_string()));
+    if pending {
+        return Err(FolioError::ServiceUnavailable {
+            reason: format!("{} is still being processed", path),
+            retry_after_secs: 1,
+        });
+    }
+    Ok(())
+}
+
+/// Decode an image upload after the response was sent, as `verify_images`
+/// would have before it. Undecodable files are deleted along with their
+/// metadata.
+pub fn verify_image_in_background(
+    tracker: Arc<ProcessingTracker>,
+    metadata_store: Arc<MetadataStore>,
+    full_path: PathBuf,
+    file_name: String,
+    format: ImageFormat,
+) {
+    tracker.set(&file_name, UploadStatus::pending());
+    actix_web::rt::spawn(async move {
+        let decode_path = full_path.clone();
+        let decoded = web::block(move || images::decode_dimensions(&decode_path, format))
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+
+        let status = match decoded {
+            Ok((width, height)) => UploadStatus {
+                status: ProcessingStatus::Ready,
+                detail: None,
+                width: Some(width),
+                height: Some(height),
+            },
+            Err(e) => {
+                log::warn!("rejected undecodable image {}: {}", file_name, e);
+                if let Err(e) = tokio::fs::remove_file(&full_path).await {
+                    log::error!("failed to remove rejected image {}: {}", file_name, e);
+                }
+                meta_file::remove(&full_path);
+                if let Err(e) = metadata_store.remove(Path::new(&file_name)).await {
+                    log::error!("failed to drop metadata for {}: {}", file_name, e);
+                }
+                UploadStatus {
+                    status: ProcessingStatus::Rejected,
+                    detail: Some(format!("invalid image: {}", e)),
+                    width: None,
+                    height: None,
+                }
+            }
+        };
+        tracker.set(&file_name, status);
+    });
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadStatusResponse {
+    pub path: String,
+    #[serde(flatten)]
+    pub status: UploadStatus,
+}
+
+/// Poll an upload until processing is done. `<id>` is resolved as in
+/// `POST /uploads/<id>/alias`; untracked files that exist are `ready`.
+#[get("/uploads/{id:.*}/status")]
+pub async fn get_upload_status(
+    config: web::Data<config::Folio>,
+    tracker: web::Data<Arc<ProcessingTracker>>,
+    id: web::Path<String>,
+) -> Result<impl Responder, FolioError> {
+    if let Some((path, status)) = tracker.find(&id) {
+        return Ok(HttpResponse::Ok().json(UploadStatusResponse { path, status }));
+    }
+
+    let path = find_upload(&config, &id)?;
+    Ok(HttpResponse::Ok().json(UploadStatusResponse {
+        path: path.to_string(),
+        status: UploadStatus {
+            status: ProcessingStatus::Ready,
+            ..UploadStatus::pending()
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_uploads_by_path_or_id() {
+        let tracker = ProcessingTracker::default();
+        tracker.set("2024/06/a1B2c3D4.png", UploadStatus::pending());
+
+        assert!(tracker.is_pending("2024/06/a1B2c3D4.png"));
+        assert!(tracker.find("a1B2c3D4").is_some());
+        assert!(tracker.find("2024/06/a1B2c3D4.png").is_some());
+        assert!(tracker.find("zzzzzzzz").is_none());
+    }
+}
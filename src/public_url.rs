@@ -0,0 +1,273 @@
+use std::net::IpAddr;
+
+use actix_web::HttpRequest;
+use actix_web::http::header;
+
+use super::config;
+
+/// URL to return for `path` (e.g. `/files/a1B2c3D4.png`) in responses.
+///
+/// Paths stay relative unless `public_base_url` or `trusted_proxies` is set.
+/// Then the origin comes from the first of:
+/// - `X-Forwarded-Proto` / `X-Forwarded-Host`, when sent by a trusted proxy
+/// - `public_base_url`
+/// - the request's own scheme and `Host`
+pub fn public_url(req: &HttpRequest, config: &config::Folio, path: &str) -> String {
+    match public_origin(req, config) {
+        Some(origin) => format!("{}{}", origin, path),
+        None => path.to_string(),
+    }
+}
+
+fn public_origin(req: &HttpRequest, config: &config::Folio) -> Option<String> {
+    if config.public_base_url.is_empty() && config.trusted_proxies.is_empty() {
+        return None;
+    }
+
+    let (proto, host) = forwarded_origin(req, config);
+    if proto.is_none() && host.is_none() && !config.public_base_url.is_empty() {
+        return Some(config.public_base_url.trim_end_matches('/').to_string());
+    }
+    Some(format!(
+        "{}://{}",
+        proto.unwrap_or_else(|| own_scheme(req).to_string()),
+        host.unwrap_or_else(|| own_host(req))
+    ))
+}
+
+/// Scheme and host the client asked for: the request's own, unless a
+/// trusted proxy sent `X-Forwarded-Proto` / `X-Forwarded-Host`.
+pub fn request_origin(req: &HttpRequest, config: &config::Folio) -> (String, String) {
+    let (proto, host) = forwarded_origin(req, config);
+    (
+        proto.unwrap_or_else(|| own_scheme(req).to_string()),
+        host.unwrap_or_else(|| own_host(req)),
+    )
+}
+
+/// `X-Forwarded-Proto` and `X-Forwarded-Host`, each only when valid and sent
+/// by a trusted proxy.
+fn forwarded_origin(req: &HttpRequest, config: &config::Folio) -> (Option<String>, Option<String>) {
+    let trusted = req
+        .peer_addr()
+        .is_some_and(|peer| is_trusted_proxy(config, peer.ip()));
+    if !trusted {
+        return (None, None);
+    }
+    let proto = forwarded_value(req, "x-forwarded-proto")
+        .filter(|p| p.eq_ignore_ascii_case("http") || p.eq_ignore_ascii_case("https"))
+        .map(|p| p.to_ascii_lowercase());
+    let host = forwarded_value(req, "x-forwarded-host").filter(|h| valid_host(h));
+    (proto, host)
+}
+
+fn own_scheme(req: &HttpRequest) -> &'static str {
+    if req.app_config().secure() {
+        "https"
+    } else {
+        "http"
+    }
+}
+
+fn own_host(req: &HttpRequest) -> String {
+    req.headers()
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_else(|| req.app_config().host())
+        .to_string()
+}
+
+/// Address of the client behind the request: the peer, or the first
+/// `X-Forwarded-For` entry when the peer is a trusted proxy.
+pub fn client_ip(req: &HttpRequest, config: &config::Folio) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip().to_canonical();
+    if is_trusted_proxy(config, peer)
+        && let Some(forwarded) =
+            forwarded_value(req, "x-forwarded-for").and_then(|value| value.parse::<IpAddr>().ok())
+    {
+        return Some(forwarded.to_canonical());
+    }
+    Some(peer)
+}
+
+/// First value of a possibly comma-separated forwarding header: the one the
+/// client-facing proxy added.
+fn forwarded_value(req: &HttpRequest, name: &str) -> Option<String> {
+    let value = req.headers().get(name)?.to_str().ok()?;
+    let first = value.split(',').next()?.trim();
+    (!first.is_empty()).then(|| first.to_string())
+}
+
+fn valid_host(host: &str) -> bool {
+    !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']'))
+}
+
+/// Whether `ip` matches a `trusted_proxies` entry: an address or a CIDR
+/// range such as `10.0.0.0/8`.
+fn is_trusted_proxy(config: &config::Folio, ip: IpAddr) -> bool {
+    let ip = ip.to_canonical();
+    config.trusted_proxies.iter().any(|entry| {
+        let Some((network, bits)) = entry.split_once('/') else {
+            return entry
+                .parse::<IpAddr>()
+                .is_ok_and(|e| e.to_canonical() == ip);
+        };
+        let (Ok(network), Ok(bits)) = (network.parse::<IpAddr>(), bits.parse::<u32>()) else {
+            return false;
+        };
+        match (network.to_canonical(), ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) if bits <= 32 => {
+                let mask = u32::MAX.checked_shl(32 - bits).unwrap_or(0);
+                net.to_bits() & mask == ip.to_bits() & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) if bits <= 128 => {
+                let mask = u128::MAX.checked_shl(128 - bits).unwrap_or(0);
+                net.to_bits() & mask == ip.to_bits() & mask
+            }
+            _ => false,
+        }
+    })
+}
+
+/// Percent-encode a file path for use in a URL, keeping its `/` separators,
+/// so any file name makes a valid `Location` header.
+pub fn encode_path(path: &str) -> String {
+    path.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Undo `encode_path`.
+pub fn decode_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| path.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Path part of a `Location` we generated, whether relative or absolute.
+pub fn location_path(location: &str) -> &str {
+    match location.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("/", |i| &rest[i..]),
+        None => location,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn request(peer: &str) -> TestRequest {
+        TestRequest::default()
+            .peer_addr(peer.parse().unwrap())
+            .insert_header((header::HOST, "folio.internal:8000"))
+            .insert_header(("X-Forwarded-Proto", "https"))
+            .insert_header(("X-Forwarded-Host", "files.example.com, proxy.internal"))
+    }
+
+    #[test]
+    fn relative_unless_configured() {
+        let req = request("10.0.0.2:5000").to_http_request();
+        let config = config::Folio::default();
+
+        assert_eq!(public_url(&req, &config, "/files/a.txt"), "/files/a.txt");
+    }
+
+    #[test]
+    fn trusts_forwarded_headers_only_from_listed_proxies() {
+        let config = config::Folio {
+            trusted_proxies: vec!["10.0.0.0/8".to_string()],
+            ..config::Folio::default()
+        };
+
+        let req = request("10.0.0.2:5000").to_http_request();
+        assert_eq!(
+            public_url(&req, &config, "/files/a.txt"),
+            "https://files.example.com/files/a.txt"
+        );
+
+        let req = request("203.0.113.9:5000").to_http_request();
+        assert_eq!(
+            public_url(&req, &config, "/files/a.txt"),
+            "http://folio.internal:8000/files/a.txt"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_public_base_url() {
+        let config = config::Folio {
+            public_base_url: "https://cdn.example.com/".to_string(),
+            ..config::Folio::default()
+        };
+        let req = request("10.0.0.2:5000").to_http_request();
+
+        assert_eq!(
+            public_url(&req, &config, "/files/a.txt"),
+            "https://cdn.example.com/files/a.txt"
+        );
+    }
+
+    #[test]
+    fn client_ip_trusts_forwarded_for_only_from_listed_proxies() {
+        let config = config::Folio {
+            trusted_proxies: vec!["10.0.0.0/8".to_string()],
+            ..config::Folio::default()
+        };
+        let forwarded = |peer: &str| {
+            request(peer)
+                .insert_header(("X-Forwarded-For", "198.51.100.4, 10.0.0.2"))
+                .to_http_request()
+        };
+
+        assert_eq!(
+            client_ip(&forwarded("10.0.0.2:5000"), &config),
+            Some("198.51.100.4".parse().unwrap())
+        );
+        assert_eq!(
+            client_ip(&forwarded("203.0.113.9:5000"), &config),
+            Some("203.0.113.9".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn encoded_paths_round_trip() {
+        let path = "docs/r\u{e9}sum\u{e9} (1).txt";
+        assert_eq!(encode_path(path), "docs/r%C3%A9sum%C3%A9%20%281%29.txt");
+        assert_eq!(decode_path(&encode_path(path)), path);
+        assert_eq!(decode_path("100%"), "100%");
+    }
+
+    #[test]
+    fn location_path_strips_origin() {
+        assert_eq!(location_path("/files/a.txt"), "/files/a.txt");
+        assert_eq!(
+            location_path("https://files.example.com/files/a.txt"),
+            "/files/a.txt"
+        );
+    }
+}
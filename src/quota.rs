@@ -0,0 +1,439 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::middleware::Next;
+use actix_web::{ResponseError, web};
+use serde::Deserialize;
+
+use super::config;
+use super::disk::DiskSpace;
+use super::error::FolioError;
+use super::files::disk_usage;
+use super::meta_file;
+use super::path::SafePath;
+use super::public_url::{decode_path, location_path};
+
+/// How long a walked usage figure is trusted. Our own writes adjust it in
+/// place; deletions (API, sweeper, by hand) show up after the next rescan.
+const RESCAN_INTERVAL: Duration = Duration::from_secs(30);
+
+struct CachedUsage {
+    bytes: u64,
+    scanned_at: Instant,
+}
+
+/// Bytes stored under each named root, for enforcing `quota_bytes`.
+#[derive(Default)]
+pub struct RootQuotas {
+    cache: Mutex<HashMap<String, CachedUsage>>,
+}
+
+impl RootQuotas {
+    /// Current usage of `root`, walking it when the cached figure is stale.
+    pub fn usage(&self, config: &config::Folio, root: &str) -> std::io::Result<u64> {
+        if let Some(cached) = self.cache.lock().unwrap().get(root)
+            && cached.scanned_at.elapsed() < RESCAN_INTERVAL
+        {
+            return Ok(cached.bytes);
+        }
+
+        let bytes = match disk_usage(&config.build_full_upload_path(Path::new(root))) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e),
+        };
+        self.cache.lock().unwrap().insert(
+            root.to_string(),
+            CachedUsage {
+                bytes,
+                scanned_at: Instant::now(),
+            },
+        );
+        Ok(bytes)
+    }
+
+    /// Account for a file under `root` growing from `old` to `new` bytes.
+    pub fn record_write(&self, root: &str, old: u64, new: u64) {
+        if let Some(cached) = self.cache.lock().unwrap().get_mut(root) {
+            cached.bytes = (cached.bytes + new).saturating_sub(old);
+        }
+    }
+}
+
+/// The named root `path` lives in, with its quota, if it has one. Only
+/// paths inside a root's directory count; a file named like a root does not.
+pub fn root_quota<'a>(config: &'a config::Folio, path: &Path) -> Option<(&'a str, u64)> {
+    let mut components = path.components();
+    let Some(Component::Normal(first)) = components.next() else {
+        return None;
+    };
+    components.next()?;
+
+    let (name, root) = config.roots.get_key_value(first.to_str()?)?;
+    (root.quota_bytes > 0).then_some((name.as_str(), root.quota_bytes))
+}
+
+fn file_size(config: &config::Folio, path: &Path) -> u64 {
+    std::fs::metadata(config.build_full_upload_path(path))
+        .ok()
+        .filter(|m| m.is_file())
+        .map_or(0, |m| m.len())
+}
+
+pub(crate) fn quota_exceeded(root: &str, used: u64, quota: u64) -> FolioError {
+    log::warn!(
+        "root '{}' over quota: {} of {} bytes used",
+        root,
+        used,
+        quota
+    );
+    FolioError::InsufficientStorage {
+        reason: format!(
+            "quota exceeded for root '{}': {} of {} bytes used",
+            root, used, quota
+        ),
+        limit: Some("quota_bytes"),
+    }
+}
+
+/// `507` when writing `request_bytes` to `path` would leave less than its
+/// `min_free_percent` free on the filesystem below it.
+pub(crate) fn check_free_space(
+    config: &config::Folio,
+    path: &Path,
+    request_bytes: u64,
+) -> Result<(), FolioError> {
+    let min_percent = config.min_free_percent_in(path.parent().unwrap_or(Path::new("")));
+    if min_percent <= 0.0 {
+        return Ok(());
+    }
+    let full_path = config.build_full_upload_path(path);
+    let Some(space) = full_path
+        .ancestors()
+        .find_map(|dir| DiskSpace::of(dir).ok())
+    else {
+        return Ok(());
+    };
+    let after = DiskSpace {
+        available_bytes: space.available_bytes.saturating_sub(request_bytes),
+        ..space
+    };
+    if after.available_percent() >= min_percent {
+        return Ok(());
+    }
+    log::warn!(
+        "refusing write to {}: {:.1}% of its filesystem would be free, below {}%",
+        path.display(),
+        after.available_percent(),
+        min_percent
+    );
+    Err(FolioError::InsufficientStorage {
+        reason: format!(
+            "not enough free disk space: {:.1}% would be left, below the minimum of {}%",
+            after.available_percent(),
+            min_percent
+        ),
+        limit: Some("min_free_percent"),
+    })
+}
+
+#[derive(Deserialize)]
+struct UploadDirQuery {
+    dir: Option<String>,
+}
+
+/// Where a write lands: the `/files/<path>`, or a file below the `?dir=` of
+/// `/uploads` (the uploads root without it), whose name is not known until
+/// it is stored.
+fn target_path(req: &ServiceRequest) -> Option<SafePath> {
+    let path = match req.match_info().get("path") {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let query = web::Query::<UploadDirQuery>::from_query(req.query_string()).ok()?;
+            let dir = query.into_inner().dir.unwrap_or_default();
+            Path::new(dir.trim_matches('/')).join(".upload")
+        }
+    };
+    SafePath::from_user_input(&path).ok()
+}
+
+/// Route middleware enforcing `min_free_percent` on every write and
+/// `quota_bytes` on writes into named roots.
+///
+/// Requests are rejected up front when `Content-Length` would take the
+/// filesystem below its free space minimum or the root past its quota.
+/// Since the request size is only known after the body is read without it,
+/// newly created files that end up over quota are removed again.
+pub async fn enforce_quota(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let quotas = req.app_data::<web::Data<Arc<RootQuotas>>>().cloned();
+    let config = req.app_data::<web::Data<config::Folio>>().cloned();
+    let path = target_path(&req);
+    let (Some(config), Some(path)) = (config, path) else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    };
+    let request_bytes: u64 = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    if let Err(e) = check_free_space(&config, path.as_path(), request_bytes) {
+        return Ok(req.into_response(e.error_response()).map_into_right_body());
+    }
+    let Some(quotas) = quotas else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    };
+    let Some((root, quota)) = root_quota(&config, path.as_path()) else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    };
+    let root = root.to_string();
+
+    let used = match web::block({
+        let (quotas, config, root) = (quotas.clone(), config.clone(), root.clone());
+        move || quotas.usage(&config, &root)
+    })
+    .await
+    {
+        Ok(Ok(used)) => used,
+        Ok(Err(e)) => {
+            let e = FolioError::store_error(e.to_string(), "measure root usage");
+            return Ok(req.into_response(e.error_response()).map_into_right_body());
+        }
+        Err(e) => {
+            let e = FolioError::store_error(e.to_string(), "measure root usage");
+            return Ok(req.into_response(e.error_response()).map_into_right_body());
+        }
+    };
+    let old_size = file_size(&config, path.as_path());
+    if used.saturating_sub(old_size) + request_bytes > quota {
+        let e = quota_exceeded(&root, used, quota);
+        return Ok(req.into_response(e.error_response()).map_into_right_body());
+    }
+
+    let res = next.call(req).await?;
+    if !res.status().is_success() {
+        return Ok(res.map_into_left_body());
+    }
+
+    // Renamed creates land somewhere else; `Location` says where.
+    let written = res
+        .headers()
+        .get(header::LOCATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| location_path(v).strip_prefix("/files/"))
+        .map(decode_path)
+        .and_then(|p| SafePath::from_user_input(Path::new(&p)).ok())
+        .unwrap_or(path);
+    let old_size = if res.status() == actix_web::http::StatusCode::CREATED {
+        0
+    } else {
+        old_size
+    };
+    let new_size = file_size(&config, written.as_path());
+    quotas.record_write(&root, old_size, new_size);
+
+    let used = (used + new_size).saturating_sub(old_size);
+    if old_size == 0 && used > quota {
+        let full_path = config.build_full_upload_path(written.as_path());
+        if let Err(e) = std::fs::remove_file(&full_path) {
+            log::error!("failed to remove over-quota file {}: {}", written, e);
+        } else {
+            quotas.record_write(&root, new_size, 0);
+        }
+        meta_file::remove(&full_path);
+        let e = quota_exceeded(&root, used, quota);
+        let (req, _) = res.into_parts();
+        return Ok(ServiceResponse::new(req, e.error_response()).map_into_right_body());
+    }
+
+    Ok(res.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::{App, HttpResponse, test as awtest};
+    use std::collections::BTreeMap;
+
+    fn quota_config(temp_dir: &tempfile::TempDir) -> config::Folio {
+        config::Folio {
+            uploads_path: temp_dir.path().to_string_lossy().to_string(),
+            roots: BTreeMap::from([(
+                "team-a".to_string(),
+                config::RootConfig {
+                    quota_bytes: 10,
+                    ..config::RootConfig::default()
+                },
+            )]),
+            ..config::Folio::default()
+        }
+    }
+
+    #[test]
+    fn root_quota_requires_a_path_inside_the_root() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = quota_config(&temp_dir);
+
+        assert_eq!(
+            root_quota(&config, Path::new("team-a/a.txt")),
+            Some(("team-a", 10))
+        );
+        assert_eq!(root_quota(&config, Path::new("team-a")), None);
+        assert_eq!(root_quota(&config, Path::new("team-b/a.txt")), None);
+    }
+
+    #[actix_web::test]
+    async fn rejects_writes_past_the_quota() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("team-a")).unwrap();
+        std::fs::write(temp_dir.path().join("team-a/old.txt"), "123456").unwrap();
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(quota_config(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(RootQuotas::default())))
+                .service(
+                    web::resource("/files/{path:.*}")
+                        .wrap(from_fn(enforce_quota))
+                        .route(web::post().to(|| async { HttpResponse::Created().finish() })),
+                )
+                .service(
+                    web::resource("/uploads")
+                        .wrap(from_fn(enforce_quota))
+                        .route(web::post().to(|| async { HttpResponse::Created().finish() })),
+                ),
+        )
+        .await;
+
+        let req = awtest::TestRequest::post()
+            .uri("/files/team-a/new.txt")
+            .set_payload("12345")
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads?dir=team-a/reports")
+            .set_payload("12345")
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+
+        let req = awtest::TestRequest::post()
+            .uri("/files/team-a/new.txt")
+            .set_payload("1234")
+            .to_request();
+        assert_eq!(
+            awtest::call_service(&app, req).await.status(),
+            StatusCode::CREATED
+        );
+
+        let req = awtest::TestRequest::post()
+            .uri("/files/team-b/new.txt")
+            .set_payload("12345")
+            .to_request();
+        assert_eq!(
+            awtest::call_service(&app, req).await.status(),
+            StatusCode::CREATED
+        );
+    }
+
+    #[actix_web::test]
+    async fn reports_which_limit_refused_the_write() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = quota_config(&temp_dir);
+        config.roots.insert(
+            "full".to_string(),
+            config::RootConfig {
+                // No real filesystem is this empty.
+                min_free_percent: Some(100.0),
+                ..config::RootConfig::default()
+            },
+        );
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(Arc::new(RootQuotas::default())))
+                .service(
+                    web::resource("/files/{path:.*}")
+                        .wrap(from_fn(enforce_quota))
+                        .route(web::post().to(|| async { HttpResponse::Created().finish() })),
+                ),
+        )
+        .await;
+
+        for (uri, limit) in [
+            ("/files/full/a.txt", "min_free_percent"),
+            ("/files/team-a/a.txt", "quota_bytes"),
+        ] {
+            let req = awtest::TestRequest::post()
+                .uri(uri)
+                .set_payload("more than ten bytes")
+                .to_request();
+            let response = awtest::call_service(&app, req).await;
+            assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+            let body: serde_json::Value = awtest::read_body_json(response).await;
+            assert_eq!(body["code"], "INSUFFICIENT_STORAGE");
+            assert_eq!(body["limit"], limit);
+        }
+
+        let req = awtest::TestRequest::post()
+            .uri("/files/elsewhere/a.txt")
+            .set_payload("more than ten bytes")
+            .to_request();
+        assert_eq!(
+            awtest::call_service(&app, req).await.status(),
+            StatusCode::CREATED
+        );
+    }
+
+    #[actix_web::test]
+    async fn removes_new_files_that_end_up_over_quota() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(quota_config(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(RootQuotas::default())))
+                .service(
+                    web::resource("/files/{path:.*}")
+                        .wrap(from_fn(enforce_quota))
+                        .route(web::post().to(
+                            |path: web::Path<String>, config: web::Data<config::Folio>| async move {
+                                let full_path = config.build_full_upload_path(Path::new(&*path));
+                                std::fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+                                std::fs::write(full_path, "more than ten bytes").unwrap();
+                                HttpResponse::Created().finish()
+                            },
+                        )),
+                ),
+        )
+        .await;
+
+        // No Content-Length, so only the written size can be checked.
+        let req = awtest::TestRequest::post()
+            .uri("/files/team-a/big.txt")
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+        assert!(!temp_dir.path().join("team-a/big.txt").exists());
+    }
+}
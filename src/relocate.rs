@@ -0,0 +1,317 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use actix_web::{HttpRequest, HttpResponse, Responder, post, web};
+use serde::{Deserialize, Serialize};
+
+use super::alias::{AliasStore, find_upload};
+use super::config;
+use super::error::FolioError;
+use super::expiry::ExpiryStore;
+use super::meta_file;
+use super::metadata::MetadataStore;
+use super::path::SafePath;
+use super::private_index::PrivateIndexStore;
+use super::public_url::{encode_path, public_url};
+use super::quota::{RootQuotas, quota_exceeded, root_quota};
+use super::upload_meta::copy_xattrs;
+use super::uploads::ensure_writable;
+
+#[derive(Deserialize)]
+pub struct RelocateRequest {
+    root: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RelocateResponse {
+    pub message: String,
+    pub url: String,
+    /// The file's new path, relative to the uploads root.
+    pub path: String,
+}
+
+/// Where `path` ends up in `root`: its path below its current named root,
+/// or below the uploads root when it isn't in one.
+fn relocated_path(config: &config::Folio, path: &Path, root: &str) -> PathBuf {
+    let mut components = path.components();
+    let in_root = components
+        .next()
+        .and_then(|first| first.as_os_str().to_str())
+        .is_some_and(|first| config.roots.contains_key(first));
+    let rest = components.as_path();
+
+    if in_root && !rest.as_os_str().is_empty() {
+        Path::new(root).join(rest)
+    } else {
+        Path::new(root).join(path)
+    }
+}
+
+/// Move a file without ever replacing `to`: a hard link when both sides share
+/// a filesystem, otherwise a copy (with its upload xattrs). `AlreadyExists`
+/// means `to` is taken.
+pub(crate) fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    match std::fs::hard_link(from, to) {
+        Ok(()) => return std::fs::remove_file(from),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => return Err(e),
+        Err(e) => log::debug!("hard link {} failed ({}), copying", to.display(), e),
+    }
+
+    let mut source = std::fs::File::open(from)?;
+    let mut dest = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(to)?;
+    if let Err(e) = std::io::copy(&mut source, &mut dest).and_then(|_| dest.sync_all()) {
+        drop(dest);
+        let _ = std::fs::remove_file(to);
+        return Err(e);
+    }
+    copy_xattrs(from, to);
+    std::fs::remove_file(from)
+}
+
+/// Move an upload into another named root, taking its metadata, private
+/// access list, aliases and expiration along.
+///
+/// `<id>` is resolved as in `POST /uploads/<id>/alias`.
+#[post("/uploads/{id:.*}/relocate")]
+#[allow(clippy::too_many_arguments)] // one extractor per index the file is tracked in
+pub async fn relocate_upload(
+    req: HttpRequest,
+    config: web::Data<config::Folio>,
+    metadata_store: web::Data<Arc<MetadataStore>>,
+    private_index: web::Data<Arc<PrivateIndexStore>>,
+    alias_store: web::Data<Arc<AliasStore>>,
+    expiry_store: web::Data<Arc<ExpiryStore>>,
+    root_quotas: web::Data<Arc<RootQuotas>>,
+    id: web::Path<String>,
+    body: web::Json<RelocateRequest>,
+) -> Result<impl Responder, FolioError> {
+    ensure_writable(&config)?;
+    if !config.roots.contains_key(&body.root) {
+        return Err(FolioError::BadRequest {
+            reason: format!("unknown root '{}'", body.root),
+        });
+    }
+
+    let from = find_upload(&config, &id)?;
+    let to = relocated_path(&config, from.as_path(), &body.root);
+    if to == from.as_path() {
+        return Err(FolioError::BadRequest {
+            reason: format!("{} is already in root '{}'", from, body.root),
+        });
+    }
+    let to = SafePath::from_user_input(&to)?;
+    let from_full = config.build_full_upload_path(from.as_path());
+    let to_full = config.build_full_upload_path(to.as_path());
+    let size = std::fs::metadata(&from_full)
+        .map_err(|e| FolioError::store_error(e.to_string(), "read upload size"))?
+        .len();
+
+    let dest_quota =
+        root_quota(&config, to.as_path()).map(|(root, quota)| (root.to_string(), quota));
+    if let Some((root, quota)) = &dest_quota {
+        let used = web::block({
+            let (quotas, config, root) = (root_quotas.clone(), config.clone(), root.clone());
+            move || quotas.usage(&config, &root)
+        })
+        .await
+        .map_err(|e| FolioError::store_error(e.to_string(), "measure root usage"))?
+        .map_err(|e| FolioError::store_error(e.to_string(), "measure root usage"))?;
+        if used + size > *quota {
+            return Err(quota_exceeded(root, used, *quota));
+        }
+    }
+
+    web::block({
+        let (from_full, to_full) = (from_full.clone(), to_full.clone());
+        move || move_file(&from_full, &to_full)
+    })
+    .await
+    .map_err(|e| FolioError::store_error(e.to_string(), "move upload"))?
+    .map_err(|e| match e.kind() {
+        ErrorKind::AlreadyExists => FolioError::Conflict {
+            path: to.to_string(),
+        },
+        _ => FolioError::store_error(e.to_string(), "move upload"),
+    })?;
+    if let Some((root, _)) = &dest_quota {
+        root_quotas.record_write(root, 0, size);
+    }
+    if let Some((root, _)) = root_quota(&config, from.as_path()) {
+        root_quotas.record_write(root, size, 0);
+    }
+    meta_file::rename(&from_full, &to_full);
+    log::info!("relocated {} to {}", from, to);
+
+    metadata_store
+        .rename(from.as_path(), to.as_path())
+        .await
+        .map_err(|e| FolioError::store_error(e, "move file metadata"))?;
+    private_index
+        .rename(from.as_path(), to.as_path())
+        .await
+        .map_err(|e| FolioError::store_error(e, "move private index entry"))?;
+    alias_store
+        .retarget(from.as_path(), to.as_path())
+        .await
+        .map_err(|e| FolioError::store_error(e, "move aliases"))?;
+    expiry_store
+        .rename(&from_full, &config.build_full_upload_path(to.as_path()))
+        .await
+        .map_err(|e| FolioError::store_error(e, "move expiration"))?;
+
+    let is_private = private_index
+        .is_private(to.as_path())
+        .await
+        .map_err(|e| FolioError::store_error(e, "check private index"))?;
+    let encoded = encode_path(&to.to_string());
+    let url = if is_private {
+        format!("/private-files/{}", encoded)
+    } else {
+        format!("/files/{}", encoded)
+    };
+    let url = public_url(&req, &config, &url);
+    Ok(HttpResponse::Ok()
+        .append_header(("Location", url.clone()))
+        .json(RelocateResponse {
+            message: "file relocated successfully".to_string(),
+            url,
+            path: to.to_string(),
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{App, test as awtest};
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    fn relocate_config(temp_dir: &tempfile::TempDir) -> config::Folio {
+        let uploads = temp_dir.path().join("uploads");
+        std::fs::create_dir_all(uploads.join("staging")).unwrap();
+        config::Folio {
+            uploads_path: uploads.to_string_lossy().to_string(),
+            data_path: temp_dir.path().join("data").to_string_lossy().to_string(),
+            roots: BTreeMap::from([
+                ("staging".to_string(), config::RootConfig::default()),
+                ("production".to_string(), config::RootConfig::default()),
+            ]),
+            ..config::Folio::default()
+        }
+    }
+
+    #[test]
+    fn relocated_path_keeps_the_path_below_the_root() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = relocate_config(&temp_dir);
+
+        assert_eq!(
+            relocated_path(&config, Path::new("staging/2024/a.txt"), "production"),
+            Path::new("production/2024/a.txt")
+        );
+        assert_eq!(
+            relocated_path(&config, Path::new("a.txt"), "production"),
+            Path::new("production/a.txt")
+        );
+    }
+
+    #[actix_web::test]
+    async fn moves_file_and_its_records() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = relocate_config(&temp_dir);
+        let source = config.build_full_upload_path(Path::new("staging/a.txt"));
+        std::fs::write(&source, "report").unwrap();
+        std::fs::write(
+            config.build_full_upload_path(Path::new("staging/b.txt")),
+            "b",
+        )
+        .unwrap();
+        std::fs::create_dir_all(config.build_full_upload_path(Path::new("production"))).unwrap();
+        std::fs::write(
+            config.build_full_upload_path(Path::new("production/b.txt")),
+            "taken",
+        )
+        .unwrap();
+
+        let metadata_store = Arc::new(MetadataStore::new(&config));
+        metadata_store
+            .update(Path::new("staging/a.txt"), |m| {
+                m.content_type = Some("text/csv".to_string())
+            })
+            .await
+            .unwrap();
+        let alias_store = Arc::new(AliasStore::new(&config));
+        alias_store
+            .create("report", Path::new("staging/a.txt"))
+            .await
+            .unwrap();
+        let expiry_store = Arc::new(ExpiryStore::new(&config));
+        expiry_store
+            .schedule(&source, Duration::from_secs(3600))
+            .await
+            .unwrap();
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(metadata_store.clone()))
+                .app_data(web::Data::new(Arc::new(PrivateIndexStore::new(&config))))
+                .app_data(web::Data::new(alias_store.clone()))
+                .app_data(web::Data::new(expiry_store.clone()))
+                .app_data(web::Data::new(Arc::new(RootQuotas::default())))
+                .app_data(web::Data::new(config))
+                .service(relocate_upload),
+        )
+        .await;
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads/staging/a.txt/relocate")
+            .set_json(serde_json::json!({ "root": "production" }))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = awtest::read_body_json(response).await;
+        assert_eq!(body["url"], "/files/production/a.txt");
+
+        let uploads = temp_dir.path().join("uploads");
+        assert!(!uploads.join("staging/a.txt").exists());
+        assert_eq!(
+            std::fs::read_to_string(uploads.join("production/a.txt")).unwrap(),
+            "report"
+        );
+        let moved = metadata_store
+            .get(Path::new("production/a.txt"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(moved.content_type.as_deref(), Some("text/csv"));
+        assert_eq!(
+            alias_store.resolve("report").await.unwrap().as_deref(),
+            Some("production/a.txt")
+        );
+        let new_full = uploads.join("production/a.txt").canonicalize().unwrap();
+        assert!(expiry_store.expire_at(&new_full).await.unwrap().is_some());
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads/staging/b.txt/relocate")
+            .set_json(serde_json::json!({ "root": "production" }))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert!(uploads.join("staging/b.txt").exists());
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads/staging/b.txt/relocate")
+            .set_json(serde_json::json!({ "root": "archive" }))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
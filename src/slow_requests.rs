@@ -0,0 +1,155 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::web;
+
+use super::config;
+
+/// A request being timed, logged when its response body is dropped, i.e.
+/// once it was sent or the client went away.
+struct Timing {
+    started: Instant,
+    threshold: Duration,
+    method: Method,
+    route: String,
+    path: String,
+    status: StatusCode,
+}
+
+impl Timing {
+    fn message(&self, elapsed: Duration) -> Option<String> {
+        (elapsed > self.threshold).then(|| {
+            format!(
+                "slow request: {} {} (route {}) -> {} in {} ms",
+                self.method,
+                self.path,
+                self.route,
+                self.status.as_u16(),
+                elapsed.as_millis()
+            )
+        })
+    }
+}
+
+impl Drop for Timing {
+    fn drop(&mut self) {
+        if let Some(message) = self.message(self.started.elapsed()) {
+            log::warn!("{}", message);
+        }
+    }
+}
+
+/// The response body, carrying the `Timing` so streamed downloads are timed
+/// to their last byte.
+struct TimedBody {
+    body: BoxBody,
+    _timing: Timing,
+}
+
+impl MessageBody for TimedBody {
+    type Error = Box<dyn std::error::Error>;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<web::Bytes, Self::Error>>> {
+        Pin::new(&mut self.body).poll_next(cx)
+    }
+}
+
+/// Log a warning for every request that takes longer than
+/// `slow_request_threshold_ms`, including the time spent streaming the
+/// response. Wrapped around the whole app.
+pub async fn log_slow_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let threshold = req
+        .app_data::<web::Data<config::Folio>>()
+        .map_or(0, |config| config.slow_request_threshold_ms);
+    if threshold == 0 {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_boxed_body);
+    }
+
+    let started = Instant::now();
+    let method = req.method().clone();
+    let path = req.path().to_string();
+    let res = next.call(req).await?;
+    let timing = Timing {
+        started,
+        threshold: Duration::from_millis(threshold),
+        method,
+        route: res
+            .request()
+            .match_pattern()
+            .unwrap_or_else(|| "-".to_string()),
+        path,
+        status: res.status(),
+    };
+    Ok(res.map_body(|_, body| {
+        TimedBody {
+            body: body.boxed(),
+            _timing: timing,
+        }
+        .boxed()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::middleware::from_fn;
+    use actix_web::{App, HttpResponse, test as awtest};
+
+    #[test]
+    fn only_requests_over_the_threshold_are_reported() {
+        let timing = Timing {
+            started: Instant::now(),
+            threshold: Duration::from_millis(500),
+            method: Method::POST,
+            route: "/uploads".to_string(),
+            path: "/uploads".to_string(),
+            status: StatusCode::CREATED,
+        };
+        assert!(timing.message(Duration::from_millis(500)).is_none());
+        assert_eq!(
+            timing.message(Duration::from_millis(1200)).unwrap(),
+            "slow request: POST /uploads (route /uploads) -> 201 in 1200 ms"
+        );
+    }
+
+    #[actix_web::test]
+    async fn timed_responses_keep_their_body() {
+        let config = config::Folio {
+            slow_request_threshold_ms: 1,
+            ..config::Folio::default()
+        };
+        let app = awtest::init_service(
+            App::new()
+                .wrap(from_fn(log_slow_requests))
+                .app_data(web::Data::new(config))
+                .route(
+                    "/files/a.txt",
+                    web::get().to(|| async { HttpResponse::Ok().body("hello") }),
+                ),
+        )
+        .await;
+
+        let req = awtest::TestRequest::get().uri("/files/a.txt").to_request();
+        let res = awtest::call_service(&app, req).await;
+        assert_eq!(res.response().body().size(), BodySize::Sized(5));
+        assert_eq!(awtest::read_body(res).await, "hello");
+    }
+}
@@ -0,0 +1,249 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, Responder, post, web};
+use serde::{Deserialize, Serialize};
+
+use super::alias::find_in;
+use super::config;
+use super::durable::persist;
+use super::error::FolioError;
+use super::expiry::ExpiryStore;
+use super::meta_file;
+use super::processing::ProcessingStatus;
+use super::public_url::public_url;
+use super::relocate::move_file;
+use super::uploads::{
+    UploadResponse, ensure_writable, parse_duration, requested_expire, resolve_ttl,
+};
+
+/// Fallback when `staged_upload_ttl` cannot be parsed.
+const FALLBACK_STAGED_TTL: Duration = Duration::from_secs(3600);
+
+/// Where `?staged=1` uploads wait for their commit: below `data_path`, so
+/// they are never served.
+pub fn staging_root(config: &config::Folio) -> PathBuf {
+    config.build_full_data_path(Path::new("staging"))
+}
+
+fn staged_ttl(config: &config::Folio) -> Duration {
+    parse_duration(&config.staged_upload_ttl).unwrap_or_else(|e| {
+        log::warn!(
+            "invalid staged_upload_ttl '{}': {}, falling back to 1h",
+            config.staged_upload_ttl,
+            e
+        );
+        FALLBACK_STAGED_TTL
+    })
+}
+
+/// JSON body returned by `POST /uploads?staged=1`.
+#[derive(Debug, Serialize)]
+pub struct StagedResponse {
+    pub message: String,
+    /// Pass to `POST /uploads/<id>/commit`.
+    pub id: String,
+    /// Where the file will be served once committed.
+    pub path: String,
+    /// When the upload is dropped unless it is committed first.
+    pub expire_at_unix: u64,
+}
+
+/// Move a freshly stored upload from the uploads root into the staging area,
+/// keeping its relative path, and let it expire after `staged_upload_ttl`.
+pub(crate) async fn stage_upload(
+    config: &config::Folio,
+    expiry_store: &ExpiryStore,
+    file_name: &str,
+    id: String,
+) -> Result<HttpResponse, FolioError> {
+    let full_path = config.build_full_upload_path(Path::new(file_name));
+    let staged_path = staging_root(config).join(file_name);
+    web::block({
+        let (from, to) = (full_path.clone(), staged_path.clone());
+        move || {
+            move_file(&from, &to)?;
+            meta_file::rename(&from, &to);
+            Ok::<_, std::io::Error>(())
+        }
+    })
+    .await
+    .map_err(|e| FolioError::store_error(e.to_string(), "stage upload"))?
+    .map_err(|e| FolioError::store_error(e.to_string(), "stage upload"))?;
+
+    let expire_at_unix = expiry_store
+        .schedule(&staged_path, staged_ttl(config))
+        .await
+        .map_err(|e| FolioError::store_error(e, "schedule staged expiration"))?;
+    log::info!("staged upload {} until {}", file_name, expire_at_unix);
+
+    Ok(HttpResponse::Accepted().json(StagedResponse {
+        message: "file staged, commit it to publish".to_string(),
+        id,
+        path: file_name.to_string(),
+        expire_at_unix,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct CommitQuery {
+    expire: Option<String>,
+}
+
+/// Publish a `?staged=1` upload: move it to the path it was staged for and
+/// schedule its expiration as a regular upload (`?expire=` as on
+/// `POST /uploads`).
+///
+/// `<id>` is the `id` or `path` from the staging response.
+#[post("/uploads/{id:.*}/commit")]
+pub async fn commit_upload(
+    req: HttpRequest,
+    config: web::Data<config::Folio>,
+    expiry_store: web::Data<Arc<ExpiryStore>>,
+    id: web::Path<String>,
+    query: web::Query<CommitQuery>,
+) -> Result<impl Responder, FolioError> {
+    ensure_writable(&config)?;
+    let path = find_in(&staging_root(&config), &id)?;
+    let file_name = path.to_string();
+    let requested = requested_expire(&config, query.expire.as_deref(), &file_name);
+    let (ttl, expire_clamped) = resolve_ttl(&config, requested)?;
+
+    let staged_path = staging_root(&config).join(path.as_path());
+    let full_path = config.build_full_upload_path(path.as_path());
+    web::block({
+        let (from, to) = (staged_path.clone(), full_path.clone());
+        move || {
+            move_file(&from, &to)?;
+            meta_file::rename(&from, &to);
+            Ok::<_, std::io::Error>(())
+        }
+    })
+    .await
+    .map_err(|e| FolioError::store_error(e.to_string(), "commit upload"))?
+    .map_err(|e| match e.kind() {
+        std::io::ErrorKind::AlreadyExists => FolioError::Conflict {
+            path: file_name.clone(),
+        },
+        _ => FolioError::store_error(e.to_string(), "commit upload"),
+    })?;
+    persist(&config, &full_path).await?;
+
+    if let Err(e) = expiry_store.cancel(&staged_path).await {
+        log::error!("failed to cancel staged expiration of {}: {}", file_name, e);
+    }
+    let expire_at_unix = expiry_store
+        .schedule(&full_path, ttl)
+        .await
+        .map_err(|e| FolioError::store_error(e, "schedule expiration"))?;
+    meta_file::set_expire_at(&full_path, Some(expire_at_unix));
+    log::info!("committed staged upload {}", file_name);
+
+    let base_name = path
+        .as_path()
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let upload_id = base_name
+        .split_once('.')
+        .map_or(base_name.as_str(), |(id, _)| id)
+        .to_string();
+    let url = public_url(&req, &config, &format!("/files/{}", file_name));
+    let status = match config.upload_success_status {
+        config::UploadSuccessStatus::Created => StatusCode::CREATED,
+        config::UploadSuccessStatus::Ok => StatusCode::OK,
+    };
+    Ok(HttpResponse::build(status)
+        .append_header(("Location", url.clone()))
+        .json(UploadResponse {
+            message: "file committed successfully".to_string(),
+            id: upload_id,
+            url,
+            expire_at_unix,
+            expire_clamped,
+            width: None,
+            height: None,
+            status: ProcessingStatus::Ready,
+            echo: None,
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dir_count::DirEntryCounter;
+    use crate::metadata::MetadataStore;
+    use crate::private_index::PrivateIndexStore;
+    use crate::uploads::upload_json;
+    use actix_web::{App, test as awtest};
+
+    #[actix_web::test]
+    async fn staged_uploads_are_hidden_until_committed() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let test_config = || config::Folio {
+            uploads_path: temp_dir
+                .path()
+                .join("uploads")
+                .to_string_lossy()
+                .to_string(),
+            data_path: temp_dir.path().join("data").to_string_lossy().to_string(),
+            ..config::Folio::default()
+        };
+        let config = test_config();
+        let expiry_store = Arc::new(ExpiryStore::new(&config));
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(expiry_store.clone()))
+                .app_data(web::Data::new(Arc::new(PrivateIndexStore::new(&config))))
+                .app_data(web::Data::new(Arc::new(MetadataStore::new(&config))))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(test_config()))
+                .service(upload_json)
+                .service(commit_upload),
+        )
+        .await;
+        let upload = |uri: &str| {
+            awtest::TestRequest::post()
+                .uri(uri)
+                .set_json(serde_json::json!({
+                    "filename": "report.txt",
+                    "content_base64": "aGVsbG8=",
+                }))
+                .to_request()
+        };
+
+        let res = awtest::call_service(&app, upload("/uploads?staged=1&expire=1d")).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = awtest::call_service(&app, upload("/uploads?staged=1")).await;
+        assert_eq!(res.status(), StatusCode::ACCEPTED);
+        let body: serde_json::Value = awtest::read_body_json(res).await;
+        let (id, path) = (body["id"].as_str().unwrap(), body["path"].as_str().unwrap());
+        let served = config.build_full_upload_path(Path::new(path));
+        let staged = staging_root(&config).join(path);
+        assert!(!served.exists());
+        assert!(staged.is_file());
+        assert!(expiry_store.expire_at(&staged).await.unwrap().is_some());
+
+        let commit = || {
+            awtest::TestRequest::post()
+                .uri(&format!("/uploads/{}/commit?expire=1d", id))
+                .to_request()
+        };
+        let res = awtest::call_service(&app, commit()).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(
+            res.headers().get("Location").unwrap(),
+            &format!("/files/{}", path)
+        );
+        assert_eq!(std::fs::read_to_string(&served).unwrap(), "hello");
+        assert!(!staged.exists());
+        assert!(expiry_store.expire_at(&staged).await.unwrap().is_none());
+        assert!(expiry_store.expire_at(&served).await.unwrap().is_some());
+
+        let res = awtest::call_service(&app, commit()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{HttpRequest, HttpResponse, Responder, get, web};
+use globset::{Glob, GlobMatcher};
+use serde::{Deserialize, Serialize};
+
+use super::admin::require_admin;
+use super::auth::AccessAuth;
+use super::config;
+use super::error::FolioError;
+use super::meta_file;
+use super::quota::RootQuotas;
+use super::upload_limit::UploadLimiter;
+
+/// How long a `GET /stats/usage` figure is reused for the same glob.
+const USAGE_TTL: Duration = Duration::from_secs(30);
+
+/// Point-in-time server load figures.
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub uploads_in_flight: usize,
+    /// `0` means unlimited.
+    pub max_concurrent_uploads: usize,
+    /// Storage used by each named root.
+    pub roots: BTreeMap<String, RootUsage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RootUsage {
+    pub used_bytes: u64,
+    /// `0` means unlimited.
+    pub quota_bytes: u64,
+}
+
+#[get("/stats")]
+pub async fn get_stats(
+    config: web::Data<config::Folio>,
+    upload_limiter: web::Data<Arc<UploadLimiter>>,
+    root_quotas: web::Data<Arc<RootQuotas>>,
+) -> Result<impl Responder, FolioError> {
+    let roots = web::block(move || {
+        config
+            .roots
+            .iter()
+            .map(|(name, root)| {
+                let used_bytes = root_quotas.usage(&config, name)?;
+                Ok((
+                    name.clone(),
+                    RootUsage {
+                        used_bytes,
+                        quota_bytes: root.quota_bytes,
+                    },
+                ))
+            })
+            .collect::<std::io::Result<BTreeMap<_, _>>>()
+    })
+    .await
+    .map_err(|e| FolioError::store_error(e.to_string(), "measure root usage"))?
+    .map_err(|e| FolioError::store_error(e.to_string(), "measure root usage"))?;
+
+    Ok(HttpResponse::Ok().json(Stats {
+        uploads_in_flight: upload_limiter.in_flight(),
+        max_concurrent_uploads: upload_limiter.max(),
+        roots,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct UsageQuery {
+    /// Matched against paths relative to the uploads root.
+    glob: String,
+}
+
+/// Storage taken by the files matching a glob.
+#[derive(Debug, Clone, Serialize)]
+pub struct GlobUsageResponse {
+    pub glob: String,
+    pub used_bytes: u64,
+    pub file_count: u64,
+}
+
+struct CachedGlobUsage {
+    usage: GlobUsageResponse,
+    scanned_at: Instant,
+}
+
+/// Recent `GET /stats/usage` results by glob, so repeated polling does not
+/// walk the uploads tree every time.
+#[derive(Default)]
+pub struct GlobUsage {
+    cache: Mutex<HashMap<String, CachedGlobUsage>>,
+}
+
+impl GlobUsage {
+    fn get(&self, glob: &str) -> Option<GlobUsageResponse> {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .get(glob)
+            .filter(|cached| cached.scanned_at.elapsed() < USAGE_TTL)
+            .map(|cached| cached.usage.clone())
+    }
+
+    fn insert(&self, usage: GlobUsageResponse) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.retain(|_, cached| cached.scanned_at.elapsed() < USAGE_TTL);
+        cache.insert(
+            usage.glob.clone(),
+            CachedGlobUsage {
+                usage,
+                scanned_at: Instant::now(),
+            },
+        );
+    }
+}
+
+/// Bytes and number of files below `uploads_path` whose relative path
+/// matches `?glob=`, e.g. `projectA/**`. Results are reused for
+/// `USAGE_TTL`, so recent writes and deletes may not show yet.
+#[get("/stats/usage")]
+pub async fn get_usage(
+    req: HttpRequest,
+    config: web::Data<config::Folio>,
+    access_auth: web::Data<Arc<AccessAuth>>,
+    glob_usage: web::Data<Arc<GlobUsage>>,
+    query: web::Query<UsageQuery>,
+) -> Result<impl Responder, FolioError> {
+    if config.usage_requires_admin {
+        require_admin(&req, &access_auth, &config).await?;
+    }
+    let cache_control = CacheControl(vec![
+        if config.usage_requires_admin {
+            CacheDirective::Private
+        } else {
+            CacheDirective::Public
+        },
+        CacheDirective::MaxAge(USAGE_TTL.as_secs() as u32),
+    ]);
+    if let Some(usage) = glob_usage.get(&query.glob) {
+        return Ok(HttpResponse::Ok().insert_header(cache_control).json(usage));
+    }
+
+    let matcher = Glob::new(&query.glob)
+        .map_err(|e| FolioError::BadRequest {
+            reason: format!("invalid glob '{}': {}", query.glob, e),
+        })?
+        .compile_matcher();
+    let root = config.resolve_base(&config.uploads_path);
+    let (used_bytes, file_count) = web::block(move || {
+        let mut totals = (0, 0);
+        match sum_matches(&root, &root, &matcher, &mut totals) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(totals),
+        }
+    })
+    .await
+    .map_err(|e| FolioError::store_error(e.to_string(), "measure glob usage"))?
+    .map_err(|e| FolioError::store_error(e.to_string(), "measure glob usage"))?;
+
+    let usage = GlobUsageResponse {
+        glob: query.into_inner().glob,
+        used_bytes,
+        file_count,
+    };
+    glob_usage.insert(usage.clone());
+    Ok(HttpResponse::Ok().insert_header(cache_control).json(usage))
+}
+
+/// Add the size and count of the files below `dir` matching `matcher`
+/// (relative to `root`) to `totals`. Symbolic links and meta files are not
+/// counted.
+fn sum_matches(
+    root: &Path,
+    dir: &Path,
+    matcher: &GlobMatcher,
+    totals: &mut (u64, u64),
+) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+        if file_type.is_dir() {
+            sum_matches(root, &path, matcher, totals)?;
+        } else if file_type.is_file()
+            && !meta_file::is_meta_file(&path)
+            && let Ok(relative) = path.strip_prefix(root)
+            && matcher.is_match(relative)
+        {
+            totals.0 += entry.metadata()?.len();
+            totals.1 += 1;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{StatusCode, header};
+    use actix_web::{App, test};
+
+    use crate::test_utils::make_hs256_token;
+
+    #[actix_web::test]
+    async fn reports_in_flight_uploads_and_root_usage() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("team-a")).unwrap();
+        std::fs::write(temp_dir.path().join("team-a/a.txt"), "hello").unwrap();
+        let config = config::Folio {
+            uploads_path: temp_dir.path().to_string_lossy().to_string(),
+            max_concurrent_uploads: 4,
+            roots: BTreeMap::from([(
+                "team-a".to_string(),
+                config::RootConfig {
+                    quota_bytes: 100,
+                    ..config::RootConfig::default()
+                },
+            )]),
+            ..config::Folio::default()
+        };
+        let limiter = Arc::new(UploadLimiter::new(&config));
+        let _slot = limiter.try_acquire().unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(limiter.clone()))
+                .app_data(web::Data::new(Arc::new(RootQuotas::default())))
+                .service(get_stats),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/stats").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        assert_eq!(body["uploads_in_flight"], 1);
+        assert_eq!(body["max_concurrent_uploads"], 4);
+        assert_eq!(body["roots"]["team-a"]["used_bytes"], 5);
+        assert_eq!(body["roots"]["team-a"]["quota_bytes"], 100);
+    }
+
+    #[actix_web::test]
+    async fn usage_sums_matching_files_and_caches_results() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("projectA/sub")).unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("projectB")).unwrap();
+        std::fs::write(temp_dir.path().join("projectA/a.txt"), "hello").unwrap();
+        std::fs::write(temp_dir.path().join("projectA/a.txt.meta.json"), "{}").unwrap();
+        std::fs::write(temp_dir.path().join("projectA/sub/b.bin"), "123").unwrap();
+        std::fs::write(temp_dir.path().join("projectB/c.txt"), "other").unwrap();
+        let config = config::Folio {
+            uploads_path: temp_dir.path().to_string_lossy().to_string(),
+            ..config::Folio::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(Arc::new(AccessAuth::from_parts(
+                    "https://issuer.example.com",
+                    "folio-app",
+                    Some("test-secret"),
+                ))))
+                .app_data(web::Data::new(Arc::new(GlobUsage::default())))
+                .service(get_usage),
+        )
+        .await;
+        let usage = async |glob: &str| {
+            let req = test::TestRequest::get()
+                .uri(&format!("/stats/usage?glob={}", glob))
+                .to_request();
+            let response = test::call_service(&app, req).await;
+            let status = response.status();
+            (
+                status,
+                test::read_body_json::<serde_json::Value, _>(response).await,
+            )
+        };
+
+        let (status, body) = usage("projectA/**").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["used_bytes"], 8);
+        assert_eq!(body["file_count"], 2);
+        let (_, body) = usage("**/*.txt").await;
+        assert_eq!(body["used_bytes"], 10);
+
+        std::fs::write(temp_dir.path().join("projectA/new.txt"), "later").unwrap();
+        let (_, body) = usage("projectA/**").await;
+        assert_eq!(body["file_count"], 2);
+
+        let (status, _) = usage("projectA/[").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn usage_can_require_admin() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = config::Folio {
+            uploads_path: temp_dir.path().to_string_lossy().to_string(),
+            admin_emails: vec!["admin@example.com".to_string()],
+            usage_requires_admin: true,
+            ..config::Folio::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(Arc::new(AccessAuth::from_parts(
+                    "https://issuer.example.com",
+                    "folio-app",
+                    Some("test-secret"),
+                ))))
+                .app_data(web::Data::new(Arc::new(GlobUsage::default())))
+                .service(get_usage),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/stats/usage?glob=**")
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let token = make_hs256_token(
+            "test-secret",
+            "user-1",
+            Some("admin@example.com"),
+            &[],
+            "https://issuer.example.com",
+            "folio-app",
+            3600,
+        );
+        let req = test::TestRequest::get()
+            .uri("/stats/usage?glob=**")
+            .insert_header(("Cf-Access-Jwt-Assertion", token))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CACHE_CONTROL).unwrap(),
+            "private, max-age=30"
+        );
+    }
+}
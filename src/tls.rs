@@ -0,0 +1,81 @@
+use std::io::{Error, ErrorKind};
+
+use rustls::ServerConfig;
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+
+use super::config;
+
+/// TLS settings from `tls_cert_path` and `tls_key_path`, or `None` to serve
+/// plain HTTP. Actix adds `h2` and `http/1.1` to ALPN when binding, so
+/// clients that support it get HTTP/2.
+pub fn server_config(config: &config::Folio) -> std::io::Result<Option<ServerConfig>> {
+    let (cert_path, key_path) = match (config.tls_cert_path.as_str(), config.tls_key_path.as_str())
+    {
+        ("", "") => return Ok(None),
+        ("", _) | (_, "") => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "tls_cert_path and tls_key_path must be set together",
+            ));
+        }
+        (cert, key) => (config.resolve_base(cert), config.resolve_base(key)),
+    };
+
+    let invalid = |what: &str, path: &std::path::Path, e: rustls_pki_types::pem::Error| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("failed to read {} {}: {}", what, path.display(), e),
+        )
+    };
+    let certs = CertificateDer::pem_file_iter(&cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| invalid("certificate chain", &cert_path, e))?;
+    let key = PrivateKeyDer::from_pem_file(&key_path)
+        .map_err(|e| invalid("private key", &key_path, e))?;
+
+    ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map(Some)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_http_unless_both_paths_are_set() {
+        let config = config::Folio::default();
+        assert!(server_config(&config).unwrap().is_none());
+
+        let config = config::Folio {
+            tls_cert_path: "cert.pem".to_string(),
+            ..config::Folio::default()
+        };
+        let err = server_config(&config).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn unreadable_files_are_reported() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("cert.pem"), "not a certificate").unwrap();
+        let config = config::Folio {
+            tls_cert_path: temp_dir
+                .path()
+                .join("cert.pem")
+                .to_string_lossy()
+                .to_string(),
+            tls_key_path: temp_dir
+                .path()
+                .join("key.pem")
+                .to_string_lossy()
+                .to_string(),
+            ..config::Folio::default()
+        };
+        let err = server_config(&config).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,139 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{ResponseError, web};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use super::config;
+use super::error::FolioError;
+
+/// Caps how many uploads are written concurrently so a burst of large
+/// uploads can't exhaust memory and disk IO for everyone.
+#[derive(Debug)]
+pub struct UploadLimiter {
+    /// `None` when `max_concurrent_uploads` is `0` (unlimited).
+    semaphore: Option<Semaphore>,
+    max: usize,
+    retry_after_secs: u64,
+    in_flight: Arc<AtomicUsize>,
+}
+
+/// Held for the duration of one upload.
+pub struct UploadSlot<'a> {
+    _permit: Option<SemaphorePermit<'a>>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for UploadSlot<'_> {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl UploadLimiter {
+    pub fn new(config: &config::Folio) -> Self {
+        let max = config.max_concurrent_uploads;
+        Self {
+            semaphore: (max > 0).then(|| Semaphore::new(max)),
+            max,
+            retry_after_secs: config.throttle_retry_after_secs,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Claim a slot without waiting; `503` with `Retry-After` when saturated.
+    pub fn try_acquire(&self) -> Result<UploadSlot<'_>, FolioError> {
+        let permit = match &self.semaphore {
+            Some(semaphore) => Some(semaphore.try_acquire().map_err(|_| {
+                log::warn!("upload rejected: {} uploads already in flight", self.max);
+                FolioError::Throttled {
+                    reason: "too many concurrent uploads, retry later".to_string(),
+                    retry_after_secs: self.retry_after_secs,
+                }
+            })?),
+            None => None,
+        };
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+
+        Ok(UploadSlot {
+            _permit: permit,
+            in_flight: self.in_flight.clone(),
+        })
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// `0` means unlimited.
+    pub fn max(&self) -> usize {
+        self.max
+    }
+}
+
+/// Route middleware holding an upload slot until the handler has finished
+/// reading and writing the request body.
+pub async fn limit_uploads(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let Some(limiter) = req.app_data::<web::Data<Arc<UploadLimiter>>>().cloned() else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    };
+
+    let _slot = match limiter.try_acquire() {
+        Ok(slot) => slot,
+        Err(e) => {
+            let response = e.error_response();
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    };
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(max: usize) -> UploadLimiter {
+        UploadLimiter::new(&config::Folio {
+            max_concurrent_uploads: max,
+            ..config::Folio::default()
+        })
+    }
+
+    #[test]
+    fn rejects_when_saturated_and_frees_on_drop() {
+        let limiter = limiter(1);
+
+        let slot = limiter.try_acquire().unwrap();
+        assert_eq!(limiter.in_flight(), 1);
+        let err = limiter.try_acquire().err().unwrap();
+        assert_eq!(
+            err.status(),
+            actix_web::http::StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        drop(slot);
+        assert_eq!(limiter.in_flight(), 0);
+        assert!(limiter.try_acquire().is_ok());
+    }
+
+    #[test]
+    fn zero_is_unlimited_but_still_counted() {
+        let limiter = limiter(0);
+
+        let slots: Vec<_> = (0..10).map(|_| limiter.try_acquire().unwrap()).collect();
+        assert_eq!(limiter.in_flight(), 10);
+        drop(slots);
+        assert_eq!(limiter.in_flight(), 0);
+    }
+}
@@ -0,0 +1,371 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, Responder, get, web};
+use serde::Serialize;
+
+use super::access::AccessTracker;
+use super::alias::find_upload;
+use super::auth::{AccessAuth, AccessIdentity, VerifiedIdentity};
+use super::config;
+use super::error::FolioError;
+use super::hashing::HashAlgorithm;
+use super::meta_file::MetaFile;
+use super::metadata::MetadataStore;
+use super::private_index::PrivateIndexStore;
+
+/// Namespace of the extended attributes written with `xattr_metadata`.
+pub const XATTR_PREFIX: &str = "user.folio.";
+
+/// What the client told us about an upload, as opposed to what we derived
+/// (the generated name, the chosen extension).
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UploadAttributes {
+    pub original_name: Option<String>,
+    pub content_type: Option<String>,
+    pub uploader: Option<String>,
+}
+
+impl UploadAttributes {
+    pub fn is_empty(&self) -> bool {
+        self.original_name.is_none() && self.content_type.is_none() && self.uploader.is_none()
+    }
+
+    fn fields(&self) -> [(&'static str, Option<&String>); 3] {
+        [
+            ("original_name", self.original_name.as_ref()),
+            ("content_type", self.content_type.as_ref()),
+            ("uploader", self.uploader.as_ref()),
+        ]
+    }
+}
+
+/// Store `attrs` as `user.folio.*` extended attributes on `path`.
+///
+/// Fails with `Unsupported` on filesystems without user xattrs (and on
+/// platforms other than Linux).
+#[cfg(target_os = "linux")]
+pub fn write_xattrs(path: &Path, attrs: &UploadAttributes) -> std::io::Result<()> {
+    for (name, value) in attrs.fields() {
+        if let Some(value) = value {
+            xattr::set(path, format!("{}{}", XATTR_PREFIX, name), value.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn write_xattrs(_path: &Path, _attrs: &UploadAttributes) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Read back what `write_xattrs` stored. Missing or unreadable attributes
+/// come back as `None`.
+#[cfg(target_os = "linux")]
+pub fn read_xattrs(path: &Path) -> UploadAttributes {
+    let get = |name: &str| {
+        xattr::get(path, format!("{}{}", XATTR_PREFIX, name))
+            .ok()
+            .flatten()
+            .and_then(|v| String::from_utf8(v).ok())
+    };
+    UploadAttributes {
+        original_name: get("original_name"),
+        content_type: get("content_type"),
+        uploader: get("uploader"),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_xattrs(_path: &Path) -> UploadAttributes {
+    UploadAttributes::default()
+}
+
+/// Carry `user.folio.*` attributes over to a copy of a file, as far as the
+/// destination filesystem allows.
+pub fn copy_xattrs(from: &Path, to: &Path) {
+    let attrs = read_xattrs(from);
+    if attrs.is_empty() {
+        return;
+    }
+    if let Err(e) = write_xattrs(to, &attrs) {
+        log::debug!("xattrs not copied to {}: {}", to.display(), e);
+    }
+}
+
+/// Who is uploading, when the request carries an Access identity.
+///
+/// Uploads don't require one, so a missing or invalid token just means no
+/// uploader is recorded.
+pub async fn uploader(req: &HttpRequest) -> Option<String> {
+    let known = req
+        .extensions()
+        .get::<AccessIdentity>()
+        .map(|id| id.email.clone().unwrap_or_else(|| id.sub.clone()));
+    if known.is_some() {
+        return known;
+    }
+
+    let has_token = req.headers().contains_key("Cf-Access-Jwt-Assertion")
+        || req.headers().contains_key("Authorization");
+    if !has_token {
+        return None;
+    }
+    let auth = req.app_data::<web::Data<Arc<AccessAuth>>>()?;
+    let VerifiedIdentity(identity) = VerifiedIdentity::from_request(req, auth).await.ok()?;
+    Some(identity.email.unwrap_or(identity.sub))
+}
+
+/// Record `attrs` for a stored upload: as xattrs when the filesystem takes
+/// them, otherwise in the metadata sidecar. Never fails the upload.
+pub async fn record_upload_attributes(
+    metadata_store: &MetadataStore,
+    full_path: &Path,
+    relative_path: &Path,
+    attrs: UploadAttributes,
+) {
+    if attrs.is_empty() {
+        return;
+    }
+
+    let written = web::block({
+        let (full_path, attrs) = (full_path.to_path_buf(), attrs.clone());
+        move || write_xattrs(&full_path, &attrs)
+    })
+    .await
+    .map_err(std::io::Error::other)
+    .and_then(|r| r);
+    let Err(e) = written else {
+        return;
+    };
+    log::debug!(
+        "xattrs unavailable for {} ({}), using metadata sidecar",
+        relative_path.display(),
+        e
+    );
+
+    // The sidecar content type overrides downloads, so only the purely
+    // informational fields fall back to it.
+    if attrs.original_name.is_none() && attrs.uploader.is_none() {
+        return;
+    }
+    let result = metadata_store
+        .update(relative_path, |m| {
+            m.original_name = attrs.original_name;
+            m.uploader = attrs.uploader;
+        })
+        .await;
+    if let Err(e) = result {
+        log::error!(
+            "failed to record upload metadata for {}: {}",
+            relative_path.display(),
+            e
+        );
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadMetadataResponse {
+    pub path: String,
+    pub size_bytes: u64,
+    pub original_name: Option<String>,
+    pub content_type: Option<String>,
+    pub uploader: Option<String>,
+    /// From `<file>.meta.json`, when the upload has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash_algorithm: Option<HashAlgorithm>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at_unix: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expire_at_unix: Option<u64>,
+    /// Downloads so far, with `track_access`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_access_unix: Option<u64>,
+    /// Where the fields came from: `xattr`, `meta_json`, `sidecar` or `none`.
+    pub source: &'static str,
+}
+
+/// Original filename, content type and uploader recorded for an upload,
+/// and its downloads with `track_access`.
+///
+/// `<id>` is resolved as in `POST /uploads/<id>/alias`. Private and
+/// password-protected files are refused so the endpoint can't be used to
+/// learn who shared them.
+#[get("/uploads/{id:.*}/metadata")]
+pub async fn get_upload_metadata(
+    req: HttpRequest,
+    config: web::Data<config::Folio>,
+    metadata_store: web::Data<Arc<MetadataStore>>,
+    private_index: web::Data<Arc<PrivateIndexStore>>,
+    id: web::Path<String>,
+) -> Result<impl Responder, FolioError> {
+    let path = find_upload(&config, &id)?;
+    let is_private = private_index
+        .is_private(path.as_path())
+        .await
+        .map_err(|e| FolioError::store_error(e, "check private index"))?;
+    let sidecar = metadata_store
+        .get(path.as_path())
+        .await
+        .map_err(|e| FolioError::store_error(e, "read file metadata"))?
+        .unwrap_or_default();
+    if is_private || sidecar.password_hash.is_some() {
+        return Err(FolioError::Forbidden {
+            reason: format!("metadata of {} is not public", path),
+        });
+    }
+
+    let full_path = config.build_full_upload_path(path.as_path());
+    let (size_bytes, from_xattrs, meta) = web::block(move || {
+        std::fs::metadata(&full_path)
+            .map(|m| (m.len(), read_xattrs(&full_path), MetaFile::read(&full_path)))
+    })
+    .await
+    .map_err(|e| FolioError::store_error(e.to_string(), "read upload metadata"))?
+    .map_err(|e| FolioError::store_error(e.to_string(), "read upload metadata"))?;
+
+    // Downloads since the last flush are still only in memory.
+    let (access_count, last_access_unix) = match req.app_data::<web::Data<Arc<AccessTracker>>>() {
+        Some(tracker) => {
+            let pending = tracker.pending(path.as_path()).unwrap_or_default();
+            (
+                Some(sidecar.access_count + pending.count),
+                sidecar
+                    .last_access_unix
+                    .max(Some(pending.last_unix).filter(|&t| t > 0)),
+            )
+        }
+        None => (None, None),
+    };
+    let from_sidecar = UploadAttributes {
+        original_name: sidecar.original_name,
+        content_type: sidecar.content_type,
+        uploader: sidecar.uploader,
+    };
+    let from_meta = meta.clone().map(|m| UploadAttributes {
+        original_name: m.original_name,
+        content_type: m.content_type,
+        uploader: m.uploader,
+    });
+    let (attrs, source) = if !from_xattrs.is_empty() {
+        (from_xattrs, "xattr")
+    } else if let Some(from_meta) = from_meta.filter(|a| !a.is_empty()) {
+        (from_meta, "meta_json")
+    } else if !from_sidecar.is_empty() {
+        (from_sidecar, "sidecar")
+    } else {
+        (UploadAttributes::default(), "none")
+    };
+
+    Ok(HttpResponse::Ok().json(UploadMetadataResponse {
+        path: path.to_string(),
+        size_bytes,
+        original_name: attrs.original_name,
+        content_type: attrs.content_type,
+        uploader: attrs.uploader,
+        hash_algorithm: meta.as_ref().map(|m| m.hash_algorithm),
+        hash: meta.as_ref().map(|m| m.hash.clone()),
+        created_at_unix: meta.as_ref().map(|m| m.created_at_unix),
+        expire_at_unix: meta.and_then(|m| m.expire_at_unix),
+        access_count,
+        last_access_unix,
+        source,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{App, test as awtest};
+
+    fn attributes() -> UploadAttributes {
+        UploadAttributes {
+            original_name: Some("Q3 report.pdf".to_string()),
+            content_type: Some("application/pdf".to_string()),
+            uploader: Some("alice@example.com".to_string()),
+        }
+    }
+
+    #[actix_web::test]
+    async fn metadata_comes_from_xattrs_or_sidecar() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = config::Folio {
+            uploads_path: temp_dir
+                .path()
+                .join("uploads")
+                .to_string_lossy()
+                .to_string(),
+            data_path: temp_dir.path().join("data").to_string_lossy().to_string(),
+            ..config::Folio::default()
+        };
+        std::fs::create_dir_all(config.build_full_upload_path(Path::new(""))).unwrap();
+        let full_path = config.build_full_upload_path(Path::new("a1B2c3D4.pdf"));
+        std::fs::write(&full_path, "%PDF").unwrap();
+        let metadata_store = Arc::new(MetadataStore::new(&config));
+
+        record_upload_attributes(
+            &metadata_store,
+            &full_path,
+            Path::new("a1B2c3D4.pdf"),
+            attributes(),
+        )
+        .await;
+        // tmpfs and some container filesystems reject user xattrs; either
+        // way the recorded values must come back.
+        let expected_source = if read_xattrs(&full_path).is_empty() {
+            "sidecar"
+        } else {
+            "xattr"
+        };
+
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(metadata_store.clone()))
+                .app_data(web::Data::new(Arc::new(PrivateIndexStore::new(&config))))
+                .app_data(web::Data::new(config))
+                .service(get_upload_metadata),
+        )
+        .await;
+
+        let req = awtest::TestRequest::get()
+            .uri("/uploads/a1B2c3D4/metadata")
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = awtest::read_body_json(response).await;
+        assert_eq!(body["path"], "a1B2c3D4.pdf");
+        assert_eq!(body["size_bytes"], 4);
+        assert_eq!(body["original_name"], "Q3 report.pdf");
+        assert_eq!(body["uploader"], "alice@example.com");
+        assert_eq!(body["source"], expected_source);
+
+        metadata_store
+            .update(Path::new("a1B2c3D4.pdf"), |m| {
+                m.password_hash = Some("hash".to_string())
+            })
+            .await
+            .unwrap();
+        let req = awtest::TestRequest::get()
+            .uri("/uploads/a1B2c3D4/metadata")
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn xattrs_round_trip_when_supported() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("a.txt");
+        std::fs::write(&path, "a").unwrap();
+
+        match write_xattrs(&path, &attributes()) {
+            Ok(()) => assert_eq!(read_xattrs(&path), attributes()),
+            Err(_) => assert!(read_xattrs(&path).is_empty()),
+        }
+    }
+}
@@ -1,19 +1,54 @@
+use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 
 use actix_multipart::{Field, Multipart};
-use actix_web::http::StatusCode;
-use actix_web::{HttpResponse, Responder, post, web};
+use actix_web::guard::GuardContext;
+use actix_web::http::{StatusCode, header};
+use actix_web::middleware::from_fn;
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, post, web};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::format::StrftimeItems;
 use futures_util::StreamExt;
+use mime_guess::Mime;
 use rand::RngExt;
-use serde_json::json;
+use serde::{Deserialize, Serialize};
 use tokio::io::AsyncWriteExt;
 
+use super::alias::{AliasStore, find_upload};
 use super::config;
+use super::dir_count::DirEntryCounter;
+use super::durable::persist;
 use super::error::FolioError;
 use super::expiry::ExpiryStore;
+use super::files::{FileResponse, ensure_no_symlink_violation};
+use super::form_redirect;
+use super::hashing::HashAlgorithm;
+use super::idempotency::replay_idempotent;
+use super::images;
+use super::meta_file::{self, MetaFile};
+use super::metadata::{self, MetadataStore};
+use super::mime_pattern::MimePattern;
+use super::path::SafePath;
+use super::pending::{check_pending, pending_uploads};
 use super::private_index::PrivateIndexStore;
+use super::processing::{ProcessingStatus, deferred_tracker, verify_image_in_background};
+use super::public_url::public_url;
+use super::quota::enforce_quota;
+use super::staging::{stage_upload, staging_root};
+use super::upload_limit::limit_uploads;
+use super::upload_meta::{UploadAttributes, record_upload_attributes, uploader};
+
+/// Number of characters in a generated upload id.
+pub const UPLOAD_ID_LENGTH: usize = 8;
+
+/// Leading bytes of the stored file returned by `?echo=1`.
+const ECHO_HEAD_BYTES: usize = 256;
+
+/// Fallback TTL used when `default_expire` cannot be parsed.
+const FALLBACK_TTL: Duration = Duration::from_secs(168 * 3600);
 
 /// A _probably_ unique upload id.
 pub struct UploadId(String);
@@ -43,30 +78,638 @@ impl UploadId {
 #[derive(Default)]
 struct UploadParts {
     file_name: Option<String>,
+    content_type: Option<Mime>,
+    original_name: Option<String>,
+    authorized_emails: Option<String>,
+    ttl: Option<(Duration, bool)>,
+}
+
+/// A file already written under the uploads root, before its metadata and
+/// expiration are recorded.
+struct StoredUpload {
+    file_name: String,
+    /// Name and type the client sent, kept with `xattr_metadata`.
+    original_name: Option<String>,
+    content_type: Option<Mime>,
     authorized_emails: Option<String>,
+    image_check: ImageCheck,
+    /// From `upload_ttl`, resolved before the file was written.
+    ttl: Option<(Duration, bool)>,
+}
+
+/// What `verify_images` made of an upload before it was acknowledged.
+enum ImageCheck {
+    /// Not an image, or `verify_images` is off.
+    Skipped,
+    Verified((u32, u32)),
+    /// Left to the background with `async_processing`.
+    Deferred(image::ImageFormat),
 }
 
-#[derive(serde::Deserialize)]
+#[derive(Deserialize)]
 pub struct UploadQuery {
     expire: Option<String>,
+    /// Download password; stored only as an argon2 hash.
+    password: Option<String>,
+    /// Replaces the client-detected type for the extension and downloads.
+    content_type: Option<String>,
+    /// `1`/`true` deletes the file after its first download.
+    burn: Option<String>,
+    /// `1`/`true` describes the stored bytes in the response
+    /// (`debug_endpoints` only).
+    echo: Option<String>,
+    /// Subdirectory of the uploads root to store the file in.
+    dir: Option<String>,
+    /// `1`/`true` holds the file back until `POST /uploads/<id>/commit`.
+    staged: Option<String>,
+    /// Answer with `303 See Other` to this page instead of JSON.
+    redirect: Option<String>,
+}
+
+impl UploadQuery {
+    fn burn(&self) -> bool {
+        matches!(self.burn.as_deref(), Some("1" | "true"))
+    }
+
+    fn echo(&self) -> bool {
+        matches!(self.echo.as_deref(), Some("1" | "true"))
+    }
+
+    fn staged(&self) -> bool {
+        matches!(self.staged.as_deref(), Some("1" | "true"))
+    }
+
+    /// Refuse options that can't apply, before anything is stored: `?echo=`
+    /// unless `debug_endpoints` is on, `?expire=` on a staged upload, whose
+    /// expiration is set by its commit, `?staged=1` from a client with too
+    /// many unfinished uploads, and `?redirect=` to another site.
+    pub fn check_options(
+        &self,
+        req: &HttpRequest,
+        config: &config::Folio,
+    ) -> Result<(), FolioError> {
+        if self.echo() && !config.debug_endpoints {
+            return Err(FolioError::BadRequest {
+                reason: "echo requires debug_endpoints to be enabled".to_string(),
+            });
+        }
+        if self.staged() && self.expire.is_some() {
+            return Err(FolioError::BadRequest {
+                reason: "staged uploads take expire on commit".to_string(),
+            });
+        }
+        if self.staged() {
+            check_pending(req, config)?;
+        }
+        if let Some(target) = &self.redirect {
+            form_redirect::check_target(config, target)?;
+        }
+        Ok(())
+    }
+
+    /// `?dir=`, validated like a `/files` write path, symbolic links
+    /// included. Empty means the root.
+    fn upload_dir(&self, config: &config::Folio) -> Result<Option<SafePath>, FolioError> {
+        let Some(dir) = self
+            .dir
+            .as_deref()
+            .map(|dir| dir.trim_matches('/'))
+            .filter(|dir| !dir.is_empty())
+        else {
+            return Ok(None);
+        };
+        let dir = SafePath::from_user_input(Path::new(dir))?;
+        ensure_no_symlink_violation(config, &dir)?;
+        Ok(Some(dir))
+    }
+
+    fn content_type_override(&self) -> Result<Option<Mime>, FolioError> {
+        self.content_type
+            .as_deref()
+            .map(|ct| {
+                ct.parse::<Mime>().map_err(|_| FolioError::BadRequest {
+                    reason: format!("invalid content_type: {}", ct),
+                })
+            })
+            .transpose()
+    }
+}
+
+/// JSON body returned by both `/uploads` variants.
+#[derive(Debug, Serialize)]
+pub struct UploadResponse {
+    pub message: String,
+    pub id: String,
+    pub url: String,
+    pub expire_at_unix: u64,
+    /// Set when the requested TTL was shortened to `max_expire`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub expire_clamped: bool,
+    /// Image dimensions, reported when `verify_images` decoded the upload.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// `pending` while `async_processing` still checks the upload (the
+    /// response is then `202`); poll `GET /uploads/<id>/status` for the result.
+    pub status: ProcessingStatus,
+    /// What was stored, for `?echo=1`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub echo: Option<UploadEcho>,
+}
+
+/// The stored file as seen by the server, so client tests can check the
+/// round trip without downloading it.
+#[derive(Debug, Serialize)]
+pub struct UploadEcho {
+    pub size_bytes: u64,
+    pub hash_algorithm: HashAlgorithm,
+    pub hash: String,
+    /// First `ECHO_HEAD_BYTES` bytes of the file.
+    pub head_base64: String,
 }
 
-#[post("/uploads")]
+fn echo_file(path: &Path, hash_algorithm: HashAlgorithm) -> std::io::Result<UploadEcho> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = hash_algorithm.hasher();
+    let mut head = Vec::with_capacity(ECHO_HEAD_BYTES);
+    let mut size_bytes = 0u64;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        let take = (ECHO_HEAD_BYTES - head.len()).min(n);
+        head.extend_from_slice(&buf[..take]);
+        hasher.update(&buf[..n]);
+        size_bytes += n as u64;
+    }
+    Ok(UploadEcho {
+        size_bytes,
+        hash_algorithm,
+        hash: hasher.finalize_hex(),
+        head_base64: BASE64.encode(head),
+    })
+}
+
+/// `application/json` alternative to the multipart upload form.
+#[derive(Deserialize)]
+pub struct JsonUpload {
+    filename: Option<String>,
+    content_base64: String,
+    content_type: Option<String>,
+    authorized_emails: Option<String>,
+}
+
+#[post(
+    "/uploads",
+    wrap = "from_fn(enforce_quota)",
+    wrap = "from_fn(limit_uploads)",
+    wrap = "from_fn(replay_idempotent)"
+)]
+#[allow(clippy::too_many_arguments)]
 pub async fn upload_file(
+    req: HttpRequest,
     config: web::Data<config::Folio>,
     expiry_store: web::Data<Arc<ExpiryStore>>,
     private_store: web::Data<Arc<PrivateIndexStore>>,
+    metadata_store: web::Data<Arc<MetadataStore>>,
+    dir_counter: web::Data<Arc<DirEntryCounter>>,
     payload: Multipart,
     query: web::Query<UploadQuery>,
 ) -> Result<impl Responder, FolioError> {
+    ensure_writable(&config)?;
+    query.check_options(&req, &config)?;
+    let content_type = query.content_type_override()?;
+    let upload_dir = query.upload_dir(&config)?;
     let mut parts = UploadParts::default();
-    save_upload_payload(payload, &config, &mut parts).await?;
+    save_upload_payload(
+        payload,
+        &config,
+        &dir_counter,
+        content_type.as_ref(),
+        upload_dir.as_ref(),
+        &query,
+        &mut parts,
+    )
+    .await?;
     let file_name = parts.file_name.ok_or_else(|| FolioError::BadRequest {
-        reason: "multipart form is missing file field".to_string(),
+        reason: format!(
+            "multipart form is missing {} field",
+            config.upload_field_name
+        ),
+    })?;
+    let image_check = verify_image(
+        &req,
+        &config,
+        &file_name,
+        parts.content_type.as_ref(),
+        !query.staged(),
+    )
+    .await?;
+
+    finish_upload(
+        &req,
+        &config,
+        &expiry_store,
+        &private_store,
+        &metadata_store,
+        StoredUpload {
+            file_name,
+            original_name: parts.original_name,
+            content_type: parts.content_type,
+            authorized_emails: parts.authorized_emails,
+            image_check,
+            ttl: parts.ttl,
+        },
+        &query,
+    )
+    .await
+}
+
+/// Accept `{ "filename": "x.txt", "content_base64": "..." }` on `/uploads`
+/// for clients that cannot easily build multipart bodies.
+///
+/// Must be registered before `upload_file` so JSON requests are routed here.
+#[post(
+    "/uploads",
+    guard = "is_json",
+    wrap = "from_fn(enforce_quota)",
+    wrap = "from_fn(limit_uploads)",
+    wrap = "from_fn(replay_idempotent)"
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn upload_json(
+    req: HttpRequest,
+    config: web::Data<config::Folio>,
+    expiry_store: web::Data<Arc<ExpiryStore>>,
+    private_store: web::Data<Arc<PrivateIndexStore>>,
+    metadata_store: web::Data<Arc<MetadataStore>>,
+    dir_counter: web::Data<Arc<DirEntryCounter>>,
+    mut payload: web::Payload,
+    query: web::Query<UploadQuery>,
+) -> Result<impl Responder, FolioError> {
+    ensure_writable(&config)?;
+    query.check_options(&req, &config)?;
+    query.upload_dir(&config)?;
+    // Bound the raw body by the encoded size of the largest allowed file plus
+    // room for the other JSON fields, so oversize uploads never get buffered.
+    let largest = largest_upload_limit(&config);
+    let max_encoded = base64_encoded_len(largest);
+    let max_body = max_encoded.saturating_add(64 * 1024);
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| FolioError::BadRequest {
+            reason: format!("invalid request body: {}", e),
+        })?;
+        if body.len() + chunk.len() > max_body {
+            return Err(json_too_large(largest));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    let upload: JsonUpload = serde_json::from_slice(&body).map_err(|e| FolioError::BadRequest {
+        reason: format!("invalid json upload: {}", e),
     })?;
+    upload_filename(&config, upload.filename.as_deref())?;
+
+    if upload.content_base64.len() > max_encoded {
+        return Err(json_too_large(largest));
+    }
+
+    let content =
+        BASE64
+            .decode(upload.content_base64.trim())
+            .map_err(|e| FolioError::BadRequest {
+                reason: format!("invalid base64 content: {}", e),
+            })?;
+    if content.len() > largest {
+        return Err(json_too_large(largest));
+    }
+    if content.len() < config.min_upload_bytes {
+        return Err(too_small(content.len(), config.min_upload_bytes));
+    }
+
+    let source = UploadSource {
+        filename: upload.filename,
+        content_type: upload.content_type,
+        authorized_emails: upload.authorized_emails,
+    };
+    store_upload(
+        &req,
+        &config,
+        &expiry_store,
+        &private_store,
+        &metadata_store,
+        &dir_counter,
+        &query,
+        source,
+        content.len(),
+        async |full_path| save_bytes_to_path(&content, full_path).await,
+    )
+    .await
+}
+
+/// What the client said about an upload whose bytes arrive outside a
+/// multipart form: the JSON body, or the parts joined by
+/// `POST /uploads/<id>/complete`.
+pub struct UploadSource {
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub authorized_emails: Option<String>,
+}
+
+/// Name and store an upload of `size` bytes described by `source`, as
+/// `POST /uploads` does: `write` puts the bytes at the chosen path, then the
+/// file is checked and finished like any other upload.
+#[allow(clippy::too_many_arguments)]
+pub async fn store_upload(
+    req: &HttpRequest,
+    config: &config::Folio,
+    expiry_store: &ExpiryStore,
+    private_store: &PrivateIndexStore,
+    metadata_store: &Arc<MetadataStore>,
+    dir_counter: &DirEntryCounter,
+    query: &UploadQuery,
+    source: UploadSource,
+    size: usize,
+    write: impl AsyncFnOnce(&Path) -> Result<(), FolioError>,
+) -> Result<HttpResponse, FolioError> {
+    let upload_dir = query.upload_dir(config)?;
+    let filename = upload_filename(config, source.filename.as_deref())?;
+    let content_type_override = query.content_type_override()?;
+    let content_type = match (&content_type_override, &source.content_type) {
+        (Some(mime), _) => Some(mime.clone()),
+        (None, Some(ct)) => Some(ct.parse::<Mime>().map_err(|_| FolioError::BadRequest {
+            reason: format!("invalid content_type: {}", ct),
+        })?),
+        (None, None) => filename.and_then(|name| mime_guess::from_path(name).first()),
+    };
+    check_content_type_allowed(content_type.as_ref(), config, upload_dir.as_ref())?;
+    let max_size = upload_size_limit(config, content_type.as_ref());
+    if size > max_size {
+        return Err(too_large(size, max_size));
+    }
+
+    let filename_ext = filename_extension(filename);
+    let extension = match &content_type_override {
+        Some(mime) => override_extension(mime, filename_ext),
+        None => choose_extension(content_type_extension(content_type.as_ref()), filename_ext),
+    };
+    check_extension_allowed(config, extension.as_deref())?;
+    let file_name = generate_upload_name(config, upload_dir.as_ref(), extension.as_deref())?;
+    let full_path = config.build_full_upload_path(&PathBuf::from(&file_name));
+    let dir = full_path.parent().unwrap_or(&full_path);
+    dir_counter.ensure_capacity(dir, config.max_files_per_dir)?;
+    let ttl = upload_ttl(config, query, &file_name)?;
+    write(&full_path).await?;
+    if let Err(e) = persist(config, &full_path).await {
+        let _ = tokio::fs::remove_file(&full_path).await;
+        return Err(e);
+    }
+    dir_counter.record_created(dir);
+    let image_check = verify_image(
+        req,
+        config,
+        &file_name,
+        content_type.as_ref(),
+        !query.staged(),
+    )
+    .await?;
+
+    finish_upload(
+        req,
+        config,
+        expiry_store,
+        private_store,
+        metadata_store,
+        StoredUpload {
+            file_name,
+            original_name: source.filename,
+            content_type,
+            authorized_emails: source.authorized_emails,
+            image_check,
+            ttl,
+        },
+        query,
+    )
+    .await
+}
+
+/// Delete an upload by `<id>`, resolved as in `POST /uploads/<id>/alias`:
+/// a path, or the generated id without its extension. Its expiration,
+/// metadata, private index entry and aliases go with it.
+#[delete("/uploads/{id:.*}")]
+pub async fn delete_upload(
+    config: web::Data<config::Folio>,
+    expiry_store: web::Data<Arc<ExpiryStore>>,
+    metadata_store: web::Data<Arc<MetadataStore>>,
+    private_store: web::Data<Arc<PrivateIndexStore>>,
+    alias_store: web::Data<Arc<AliasStore>>,
+    id: web::Path<String>,
+) -> Result<impl Responder, FolioError> {
+    ensure_writable(&config)?;
+    let path = find_upload(&config, &id)?;
+    let full_path = config.build_full_upload_path(path.as_path());
+
+    let freed_bytes = std::fs::metadata(&full_path)
+        .map_err(|e| FolioError::store_error(e.to_string(), "read upload size"))?
+        .len();
+    match std::fs::remove_file(&full_path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(FolioError::NotFound {
+                path: id.to_string(),
+            });
+        }
+        Err(e) => return Err(FolioError::store_error(e.to_string(), "delete upload")),
+    }
+    meta_file::remove(&full_path);
+    log::info!("deleted upload {} ({} bytes freed)", path, freed_bytes);
+
+    if let Err(e) = expiry_store.cancel(&full_path).await {
+        log::error!("failed to cancel expiration of {}: {}", path, e);
+    }
+    if let Err(e) = metadata_store.remove(path.as_path()).await {
+        log::error!("failed to drop metadata for {}: {}", path, e);
+    }
+    if let Err(e) = private_store.remove(path.as_path()).await {
+        log::error!("failed to drop private index entry for {}: {}", path, e);
+    }
+    if let Err(e) = alias_store.remove_path(path.as_path()).await {
+        log::error!("failed to drop aliases of {}: {}", path, e);
+    }
+
+    Ok(HttpResponse::Ok().json(FileResponse {
+        path: Some(path.to_string()),
+        freed_bytes: Some(freed_bytes),
+        ..FileResponse::new("file deleted successfully")
+    }))
+}
+
+fn is_json(ctx: &GuardContext) -> bool {
+    ctx.header::<header::ContentType>()
+        .is_some_and(|ct| ct.0.essence_str() == "application/json")
+}
+
+fn base64_encoded_len(len: usize) -> usize {
+    len.div_ceil(3).saturating_mul(4)
+}
+
+/// `max_upload_size`, or the most specific `max_upload_bytes_by_type`
+/// entry matching `content_type` (`image/png` before `image/*` before
+/// `*/*`). Entries were validated at startup; invalid ones never match.
+pub fn upload_size_limit(config: &config::Folio, content_type: Option<&Mime>) -> usize {
+    let Some(content_type) = content_type else {
+        return config.max_upload_size;
+    };
+    config
+        .max_upload_bytes_by_type
+        .iter()
+        .filter_map(|(pattern, limit)| {
+            let pattern = pattern.parse::<MimePattern>().ok()?;
+            pattern
+                .matches(content_type)
+                .then_some((pattern.specificity(), *limit))
+        })
+        .max_by_key(|(specificity, _)| *specificity)
+        .map_or(config.max_upload_size, |(_, limit)| limit)
+}
+
+/// The highest size limit of any content type, for bounding request bodies
+/// before the type of the file they carry is known.
+pub fn largest_upload_limit(config: &config::Folio) -> usize {
+    config
+        .max_upload_bytes_by_type
+        .values()
+        .copied()
+        .fold(config.max_upload_size, usize::max)
+}
+
+/// `413` for a file of `size` bytes over the `limit` for its type.
+pub fn too_large(size: usize, limit: usize) -> FolioError {
+    let message = format!(
+        "file too large: {} bytes exceeds {} byte limit",
+        size, limit
+    );
+    log::error!("POST /uploads error: {}", message);
+    FolioError::PayloadTooLarge { reason: message }
+}
+
+fn json_too_large(max_size: usize) -> FolioError {
+    let message = format!("file too large: exceeds {} byte limit", max_size);
+    log::error!("POST /uploads error: {}", message);
+    FolioError::PayloadTooLarge { reason: message }
+}
+
+/// With `verify_images`, decode an uploaded image and return its dimensions.
+///
+/// Files that claim an image type but fail to decode are deleted and
+/// rejected with `422`. Non-image and undecodable-by-design types (e.g. SVG)
+/// are skipped. With `async_processing` the decode is deferred until after
+/// the response.
+/// With `allow_deferred` false the image is always checked before answering,
+/// e.g. for staged uploads, which are moved out of reach of the background
+/// check.
+async fn verify_image(
+    req: &HttpRequest,
+    config: &config::Folio,
+    file_name: &str,
+    content_type: Option<&Mime>,
+    allow_deferred: bool,
+) -> Result<ImageCheck, FolioError> {
+    if !config.verify_images {
+        return Ok(ImageCheck::Skipped);
+    }
+    let content_type = content_type
+        .cloned()
+        .or_else(|| mime_guess::from_path(file_name).first());
+    let Some(format) = content_type.as_ref().and_then(images::readable_format) else {
+        return Ok(ImageCheck::Skipped);
+    };
+    if allow_deferred && deferred_tracker(req, config).is_some() {
+        return Ok(ImageCheck::Deferred(format));
+    }
+
+    let full_path = config.build_full_upload_path(&PathBuf::from(file_name));
+    let decode_path = full_path.clone();
+    let decoded = web::block(move || images::decode_dimensions(&decode_path, format))
+        .await
+        .map_err(|e| FolioError::store_error(e.to_string(), "decode image"))?;
+
+    match decoded {
+        Ok(dimensions) => Ok(ImageCheck::Verified(dimensions)),
+        Err(e) => {
+            log::warn!(
+                "POST /uploads rejected undecodable image {}: {}",
+                file_name,
+                e
+            );
+            if let Err(e) = tokio::fs::remove_file(&full_path).await {
+                log::error!("failed to remove rejected image {}: {}", file_name, e);
+            }
+            Err(FolioError::UnprocessableEntity {
+                reason: format!("invalid image: {}", e),
+            })
+        }
+    }
+}
+
+/// Mark private, set the download password, schedule expiration, and build
+/// the response for an upload that has already been stored.
+async fn finish_upload(
+    req: &HttpRequest,
+    config: &config::Folio,
+    expiry_store: &ExpiryStore,
+    private_store: &PrivateIndexStore,
+    metadata_store: &Arc<MetadataStore>,
+    upload: StoredUpload,
+    query: &UploadQuery,
+) -> Result<HttpResponse, FolioError> {
+    let StoredUpload {
+        file_name,
+        original_name,
+        content_type,
+        authorized_emails,
+        image_check,
+        ttl,
+    } = upload;
     let full_path = config.build_full_upload_path(&PathBuf::from(&file_name));
+    // Without an extension the stored type is all downloads have to go by.
+    let recorded_type = query.content_type.clone().or_else(|| {
+        (!config.preserve_extension)
+            .then(|| {
+                content_type.clone().or_else(|| {
+                    original_name
+                        .as_deref()
+                        .and_then(|name| mime_guess::from_path(name).first())
+                })
+            })
+            .flatten()
+            .map(|mime| mime.to_string())
+    });
+
+    let attrs = if config.xattr_metadata || config.meta_json_files {
+        UploadAttributes {
+            original_name,
+            content_type: content_type.map(|mime| mime.to_string()),
+            uploader: uploader(req).await,
+        }
+    } else {
+        UploadAttributes::default()
+    };
+    if config.xattr_metadata {
+        record_upload_attributes(
+            metadata_store,
+            &full_path,
+            Path::new(&file_name),
+            attrs.clone(),
+        )
+        .await;
+    }
 
-    if let Some(emails_str) = &parts.authorized_emails {
+    if let Some(emails_str) = authorized_emails {
         let emails: Vec<String> = emails_str
             .split(',')
             .map(|s| s.trim().to_string())
@@ -88,12 +731,66 @@ pub async fn upload_file(
         }
     }
 
-    let ttl = match query.expire.as_deref() {
-        Some(s) => parse_duration(s).unwrap_or(Duration::from_secs(168 * 3600)),
-        None => Duration::from_secs(168 * 3600),
+    if let Some(password) = query.password.clone().filter(|p| !p.is_empty()) {
+        let password_hash = web::block(move || metadata::hash_password(&password))
+            .await
+            .map_err(|e| FolioError::store_error(e.to_string(), "hash password"))?
+            .map_err(|e| FolioError::store_error(e, "hash password"))?;
+        metadata_store
+            .update(&PathBuf::from(&file_name), |m| {
+                m.password_hash = Some(password_hash)
+            })
+            .await
+            .map_err(|e| FolioError::store_error(e, "save file metadata"))?;
+    }
+
+    if let Some(content_type) = recorded_type {
+        metadata_store
+            .update(&PathBuf::from(&file_name), |m| {
+                m.content_type = Some(content_type)
+            })
+            .await
+            .map_err(|e| FolioError::store_error(e, "save file metadata"))?;
+    }
+
+    if query.burn() {
+        metadata_store
+            .update(&PathBuf::from(&file_name), |m| m.burn = true)
+            .await
+            .map_err(|e| FolioError::store_error(e, "save file metadata"))?;
+    }
+
+    let echo = if query.echo() {
+        let (path, hash_algorithm) = (full_path.clone(), config.hash_algorithm);
+        let echo = web::block(move || echo_file(&path, hash_algorithm))
+            .await
+            .map_err(|e| FolioError::store_error(e.to_string(), "echo upload"))?
+            .map_err(|e| FolioError::store_error(e.to_string(), "echo upload"))?;
+        Some(echo)
+    } else {
+        None
     };
 
-    expiry_store.schedule(&full_path, ttl).await.map_err(|e| {
+    let base_name = file_name.rsplit('/').next().unwrap_or(&file_name);
+    let id = base_name
+        .split_once('.')
+        .map_or(base_name, |(id, _)| id)
+        .to_string();
+
+    if query.staged() {
+        if config.meta_json_files {
+            write_meta_file(config, &full_path, attrs, None).await?;
+        }
+        if let Some((pending, client)) = pending_uploads(req, config) {
+            pending.record(client, staging_root(config).join(&file_name));
+        }
+        return stage_upload(config, expiry_store, &file_name, id).await;
+    }
+
+    // Only staged uploads come without a TTL, and they returned above.
+    let (ttl, expire_clamped) = ttl.unwrap_or_else(|| (default_ttl(config), false));
+
+    let expire_at_unix = expiry_store.schedule(&full_path, ttl).await.map_err(|e| {
         let message = format!("failed to schedule expiration for {}: {}", file_name, e);
         log::error!("POST /uploads error: {}", message);
         FolioError::Internal {
@@ -102,14 +799,86 @@ pub async fn upload_file(
         }
     })?;
 
-    Ok(HttpResponse::build(StatusCode::CREATED)
-        .append_header(("Location", format!("/files/{}", file_name)))
-        .json(json!({ "message": "file uploaded successfully" })))
+    if config.meta_json_files {
+        write_meta_file(config, &full_path, attrs, Some(expire_at_unix)).await?;
+    }
+
+    let url = public_url(req, config, &format!("/files/{}", file_name));
+
+    let mut status = match config.upload_success_status {
+        config::UploadSuccessStatus::Created => StatusCode::CREATED,
+        config::UploadSuccessStatus::Ok => StatusCode::OK,
+    };
+    let mut processing = ProcessingStatus::Ready;
+    let dimensions = match image_check {
+        ImageCheck::Skipped => None,
+        ImageCheck::Verified(dimensions) => Some(dimensions),
+        ImageCheck::Deferred(format) => {
+            if let Some(tracker) = deferred_tracker(req, config) {
+                verify_image_in_background(
+                    tracker,
+                    metadata_store.clone(),
+                    full_path,
+                    file_name,
+                    format,
+                );
+                status = StatusCode::ACCEPTED;
+                processing = ProcessingStatus::Pending;
+            }
+            None
+        }
+    };
+
+    if query.redirect.is_some() || form_redirect::wants_html(req) {
+        return Ok(form_redirect::see_other(query.redirect.as_deref(), &url));
+    }
+
+    Ok(HttpResponse::build(status)
+        .append_header(("Location", url.clone()))
+        .json(UploadResponse {
+            message: "file uploaded successfully".to_string(),
+            id,
+            url,
+            expire_at_unix,
+            expire_clamped,
+            width: dimensions.map(|(w, _)| w),
+            height: dimensions.map(|(_, h)| h),
+            status: processing,
+            echo,
+        }))
+}
+
+/// Write `<file>.meta.json` for a finished upload.
+async fn write_meta_file(
+    config: &config::Folio,
+    full_path: &Path,
+    attrs: UploadAttributes,
+    expire_at_unix: Option<u64>,
+) -> Result<(), FolioError> {
+    let full_path = full_path.to_path_buf();
+    let hash_algorithm = config.hash_algorithm;
+    web::block(move || {
+        let meta = MetaFile {
+            original_name: attrs.original_name,
+            content_type: attrs.content_type,
+            uploader: attrs.uploader,
+            expire_at_unix,
+            ..MetaFile::describe(&full_path, hash_algorithm)?
+        };
+        meta.write(&full_path)
+    })
+    .await
+    .map_err(|e| FolioError::store_error(e.to_string(), "write meta file"))?
+    .map_err(|e| FolioError::store_error(e.to_string(), "write meta file"))
 }
 
 async fn save_upload_payload(
     mut payload: Multipart,
     config: &config::Folio,
+    dir_counter: &DirEntryCounter,
+    content_type_override: Option<&Mime>,
+    upload_dir: Option<&SafePath>,
+    query: &UploadQuery,
     parts: &mut UploadParts,
 ) -> Result<(), FolioError> {
     while let Some(field) = payload.next().await {
@@ -118,26 +887,33 @@ async fn save_upload_payload(
         })?;
 
         match field.name() {
-            Some("file") => {
-                let filename_extension = filename_extension(&field);
-                let content_type_extension = content_type_extension(&field);
-                log::info!(
-                    "Upload extension check: content-type-ext={:?}, filename-ext={:?}",
-                    content_type_extension,
-                    filename_extension
-                );
-                let extension = match (content_type_extension, filename_extension) {
-                    (Some(ext), _) if ext == "bin" => None,
-                    (_, Some(ext)) if !ext.is_empty() => Some(ext),
-                    (Some(ext), None) => Some(ext),
-                    _ => None,
+            Some(name) if name == config.upload_field_name => {
+                let content_type = content_type_override.or(field.content_type()).cloned();
+                let content_type = content_type.as_ref();
+                check_content_type_allowed(content_type, config, upload_dir)?;
+                let original_name = field
+                    .content_disposition()
+                    .and_then(|cd| cd.get_filename())
+                    .map(str::to_string);
+                let filename_ext =
+                    filename_extension(upload_filename(config, original_name.as_deref())?);
+                let extension = match content_type_override {
+                    Some(mime) => override_extension(mime, filename_ext),
+                    None => choose_extension(content_type_extension(content_type), filename_ext),
                 };
+                check_extension_allowed(config, extension.as_deref())?;
 
-                let id = generate_unique_upload_id(config, extension.as_deref())?;
-                let file_name = id.file_name(extension.as_deref());
+                let file_name = generate_upload_name(config, upload_dir, extension.as_deref())?;
                 let full_path = config.build_full_upload_path(&PathBuf::from(&file_name));
-                save_field_to_path(&mut field, &full_path, config.max_upload_size).await?;
+                let dir = full_path.parent().unwrap_or(&full_path);
+                dir_counter.ensure_capacity(dir, config.max_files_per_dir)?;
+                parts.ttl = upload_ttl(config, query, &file_name)?;
+                let max_size = upload_size_limit(config, content_type);
+                save_field_to_path(&mut field, &full_path, config, max_size).await?;
+                dir_counter.record_created(dir);
                 parts.file_name = Some(file_name);
+                parts.content_type = content_type.cloned();
+                parts.original_name = original_name;
             }
             Some("authorized_emails") => {
                 parts.authorized_emails = Some(read_text_field(&mut field).await?);
@@ -149,38 +925,334 @@ async fn save_upload_payload(
     Ok(())
 }
 
-fn filename_extension(field: &Field) -> Option<String> {
-    field
-        .content_disposition()
-        .and_then(|cd| cd.get_filename())
-        .and_then(|filename| {
-            PathBuf::from(filename)
-                .extension()
-                .map(|os| os.to_string_lossy().to_string())
+/// Reject writes with `403` while `read_only` is set.
+pub fn ensure_writable(config: &config::Folio) -> Result<(), FolioError> {
+    if config.read_only {
+        return Err(FolioError::Forbidden {
+            reason: "server is read-only: uploads, changes and deletions are disabled".to_string(),
+        });
+    }
+    Ok(())
+}
+
+pub fn default_ttl(config: &config::Folio) -> Duration {
+    parse_duration(&config.default_expire).unwrap_or_else(|e| {
+        log::warn!(
+            "invalid default_expire '{}': {}, falling back to 168h",
+            config.default_expire,
+            e
+        );
+        FALLBACK_TTL
+    })
+}
+
+/// The `expire` an upload asked for: the explicit query value, else the one
+/// configured for its extension in `expire_by_extension`, else the
+/// `default_expire` of the named root it went to. `None` means the global
+/// `default_expire`.
+pub fn requested_expire<'a>(
+    config: &'a config::Folio,
+    query_expire: Option<&'a str>,
+    file_name: &str,
+) -> Option<&'a str> {
+    if query_expire.is_some() {
+        return query_expire;
+    }
+
+    let path = Path::new(file_name);
+    let by_extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(|extension| {
+            config
+                .expire_by_extension
+                .iter()
+                .find(|(ext, _)| ext.trim_start_matches('.').eq_ignore_ascii_case(extension))
+                .map(|(_, expire)| expire.as_str())
+        });
+    by_extension.or_else(|| {
+        config
+            .root_config(path.parent()?)
+            .and_then(|root| root.default_expire.as_deref())
+    })
+}
+
+/// TTL an upload stored as `file_name` will be scheduled with, resolved
+/// before anything is written so a rejected `expire` leaves nothing behind.
+/// Staged uploads get theirs on commit.
+fn upload_ttl(
+    config: &config::Folio,
+    query: &UploadQuery,
+    file_name: &str,
+) -> Result<Option<(Duration, bool)>, FolioError> {
+    if query.staged() {
+        return Ok(None);
+    }
+    let requested = requested_expire(config, query.expire.as_deref(), file_name);
+    resolve_ttl(config, requested).map(Some)
+}
+
+/// Server-side TTL cap from `max_expire`, if one is configured.
+pub fn max_ttl(config: &config::Folio) -> Option<Duration> {
+    if config.max_expire.is_empty() {
+        return None;
+    }
+    parse_duration(&config.max_expire)
+        .inspect_err(|e| {
+            log::warn!(
+                "invalid max_expire '{}': {}, TTLs are not capped",
+                config.max_expire,
+                e
+            )
         })
+        .ok()
+}
+
+/// Turn a requested `expire` into the TTL to schedule, applying `max_expire`.
+///
+/// Returns whether the TTL was clamped. Unparseable values fall back to
+/// `default_expire` as before, except `never`, which counts as over the cap.
+pub fn resolve_ttl(
+    config: &config::Folio,
+    requested: Option<&str>,
+) -> Result<(Duration, bool), FolioError> {
+    let max = max_ttl(config);
+    let ttl = match requested {
+        Some("never") if max.is_some() => Duration::MAX,
+        Some(s) => parse_duration(s).unwrap_or_else(|_| default_ttl(config)),
+        None => default_ttl(config),
+    };
+
+    match max {
+        Some(max) if ttl > max => match config.expire_cap_mode {
+            config::ExpireCapMode::Clamp => Ok((max, true)),
+            config::ExpireCapMode::Reject => Err(FolioError::BadRequest {
+                reason: format!(
+                    "expire '{}' exceeds max_expire {}",
+                    requested.unwrap_or(&config.default_expire),
+                    config.max_expire
+                ),
+            }),
+        },
+        _ => Ok((ttl, false)),
+    }
+}
+
+/// `415` unless `content_type` is in the `allowed_content_types` for
+/// uploads into `upload_dir` (the uploads root when `None`).
+pub fn check_content_type_allowed(
+    content_type: Option<&Mime>,
+    config: &config::Folio,
+    upload_dir: Option<&SafePath>,
+) -> Result<(), FolioError> {
+    let allowed_content_types =
+        config.allowed_content_types_in(upload_dir.map_or(Path::new(""), SafePath::as_path));
+    if allowed_content_types.is_empty() {
+        return Ok(());
+    }
+
+    let content_type = content_type
+        .cloned()
+        .unwrap_or(mime_guess::mime::APPLICATION_OCTET_STREAM);
+
+    // Validated at startup, so nothing is skipped here in practice.
+    if allowed_content_types
+        .iter()
+        .filter_map(|allowed| allowed.parse::<MimePattern>().ok())
+        .any(|allowed| allowed.matches(&content_type))
+    {
+        Ok(())
+    } else {
+        let content_type = content_type.essence_str().to_string();
+        log::warn!("POST /uploads rejected content type: {}", content_type);
+        Err(FolioError::UnsupportedMediaType { content_type })
+    }
+}
+
+/// Check a stored file's extension against `allowed_extensions` and
+/// `blocked_extensions`, case-insensitively. A blocked extension wins over an
+/// allowed one.
+pub fn check_extension_allowed(
+    config: &config::Folio,
+    extension: Option<&str>,
+) -> Result<(), FolioError> {
+    let extension = extension.unwrap_or_default().to_ascii_lowercase();
+    let listed = |list: &[String]| {
+        list.iter()
+            .any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(&extension))
+    };
+    let blocked = !extension.is_empty() && listed(&config.blocked_extensions);
+    let not_allowed = !config.allowed_extensions.is_empty() && !listed(&config.allowed_extensions);
+    if blocked || not_allowed {
+        log::warn!("rejected file extension: {:?}", extension);
+        return Err(FolioError::ExtensionNotAllowed { extension });
+    }
+    Ok(())
+}
+
+fn choose_extension(
+    content_type_extension: Option<String>,
+    filename_extension: Option<String>,
+) -> Option<String> {
+    log::info!(
+        "Upload extension check: content-type-ext={:?}, filename-ext={:?}",
+        content_type_extension,
+        filename_extension
+    );
+    match (content_type_extension, filename_extension) {
+        (Some(ext), _) if ext == "bin" => None,
+        (_, Some(ext)) if !ext.is_empty() => Some(ext),
+        (Some(ext), None) => Some(ext),
+        _ => None,
+    }
+}
+
+/// Extension for an explicit `?content_type=`: keep the filename's extension
+/// if it already maps to that type, otherwise derive one from the type.
+fn override_extension(content_type: &Mime, filename_extension: Option<String>) -> Option<String> {
+    match filename_extension {
+        Some(ext)
+            if mime_guess::from_ext(&ext)
+                .iter()
+                .any(|m| m.essence_str() == content_type.essence_str()) =>
+        {
+            Some(ext)
+        }
+        _ => content_type_extension(Some(content_type)).filter(|ext| ext != "bin"),
+    }
+}
+
+/// Name an upload's extension is taken from: the one the client sent, else
+/// `default_filename`. With `require_filename`, a missing name is `400`.
+///
+/// Only the client's own name is kept as the original name.
+fn upload_filename<'a>(
+    config: &'a config::Folio,
+    sent: Option<&'a str>,
+) -> Result<Option<&'a str>, FolioError> {
+    if let Some(name) = sent.filter(|name| !name.is_empty()) {
+        return Ok(Some(name));
+    }
+    if config.require_filename {
+        return Err(FolioError::BadRequest {
+            reason: "upload has no filename".to_string(),
+        });
+    }
+    Ok(Some(config.default_filename.as_str()).filter(|name| !name.is_empty()))
+}
+
+fn filename_extension(filename: Option<&str>) -> Option<String> {
+    filename.and_then(|filename| {
+        PathBuf::from(filename)
+            .extension()
+            .map(|os| os.to_string_lossy().to_string())
+    })
 }
 
-fn content_type_extension(field: &Field) -> Option<String> {
-    field
-        .content_type()
+fn content_type_extension(content_type: Option<&Mime>) -> Option<String> {
+    content_type
         .and_then(|mime| {
             mime_guess::get_mime_extensions(mime).and_then(|exts| exts.first().copied())
         })
         .map(str::to_string)
 }
 
-fn generate_unique_upload_id(
+/// Directory prefix for new uploads from `date_layout` (UTC), e.g.
+/// `2024/06/15` for `%Y/%m/%d`. `None` when the layout is unset.
+fn date_prefix(config: &config::Folio) -> Result<Option<PathBuf>, FolioError> {
+    if config.date_layout.is_empty() {
+        return Ok(None);
+    }
+
+    let invalid = |reason: String| FolioError::Internal {
+        source: reason,
+        context: Some(format!("apply date_layout '{}'", config.date_layout)),
+    };
+    let items = StrftimeItems::new(&config.date_layout)
+        .parse()
+        .map_err(|e| invalid(e.to_string()))?;
+    let formatted = chrono::Utc::now()
+        .format_with_items(items.iter())
+        .to_string();
+    let prefix =
+        SafePath::from_user_input(Path::new(&formatted)).map_err(|e| invalid(e.to_string()))?;
+
+    Ok(Some(prefix.as_path().to_path_buf()))
+}
+
+/// Apply `max_extension_length` to the extension picked for an upload.
+fn limit_extension<'a>(
+    config: &config::Folio,
+    extension: Option<&'a str>,
+) -> Result<Option<&'a str>, FolioError> {
+    let Some(ext) = extension else {
+        return Ok(None);
+    };
+    let Some((cut, _)) = ext.char_indices().nth(config.max_extension_length) else {
+        return Ok(Some(ext));
+    };
+    match config.long_extension_mode {
+        config::LongExtensionMode::Truncate => {
+            log::warn!(
+                "truncating {}-character upload extension to {}",
+                ext.chars().count(),
+                config.max_extension_length
+            );
+            Ok(Some(&ext[..cut]).filter(|e| !e.is_empty()))
+        }
+        config::LongExtensionMode::Reject => Err(FolioError::UnprocessableEntity {
+            reason: format!(
+                "file extension too long: {} characters exceeds {} character limit",
+                ext.chars().count(),
+                config.max_extension_length
+            ),
+        }),
+    }
+}
+
+/// `upload_id_prefix`, once checked to be usable in a file name and an id.
+fn upload_id_prefix(config: &config::Folio) -> Result<&str, FolioError> {
+    let prefix = config.upload_id_prefix.as_str();
+    let valid = prefix
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(FolioError::Internal {
+            source: "upload_id_prefix may only contain A-Z, a-z, 0-9, '-' and '_'".to_string(),
+            context: Some(format!("apply upload_id_prefix '{}'", prefix)),
+        });
+    }
+    Ok(prefix)
+}
+
+/// Pick an unused upload name (relative to the uploads root), placed under
+/// `dir` and then the `date_layout` directory when those are given. Without
+/// `preserve_extension` the name has no extension.
+fn generate_upload_name(
     config: &config::Folio,
+    dir: Option<&SafePath>,
     extension: Option<&str>,
-) -> Result<UploadId, FolioError> {
+) -> Result<String, FolioError> {
+    let extension = if config.preserve_extension {
+        limit_extension(config, extension)?
+    } else {
+        None
+    };
+    let prefix = date_prefix(config)?;
+    let id_prefix = upload_id_prefix(config)?;
     let mut attempts = 0u32;
     loop {
-        let candidate = UploadId::new(8);
-        let file_name = candidate.file_name(extension);
-        let path = config.build_full_upload_path(&PathBuf::from(&file_name));
+        let candidate = UploadId::new(UPLOAD_ID_LENGTH);
+        let name = format!("{}{}", id_prefix, candidate.file_name(extension));
+        let mut relative = dir.map(|d| d.as_path().to_path_buf()).unwrap_or_default();
+        if let Some(prefix) = &prefix {
+            relative.push(prefix);
+        }
+        relative.push(name);
+        let path = config.build_full_upload_path(&relative);
 
         if !path.exists() {
-            return Ok(candidate);
+            return Ok(relative.to_string_lossy().to_string());
         }
 
         attempts += 1;
@@ -196,24 +1268,18 @@ fn generate_unique_upload_id(
 async fn save_field_to_path(
     field: &mut Field,
     full_path: &Path,
+    config: &config::Folio,
     max_size: usize,
 ) -> Result<(), FolioError> {
     if let Some(parent) = full_path.parent() {
         tokio::fs::create_dir_all(parent)
             .await
-            .map_err(|e| FolioError::Internal {
-                source: format!("failed to create upload directory: {}", e),
-                context: Some(format!("create directories for: {}", full_path.display())),
-            })?;
+            .map_err(|e| FolioError::write_error(&e, full_path, "create upload directory"))?;
     }
 
     let mut output = tokio::fs::File::create(full_path).await.map_err(|e| {
-        let message = format!("failed to save file: {}", e);
-        log::error!("POST /uploads error: {}", message);
-        FolioError::Internal {
-            source: message,
-            context: None,
-        }
+        log::error!("POST /uploads error: failed to save file: {}", e);
+        FolioError::write_error(&e, full_path, "save file")
     })?;
 
     let mut bytes_written: usize = 0;
@@ -232,34 +1298,67 @@ async fn save_field_to_path(
             let _ = tokio::fs::remove_file(full_path).await;
             return Err(FolioError::PayloadTooLarge { reason: message });
         }
-        output.write_all(&data).await.map_err(|e| {
-            let message = format!("failed to save file: {}", e);
-            log::error!("POST /uploads error: {}", message);
-            FolioError::Internal {
-                source: message,
-                context: None,
-            }
-        })?;
+        if let Err(e) = output.write_all(&data).await {
+            log::error!("POST /uploads error: failed to save file: {}", e);
+            drop(output);
+            let _ = tokio::fs::remove_file(full_path).await;
+            return Err(FolioError::write_error(&e, full_path, "save file"));
+        }
     }
 
-    output.flush().await.map_err(|e| {
-        let message = format!("failed to flush file: {}", e);
-        log::error!("POST /uploads error: {}", message);
-        FolioError::Internal {
-            source: message,
-            context: Some(format!("flush upload file: {}", full_path.display())),
-        }
-    })?;
+    if let Err(e) = output.flush().await {
+        log::error!("POST /uploads error: failed to flush file: {}", e);
+        drop(output);
+        let _ = tokio::fs::remove_file(full_path).await;
+        return Err(FolioError::write_error(&e, full_path, "flush upload file"));
+    }
+
+    if bytes_written < config.min_upload_bytes {
+        drop(output);
+        let _ = tokio::fs::remove_file(full_path).await;
+        return Err(too_small(bytes_written, config.min_upload_bytes));
+    }
 
+    drop(output);
+    if let Err(e) = persist(config, full_path).await {
+        let _ = tokio::fs::remove_file(full_path).await;
+        return Err(e);
+    }
     Ok(())
 }
 
-async fn read_text_field(field: &mut Field) -> Result<String, FolioError> {
-    let mut value = Vec::new();
-    while let Some(chunk) = field.next().await {
-        let data = chunk.map_err(|e| FolioError::BadRequest {
-            reason: format!("invalid multipart text field: {}", e),
-        })?;
+/// `400` for a file below `min_upload_bytes`, e.g. a form submitted without
+/// selecting a file.
+pub fn too_small(size: usize, min_size: usize) -> FolioError {
+    let message = format!(
+        "file too small: {} bytes is below the {} byte minimum",
+        size, min_size
+    );
+    log::warn!("upload rejected: {}", message);
+    FolioError::BadRequest { reason: message }
+}
+
+async fn save_bytes_to_path(content: &[u8], full_path: &Path) -> Result<(), FolioError> {
+    if let Some(parent) = full_path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| FolioError::write_error(&e, full_path, "create upload directory"))?;
+    }
+
+    if let Err(e) = tokio::fs::write(full_path, content).await {
+        log::error!("POST /uploads error: failed to save file: {}", e);
+        let _ = tokio::fs::remove_file(full_path).await;
+        return Err(FolioError::write_error(&e, full_path, "save file"));
+    }
+    Ok(())
+}
+
+async fn read_text_field(field: &mut Field) -> Result<String, FolioError> {
+    let mut value = Vec::new();
+    while let Some(chunk) = field.next().await {
+        let data = chunk.map_err(|e| FolioError::BadRequest {
+            reason: format!("invalid multipart text field: {}", e),
+        })?;
         value.extend_from_slice(&data);
     }
 
@@ -277,7 +1376,7 @@ async fn drain_field(field: &mut Field) -> Result<(), FolioError> {
     Ok(())
 }
 
-fn parse_duration(s: &str) -> Result<Duration, String> {
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
     const MAX_VALUE: u64 = 10_000_000;
 
     let len = s.len();
@@ -308,7 +1407,148 @@ fn parse_duration(s: &str) -> Result<Duration, String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::processing::{ProcessingTracker, get_upload_status};
+    use crate::upload_meta::read_xattrs;
     use actix_web::{App, http::header, test as awtest};
+    use std::collections::BTreeMap;
+
+    mod resolve_ttl {
+        use super::*;
+
+        fn capped(mode: config::ExpireCapMode) -> config::Folio {
+            config::Folio {
+                max_expire: "1d".to_string(),
+                expire_cap_mode: mode,
+                ..config::Folio::default()
+            }
+        }
+
+        #[test]
+        fn uncapped_by_default() {
+            let config = config::Folio::default();
+            let (ttl, clamped) = resolve_ttl(&config, Some("365d")).unwrap();
+
+            assert_eq!(ttl, Duration::from_secs(365 * 86_400));
+            assert!(!clamped);
+        }
+
+        #[test]
+        fn clamps_long_and_never() {
+            let config = capped(config::ExpireCapMode::Clamp);
+
+            assert_eq!(
+                resolve_ttl(&config, Some("1h")).unwrap(),
+                (Duration::from_secs(3_600), false)
+            );
+            assert_eq!(
+                resolve_ttl(&config, Some("30d")).unwrap(),
+                (Duration::from_secs(86_400), true)
+            );
+            assert_eq!(
+                resolve_ttl(&config, Some("never")).unwrap(),
+                (Duration::from_secs(86_400), true)
+            );
+            // default_expire (168h) is capped too
+            assert_eq!(
+                resolve_ttl(&config, None).unwrap(),
+                (Duration::from_secs(86_400), true)
+            );
+        }
+
+        #[test]
+        fn rejects_long_in_reject_mode() {
+            let config = capped(config::ExpireCapMode::Reject);
+
+            assert!(resolve_ttl(&config, Some("1h")).is_ok());
+            let err = resolve_ttl(&config, Some("30d")).unwrap_err();
+            assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+            assert!(resolve_ttl(&config, Some("never")).is_err());
+        }
+    }
+
+    mod requested_expire {
+        use super::*;
+
+        fn config() -> config::Folio {
+            config::Folio {
+                default_expire: "2d".to_string(),
+                expire_by_extension: BTreeMap::from([
+                    ("log".to_string(), "7d".to_string()),
+                    (".tmp".to_string(), "1h".to_string()),
+                ]),
+                ..config::Folio::default()
+            }
+        }
+
+        fn ttl(config: &config::Folio, query: Option<&str>, file_name: &str) -> Duration {
+            resolve_ttl(config, requested_expire(config, query, file_name))
+                .unwrap()
+                .0
+        }
+
+        #[test]
+        fn explicit_query_wins() {
+            assert_eq!(
+                ttl(&config(), Some("5m"), "a1B2c3D4.log"),
+                Duration::from_secs(300)
+            );
+        }
+
+        #[test]
+        fn extension_beats_default() {
+            let config = config();
+
+            assert_eq!(
+                ttl(&config, None, "a1B2c3D4.log"),
+                Duration::from_secs(7 * 86_400)
+            );
+            assert_eq!(
+                ttl(&config, None, "2024/06/15/a1B2c3D4.TMP"),
+                Duration::from_secs(3_600)
+            );
+        }
+
+        #[test]
+        fn falls_back_to_default() {
+            let config = config();
+
+            assert_eq!(
+                ttl(&config, None, "a1B2c3D4.png"),
+                Duration::from_secs(2 * 86_400)
+            );
+            assert_eq!(
+                ttl(&config, None, "a1B2c3D4"),
+                Duration::from_secs(2 * 86_400)
+            );
+        }
+
+        #[test]
+        fn named_root_replaces_default() {
+            let config = config::Folio {
+                roots: BTreeMap::from([(
+                    "team-a".to_string(),
+                    config::RootConfig {
+                        default_expire: Some("1h".to_string()),
+                        ..config::RootConfig::default()
+                    },
+                )]),
+                ..config()
+            };
+
+            assert_eq!(
+                ttl(&config, None, "team-a/2024/a1B2c3D4.png"),
+                Duration::from_secs(3_600)
+            );
+            assert_eq!(
+                ttl(&config, None, "team-a/a1B2c3D4.log"),
+                Duration::from_secs(7 * 86_400)
+            );
+            assert_eq!(
+                ttl(&config, None, "team-b/a1B2c3D4.png"),
+                Duration::from_secs(2 * 86_400)
+            );
+        }
+    }
 
     mod upload_id {
         use super::*;
@@ -378,6 +1618,13 @@ mod tests {
         (config, expiry_store, private_store, temp_dir)
     }
 
+    fn test_metadata_store(temp_dir: &tempfile::TempDir) -> Arc<MetadataStore> {
+        Arc::new(MetadataStore::new(&config::Folio {
+            data_path: temp_dir.path().to_string_lossy().to_string(),
+            ..config::Folio::default()
+        }))
+    }
+
     fn multipart_body(filename: &str, content_type: Option<&str>, content: &str) -> String {
         let content_type_header = content_type
             .map(|ct| format!("Content-Type: {}\r\n", ct))
@@ -402,6 +1649,8 @@ mod tests {
                 .app_data(web::Data::new(config))
                 .app_data(web::Data::new(expiry_store))
                 .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
                 .service(upload_file),
         )
         .await;
@@ -443,6 +1692,8 @@ mod tests {
                 .app_data(web::Data::new(config))
                 .app_data(web::Data::new(expiry_store))
                 .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
                 .service(upload_file),
         )
         .await;
@@ -480,6 +1731,8 @@ mod tests {
                 .app_data(web::Data::new(config))
                 .app_data(web::Data::new(expiry_store))
                 .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
                 .service(upload_file),
         )
         .await;
@@ -514,4 +1767,1276 @@ mod tests {
         assert!(raw.contains("bob@example.com"));
         assert!(raw.contains("alice@example.com"));
     }
+
+    #[actix_web::test]
+    async fn rejected_expire_leaves_nothing_behind() {
+        let (mut config, expiry_store, private_store, temp_dir) = test_state();
+        config.max_expire = "1d".to_string();
+        config.expire_cap_mode = config::ExpireCapMode::Reject;
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .service(upload_json)
+                .service(upload_file),
+        )
+        .await;
+
+        let mut body = multipart_body("test.txt", Some("text/plain"), "content");
+        body = body.replace(
+            "--X-BOUNDARY--\r\n",
+            "--X-BOUNDARY\r\nContent-Disposition: form-data; name=\"authorized_emails\"\r\n\r\nbob@example.com\r\n--X-BOUNDARY--\r\n",
+        );
+        let multipart = awtest::TestRequest::post()
+            .uri("/uploads?expire=30d&password=hunter2")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(body)
+            .to_request();
+        // No `expire`: the 168h `default_expire` is over the cap too.
+        let json = awtest::TestRequest::post()
+            .uri("/uploads?burn=1")
+            .set_json(serde_json::json!({
+                "filename": "notes.txt",
+                "content_base64": BASE64.encode("hello json"),
+            }))
+            .to_request();
+
+        for req in [multipart, json] {
+            let response = awtest::call_service(&app, req).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+        let left: Vec<_> = std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .filter_map(Result::ok)
+            .map(|entry| entry.file_name())
+            .collect();
+        assert!(left.is_empty(), "{:?}", left);
+    }
+
+    #[actix_web::test]
+    async fn rejects_content_type_not_in_allowlist() {
+        let (mut config, expiry_store, private_store, temp_dir) = test_state();
+        config.allowed_content_types = vec!["image/png".to_string()];
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .service(upload_file),
+        )
+        .await;
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("test.txt", Some("text/plain"), "content"))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let stored = std::fs::read_dir(temp_dir.path()).unwrap().count();
+        assert_eq!(stored, 0);
+    }
+
+    #[actix_web::test]
+    async fn named_root_replaces_content_type_allowlist() {
+        let (mut config, expiry_store, private_store, temp_dir) = test_state();
+        config.allowed_content_types = vec!["image/png".to_string()];
+        config.roots = BTreeMap::from([(
+            "docs".to_string(),
+            config::RootConfig {
+                allowed_content_types: Some(vec!["text/*".to_string()]),
+                ..config::RootConfig::default()
+            },
+        )]);
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .service(upload_file),
+        )
+        .await;
+        let upload = |uri: &str, content_type: &str| {
+            awtest::TestRequest::post()
+                .uri(uri)
+                .insert_header((
+                    header::CONTENT_TYPE,
+                    "multipart/form-data; boundary=X-BOUNDARY",
+                ))
+                .set_payload(multipart_body("file", Some(content_type), "content"))
+                .to_request()
+        };
+
+        let response = awtest::call_service(&app, upload("/uploads?dir=docs", "text/plain")).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = awtest::call_service(&app, upload("/uploads?dir=docs", "image/png")).await;
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let response = awtest::call_service(&app, upload("/uploads", "text/plain")).await;
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[actix_web::test]
+    async fn rejects_blocked_extension_whatever_the_content_type() {
+        let (mut config, expiry_store, private_store, temp_dir) = test_state();
+        config.blocked_extensions = vec![".PHP".to_string()];
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .service(upload_file),
+        )
+        .await;
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("shell.php", Some("image/png"), "<?php"))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let body: serde_json::Value = awtest::read_body_json(response).await;
+        assert_eq!(body["code"], "EXTENSION_NOT_ALLOWED");
+        let stored = std::fs::read_dir(temp_dir.path()).unwrap().count();
+        assert_eq!(stored, 0);
+    }
+
+    #[test]
+    fn extension_allowlist_rejects_missing_extensions() {
+        let config = config::Folio {
+            allowed_extensions: vec!["png".to_string(), "jpg".to_string()],
+            blocked_extensions: vec!["jpg".to_string()],
+            ..config::Folio::default()
+        };
+        assert!(check_extension_allowed(&config, Some("PNG")).is_ok());
+        assert!(check_extension_allowed(&config, Some("jpg")).is_err());
+        assert!(check_extension_allowed(&config, Some("txt")).is_err());
+        assert!(check_extension_allowed(&config, None).is_err());
+        assert!(check_extension_allowed(&config::Folio::default(), None).is_ok());
+    }
+
+    #[actix_web::test]
+    async fn rejects_upload_when_directory_is_full() {
+        let (mut config, expiry_store, private_store, temp_dir) = test_state();
+        config.max_files_per_dir = 1;
+        std::fs::write(temp_dir.path().join("existing.txt"), "content").unwrap();
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .service(upload_file),
+        )
+        .await;
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("test.txt", Some("text/plain"), "content"))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[actix_web::test]
+    async fn json_base64_upload() {
+        let (config, expiry_store, private_store, temp_dir) = test_state();
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .service(upload_json)
+                .service(upload_file),
+        )
+        .await;
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads?expire=1h")
+            .set_json(serde_json::json!({
+                "filename": "notes.txt",
+                "content_base64": BASE64.encode("hello json"),
+            }))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body: serde_json::Value = awtest::read_body_json(response).await;
+        let url = body["url"].as_str().unwrap();
+        assert!(url.ends_with(".txt"));
+        assert_eq!(body["id"].as_str().unwrap().len(), UPLOAD_ID_LENGTH);
+
+        let filename = url.strip_prefix("/files/").unwrap();
+        let content = std::fs::read_to_string(temp_dir.path().join(filename)).unwrap();
+        assert_eq!(content, "hello json");
+    }
+
+    #[actix_web::test]
+    async fn upload_with_password_stores_hash() {
+        let (config, expiry_store, private_store, temp_dir) = test_state();
+        let metadata_store = test_metadata_store(&temp_dir);
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(metadata_store.clone()))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .service(upload_json),
+        )
+        .await;
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads?password=hunter2")
+            .set_json(serde_json::json!({ "content_base64": BASE64.encode("x") }))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body: serde_json::Value = awtest::read_body_json(response).await;
+        let file_name = body["url"]
+            .as_str()
+            .unwrap()
+            .strip_prefix("/files/")
+            .unwrap();
+        let entry = metadata_store
+            .get(Path::new(file_name))
+            .await
+            .unwrap()
+            .unwrap();
+        let hash = entry.password_hash.unwrap();
+        assert!(metadata::verify_password("hunter2", &hash));
+        let raw = std::fs::read_to_string(temp_dir.path().join("file-metadata.json")).unwrap();
+        assert!(!raw.contains("hunter2"));
+    }
+
+    #[actix_web::test]
+    async fn upload_with_burn_marks_file_one_time() {
+        let (config, expiry_store, private_store, temp_dir) = test_state();
+        let metadata_store = test_metadata_store(&temp_dir);
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(metadata_store.clone()))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .service(upload_json),
+        )
+        .await;
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads?burn=1")
+            .set_json(serde_json::json!({ "content_base64": BASE64.encode("x") }))
+            .to_request();
+        let body: serde_json::Value = awtest::call_and_read_body_json(&app, req).await;
+
+        let file_name = body["url"]
+            .as_str()
+            .unwrap()
+            .strip_prefix("/files/")
+            .unwrap();
+        let entry = metadata_store
+            .get(Path::new(file_name))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(entry.burn);
+    }
+
+    #[actix_web::test]
+    async fn upload_dir_places_file_in_subdirectory() {
+        let (config, expiry_store, private_store, temp_dir) = test_state();
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .service(upload_file),
+        )
+        .await;
+        let upload = |dir: &str| {
+            awtest::TestRequest::post()
+                .uri(&format!("/uploads?dir={}", dir))
+                .insert_header((
+                    header::CONTENT_TYPE,
+                    "multipart/form-data; boundary=X-BOUNDARY",
+                ))
+                .set_payload(multipart_body("report.txt", Some("text/plain"), "content"))
+                .to_request()
+        };
+
+        let response = awtest::call_service(&app, upload("reports/2024/")).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body: serde_json::Value = awtest::read_body_json(response).await;
+        let file_name = body["url"]
+            .as_str()
+            .unwrap()
+            .strip_prefix("/files/reports/2024/")
+            .unwrap();
+        assert!(file_name.ends_with(".txt") && !file_name.contains('/'));
+        assert!(
+            temp_dir
+                .path()
+                .join("reports/2024")
+                .join(file_name)
+                .is_file()
+        );
+
+        let response = awtest::call_service(&app, upload("reports/../..")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[cfg(unix)]
+    #[actix_web::test]
+    async fn upload_routes_apply_follow_symlinks() {
+        for follow_symlinks in [false, true] {
+            let (config, expiry_store, private_store, temp_dir) = test_state();
+            let outside = tempfile::tempdir().unwrap();
+            std::fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+            std::fs::create_dir(temp_dir.path().join("real-dir")).unwrap();
+            std::fs::write(temp_dir.path().join("real-dir/x.txt"), "x").unwrap();
+            let link = |target: &Path, name: &str| {
+                std::os::unix::fs::symlink(target, temp_dir.path().join(name)).unwrap()
+            };
+            link(&temp_dir.path().join("real-dir"), "linked-dir");
+            link(outside.path(), "escape");
+
+            let config = config::Folio {
+                follow_symlinks,
+                ..config
+            };
+            let app = awtest::init_service(
+                App::new()
+                    .app_data(web::Data::new(Arc::new(AliasStore::new(&config))))
+                    .app_data(web::Data::new(config))
+                    .app_data(web::Data::new(expiry_store))
+                    .app_data(web::Data::new(private_store))
+                    .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                    .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                    .service(upload_file)
+                    .service(delete_upload),
+            )
+            .await;
+            let upload = |dir: &str| {
+                awtest::TestRequest::post()
+                    .uri(&format!("/uploads?dir={}", dir))
+                    .insert_header((
+                        header::CONTENT_TYPE,
+                        "multipart/form-data; boundary=X-BOUNDARY",
+                    ))
+                    .set_payload(multipart_body("report.txt", Some("text/plain"), "content"))
+                    .to_request()
+            };
+            let delete = |id: &str| {
+                awtest::TestRequest::delete()
+                    .uri(&format!("/uploads/{}", id))
+                    .to_request()
+            };
+
+            let response = awtest::call_service(&app, upload("linked-dir")).await;
+            let expected = if follow_symlinks {
+                StatusCode::CREATED
+            } else {
+                StatusCode::BAD_REQUEST
+            };
+            assert_eq!(response.status(), expected);
+            let response = awtest::call_service(&app, upload("escape")).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+            let response = awtest::call_service(&app, delete("linked-dir/x.txt")).await;
+            let expected = if follow_symlinks {
+                StatusCode::OK
+            } else {
+                StatusCode::NOT_FOUND
+            };
+            assert_eq!(response.status(), expected);
+            let response = awtest::call_service(&app, delete("escape/secret.txt")).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+            assert!(outside.path().join("secret.txt").is_file());
+            let uploaded = std::fs::read_dir(outside.path()).unwrap().count();
+            assert_eq!(uploaded, 1);
+        }
+    }
+
+    #[actix_web::test]
+    async fn content_type_override_sets_extension_and_metadata() {
+        let (config, expiry_store, private_store, temp_dir) = test_state();
+        let metadata_store = test_metadata_store(&temp_dir);
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(metadata_store.clone()))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .service(upload_file),
+        )
+        .await;
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads?content_type=text/csv")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body(
+                "data.bin",
+                Some("application/octet-stream"),
+                "a,b\n1,2\n",
+            ))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body: serde_json::Value = awtest::read_body_json(response).await;
+        let file_name = body["url"]
+            .as_str()
+            .unwrap()
+            .strip_prefix("/files/")
+            .unwrap();
+        assert!(file_name.ends_with(".csv"));
+        let entry = metadata_store
+            .get(Path::new(file_name))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.content_type.as_deref(), Some("text/csv"));
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads?content_type=not%20a%20type")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("data.bin", None, "x"))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn preserve_extension_controls_stored_names() {
+        for preserve_extension in [true, false] {
+            let (mut config, expiry_store, private_store, temp_dir) = test_state();
+            config.preserve_extension = preserve_extension;
+            let metadata_store = test_metadata_store(&temp_dir);
+            let app = awtest::init_service(
+                App::new()
+                    .app_data(web::Data::new(config))
+                    .app_data(web::Data::new(expiry_store))
+                    .app_data(web::Data::new(private_store.clone()))
+                    .app_data(web::Data::new(metadata_store.clone()))
+                    .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                    .app_data(web::Data::new(Arc::new(
+                        crate::auth::AccessAuth::from_parts(
+                            "https://issuer.example.com",
+                            "folio-app",
+                            None,
+                        ),
+                    )))
+                    .service(upload_file)
+                    .service(crate::files::get_file),
+            )
+            .await;
+
+            let req = awtest::TestRequest::post()
+                .uri("/uploads")
+                .insert_header((
+                    header::CONTENT_TYPE,
+                    "multipart/form-data; boundary=X-BOUNDARY",
+                ))
+                .set_payload(multipart_body(
+                    "report.pdf",
+                    Some("application/pdf"),
+                    "%PDF",
+                ))
+                .to_request();
+            let response = awtest::call_service(&app, req).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let body: serde_json::Value = awtest::read_body_json(response).await;
+            let url = body["url"].as_str().unwrap();
+            let file_name = url.strip_prefix("/files/").unwrap();
+            let recorded = metadata_store
+                .get(Path::new(file_name))
+                .await
+                .unwrap()
+                .and_then(|m| m.content_type);
+            if preserve_extension {
+                assert!(file_name.ends_with(".pdf"));
+                assert_eq!(recorded, None);
+            } else {
+                assert!(!file_name.contains('.'));
+                assert_eq!(body["id"], file_name);
+                assert_eq!(recorded.as_deref(), Some("application/pdf"));
+            }
+
+            let response =
+                awtest::call_service(&app, awtest::TestRequest::get().uri(url).to_request()).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers().get(header::CONTENT_TYPE).unwrap(),
+                "application/pdf"
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn accepts_configured_upload_field_name() {
+        let (mut config, expiry_store, private_store, temp_dir) = test_state();
+        config.upload_field_name = "upload".to_string();
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .service(upload_file),
+        )
+        .await;
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(
+                multipart_body("a.txt", Some("text/plain"), "hello")
+                    .replace("name=\"file\"", "name=\"upload\""),
+            )
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("a.txt", Some("text/plain"), "hello"))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn browser_uploads_are_redirected() {
+        let (config, expiry_store, private_store, temp_dir) = test_state();
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .service(upload_file),
+        )
+        .await;
+        let upload = |uri: &str, accept: &str| {
+            awtest::TestRequest::post()
+                .uri(uri)
+                .insert_header((header::ACCEPT, accept.to_string()))
+                .insert_header((
+                    header::CONTENT_TYPE,
+                    "multipart/form-data; boundary=X-BOUNDARY",
+                ))
+                .set_payload(multipart_body("a.txt", Some("text/plain"), "hello"))
+                .to_request()
+        };
+        let location = |response: &actix_web::dev::ServiceResponse| {
+            let location = response.headers().get(header::LOCATION).unwrap();
+            location.to_str().unwrap().to_string()
+        };
+
+        let response = awtest::call_service(&app, upload("/uploads?redirect=/thanks", "*/*")).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let file_url = location(&response)
+            .strip_prefix("/thanks?url=")
+            .unwrap()
+            .to_string();
+        assert!(file_url.starts_with("/files/"));
+
+        let response = awtest::call_service(&app, upload("/uploads", "text/html,*/*")).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert!(location(&response).starts_with("/files/"));
+
+        let response = awtest::call_service(&app, upload("/uploads", "application/json")).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let before = std::fs::read_dir(temp_dir.path()).unwrap().count();
+        let response =
+            awtest::call_service(&app, upload("/uploads?redirect=//evil.example", "*/*")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), before);
+    }
+
+    #[actix_web::test]
+    async fn echo_only_with_debug_endpoints() {
+        for debug_endpoints in [false, true] {
+            let (mut config, expiry_store, private_store, temp_dir) = test_state();
+            config.debug_endpoints = debug_endpoints;
+            let app = awtest::init_service(
+                App::new()
+                    .app_data(web::Data::new(config))
+                    .app_data(web::Data::new(expiry_store))
+                    .app_data(web::Data::new(private_store))
+                    .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                    .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                    .service(upload_file),
+            )
+            .await;
+
+            let req = awtest::TestRequest::post()
+                .uri("/uploads?echo=1")
+                .insert_header((
+                    header::CONTENT_TYPE,
+                    "multipart/form-data; boundary=X-BOUNDARY",
+                ))
+                .set_payload(multipart_body("a.txt", Some("text/plain"), "hello"))
+                .to_request();
+            let response = awtest::call_service(&app, req).await;
+
+            if !debug_endpoints {
+                assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+                continue;
+            }
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let body: serde_json::Value = awtest::read_body_json(response).await;
+            assert_eq!(body["echo"]["size_bytes"], 5);
+            assert_eq!(body["echo"]["hash_algorithm"], "sha256");
+            assert_eq!(
+                body["echo"]["hash"],
+                "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+            );
+            assert_eq!(body["echo"]["head_base64"], "aGVsbG8=");
+        }
+    }
+
+    #[actix_web::test]
+    async fn long_extensions_rejected_or_truncated() {
+        for (mode, expected) in [
+            (
+                config::LongExtensionMode::Reject,
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+            (config::LongExtensionMode::Truncate, StatusCode::CREATED),
+        ] {
+            let (mut config, expiry_store, private_store, temp_dir) = test_state();
+            config.max_extension_length = 4;
+            config.long_extension_mode = mode;
+            let app = awtest::init_service(
+                App::new()
+                    .app_data(web::Data::new(config))
+                    .app_data(web::Data::new(expiry_store))
+                    .app_data(web::Data::new(private_store))
+                    .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                    .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                    .service(upload_json),
+            )
+            .await;
+
+            let req = awtest::TestRequest::post()
+                .uri("/uploads")
+                .set_json(serde_json::json!({
+                    "filename": "a.averyveryverylongextension",
+                    "content_base64": BASE64.encode("hello"),
+                }))
+                .to_request();
+            let response = awtest::call_service(&app, req).await;
+            assert_eq!(response.status(), expected);
+            if expected == StatusCode::CREATED {
+                let body: serde_json::Value = awtest::read_body_json(response).await;
+                assert!(body["url"].as_str().unwrap().ends_with(".aver"));
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn zero_byte_upload_rejected_only_with_min_upload_bytes() {
+        for (min_upload_bytes, expected) in [(0, StatusCode::CREATED), (1, StatusCode::BAD_REQUEST)]
+        {
+            let (mut config, expiry_store, private_store, temp_dir) = test_state();
+            config.min_upload_bytes = min_upload_bytes;
+            let app = awtest::init_service(
+                App::new()
+                    .app_data(web::Data::new(config))
+                    .app_data(web::Data::new(expiry_store))
+                    .app_data(web::Data::new(private_store))
+                    .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                    .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                    .service(upload_file),
+            )
+            .await;
+
+            let req = awtest::TestRequest::post()
+                .uri("/uploads")
+                .insert_header((
+                    header::CONTENT_TYPE,
+                    "multipart/form-data; boundary=X-BOUNDARY",
+                ))
+                .set_payload(multipart_body("empty.txt", Some("text/plain"), ""))
+                .to_request();
+            let response = awtest::call_service(&app, req).await;
+            assert_eq!(response.status(), expected);
+
+            let stored = std::fs::read_dir(temp_dir.path())
+                .unwrap()
+                .filter_map(Result::ok)
+                .filter(|e| e.path().extension().is_some_and(|ext| ext == "txt"))
+                .count();
+            assert_eq!(stored, usize::from(expected == StatusCode::CREATED));
+        }
+    }
+
+    #[actix_web::test]
+    async fn xattr_metadata_records_original_name() {
+        let (mut config, expiry_store, private_store, temp_dir) = test_state();
+        config.xattr_metadata = true;
+        let metadata_store = test_metadata_store(&temp_dir);
+        let uploads_root = config.build_full_upload_path(Path::new(""));
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(metadata_store.clone()))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .service(upload_file),
+        )
+        .await;
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("notes.txt", Some("text/plain"), "hello"))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body: serde_json::Value = awtest::read_body_json(response).await;
+        let file_name = body["url"].as_str().unwrap().trim_start_matches("/files/");
+
+        let from_xattrs = read_xattrs(&uploads_root.join(file_name));
+        let original_name = match from_xattrs.original_name {
+            Some(name) => {
+                assert_eq!(from_xattrs.content_type.as_deref(), Some("text/plain"));
+                Some(name)
+            }
+            None => metadata_store
+                .get(Path::new(file_name))
+                .await
+                .unwrap()
+                .and_then(|m| m.original_name),
+        };
+        assert_eq!(original_name.as_deref(), Some("notes.txt"));
+    }
+
+    #[actix_web::test]
+    async fn verify_images_reports_dimensions_and_rejects_corrupt() {
+        let (mut config, expiry_store, private_store, temp_dir) = test_state();
+        config.verify_images = true;
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .service(upload_json),
+        )
+        .await;
+
+        let mut png = std::io::Cursor::new(Vec::new());
+        image::RgbImage::new(4, 3)
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        let req = awtest::TestRequest::post()
+            .uri("/uploads")
+            .set_json(serde_json::json!({
+                "filename": "pixel.png",
+                "content_base64": BASE64.encode(png.into_inner()),
+            }))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body: serde_json::Value = awtest::read_body_json(response).await;
+        assert_eq!(body["width"], 4);
+        assert_eq!(body["height"], 3);
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads")
+            .set_json(serde_json::json!({
+                "filename": "broken.png",
+                "content_base64": BASE64.encode("not really a png"),
+            }))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let leftovers = std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter(|e| e.path().extension().is_some_and(|ext| ext == "png"))
+            .count();
+        assert_eq!(leftovers, 1);
+    }
+
+    #[actix_web::test]
+    async fn async_processing_rejects_corrupt_image_after_accepting() {
+        let (mut config, expiry_store, private_store, temp_dir) = test_state();
+        config.verify_images = true;
+        config.async_processing = true;
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(Arc::new(ProcessingTracker::default())))
+                .service(upload_json)
+                .service(get_upload_status),
+        )
+        .await;
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads")
+            .set_json(serde_json::json!({
+                "filename": "broken.png",
+                "content_base64": BASE64.encode("not really a png"),
+            }))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let body: serde_json::Value = awtest::read_body_json(response).await;
+        assert_eq!(body["status"], "pending");
+        let id = body["id"].as_str().unwrap().to_string();
+
+        let mut status = serde_json::Value::Null;
+        for _ in 0..100 {
+            let req = awtest::TestRequest::get()
+                .uri(&format!("/uploads/{}/status", id))
+                .to_request();
+            status = awtest::call_and_read_body_json(&app, req).await;
+            if status["status"] != "pending" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(status["status"], "rejected");
+        assert!(
+            status["detail"]
+                .as_str()
+                .unwrap()
+                .starts_with("invalid image")
+        );
+        assert!(!temp_dir.path().join(format!("{}.png", id)).exists());
+    }
+
+    #[actix_web::test]
+    async fn rejects_upload_when_limiter_is_saturated() {
+        let (config, expiry_store, private_store, temp_dir) = test_state();
+        let limiter = Arc::new(crate::upload_limit::UploadLimiter::new(&config::Folio {
+            max_concurrent_uploads: 1,
+            ..config::Folio::default()
+        }));
+        let _busy = limiter.try_acquire().unwrap();
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .app_data(web::Data::new(limiter.clone()))
+                .service(upload_json),
+        )
+        .await;
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads")
+            .set_json(serde_json::json!({ "content_base64": BASE64.encode("x") }))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get("Retry-After").unwrap(), "5");
+        assert_eq!(limiter.in_flight(), 1);
+        let body: serde_json::Value = awtest::read_body_json(response).await;
+        assert_eq!(body["code"], "RATE_LIMITED");
+        assert_eq!(body["retry_after"], 5);
+    }
+
+    #[actix_web::test]
+    async fn date_layout_places_upload_in_dated_directory() {
+        let (mut config, expiry_store, private_store, temp_dir) = test_state();
+        config.date_layout = "%Y/%m/%d".to_string();
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .service(upload_json),
+        )
+        .await;
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads")
+            .set_json(serde_json::json!({
+                "filename": "a.txt",
+                "content_base64": BASE64.encode("dated"),
+            }))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let today = chrono::Utc::now().format("%Y/%m/%d").to_string();
+        let location = response
+            .headers()
+            .get("Location")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(location.starts_with(&format!("/files/{}/", today)));
+        let body: serde_json::Value = awtest::read_body_json(response).await;
+        assert_eq!(body["id"].as_str().unwrap().len(), UPLOAD_ID_LENGTH);
+
+        let relative = location.strip_prefix("/files/").unwrap();
+        let content = std::fs::read_to_string(temp_dir.path().join(relative)).unwrap();
+        assert_eq!(content, "dated");
+        let raw = std::fs::read_to_string(temp_dir.path().join("expiry-index.json")).unwrap();
+        assert!(raw.contains(&today));
+    }
+
+    #[test]
+    fn date_prefix_rejects_invalid_layouts() {
+        let config = config::Folio {
+            date_layout: "%Q".to_string(),
+            ..config::Folio::default()
+        };
+        assert!(date_prefix(&config).is_err());
+
+        let config = config::Folio {
+            date_layout: "../%Y".to_string(),
+            ..config::Folio::default()
+        };
+        assert!(date_prefix(&config).is_err());
+    }
+
+    #[actix_web::test]
+    async fn upload_without_filename_or_content_type() {
+        let body = "--X-BOUNDARY\r\n\
+                    Content-Disposition: form-data; name=\"file\"\r\n\
+                    \r\n\
+                    hello\r\n\
+                    --X-BOUNDARY--\r\n";
+        let cases = [
+            (false, "", StatusCode::CREATED, Some(UPLOAD_ID_LENGTH)),
+            (
+                false,
+                "upload.bin",
+                StatusCode::CREATED,
+                Some(UPLOAD_ID_LENGTH + 4),
+            ),
+            (true, "upload.bin", StatusCode::BAD_REQUEST, None),
+        ];
+        for (require_filename, default_filename, status, name_length) in cases {
+            let (mut config, expiry_store, private_store, temp_dir) = test_state();
+            config.require_filename = require_filename;
+            config.default_filename = default_filename.to_string();
+            let app = awtest::init_service(
+                App::new()
+                    .app_data(web::Data::new(config))
+                    .app_data(web::Data::new(expiry_store))
+                    .app_data(web::Data::new(private_store))
+                    .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                    .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                    .service(upload_file),
+            )
+            .await;
+
+            let req = awtest::TestRequest::post()
+                .uri("/uploads")
+                .insert_header((
+                    header::CONTENT_TYPE,
+                    "multipart/form-data; boundary=X-BOUNDARY",
+                ))
+                .set_payload(body)
+                .to_request();
+            let response = awtest::call_service(&app, req).await;
+            assert_eq!(response.status(), status, "{:?}", default_filename);
+            if let Some(length) = name_length {
+                let body: serde_json::Value = awtest::read_body_json(response).await;
+                let name = body["url"].as_str().unwrap().trim_start_matches("/files/");
+                assert_eq!(name.len(), length);
+                assert_eq!(name.ends_with(".bin"), !default_filename.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn upload_id_prefix_is_prepended_and_checked() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = config::Folio {
+            uploads_path: temp_dir.path().to_string_lossy().to_string(),
+            upload_id_prefix: "folio-".to_string(),
+            ..config::Folio::default()
+        };
+        let name = generate_upload_name(&config, None, Some("txt")).unwrap();
+        let id = name.strip_prefix("folio-").unwrap();
+        assert_eq!(id.len(), UPLOAD_ID_LENGTH + ".txt".len());
+
+        let config = config::Folio {
+            upload_id_prefix: "../x".to_string(),
+            ..config
+        };
+        assert!(generate_upload_name(&config, None, Some("txt")).is_err());
+    }
+
+    #[actix_web::test]
+    async fn success_status_can_be_200() {
+        let (mut config, expiry_store, private_store, temp_dir) = test_state();
+        config.upload_success_status = config::UploadSuccessStatus::Ok;
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .service(upload_json),
+        )
+        .await;
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads")
+            .set_json(serde_json::json!({ "content_base64": BASE64.encode("ok") }))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key("Location"));
+        let body: serde_json::Value = awtest::read_body_json(response).await;
+        assert_eq!(body["message"], "file uploaded successfully");
+    }
+
+    #[actix_web::test]
+    async fn json_upload_rejects_invalid_base64_and_oversize() {
+        let (mut config, expiry_store, private_store, temp_dir) = test_state();
+        config.max_upload_size = 6;
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .service(upload_json),
+        )
+        .await;
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads")
+            .set_json(serde_json::json!({ "content_base64": "ab*=" }))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads")
+            .set_json(serde_json::json!({ "content_base64": BASE64.encode("1234567890") }))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[actix_web::test]
+    async fn size_limits_follow_content_type() {
+        let (mut config, expiry_store, private_store, temp_dir) = test_state();
+        config.max_upload_size = 8;
+        config.max_upload_bytes_by_type =
+            BTreeMap::from([(String::from("image/*"), 16), (String::from("text/csv"), 4)]);
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .service(upload_file),
+        )
+        .await;
+        let upload = |content_type: &str, content: &str| {
+            awtest::TestRequest::post()
+                .uri("/uploads")
+                .insert_header((
+                    header::CONTENT_TYPE,
+                    "multipart/form-data; boundary=X-BOUNDARY",
+                ))
+                .set_payload(multipart_body("file", Some(content_type), content))
+                .to_request()
+        };
+
+        // Over the global limit, under the one for images.
+        let response = awtest::call_service(&app, upload("image/png", "0123456789AB")).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = awtest::call_service(&app, upload("text/plain", "0123456789AB")).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Under the global limit, over the one for CSV.
+        let response = awtest::call_service(&app, upload("text/plain", "012345")).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = awtest::call_service(&app, upload("text/csv", "012345")).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body: serde_json::Value = awtest::read_body_json(response).await;
+        assert!(body["message"].as_str().unwrap().contains("4 byte limit"));
+    }
+
+    #[test]
+    fn most_specific_size_limit_wins() {
+        let config = config::Folio {
+            max_upload_size: 10,
+            max_upload_bytes_by_type: BTreeMap::from([
+                (String::from("*/*"), 20),
+                (String::from("image/*"), 30),
+                (String::from("image/png"), 40),
+            ]),
+            ..config::Folio::default()
+        };
+        let limit =
+            |content_type: &str| upload_size_limit(&config, content_type.parse().ok().as_ref());
+        assert_eq!(limit("image/png"), 40);
+        assert_eq!(limit("image/gif"), 30);
+        assert_eq!(limit("text/plain"), 20);
+        assert_eq!(upload_size_limit(&config, None), 10);
+        assert_eq!(largest_upload_limit(&config), 40);
+    }
+
+    #[actix_web::test]
+    async fn delete_upload_by_id_cancels_expiration() {
+        let (config, expiry_store, private_store, temp_dir) = test_state();
+        let full_path = temp_dir.path().join("a1B2c3D4.txt");
+        std::fs::write(&full_path, "content").unwrap();
+        expiry_store
+            .schedule(&full_path, Duration::from_secs(3_600))
+            .await
+            .unwrap();
+        private_store
+            .mark_private(Path::new("a1B2c3D4.txt"), vec![])
+            .await
+            .unwrap();
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(Arc::new(AliasStore::new(&config))))
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store.clone()))
+                .app_data(web::Data::new(private_store.clone()))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .service(delete_upload),
+        )
+        .await;
+        let delete = || {
+            awtest::TestRequest::delete()
+                .uri("/uploads/a1B2c3D4")
+                .to_request()
+        };
+
+        let response = awtest::call_service(&app, delete()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = awtest::read_body_json(response).await;
+        assert_eq!(body["path"], "a1B2c3D4.txt");
+        assert_eq!(body["freed_bytes"], 7);
+        assert!(!full_path.exists());
+        assert_eq!(expiry_store.expire_at(&full_path).await.unwrap(), None);
+        assert!(
+            !private_store
+                .is_private(Path::new("a1B2c3D4.txt"))
+                .await
+                .unwrap()
+        );
+
+        let response = awtest::call_service(&app, delete()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn meta_json_files_are_written_next_to_uploads() {
+        let (mut config, expiry_store, private_store, temp_dir) = test_state();
+        config.meta_json_files = true;
+        let app = awtest::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(expiry_store))
+                .app_data(web::Data::new(private_store))
+                .app_data(web::Data::new(test_metadata_store(&temp_dir)))
+                .app_data(web::Data::new(Arc::new(DirEntryCounter::default())))
+                .service(upload_file),
+        )
+        .await;
+
+        let req = awtest::TestRequest::post()
+            .uri("/uploads?expire=1h")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X-BOUNDARY",
+            ))
+            .set_payload(multipart_body("notes.txt", Some("text/plain"), "hello"))
+            .to_request();
+        let response = awtest::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body: serde_json::Value = awtest::read_body_json(response).await;
+        let file_name = body["url"]
+            .as_str()
+            .unwrap()
+            .strip_prefix("/files/")
+            .unwrap();
+
+        let meta = MetaFile::read(&temp_dir.path().join(file_name)).unwrap();
+        assert_eq!(meta.original_name.as_deref(), Some("notes.txt"));
+        assert_eq!(meta.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(meta.size_bytes, 5);
+        assert_eq!(
+            meta.hash,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(meta.expire_at_unix, body["expire_at_unix"].as_u64());
+    }
 }